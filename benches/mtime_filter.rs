@@ -0,0 +1,68 @@
+//! Compare searching every session against searching only recent ones with
+//! `since`, which skips files last modified long before it, over 100 old and
+//! 5 recent synthetic sessions
+//!
+//! Run with `cargo bench --bench mtime_filter`.
+
+use chrono::{DateTime, Duration, Utc};
+use claude_code_mcp::sessions::{SearchMode, SearchOptions, SessionStore};
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::path::Path;
+
+const OLD_SESSIONS: usize = 100;
+
+const RECENT_SESSIONS: usize = 5;
+
+/// Write one session of 50 messages ending at `updated`, with the file's
+/// mtime set to match as Claude Code leaves it
+fn write_session(project: &Path, id: &str, updated: DateTime<Utc>) {
+    let lines: Vec<String> = (0..50)
+        .map(|n| {
+            let role = if n % 2 == 0 { "user" } else { "assistant" };
+            serde_json::json!({
+                "type": role,
+                "sessionId": id,
+                "timestamp": (updated - Duration::minutes(50 - n)).to_rfc3339(),
+                "message": {"role": role, "content": format!("step {} of the websocket reconnect work in {}", n, id)}
+            })
+            .to_string()
+        })
+        .collect();
+    let path = project.join(format!("{}.jsonl", id));
+    std::fs::write(&path, lines.join("\n")).unwrap();
+    let file = std::fs::File::options().write(true).open(&path).unwrap();
+    file.set_modified(updated.into()).unwrap();
+}
+
+fn mtime_filter_bench(c: &mut Criterion) {
+    let claude_dir = tempfile::tempdir().unwrap();
+    let project = claude_dir.path().join("projects").join("-home-user-project");
+    std::fs::create_dir_all(&project).unwrap();
+    let now = Utc::now();
+    for i in 0..OLD_SESSIONS {
+        write_session(&project, &format!("old-{:03}", i), now - Duration::days(365 + i as i64));
+    }
+    for i in 0..RECENT_SESSIONS {
+        write_session(&project, &format!("recent-{}", i), now - Duration::hours(i as i64));
+    }
+    let store = SessionStore::with_path(claude_dir.path().to_path_buf());
+    let search = |options: SearchOptions| {
+        store.search_sessions("reconnect", 50, None, SearchMode::Regex, options).unwrap()
+    };
+
+    let mut group = c.benchmark_group("search_recent_sessions");
+    group.sample_size(20);
+    group.bench_function("all_sessions", |b| b.iter(|| search(SearchOptions::default())));
+    group.bench_function("since_last_week", |b| {
+        b.iter(|| {
+            search(SearchOptions {
+                since: Some(now - Duration::days(7)),
+                ..Default::default()
+            })
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, mtime_filter_bench);
+criterion_main!(benches);
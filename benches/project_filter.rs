@@ -0,0 +1,72 @@
+//! Compare finding one project's sessions by parsing every session against
+//! the project index, over 500 synthetic sessions in 10 projects
+//!
+//! Run with `cargo bench --bench project_filter`.
+
+use claude_code_mcp::sessions::{ListOptions, SessionStore};
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::path::Path;
+
+const SESSION_COUNT: usize = 500;
+
+const PROJECT: &str = "/home/user/project3";
+
+/// Write `SESSION_COUNT` sessions spread over 10 projects under `claude_dir`
+fn write_sessions(claude_dir: &Path) {
+    for i in 0..SESSION_COUNT {
+        let project = claude_dir.join("projects").join(format!("-home-user-project{}", i % 10));
+        std::fs::create_dir_all(&project).unwrap();
+
+        let id = format!("session-{:04}", i);
+        let lines: Vec<String> = (0..20)
+            .map(|n| {
+                let role = if n % 2 == 0 { "user" } else { "assistant" };
+                serde_json::json!({
+                    "type": role,
+                    "sessionId": id,
+                    "timestamp": format!("2024-01-{:02}T10:{:02}:00Z", i % 28 + 1, n),
+                    "message": {"role": role, "content": format!("step {} of session {}", n, i)}
+                })
+                .to_string()
+            })
+            .collect();
+        std::fs::write(project.join(format!("{}.jsonl", id)), lines.join("\n")).unwrap();
+    }
+}
+
+fn project_filter_bench(c: &mut Criterion) {
+    let claude_dir = tempfile::tempdir().unwrap();
+    write_sessions(claude_dir.path());
+    let store = SessionStore::with_path(claude_dir.path().to_path_buf());
+
+    let mut group = c.benchmark_group("session_ids_for_project");
+    group.sample_size(10);
+    group.bench_function("parse_all", |b| {
+        b.iter(|| {
+            let page = store.list_sessions(usize::MAX, None, ListOptions::default(), None).unwrap();
+            page.items
+                .into_iter()
+                .filter(|s| s.project_path.as_deref().is_some_and(|p| Path::new(p).starts_with(PROJECT)))
+                .map(|s| s.id)
+                .collect::<Vec<_>>()
+        })
+    });
+    group.bench_function("project_index", |b| {
+        b.iter(|| {
+            // A fresh store each time, so building the index is measured too
+            SessionStore::with_path(claude_dir.path().to_path_buf()).session_ids_for_project_prefix(PROJECT)
+        })
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("tool_usage_stats");
+    group.sample_size(10);
+    group.bench_function("all_projects", |b| b.iter(|| store.tool_usage_stats(None, usize::MAX).unwrap()));
+    group.bench_function("one_project", |b| {
+        b.iter(|| store.tool_usage_stats(Some(PROJECT), usize::MAX).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, project_filter_bench);
+criterion_main!(benches);
@@ -0,0 +1,70 @@
+//! Compare indexed and scan-based `search_sessions` over 500 synthetic sessions
+//!
+//! Run with `cargo bench --bench search_bench`.
+
+use claude_code_mcp::index::IndexedSessionStore;
+use claude_code_mcp::sessions::{SearchMode, SearchOptions, SessionStore};
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::path::Path;
+
+const SESSION_COUNT: usize = 500;
+
+const TOPICS: &[&str] = &[
+    "refactor the websocket reconnect logic",
+    "add dark mode to the settings page",
+    "fix flaky integration test in the payment service",
+    "migrate the database schema to add audit columns",
+    "profile slow startup of the CLI",
+];
+
+/// Write `SESSION_COUNT` sessions spread over a few projects under `claude_dir`
+fn write_sessions(claude_dir: &Path) {
+    for i in 0..SESSION_COUNT {
+        let project = claude_dir.join("projects").join(format!("-home-user-project{}", i % 10));
+        std::fs::create_dir_all(&project).unwrap();
+
+        let id = format!("session-{:04}", i);
+        let lines: Vec<String> = (0..20)
+            .map(|n| {
+                let role = if n % 2 == 0 { "user" } else { "assistant" };
+                serde_json::json!({
+                    "type": role,
+                    "sessionId": id,
+                    "cwd": format!("/home/user/project{}", i % 10),
+                    "timestamp": format!("2024-01-{:02}T10:{:02}:00Z", i % 28 + 1, n),
+                    "message": {
+                        "role": role,
+                        "content": format!("{} (step {} of session {})", TOPICS[(i + n) % TOPICS.len()], n, i)
+                    }
+                })
+                .to_string()
+            })
+            .collect();
+        std::fs::write(project.join(format!("{}.jsonl", id)), lines.join("\n")).unwrap();
+    }
+}
+
+fn search_bench(c: &mut Criterion) {
+    let claude_dir = tempfile::tempdir().unwrap();
+    let index_dir = tempfile::tempdir().unwrap();
+    write_sessions(claude_dir.path());
+
+    let store = SessionStore::with_path(claude_dir.path().to_path_buf());
+    let indexed =
+        IndexedSessionStore::open(SessionStore::with_path(claude_dir.path().to_path_buf()), index_dir.path())
+            .unwrap();
+    indexed.rebuild().unwrap();
+
+    let mut group = c.benchmark_group("search_sessions");
+    group.sample_size(10);
+    group.bench_function("scan", |b| {
+        b.iter(|| store.search_sessions("websocket reconnect", 10, None, SearchMode::Fuzzy, SearchOptions::default()).unwrap())
+    });
+    group.bench_function("indexed", |b| {
+        b.iter(|| indexed.search_sessions("websocket reconnect", 10, None, SearchMode::Fuzzy, SearchOptions::default()).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, search_bench);
+criterion_main!(benches);
@@ -0,0 +1,56 @@
+//! Load a 10 MB synthetic session in full and as its first 100 messages
+//!
+//! Run with `cargo bench --bench session_load`.
+
+use claude_code_mcp::sessions::SessionStore;
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::io::Write;
+use std::path::Path;
+
+/// Size of the synthetic session file
+const FILE_BYTES: usize = 10 * 1024 * 1024;
+
+/// Messages loaded by the `head` benchmark
+const HEAD_MESSAGES: usize = 100;
+
+/// Write one long session of alternating user and assistant messages under `claude_dir`
+fn write_long_session(claude_dir: &Path) {
+    let project = claude_dir.join("projects").join("-home-user-project");
+    std::fs::create_dir_all(&project).unwrap();
+    let mut file = std::io::BufWriter::new(std::fs::File::create(project.join("long-session.jsonl")).unwrap());
+
+    let filler = "Refactor the websocket reconnect logic and keep the retry budget. ".repeat(15);
+    let mut written = 0;
+    let mut n = 0;
+    while written < FILE_BYTES {
+        let role = if n % 2 == 0 { "user" } else { "assistant" };
+        let line = serde_json::json!({
+            "type": role,
+            "sessionId": "long-session",
+            "cwd": "/home/user/project",
+            "timestamp": format!("2024-01-01T{:02}:{:02}:{:02}Z", n / 3600 % 24, n / 60 % 60, n % 60),
+            "message": {"role": role, "content": format!("{} (message {})", filler, n)}
+        })
+        .to_string();
+        writeln!(file, "{}", line).unwrap();
+        written += line.len() + 1;
+        n += 1;
+    }
+}
+
+fn session_load_bench(c: &mut Criterion) {
+    let claude_dir = tempfile::tempdir().unwrap();
+    write_long_session(claude_dir.path());
+    let store = SessionStore::with_path(claude_dir.path().to_path_buf());
+
+    let mut group = c.benchmark_group("get_session");
+    group.sample_size(10);
+    group.bench_function("full", |b| b.iter(|| store.get_session("long-session").unwrap().unwrap()));
+    group.bench_function("head", |b| {
+        b.iter(|| store.get_session_head("long-session", Some(HEAD_MESSAGES)).unwrap().unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, session_load_bench);
+criterion_main!(benches);
@@ -0,0 +1,65 @@
+//! Compare listing every summary field against only `id` and `updated_at`,
+//! over 500 parsed synthetic sessions with long opening messages. Like
+//! `list_sessions` with its cache, summaries are built from parsed sessions
+//! and go through a `serde_json::Value` before being rendered.
+//!
+//! Run with `cargo bench --bench summary_fields`.
+
+use claude_code_mcp::sessions::{fast_summary, retain_fields, session_to_summary, SessionStore, DEFAULT_PREVIEW_CHARS};
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::path::Path;
+
+const SESSION_COUNT: usize = 500;
+
+/// Write `SESSION_COUNT` sessions under `claude_dir`
+fn write_sessions(claude_dir: &Path) {
+    let project = claude_dir.join("projects").join("-home-user-project");
+    std::fs::create_dir_all(&project).unwrap();
+    let request = "please refactor the request handler so that it streams responses ".repeat(50);
+
+    for i in 0..SESSION_COUNT {
+        let id = format!("session-{:04}", i);
+        let lines: Vec<String> = (0..20)
+            .map(|n| {
+                let role = if n % 2 == 0 { "user" } else { "assistant" };
+                serde_json::json!({
+                    "type": role,
+                    "sessionId": id,
+                    "timestamp": format!("2024-01-{:02}T10:{:02}:00Z", i % 28 + 1, n),
+                    "message": {"role": role, "content": format!("{} (step {})", request, n)}
+                })
+                .to_string()
+            })
+            .collect();
+        std::fs::write(project.join(format!("{}.jsonl", id)), lines.join("\n")).unwrap();
+    }
+}
+
+fn summary_fields_bench(c: &mut Criterion) {
+    let claude_dir = tempfile::tempdir().unwrap();
+    write_sessions(claude_dir.path());
+    let sessions = SessionStore::with_path(claude_dir.path().to_path_buf()).sessions_in_project(None);
+    let fields = vec!["id".to_string(), "updated_at".to_string()];
+
+    let mut group = c.benchmark_group("list_sessions_fields");
+    group.bench_function("all_fields", |b| {
+        b.iter(|| {
+            let summaries: Vec<_> = sessions.iter().map(|s| session_to_summary(s, DEFAULT_PREVIEW_CHARS)).collect();
+            serde_json::to_string_pretty(&serde_json::to_value(summaries).unwrap()).unwrap()
+        })
+    });
+    group.bench_function("id_and_updated_at", |b| {
+        b.iter(|| {
+            let summaries: Vec<_> = sessions.iter().map(fast_summary).collect();
+            let mut summaries = serde_json::to_value(summaries).unwrap();
+            for summary in summaries.as_array_mut().unwrap() {
+                retain_fields(summary, &fields);
+            }
+            serde_json::to_string_pretty(&summaries).unwrap()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, summary_fields_bench);
+criterion_main!(benches);
@@ -0,0 +1,190 @@
+//! In-memory cache of parsed sessions for `list_sessions`
+//!
+//! Listing means parsing every session file, so the parsed sessions are kept
+//! between calls, along with the index of their key terms that
+//! `suggestions_for` completes queries from, once it is first needed. The
+//! file-system watcher marks the cache stale whenever a session file changes,
+//! and the next call that needs it parses the files again. If no watcher is
+//! running, nothing can mark the cache stale, so every call parses the files
+//! as it did before.
+
+use serde::Serialize;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+use claude_code_mcp::sessions::{session_to_summary, Session, SessionStore, SessionSummary};
+use claude_code_mcp::suggestions::TermIndex;
+
+/// Sessions as parsed at one point in time
+pub struct Snapshot {
+    sessions: Vec<Session>,
+    agents: Vec<Session>,
+    loaded_at: Instant,
+    /// Key terms of `sessions`, indexed on first use
+    term_index: OnceLock<Arc<TermIndex>>,
+}
+
+impl Snapshot {
+    /// Parse every session and agent sub-session in `store` now. Blocks on
+    /// file I/O.
+    pub fn load(store: &SessionStore) -> Self {
+        Self::new(store.sessions_in_project(None), store.agent_sessions())
+    }
+
+    /// Top-level `sessions` and `agents` parsed just now
+    pub fn new(sessions: Vec<Session>, agents: Vec<Session>) -> Self {
+        Self {
+            sessions,
+            agents,
+            loaded_at: Instant::now(),
+            term_index: OnceLock::new(),
+        }
+    }
+
+    /// The session or agent sub-session with ID `id`
+    pub fn session(&self, id: &str) -> Option<&Session> {
+        self.sessions.iter().chain(&self.agents).find(|s| s.id == id)
+    }
+
+    /// Index of the key terms of the top-level sessions, built the first
+    /// time it is asked for and kept as long as the snapshot is
+    pub fn term_index(&self) -> Arc<TermIndex> {
+        self.term_index.get_or_init(|| Arc::new(TermIndex::build(&self.sessions))).clone()
+    }
+
+    /// How long ago the sessions were parsed
+    pub fn age(&self) -> Duration {
+        self.loaded_at.elapsed()
+    }
+
+    /// Summaries of the sessions, agent sub-sessions included only with
+    /// `include_agents`, in no particular order, with previews of
+    /// `preview_length` characters. With a `preview_length` of 0 they are
+    /// `fast_summary`s.
+    pub fn summaries(&self, include_agents: bool, min_messages: Option<usize>, preview_length: usize) -> Vec<SessionSummary> {
+        let agents = if include_agents { self.agents.as_slice() } else { &[] };
+        self.sessions
+            .iter()
+            .chain(agents)
+            .filter(|s| min_messages.is_none_or(|min| s.messages.len() >= min))
+            .map(|session| session_to_summary(session, preview_length))
+            .collect()
+    }
+}
+
+/// A `SessionStore` whose parsed sessions are kept between calls until a
+/// session file changes
+pub struct CachedSessionStore {
+    store: SessionStore,
+    /// More sessions than this are parsed on every call instead of kept
+    max_sessions: usize,
+    /// Changes whenever a session file does; closed if the watcher stopped
+    invalidated: Mutex<watch::Receiver<()>>,
+    /// The same changes, seen separately for `store`'s project index
+    index_invalidated: Mutex<watch::Receiver<()>>,
+    snapshot: Mutex<Option<Arc<Snapshot>>>,
+}
+
+impl CachedSessionStore {
+    /// A cache over `store` that goes stale when `invalidated` changes,
+    /// holding at most `max_sessions` sessions. Nothing is parsed until the
+    /// first call to `snapshot`.
+    pub fn new(store: SessionStore, invalidated: watch::Receiver<()>, max_sessions: usize) -> Self {
+        Self {
+            store,
+            max_sessions,
+            index_invalidated: Mutex::new(invalidated.clone()),
+            invalidated: Mutex::new(invalidated),
+            snapshot: Mutex::new(None),
+        }
+    }
+
+    /// The underlying store, sharing its project index across calls. The
+    /// index is dropped first if a session file changed since it was built.
+    pub fn store(&self) -> SessionStore {
+        let mut invalidated = self.index_invalidated.lock().unwrap_or_else(|e| e.into_inner());
+        if invalidated.has_changed().unwrap_or(true) {
+            invalidated.mark_unchanged();
+            self.store.invalidate_index();
+        }
+        self.store.clone()
+    }
+
+    /// The cached sessions, parsed again first if a session file changed
+    /// since they were loaded. Blocks on file I/O.
+    pub fn snapshot(&self) -> Arc<Snapshot> {
+        // Held while parsing, so concurrent calls wait for one refresh
+        // instead of each doing their own
+        let mut snapshot = self.snapshot.lock().unwrap_or_else(|e| e.into_inner());
+        let stale = {
+            let mut invalidated = self.invalidated.lock().unwrap_or_else(|e| e.into_inner());
+            let stale = invalidated.has_changed().unwrap_or(true);
+            // Changes from here on are after the files are read, and count
+            invalidated.mark_unchanged();
+            stale
+        };
+
+        match &*snapshot {
+            Some(current) if !stale => current.clone(),
+            _ => {
+                let fresh = Arc::new(Snapshot::load(&self.store));
+                let fits = fresh.sessions.len() + fresh.agents.len() <= self.max_sessions;
+                *snapshot = fits.then(|| fresh.clone());
+                fresh
+            }
+        }
+    }
+
+    /// Parse every session again with `load`, whether or not a file changed,
+    /// and keep the result. Calls to `snapshot` meanwhile get the sessions
+    /// cached before rather than waiting. Blocks on file I/O.
+    pub fn rebuild(&self, load: impl FnOnce(&SessionStore) -> Snapshot) -> Arc<Snapshot> {
+        // Changes from here on are after the files are read, and count
+        self.invalidated.lock().unwrap_or_else(|e| e.into_inner()).mark_unchanged();
+        let fresh = Arc::new(load(&self.store));
+        let fits = fresh.sessions.len() + fresh.agents.len() <= self.max_sessions;
+        *self.snapshot.lock().unwrap_or_else(|e| e.into_inner()) = fits.then(|| fresh.clone());
+        fresh
+    }
+}
+
+/// Outcome of `rebuild_index`
+#[derive(Debug, Serialize)]
+pub struct RebuildResult {
+    pub sessions_found: usize,
+    pub sessions_failed: usize,
+    pub duration_ms: u64,
+    /// Sessions in the rebuilt search index, with `--index-path`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub indexed_sessions: Option<usize>,
+    /// `<path>: <reason>` for each session file that failed to parse, and the
+    /// search index's error if it couldn't be rebuilt
+    pub errors: Vec<String>,
+}
+
+/// Freshness of cached results, sent as `_meta`
+#[derive(Debug, Serialize)]
+pub struct CacheMeta {
+    pub cache_age_seconds: f64,
+}
+
+/// A result followed by a `_meta` object saying how old its data is
+#[derive(Debug, Serialize)]
+pub struct WithCacheMeta<T> {
+    #[serde(flatten)]
+    pub result: T,
+    #[serde(rename = "_meta")]
+    pub meta: CacheMeta,
+}
+
+impl<T> WithCacheMeta<T> {
+    pub fn new(result: T, age: Duration) -> Self {
+        Self {
+            result,
+            meta: CacheMeta {
+                cache_age_seconds: age.as_secs_f64(),
+            },
+        }
+    }
+}
@@ -0,0 +1,28 @@
+//! Limit on how many tool calls run at once
+//!
+//! Every tool call holds a permit while it runs on the blocking pool. Once all
+//! permits are taken, further calls are turned away instead of queued, so a
+//! burst from many HTTP clients can't pile up more file I/O than the disk
+//! keeps up with.
+
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+pub struct ConcurrencyLimiter {
+    permits: Arc<Semaphore>,
+}
+
+impl ConcurrencyLimiter {
+    /// A limiter letting `max` calls run at once
+    pub fn new(max: usize) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(max)),
+        }
+    }
+
+    /// A permit to run one call, held until dropped, or `None` if `max` calls
+    /// are already running
+    pub fn try_acquire(&self) -> Option<OwnedSemaphorePermit> {
+        self.permits.clone().try_acquire_owned().ok()
+    }
+}
@@ -0,0 +1,344 @@
+//! Embedding vectors of sessions for semantic search
+//!
+//! `claude-code-mcp build-embeddings` sends the text of every session to a
+//! local embedding model endpoint and saves the vectors it returns in
+//! `~/.claude-code-mcp/embeddings.cache`. A `search_sessions` call in semantic
+//! mode embeds its query through the same endpoint and ranks the cached
+//! sessions by cosine similarity to it.
+//!
+//! The endpoint gets `POST {"input": "<text>"}` and may answer in either of
+//! the common shapes, `{"embedding": [...]}` or `{"data": [{"embedding": [...]}]}`.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::sessions::{
+    extract_full_text_for_search, paginate_matches, session_to_summary, updated_in_range, SearchCursor, SearchMode,
+    SearchOptions, SearchResult, Session, SessionStore, SessionSummary, DEFAULT_PREVIEW_CHARS,
+};
+
+/// First bytes of a cache file, changed whenever the layout does
+const MAGIC: &[u8; 8] = b"CCMEMB01";
+
+/// Characters of a session's text that are embedded; embedding models only
+/// take so much input, and the start of a session says what it is about
+pub const EMBEDDING_TEXT_CHARS: usize = 8000;
+
+/// How long the endpoint gets to embed one text
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Embedding vectors by session ID
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EmbeddingCache {
+    embeddings: BTreeMap<String, Vec<f32>>,
+}
+
+impl EmbeddingCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The cache saved at `path`, or `None` if there isn't one
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let bytes = match std::fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        Self::from_bytes(&bytes)
+            .map(Some)
+            .with_context(|| format!("Invalid embeddings cache {}", path.display()))
+    }
+
+    /// Write the cache to `path`, creating its directory if needed
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        std::fs::write(path, self.to_bytes()).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    pub fn get(&self, session_id: &str) -> Option<&[f32]> {
+        self.embeddings.get(session_id).map(Vec::as_slice)
+    }
+
+    pub fn insert(&mut self, session_id: impl Into<String>, embedding: Vec<f32>) {
+        self.embeddings.insert(session_id.into(), embedding);
+    }
+
+    pub fn len(&self) -> usize {
+        self.embeddings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.embeddings.is_empty()
+    }
+
+    /// Length of the cached vectors, `None` when the cache is empty
+    pub fn dimensions(&self) -> Option<usize> {
+        self.embeddings.values().next().map(Vec::len)
+    }
+
+    /// `MAGIC`, then the number of entries, then for each its session ID and
+    /// vector, each prefixed by its length. Integers and floats are little-endian.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend((self.embeddings.len() as u32).to_le_bytes());
+        for (session_id, embedding) in &self.embeddings {
+            bytes.extend((session_id.len() as u32).to_le_bytes());
+            bytes.extend(session_id.as_bytes());
+            bytes.extend((embedding.len() as u32).to_le_bytes());
+            for value in embedding {
+                bytes.extend(value.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut reader = ByteReader { bytes };
+        if reader.take(MAGIC.len())? != MAGIC {
+            anyhow::bail!("not an embeddings cache, or written by another version");
+        }
+        let mut cache = Self::new();
+        for _ in 0..reader.u32()? {
+            let len = reader.u32()? as usize;
+            let session_id = String::from_utf8(reader.take(len)?.to_vec()).context("session ID is not UTF-8")?;
+            let dimensions = reader.u32()? as usize;
+            let embedding = reader
+                .take(dimensions.checked_mul(4).context("vector too long")?)?
+                .chunks_exact(4)
+                .map(|chunk| f32::from_le_bytes(chunk.try_into().expect("chunks of 4 bytes")))
+                .collect();
+            cache.insert(session_id, embedding);
+        }
+        if !reader.bytes.is_empty() {
+            anyhow::bail!("unexpected data after the last entry");
+        }
+        Ok(cache)
+    }
+}
+
+/// Reads a cache file from the front
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            anyhow::bail!("file is truncated");
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().expect("4 bytes")))
+    }
+}
+
+/// Where the embeddings cache is kept
+pub fn cache_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".claude-code-mcp").join("embeddings.cache"))
+}
+
+/// Cosine of the angle between `a` and `b`, from -1 to 1. Vectors of
+/// different lengths, or with no length at all, aren't similar: 0.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+/// The text of `session` that is embedded for it: its messages, cut to
+/// `EMBEDDING_TEXT_CHARS` characters
+pub fn embedding_text(session: &Session) -> String {
+    extract_full_text_for_search(session, false, None)
+        .chars()
+        .take(EMBEDDING_TEXT_CHARS)
+        .collect()
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum EmbeddingResponse {
+    Single { embedding: Vec<f32> },
+    Data { data: Vec<EmbeddingData> },
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    embedding: Vec<f32>,
+}
+
+/// Client of an embedding model endpoint. Blocks on the network, so it must
+/// not be used from async code.
+pub struct EmbeddingClient {
+    endpoint: String,
+    client: reqwest::blocking::Client,
+}
+
+impl EmbeddingClient {
+    pub fn new(endpoint: &str) -> Result<Self> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .context("Failed to create HTTP client")?;
+        Ok(Self {
+            endpoint: endpoint.to_string(),
+            client,
+        })
+    }
+
+    /// The embedding vector of `text`
+    pub fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        let response = self
+            .client
+            .post(&self.endpoint)
+            .json(&serde_json::json!({ "input": text }))
+            .send()
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("Embedding request to {} failed", self.endpoint))?;
+        let embedding = match response
+            .json::<EmbeddingResponse>()
+            .with_context(|| format!("Unexpected response from {}", self.endpoint))?
+        {
+            EmbeddingResponse::Single { embedding } => embedding,
+            EmbeddingResponse::Data { data } => data.into_iter().next().map(|d| d.embedding).unwrap_or_default(),
+        };
+        if embedding.is_empty() {
+            anyhow::bail!("{} returned an empty embedding", self.endpoint);
+        }
+        Ok(embedding)
+    }
+}
+
+/// Embed every non-agent session in `store` with `client`, calling `progress`
+/// with the number done and the total after each
+pub fn build_cache(store: &SessionStore, client: &EmbeddingClient, progress: impl Fn(usize, usize)) -> Result<EmbeddingCache> {
+    let sessions: Vec<Session> = store
+        .sessions_in_project(None)
+        .into_iter()
+        .filter(|s| !s.messages.is_empty())
+        .collect();
+    let mut cache = EmbeddingCache::new();
+    for (done, session) in sessions.iter().enumerate() {
+        let embedding = client
+            .embed(&embedding_text(session))
+            .with_context(|| format!("Failed to embed session {}", session.id))?;
+        cache.insert(session.id.clone(), embedding);
+        progress(done + 1, sessions.len());
+    }
+    Ok(cache)
+}
+
+/// Rank the sessions in `store` by the cosine similarity of their cached
+/// embedding to `query_embedding`, the embedding of `query`. Scores are the
+/// similarity times 1000. Sessions without a cached embedding, such as those
+/// started since the cache was built, aren't found.
+pub fn semantic_search(
+    store: &SessionStore,
+    cache: &EmbeddingCache,
+    query: &str,
+    query_embedding: &[f32],
+    limit: usize,
+    cursor: Option<&str>,
+    options: SearchOptions,
+) -> Result<SearchResult<SessionSummary>> {
+    if let Some(dimensions) = cache.dimensions().filter(|&d| d != query_embedding.len()) {
+        anyhow::bail!(
+            "The embeddings cache holds {}-dimensional vectors, but the endpoint returned {} dimensions for the query; \
+             rebuild the cache with build-embeddings",
+            dimensions,
+            query_embedding.len()
+        );
+    }
+    let SearchOptions { min_score, since, until, .. } = options;
+
+    let mut matches = Vec::new();
+    for session in store.sessions_in_project(None) {
+        if !updated_in_range(&session, since, until) {
+            continue;
+        }
+        let Some(embedding) = cache.get(&session.id) else {
+            continue;
+        };
+        // Cursors hold integer scores; keep three decimals of the similarity
+        let score = (f64::from(cosine_similarity(embedding, query_embedding)) * 1000.0).round() as i64;
+        if min_score.is_some_and(|min| score < min) {
+            continue;
+        }
+        let mut summary = session_to_summary(&session, DEFAULT_PREVIEW_CHARS);
+        summary.score = Some(score);
+        matches.push((SearchCursor::new(score, &summary.id, None), summary));
+    }
+    let (page, total_matched) = paginate_matches(matches, cursor, limit.min(50))?;
+
+    Ok(SearchResult {
+        items: page.items,
+        next_cursor: page.next_cursor,
+        query: query.to_string(),
+        search_mode: SearchMode::Semantic,
+        total_matched,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cosine_similarity() {
+        assert!((cosine_similarity(&[1.0, 2.0, 3.0], &[2.0, 4.0, 6.0]) - 1.0).abs() < 1e-6);
+        assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]).abs() < 1e-6);
+        assert!((cosine_similarity(&[1.0, 0.0], &[-1.0, 0.0]) + 1.0).abs() < 1e-6);
+        assert!((cosine_similarity(&[1.0, 1.0], &[1.0, 0.0]) - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[1.0, 0.0, 0.0]), 0.0);
+    }
+
+    #[test]
+    fn test_cache_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("embeddings.cache");
+        assert_eq!(EmbeddingCache::load(&path).unwrap(), None);
+
+        let mut cache = EmbeddingCache::new();
+        cache.insert("session-a", vec![0.25, -1.5, 3.0]);
+        cache.insert("séance-b", vec![f32::MIN_POSITIVE, 0.0, 1e10]);
+        cache.save(&path).unwrap();
+
+        let loaded = EmbeddingCache::load(&path).unwrap().unwrap();
+        assert_eq!(loaded, cache);
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded.dimensions(), Some(3));
+        assert_eq!(loaded.get("session-a"), Some([0.25, -1.5, 3.0].as_slice()));
+        assert_eq!(loaded.get("missing"), None);
+    }
+
+    #[test]
+    fn test_cache_rejects_bad_files() {
+        let mut cache = EmbeddingCache::new();
+        cache.insert("session-a", vec![1.0, 2.0]);
+        let bytes = cache.to_bytes();
+
+        assert!(EmbeddingCache::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(EmbeddingCache::from_bytes(b"not a cache").is_err());
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(EmbeddingCache::from_bytes(&trailing).is_err());
+        assert_eq!(EmbeddingCache::from_bytes(&EmbeddingCache::new().to_bytes()).unwrap(), EmbeddingCache::new());
+    }
+}
@@ -0,0 +1,135 @@
+//! Bulk export of session summaries for spreadsheets and databases
+
+use crate::sessions::SessionSummary;
+
+/// Columns written by `sessions_to_csv`, in order
+pub const CSV_COLUMNS: &[&str] = &[
+    "id",
+    "project_path",
+    "cwd",
+    "created_at",
+    "updated_at",
+    "message_count",
+    "user_messages",
+    "assistant_messages",
+    "preview",
+];
+
+/// Render sessions as CSV with a header row. Fields containing commas, quotes
+/// or line breaks are quoted (RFC 4180); missing values are empty fields.
+pub fn sessions_to_csv(sessions: &[SessionSummary]) -> String {
+    let mut out = CSV_COLUMNS.join(",");
+    out.push('\n');
+
+    for session in sessions {
+        let fields = [
+            session.id.clone(),
+            session.project_path.clone().unwrap_or_default(),
+            session.cwd.clone().unwrap_or_default(),
+            session.created_at.clone().unwrap_or_default(),
+            session.updated_at.clone().unwrap_or_default(),
+            session.message_count.to_string(),
+            session.user_messages.to_string(),
+            session.assistant_messages.to_string(),
+            session.preview.clone(),
+        ];
+        let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        out.push_str(&row.join(","));
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Quote a field if it would otherwise break the row apart
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Minimal RFC 4180 reader, enough to check the output round-trips
+    fn parse_csv(text: &str) -> Vec<Vec<String>> {
+        let mut rows = Vec::new();
+        let mut row = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut chars = text.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match (quoted, c) {
+                (true, '"') if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                (true, '"') => quoted = false,
+                (true, c) => field.push(c),
+                (false, '"') => quoted = true,
+                (false, ',') => row.push(std::mem::take(&mut field)),
+                (false, '\n') => {
+                    row.push(std::mem::take(&mut field));
+                    rows.push(std::mem::take(&mut row));
+                }
+                (false, c) => field.push(c),
+            }
+        }
+        rows
+    }
+
+    fn summary(id: &str, preview: &str) -> SessionSummary {
+        SessionSummary {
+            id: id.to_string(),
+            project_path: Some("/home/user/project".to_string()),
+            cwd: None,
+            created_at: Some("2025-01-01T10:00:00+00:00".to_string()),
+            updated_at: Some("2025-01-01T11:00:00+00:00".to_string()),
+            message_count: 3,
+            user_messages: 2,
+            assistant_messages: 1,
+            preview: preview.to_string(),
+            tags: None,
+            match_context: None,
+            score: None,
+            total_cost_usd: None,
+            total_duration_ms: None,
+            git_repo: None,
+            git_branch: None,
+        }
+    }
+
+    #[test]
+    fn test_sessions_to_csv_round_trips() {
+        let sessions = [
+            summary("s1", "Fix the parser, then the lexer"),
+            summary("s2", "Line one\nLine two, with \"quotes\"\r\nand more"),
+        ];
+
+        let csv = sessions_to_csv(&sessions);
+        let rows = parse_csv(&csv);
+
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0], CSV_COLUMNS);
+        assert_eq!(
+            rows[1],
+            vec![
+                "s1",
+                "/home/user/project",
+                "",
+                "2025-01-01T10:00:00+00:00",
+                "2025-01-01T11:00:00+00:00",
+                "3",
+                "2",
+                "1",
+                "Fix the parser, then the lexer",
+            ]
+        );
+        assert_eq!(rows[2][0], "s2");
+        assert_eq!(rows[2][8], sessions[1].preview);
+    }
+}
@@ -0,0 +1,165 @@
+//! Responses of the HTTP transport
+//!
+//! Clients disagree on the media type of JSON-RPC over HTTP: most send and
+//! expect `application/json`, some `application/json-rpc` or
+//! `application/jsonrpc+json`. `POST /mcp` answers in whichever of these the
+//! request's `Accept` header prefers, and with `X-Request-Id` echoing the
+//! JSON-RPC `id` so a response can be traced back to its request.
+
+use axum::body::Body;
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::response::Response;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Media types a JSON-RPC response can be sent as, the default first
+pub const JSON_RPC_MEDIA_TYPES: [&str; 3] = ["application/json", "application/json-rpc", "application/jsonrpc+json"];
+
+/// Content type of the Server-Sent Events stream
+pub const EVENT_STREAM_CONTENT_TYPE: &str = "text/event-stream; charset=utf-8";
+
+/// Header carrying the JSON-RPC `id` of the request a response answers
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// The media type in `JSON_RPC_MEDIA_TYPES` that `accept` prefers: the one
+/// with the highest quality, where a type named outright beats one matched by
+/// `application/*`, which beats `*/*`. Without an `Accept` header, or one
+/// accepting none of them, this is `application/json`.
+pub fn negotiate_content_type(accept: Option<&str>) -> &'static str {
+    let Some(accept) = accept else {
+        return JSON_RPC_MEDIA_TYPES[0];
+    };
+    let ranges: Vec<(&str, f32)> = accept.split(',').filter_map(parse_media_range).collect();
+
+    // Ties go to the earlier, more widely understood type
+    let mut best = (JSON_RPC_MEDIA_TYPES[0], 0.0, 0);
+    for media_type in JSON_RPC_MEDIA_TYPES {
+        let matched = ranges
+            .iter()
+            .filter_map(|&(range, quality)| {
+                let specificity = match range {
+                    "*/*" => 0,
+                    "application/*" => 1,
+                    range if range.eq_ignore_ascii_case(media_type) => 2,
+                    _ => return None,
+                };
+                Some((specificity, quality))
+            })
+            // The most specific range decides, whatever its quality
+            .max_by_key(|&(specificity, _)| specificity);
+        if let Some((specificity, quality)) = matched {
+            if quality > best.1 || (quality == best.1 && quality > 0.0 && specificity > best.2) {
+                best = (media_type, quality, specificity);
+            }
+        }
+    }
+    best.0
+}
+
+/// A media range and its quality from one entry of an `Accept` header
+fn parse_media_range(entry: &str) -> Option<(&str, f32)> {
+    let mut parts = entry.split(';').map(str::trim);
+    let range = parts.next().filter(|range| !range.is_empty())?;
+    let quality = parts
+        .find_map(|param| param.strip_prefix("q=").or_else(|| param.strip_prefix("Q=")))
+        .map_or(Some(1.0), |q| q.parse::<f32>().ok())?;
+    Some((range, quality.clamp(0.0, 1.0)))
+}
+
+/// Builds the response to a JSON-RPC request: the negotiated `Content-Type`,
+/// `Content-Length` and `X-Request-Id`
+pub struct ResponseBuilder {
+    status: StatusCode,
+    content_type: &'static str,
+    request_id: Option<HeaderValue>,
+}
+
+impl ResponseBuilder {
+    /// A `200 OK` response in the media type `accept` prefers
+    pub fn new(accept: Option<&str>) -> Self {
+        Self {
+            status: StatusCode::OK,
+            content_type: negotiate_content_type(accept),
+            request_id: None,
+        }
+    }
+
+    pub fn status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Echo `id` in `X-Request-Id`: strings as they are, numbers in decimal.
+    /// A null `id`, or one that can't be a header value, sends no header.
+    pub fn request_id(mut self, id: &Value) -> Self {
+        let id = match id {
+            Value::String(id) => id.clone(),
+            Value::Number(id) => id.to_string(),
+            _ => return self,
+        };
+        self.request_id = HeaderValue::from_str(&id).ok();
+        self
+    }
+
+    /// The response with `body` serialized as JSON
+    pub fn json(self, body: &impl Serialize) -> Response {
+        let body = serde_json::to_vec(body).expect("JSON-RPC messages serialize");
+        let mut response = self.empty();
+        let headers = response.headers_mut();
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(self.content_type));
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from(body.len()));
+        *response.body_mut() = Body::from(body);
+        response
+    }
+
+    /// The response without a body, e.g. `202 Accepted` for a notification
+    pub fn empty(&self) -> Response {
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = self.status;
+        response.headers_mut().insert(header::CONTENT_LENGTH, HeaderValue::from(0));
+        if let Some(id) = &self.request_id {
+            response.headers_mut().insert(REQUEST_ID_HEADER, id.clone());
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate_content_type() {
+        assert_eq!(negotiate_content_type(None), "application/json");
+        assert_eq!(negotiate_content_type(Some("*/*")), "application/json");
+        assert_eq!(negotiate_content_type(Some("application/*")), "application/json");
+        assert_eq!(negotiate_content_type(Some("application/json-rpc")), "application/json-rpc");
+        assert_eq!(negotiate_content_type(Some("Application/JSONRPC+JSON")), "application/jsonrpc+json");
+        // Named outright beats a wildcard of the same quality
+        assert_eq!(negotiate_content_type(Some("*/*, application/json-rpc")), "application/json-rpc");
+        assert_eq!(
+            negotiate_content_type(Some("application/json;q=0.5, application/jsonrpc+json;q=0.9")),
+            "application/jsonrpc+json"
+        );
+        assert_eq!(negotiate_content_type(Some("application/json;q=0, */*")), "application/json-rpc");
+        // Nothing acceptable: answer in the default anyway
+        assert_eq!(negotiate_content_type(Some("text/html")), "application/json");
+        assert_eq!(negotiate_content_type(Some("")), "application/json");
+    }
+
+    #[test]
+    fn test_request_id_header() {
+        let response = ResponseBuilder::new(None).request_id(&serde_json::json!(7)).json(&serde_json::json!({}));
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "7");
+        assert_eq!(response.headers()[header::CONTENT_LENGTH], "2");
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+
+        let response = ResponseBuilder::new(None).request_id(&serde_json::json!("abc-1")).empty();
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "abc-1");
+
+        for id in [Value::Null, serde_json::json!("line\nbreak")] {
+            let response = ResponseBuilder::new(None).request_id(&id).empty();
+            assert!(response.headers().get(REQUEST_ID_HEADER).is_none());
+        }
+    }
+}
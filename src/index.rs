@@ -0,0 +1,361 @@
+//! Optional tantivy full-text index over session content
+//!
+//! Enabled with `--index-path`. Each session file becomes one document; the
+//! index is brought up to date with the session files before every search by
+//! re-indexing only files whose modification time changed.
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+use tantivy::collector::{Count, DocSetCollector, TopDocs};
+use tantivy::directory::MmapDirectory;
+use tantivy::query::{AllQuery, QueryParser};
+use tantivy::schema::{Field, Schema, Value, FAST, STORED, STRING, TEXT};
+use tantivy::{doc, Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, Term};
+
+use crate::sessions::{
+    extract_full_text_for_search, fuzzy_match_context, paginate_matches, session_to_summary, SearchCursor, SearchMode,
+    SearchOptions, SearchResult, Session, SessionStore, SessionSummary, DEFAULT_PREVIEW_CHARS,
+};
+
+/// Memory budget for the index writer (tantivy requires at least 15MB)
+const WRITER_MEMORY_BYTES: usize = 50_000_000;
+
+/// Indexed fields
+struct Fields {
+    session_id: Field,
+    cwd: Field,
+    project_path: Field,
+    body: Field,
+    updated_at: Field,
+    /// Session file the document was built from
+    file_path: Field,
+    /// File modification time (ms since the epoch) when it was indexed
+    modified: Field,
+}
+
+impl Fields {
+    fn schema() -> (Schema, Self) {
+        let mut builder = Schema::builder();
+        let fields = Self {
+            session_id: builder.add_text_field("session_id", STRING | STORED),
+            cwd: builder.add_text_field("cwd", TEXT),
+            project_path: builder.add_text_field("project_path", TEXT),
+            body: builder.add_text_field("body", TEXT),
+            updated_at: builder.add_date_field("updated_at", FAST | STORED),
+            file_path: builder.add_text_field("file_path", STRING | STORED),
+            modified: builder.add_i64_field("modified", STORED),
+        };
+        (builder.build(), fields)
+    }
+}
+
+/// A `SessionStore` whose `search_sessions` is answered from a tantivy index.
+/// Every other `SessionStore` method is available through `Deref`.
+pub struct IndexedSessionStore {
+    store: SessionStore,
+    index: Index,
+    reader: IndexReader,
+    writer: Mutex<IndexWriter>,
+    fields: Fields,
+}
+
+impl IndexedSessionStore {
+    /// Open (or create) the index at `index_path` over the sessions in `store`.
+    /// Fails if another process is writing to the same index.
+    pub fn open(store: SessionStore, index_path: &Path) -> Result<Self> {
+        std::fs::create_dir_all(index_path)
+            .with_context(|| format!("Failed to create index directory {}", index_path.display()))?;
+        let (schema, fields) = Fields::schema();
+        let directory = MmapDirectory::open(index_path)
+            .with_context(|| format!("Failed to open index at {}", index_path.display()))?;
+        let index = Index::open_or_create(directory, schema)
+            .with_context(|| format!("Failed to open index at {}", index_path.display()))?;
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+        let writer = index
+            .writer(WRITER_MEMORY_BYTES)
+            .with_context(|| format!("Index at {} is in use by another process", index_path.display()))?;
+
+        Ok(Self {
+            store,
+            index,
+            reader,
+            writer: Mutex::new(writer),
+            fields,
+        })
+    }
+
+    /// Drop everything and index every session again, returning how many were indexed
+    pub fn rebuild(&self) -> Result<usize> {
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        writer.delete_all_documents()?;
+
+        let mut indexed = 0;
+        for path in self.store.session_files() {
+            if self.add_session_file(&writer, &path)? {
+                indexed += 1;
+            }
+        }
+
+        writer.commit()?;
+        self.reader.reload()?;
+        Ok(indexed)
+    }
+
+    /// Re-index session files added, changed or removed since they were last
+    /// indexed, returning how many documents changed
+    pub fn refresh(&self) -> Result<usize> {
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        let mut indexed = self.indexed_files()?;
+        let mut changed = 0;
+
+        for path in self.store.session_files() {
+            let key = path.to_string_lossy().to_string();
+            if indexed.remove(&key) == Some(modified_millis(&path)) {
+                continue;
+            }
+            writer.delete_term(Term::from_field_text(self.fields.file_path, &key));
+            self.add_session_file(&writer, &path)?;
+            changed += 1;
+        }
+
+        // Whatever is left was indexed but no longer exists on disk
+        for key in indexed.keys() {
+            writer.delete_term(Term::from_field_text(self.fields.file_path, key));
+            changed += 1;
+        }
+
+        if changed > 0 {
+            writer.commit()?;
+            self.reader.reload()?;
+        }
+        Ok(changed)
+    }
+
+    /// How many sessions are in the index
+    pub fn num_sessions(&self) -> u64 {
+        self.reader.searcher().num_docs()
+    }
+
+    /// Search session content through the index. Regex and boolean searches,
+    /// searches of tool call inputs, of one role's messages or of a date range
+    /// aren't supported by the index and fall back to scanning every session.
+    pub fn search_sessions(
+        &self,
+        query: &str,
+        limit: usize,
+        cursor: Option<&str>,
+        mode: SearchMode,
+        options: SearchOptions,
+    ) -> Result<SearchResult<SessionSummary>> {
+        let scan = options.include_tool_inputs
+            || options.role_filter.is_some()
+            || options.since.is_some()
+            || options.until.is_some();
+        if mode != SearchMode::Fuzzy || scan {
+            return self.store.search_sessions(query, limit, cursor, mode, options);
+        }
+        let SearchOptions { highlight, min_score, .. } = options;
+
+        self.refresh()?;
+
+        let mut parser = QueryParser::for_index(
+            &self.index,
+            vec![self.fields.body, self.fields.cwd, self.fields.project_path],
+        );
+        parser.set_conjunction_by_default();
+        // Tolerate one typo per term, like the fuzzy scan tolerates gaps
+        parser.set_field_fuzzy(self.fields.body, false, 1, true);
+        let (parsed, _) = parser.parse_query_lenient(query);
+
+        // Count the matches, then rank all of them so the page after the
+        // cursor can be cut out
+        let searcher = self.reader.searcher();
+        let total = searcher.search(&parsed, &Count)?;
+        let top_docs = searcher.search(&parsed, &TopDocs::with_limit(total.max(1)))?;
+
+        let mut matches = Vec::new();
+        for (score, address) in top_docs {
+            let doc: TantivyDocument = searcher.doc(address)?;
+            let session_id = doc.get_first(self.fields.session_id).and_then(|v| v.as_str());
+            let path = doc.get_first(self.fields.file_path).and_then(|v| v.as_str());
+            if let (Some(session_id), Some(path)) = (session_id, path) {
+                // Cursors hold integer scores; keep three decimals of tantivy's
+                let score = (f64::from(score) * 1000.0).round() as i64;
+                if min_score.is_some_and(|min| score < min) {
+                    continue;
+                }
+                matches.push((SearchCursor::new(score, session_id, None), (score, path.to_string())));
+            }
+        }
+        let (page, total_matched) = paginate_matches(matches, cursor, limit.min(50))?;
+
+        let mut items = Vec::new();
+        for (score, path) in page.items {
+            // The file may have gone away since the index was refreshed
+            let Ok(Some(session)) = self.store.try_parse_jsonl_session(Path::new(&path)) else {
+                continue;
+            };
+
+            let mut summary = session_to_summary(&session, DEFAULT_PREVIEW_CHARS);
+            summary.score = Some(score);
+            if highlight {
+                summary.match_context = fuzzy_match_context(&extract_full_text_for_search(&session, false, None), query);
+            }
+            items.push(summary);
+        }
+
+        Ok(SearchResult {
+            items,
+            next_cursor: page.next_cursor,
+            query: query.to_string(),
+            search_mode: mode,
+            total_matched,
+        })
+    }
+
+    /// Indexed session files and the modification time they were indexed at
+    fn indexed_files(&self) -> Result<HashMap<String, i64>> {
+        let searcher = self.reader.searcher();
+        let mut files = HashMap::new();
+        for address in searcher.search(&AllQuery, &DocSetCollector)? {
+            let doc: TantivyDocument = searcher.doc(address)?;
+            let path = doc.get_first(self.fields.file_path).and_then(|v| v.as_str());
+            let modified = doc.get_first(self.fields.modified).and_then(|v| v.as_i64());
+            if let (Some(path), Some(modified)) = (path, modified) {
+                files.insert(path.to_string(), modified);
+            }
+        }
+        Ok(files)
+    }
+
+    /// Add the session in `path` to the index, returning whether it held a session
+    fn add_session_file(&self, writer: &IndexWriter, path: &Path) -> Result<bool> {
+        let Ok(Some(session)) = self.store.try_parse_jsonl_session(path) else {
+            return Ok(false);
+        };
+        writer.add_document(self.document(&session, path))?;
+        Ok(true)
+    }
+
+    fn document(&self, session: &Session, path: &Path) -> TantivyDocument {
+        let f = &self.fields;
+        let mut document = doc!(
+            f.session_id => session.id.as_str(),
+            f.body => extract_full_text_for_search(session, false, None),
+            f.file_path => path.to_string_lossy().as_ref(),
+            f.modified => modified_millis(path),
+        );
+        if let Some(cwd) = &session.cwd {
+            document.add_text(f.cwd, cwd);
+        }
+        if let Some(project_path) = &session.project_path {
+            document.add_text(f.project_path, project_path);
+        }
+        if let Some(updated_at) = session.updated_at {
+            document.add_date(
+                f.updated_at,
+                tantivy::DateTime::from_timestamp_millis(updated_at.timestamp_millis()),
+            );
+        }
+        document
+    }
+}
+
+impl Deref for IndexedSessionStore {
+    type Target = SessionStore;
+
+    fn deref(&self) -> &SessionStore {
+        &self.store
+    }
+}
+
+/// File modification time in ms since the epoch, or 0 if unavailable
+fn modified_millis(path: &Path) -> i64 {
+    std::fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_millis() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn write_session(base: &Path, id: &str, text: &str) -> PathBuf {
+        let dir = base.join("projects").join("-proj");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{}.jsonl", id));
+        let line = serde_json::json!({
+            "type": "user",
+            "sessionId": id,
+            "cwd": "/proj",
+            "timestamp": "2024-01-01T00:00:00Z",
+            "message": {"role": "user", "content": text}
+        });
+        std::fs::write(&path, line.to_string()).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_indexed_search_tracks_file_changes() {
+        let claude_dir = tempfile::tempdir().unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        write_session(claude_dir.path(), "s1", "refactor the websocket reconnect logic");
+        write_session(claude_dir.path(), "s2", "add dark mode to the settings page");
+
+        let store = SessionStore::with_path(claude_dir.path().to_path_buf());
+        let indexed = IndexedSessionStore::open(store, index_dir.path()).unwrap();
+
+        let results = indexed.search_sessions("websocket", 10, None, SearchMode::Fuzzy, SearchOptions { highlight: true, ..Default::default() }).unwrap().items;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, "s1");
+        assert!(results[0].match_context.as_ref().unwrap().contains("**websocket**"));
+
+        // One typo still matches
+        let results = indexed.search_sessions("websockt", 10, None, SearchMode::Fuzzy, SearchOptions::default()).unwrap().items;
+        assert_eq!(results.len(), 1);
+
+        // New files are picked up and removed files dropped on the next search
+        write_session(claude_dir.path(), "s3", "websocket heartbeat timeout");
+        std::fs::remove_file(claude_dir.path().join("projects/-proj/s1.jsonl")).unwrap();
+        let results = indexed.search_sessions("websocket", 10, None, SearchMode::Fuzzy, SearchOptions::default()).unwrap().items;
+        let ids: Vec<_> = results.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, vec!["s3"]);
+
+        assert_eq!(indexed.rebuild().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_indexed_search_pagination() {
+        let claude_dir = tempfile::tempdir().unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        for id in ["s1", "s2", "s3"] {
+            write_session(claude_dir.path(), id, "websocket reconnect");
+        }
+        write_session(claude_dir.path(), "s4", "dark mode");
+
+        let store = SessionStore::with_path(claude_dir.path().to_path_buf());
+        let indexed = IndexedSessionStore::open(store, index_dir.path()).unwrap();
+
+        let first = indexed.search_sessions("websocket", 2, None, SearchMode::Fuzzy, SearchOptions::default()).unwrap();
+        assert_eq!(first.total_matched, 3);
+        assert_eq!(first.items.len(), 2);
+        let cursor = first.next_cursor.expect("a second page");
+
+        let second = indexed.search_sessions("websocket", 2, Some(&cursor), SearchMode::Fuzzy, SearchOptions::default()).unwrap();
+        assert_eq!(second.total_matched, 3);
+        assert!(second.next_cursor.is_none());
+        let mut ids: Vec<_> = first.items.iter().chain(&second.items).map(|s| s.id.as_str()).collect();
+        ids.sort();
+        assert_eq!(ids, vec!["s1", "s2", "s3"]);
+    }
+}
@@ -0,0 +1,14 @@
+//! Session parsing, metadata and search for claude-code-mcp
+//!
+//! The server binary in `main.rs` wraps these in the MCP protocol; they live in
+//! a library so the benchmarks can drive them directly.
+
+pub mod embeddings;
+pub mod export;
+pub mod index;
+pub mod metadata;
+pub mod protocol;
+pub mod query;
+pub mod security;
+pub mod sessions;
+pub mod suggestions;
@@ -0,0 +1,99 @@
+//! Audit log of the requests the server answers
+//!
+//! With `log_file` configured, every JSON-RPC request is appended to the file
+//! as one JSON line: when it arrived, its ID and method, the tool it called,
+//! how long it took and whether it failed. Parameters and results are never
+//! written, since they carry session contents.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+
+/// Whether a request succeeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordStatus {
+    Success,
+    Error,
+}
+
+/// One line of the log
+#[derive(Debug, Serialize)]
+pub struct RequestRecord {
+    /// When the request arrived
+    pub ts: DateTime<Utc>,
+    pub request_id: Value,
+    pub method: String,
+    pub duration_ms: u64,
+    pub status: RecordStatus,
+    /// Name of the tool, for `tools/call`
+    pub tool: Option<String>,
+    /// JSON-RPC error code, when the request failed with one
+    pub error_code: Option<i32>,
+}
+
+/// Appends `RequestRecord`s to a log file
+pub struct RequestLogger {
+    path: PathBuf,
+}
+
+impl RequestLogger {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Append `record` in the background. Failing to write is ignored, so the
+    /// log can never fail a request.
+    pub fn log(&self, record: RequestRecord) {
+        let path = self.path.clone();
+        tokio::spawn(async move {
+            let _ = append_record(&path, &record).await;
+        });
+    }
+}
+
+/// Append `record` to the file at `path` as one line, creating the file if needed
+async fn append_record(path: &Path, record: &RequestRecord) -> std::io::Result<()> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(path).await?;
+    // One write per line, so concurrent appends don't interleave
+    file.write_all(&line).await?;
+    // tokio finishes writes in the background; wait for this one to land
+    file.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_append_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        for (id, status) in [(1, RecordStatus::Success), (2, RecordStatus::Error)] {
+            let record = RequestRecord {
+                ts: Utc::now(),
+                request_id: serde_json::json!(id),
+                method: "tools/call".to_string(),
+                duration_ms: 3,
+                status,
+                tool: Some("list_sessions".to_string()),
+                error_code: (status == RecordStatus::Error).then_some(-32602),
+            };
+            append_record(&path, &record).await.unwrap();
+        }
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let records: Vec<Value> = contents.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["status"], "success");
+        assert_eq!(records[0]["error_code"], Value::Null);
+        assert_eq!(records[1]["request_id"], 2);
+        assert_eq!(records[1]["status"], "error");
+        assert_eq!(records[1]["error_code"], -32602);
+        assert_eq!(records[1]["tool"], "list_sessions");
+    }
+}
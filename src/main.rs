@@ -4,29 +4,656 @@
 //! allowing Claude.ai (or any MCP client) to search and reference CLI work.
 
 use anyhow::Result;
+use clap::Parser;
 use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
 #[allow(unused_imports)]
 use serde_json::Value as JsonValue;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 
-mod protocol;
-mod sessions;
+mod cache;
+mod concurrency;
+mod config;
+mod http;
+mod logging;
+mod manifest;
+mod metrics;
+mod prompts;
+mod ratelimit;
+mod transport;
+mod watcher;
 
+use claude_code_mcp::{embeddings, export, index, metadata, protocol, query, security, sessions, suggestions};
+use cache::{CachedSessionStore, RebuildResult, Snapshot, WithCacheMeta};
+use concurrency::ConcurrencyLimiter;
+use config::{warning, Config, ConfigArgs, LogLevel};
+use embeddings::{EmbeddingCache, EmbeddingClient};
+use index::IndexedSessionStore;
+use logging::{RecordStatus, RequestLogger, RequestRecord};
+use manifest::ToolManifest;
+use metadata::{MetadataStore, NotesLog};
+use metrics::{MetricsCollector, Status};
 use protocol::*;
-use sessions::SessionStore;
+use ratelimit::RateLimiter;
+use sessions::{
+    ContentFormat, FileMatchMode, ListOptions, SearchMode, SearchOptions, SessionFormat, SessionStore, SortOptions, SortOrder,
+};
+use transport::Transport;
 
-/// Define available tools
-fn get_tools() -> Vec<Tool> {
+/// Length limit of `get_session_context` in prompt format
+const CONTEXT_PROMPT_CHARS: usize = 1000;
+
+/// Shortest interval `watch_session` polls at
+const MIN_WATCH_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// `get_session_context` with `streaming` only streams sessions longer than this
+const STREAMING_CONTEXT_MESSAGES: usize = 200;
+
+/// Most bytes of session file content `get_session` returns with `raw`
+const RAW_LINES_MAX_BYTES: usize = 100 * 1024;
+
+/// Configuration the server was started with
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Validators for each tool's input schema, compiled on first use
+static TOOL_VALIDATORS: OnceLock<HashMap<String, jsonschema::Validator>> = OnceLock::new();
+
+/// Search index opened from `--index-path`, if one was given
+static SEARCH_INDEX: OnceLock<IndexedSessionStore> = OnceLock::new();
+
+/// Parsed sessions for `list_sessions`, kept fresh by the session watcher
+static SESSION_CACHE: OnceLock<CachedSessionStore> = OnceLock::new();
+
+/// Clipboard handle kept for the life of the server: on X11 the copied text is
+/// served by its owner, so it would vanish as soon as the handle was dropped
+static CLIPBOARD: Mutex<Option<arboard::Clipboard>> = Mutex::new(None);
+
+/// State shared by every request handler for the life of the server
+#[derive(Clone)]
+pub struct ServerState {
+    /// Resource URIs clients asked to hear about through `resources/subscribe`
+    subscriptions: Arc<Mutex<HashSet<String>>>,
+    /// Limits tool calls per second; `None` with `--rate-limit 0`
+    rate_limiter: Option<Arc<RateLimiter>>,
+    /// Limits tool calls running at once; `None` with `--max-concurrent-requests 0`
+    concurrency: Option<Arc<ConcurrencyLimiter>>,
+    /// Tool calls still running after this get an error response
+    tool_timeout: Duration,
+    /// Request and tool call counts, served at `GET /metrics` over HTTP
+    metrics: Arc<MetricsCollector>,
+    /// Tokens of running tool calls, keyed by their serialized request ID, so
+    /// `notifications/cancelled` can abort them
+    in_flight: Arc<Mutex<HashMap<String, CancellationToken>>>,
+    /// Whether the tools differ from those of the last run, which clients
+    /// are told once they are initialized
+    tools_changed: bool,
+    /// Where `notifications/tools/list_changed` is sent for `tools_changed`
+    notifications: Option<tokio::sync::mpsc::Sender<String>>,
+    /// Whether tool calls may send the rest of a result in progress
+    /// notifications. Only over stdio, where the client reading notifications
+    /// is the one that made the call; over HTTP they would go to every SSE
+    /// subscriber.
+    tool_progress: bool,
+    /// Audit log of requests; `None` unless `log_file` is set
+    logger: Option<Arc<RequestLogger>>,
+}
+
+impl Default for ServerState {
+    fn default() -> Self {
+        Self {
+            subscriptions: Arc::default(),
+            rate_limiter: None,
+            concurrency: None,
+            tool_timeout: config::DEFAULT_TOOL_TIMEOUT,
+            metrics: Arc::new(MetricsCollector::new(
+                get_tools(&CONFIG.get().cloned().unwrap_or_default()).iter().map(|t| t.name.as_str()),
+            )),
+            in_flight: Arc::default(),
+            tools_changed: false,
+            notifications: None,
+            tool_progress: false,
+            logger: None,
+        }
+    }
+}
+
+impl ServerState {
+    /// Current metrics in the Prometheus text format
+    fn render_metrics(&self) -> String {
+        let cached = SEARCH_INDEX.get().map_or(0, |index| index.num_sessions());
+        self.metrics.set_sessions_cached(cached);
+        self.metrics.render()
+    }
+
+    fn subscriptions(&self) -> std::sync::MutexGuard<'_, HashSet<String>> {
+        self.subscriptions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn in_flight(&self) -> std::sync::MutexGuard<'_, HashMap<String, CancellationToken>> {
+        self.in_flight.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Make the tool call with request ID `id` cancellable before it is
+    /// handled, for transports that read a cancellation before the call's
+    /// task has started
+    fn register_call(&self, id: &Value) {
+        self.in_flight().insert(id.to_string(), CancellationToken::new());
+    }
+
+    /// Whether a client subscribed to updates of `uri`
+    pub fn is_subscribed(&self, uri: &str) -> bool {
+        self.subscriptions().contains(uri)
+    }
+}
+
+/// URI of the resource for a session file
+pub fn session_uri(path: &Path) -> Option<String> {
+    sessions::session_file_stem(path).map(|id| format!("{}{}", SESSION_URI_SCHEME, id))
+}
+
+/// Read a session file as the contents of the resource at `uri`
+pub fn read_session_resource(uri: &str, path: &Path) -> Result<ResourceContent> {
+    let mut text = String::new();
+    sessions::open_session_file(path)?.read_to_string(&mut text)?;
+    Ok(ResourceContent {
+        uri: uri.to_string(),
+        mime_type: "application/jsonl".to_string(),
+        text,
+    })
+}
+
+/// Read one of a session's derived resources, `/context` or `/commands`, as
+/// JSON, or `None` if there is no such session
+fn read_session_view(store: &SessionStore, uri: &str, request: &SessionUriRequest) -> Result<Option<ResourceContent>> {
+    let id = request.session_id();
+    let text = match request {
+        SessionUriRequest::Session(_) => unreachable!("session files are read by read_session_resource"),
+        SessionUriRequest::Context(_) => store
+            .get_session_context(id, sessions::ContextDepth::All, false, true, sessions::ContextLimits::default())?
+            .map(|context| serde_json::to_string_pretty(&context)),
+        SessionUriRequest::Commands(_) => store
+            .get_session_commands(id, false)?
+            .map(|commands| serde_json::to_string_pretty(&commands)),
+    };
+    Ok(text.transpose()?.map(|text| ResourceContent {
+        uri: uri.to_string(),
+        mime_type: "application/json".to_string(),
+        text,
+    }))
+}
+
+/// The rest of a result a tool call answered early with
+type DeferredResult = Box<dyn FnOnce() -> Value + Send>;
+
+/// Where a tool call sends the rest of a result it answered early with
+struct ToolProgress {
+    request_id: Value,
+    notifications: tokio::sync::mpsc::Sender<String>,
+    /// When the call times out; nothing is sent after this
+    deadline: Instant,
+    deferred: Option<DeferredResult>,
+}
+
+impl ToolProgress {
+    /// Work out the rest of the result with `work` once the answer is out,
+    /// and send it in a `notifications/tools/progress` notification
+    fn defer(&mut self, work: impl FnOnce() -> Value + Send + 'static) {
+        self.deferred = Some(Box::new(work));
+    }
+
+    /// Run the work left by `defer` and send its result, unless the call is
+    /// cancelled or times out first. Runs on the call's thread, so it still
+    /// holds the call's permit. Blocks, so only call this off the async runtime.
+    fn finish(self, cancel: &CancellationToken) {
+        let Some(work) = self.deferred else {
+            return;
+        };
+        let partial = work();
+        let params = ProgressNotification {
+            request_id: self.request_id,
+            partial,
+        };
+        let notification = JsonRpcNotification::new(TOOL_PROGRESS_METHOD, json!(params));
+        let Ok(message) = serde_json::to_string(&notification) else {
+            return;
+        };
+        // The client may be gone by now, or not reading
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        tokio::runtime::Handle::current().block_on(async {
+            tokio::select! {
+                _ = tokio::time::timeout(remaining, self.notifications.send(message)) => {}
+                _ = cancel.cancelled() => {}
+            }
+        });
+    }
+}
+
+/// Answer `get_session_context` for a long session without its files and key
+/// terms, listed in `pending`, and send those in a progress notification once
+/// they are extracted
+fn stream_session_context(
+    session: sessions::Session,
+    depth: sessions::ContextDepth,
+    include_tool_summary: bool,
+    include_token_estimate: bool,
+    limits: sessions::ContextLimits,
+    progress: &mut ToolProgress,
+) -> ToolResult {
+    let context =
+        sessions::session_context_without_terms(&session, depth, include_tool_summary, include_token_estimate, limits);
+    let mut result = json!(context);
+    if let Some(fields) = result.as_object_mut() {
+        fields.remove("files_mentioned");
+        fields.remove("key_terms");
+        fields.insert("pending".to_string(), json!(["files_mentioned", "key_terms"]));
+    }
+
+    progress.defer(move || json!(sessions::context_terms(&session, depth, limits)));
+    ToolResult::text(serde_json::to_string_pretty(&result).unwrap_or_else(|_| "{}".to_string()))
+}
+
+/// Poll the file of `session` every `poll_interval` until messages are added
+/// to it, or `timeout` passes. The file's size tells whether to parse it
+/// again; lines that aren't messages keep the wait going. Fails if `cancel`
+/// fires first. Blocks, so only call this off the async runtime.
+fn watch_session(
+    store: &SessionStore,
+    session: &sessions::Session,
+    poll_interval: Duration,
+    timeout: Duration,
+    cancel: &CancellationToken,
+) -> Result<sessions::WatchResult> {
+    let file_size = |path: &Path| std::fs::metadata(path).map_or(0, |m| m.len());
+    let mut message_count = session.messages.len();
+
+    let poll = async {
+        let mut interval = tokio::time::interval(poll_interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut size = file_size(&session.file_path);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = cancel.cancelled() => anyhow::bail!("Request cancelled by client"),
+            }
+            let current_size = file_size(&session.file_path);
+            if current_size == size {
+                continue;
+            }
+            size = current_size;
+
+            let Some(latest) = store.try_parse_jsonl_session(&session.file_path)? else {
+                continue;
+            };
+            if latest.messages.len() > message_count {
+                return Ok(sessions::WatchResult {
+                    new_messages: latest.messages[message_count..].to_vec(),
+                    timed_out: false,
+                    current_message_count: latest.messages.len(),
+                });
+            }
+            // Rewritten shorter, e.g. by a compaction; count from here
+            message_count = latest.messages.len();
+        }
+    };
+
+    match tokio::runtime::Handle::current().block_on(tokio::time::timeout(timeout, poll)) {
+        Ok(result) => result,
+        Err(_) => Ok(sessions::WatchResult {
+            new_messages: Vec::new(),
+            timed_out: true,
+            current_message_count: message_count,
+        }),
+    }
+}
+
+/// Open the session store over the configured `claude_dir`, else `~/.claude`.
+/// Once the session cache is set up, stores share its project index.
+fn session_store() -> Result<SessionStore> {
+    if let Some(cache) = SESSION_CACHE.get() {
+        return Ok(cache.store());
+    }
+    SessionStore::new(CONFIG.get().and_then(|config| config.claude_dir.clone()))
+}
+
+/// Name of the local time zone, from `TZ` or else `/etc/localtime`, or `None`
+/// if neither says
+fn local_timezone() -> Option<String> {
+    let tz = std::env::var("TZ").ok().filter(|tz| !tz.is_empty());
+    let zone_file = Path::new("/etc/localtime");
+    if tz.is_none() && cfg!(unix) && !zone_file.exists() {
+        return None;
+    }
+    let name = tz
+        .or_else(|| {
+            // Usually a link into the zoneinfo database, named after the zone
+            let target = std::fs::read_link(zone_file).ok()?;
+            let name = target.to_string_lossy().split_once("zoneinfo/")?.1.to_string();
+            Some(name)
+        })
+        .unwrap_or_else(|| format!("UTC{}", chrono::Local::now().format("%:z")));
+    Some(name)
+}
+
+/// Parse a date-range argument given as an RFC 3339 timestamp or a
+/// `YYYY-MM-DD` date, which starts at midnight UTC or, with `end_of_day`,
+/// covers the whole day
+fn parse_date_arg(value: &str, end_of_day: bool) -> Result<chrono::DateTime<chrono::Utc>> {
+    if let Ok(timestamp) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Ok(timestamp.to_utc());
+    }
+    let date = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| anyhow::anyhow!("expected an RFC 3339 timestamp or YYYY-MM-DD date, got {}", value))?;
+    let time = if end_of_day {
+        date.and_hms_milli_opt(23, 59, 59, 999)
+    } else {
+        date.and_hms_opt(0, 0, 0)
+    };
+    Ok(time.expect("valid time of day").and_utc())
+}
+
+/// Inclusive start and end of a date range, either of which may be open
+type DateRange = (Option<chrono::DateTime<chrono::Utc>>, Option<chrono::DateTime<chrono::Utc>>);
+
+/// Read the optional `since` and `until` date-range arguments
+fn date_range_args(arguments: &Value) -> Result<DateRange> {
+    let bound = |key: &str, end_of_day: bool| {
+        arguments
+            .get(key)
+            .and_then(|v| v.as_str())
+            .map(|v| parse_date_arg(v, end_of_day).map_err(|e| anyhow::anyhow!("Invalid {}: {}", key, e)))
+            .transpose()
+    };
+    Ok((bound("since", false)?, bound("until", true)?))
+}
+
+/// Read `list_sessions`' `sort_by` and `sort_order` arguments
+fn list_sort_options(arguments: &Value) -> Result<SortOptions> {
+    let parse = |key: &str| arguments.get(key).and_then(|v| v.as_str());
+    Ok(SortOptions {
+        field: parse("sort_by").map(str::parse).transpose()?.unwrap_or_default(),
+        order: parse("sort_order").map(str::parse).transpose()?.unwrap_or_default(),
+    })
+}
+
+/// The serialized session summaries in a `list_sessions` result, whether a
+/// page or grouped by project
+fn listed_summaries(listed: &mut Value) -> Vec<&mut Value> {
+    fn sessions(v: &mut Value) -> Vec<&mut Value> {
+        v.as_array_mut().map(|a| a.iter_mut().collect()).unwrap_or_default()
+    }
+    if listed.get("groups").is_some() {
+        sessions(&mut listed["groups"]).into_iter().flat_map(|g| sessions(&mut g["sessions"])).collect()
+    } else {
+        sessions(&mut listed["items"])
+    }
+}
+
+/// Classify a failure by the first recognized cause in its chain
+fn error_kind(error: &anyhow::Error) -> McpErrorKind {
+    for cause in error.chain() {
+        if cause.is::<serde_json::Error>() {
+            return McpErrorKind::ParseError;
+        }
+        if cause.is::<regex::Error>()
+            || cause.is::<base64::DecodeError>()
+            || cause.is::<query::QueryError>()
+            || cause.is::<sessions::SessionLookupError>()
+        {
+            return McpErrorKind::InvalidArgument;
+        }
+        if cause.is::<std::io::Error>() {
+            return McpErrorKind::IoError;
+        }
+    }
+    // Everything else comes from reading the session store or its databases
+    McpErrorKind::IoError
+}
+
+/// The `patterns` argument of the todo tools, or the default markers
+fn todo_patterns(arguments: &Value) -> Result<Vec<String>, ToolResult> {
+    let Some(patterns) = arguments.get("patterns").and_then(|v| v.as_array()) else {
+        return Ok(sessions::DEFAULT_TODO_PATTERNS.iter().map(|p| p.to_string()).collect());
+    };
+    let patterns: Vec<String> = patterns
+        .iter()
+        .filter_map(|p| p.as_str())
+        .filter(|p| !p.trim().is_empty())
+        .map(|p| p.trim().to_string())
+        .collect();
+    if patterns.is_empty() {
+        return Err(ToolResult::error(McpErrorKind::InvalidArgument, "patterns must contain at least one marker"));
+    }
+    Ok(patterns)
+}
+
+/// Run a semantic `search_sessions`: embed the query through the configured
+/// endpoint and rank sessions against the embeddings cache
+fn semantic_search(
+    store: &SessionStore,
+    query: &str,
+    limit: usize,
+    cursor: Option<&str>,
+    options: SearchOptions,
+) -> Result<sessions::SearchResult<sessions::SessionSummary>, ToolResult> {
+    let Some(endpoint) = CONFIG.get().and_then(|config| config.embedding_endpoint.as_deref()) else {
+        return Err(ToolResult::error(
+            McpErrorKind::InvalidArgument,
+            "Semantic search needs an embedding endpoint; start the server with --embedding-endpoint <url> \
+             or set embedding_endpoint in config.toml",
+        ));
+    };
+    // Nothing here could take these into account, so say so rather than
+    // quietly answer as if they weren't given
+    let unsupported = [
+        ("highlight", options.highlight),
+        ("search_tool_inputs", options.include_tool_inputs),
+        ("role_filter", options.role_filter.is_some()),
+    ];
+    if let Some((name, _)) = unsupported.iter().find(|(_, given)| *given) {
+        return Err(ToolResult::error(
+            McpErrorKind::InvalidArgument,
+            format!("{} is not supported in semantic search_mode", name),
+        ));
+    }
+    let Some(path) = embeddings::cache_path() else {
+        return Err(ToolResult::error(McpErrorKind::NotFound, "Home directory not found"));
+    };
+    let cache = match EmbeddingCache::load(&path) {
+        Ok(Some(cache)) => cache,
+        Ok(None) => {
+            return Err(ToolResult::from(
+                McpError::new(
+                    McpErrorKind::NotFound,
+                    format!(
+                        "No embeddings cache at {}; build it with `claude-code-mcp build-embeddings --embedding-endpoint {}`",
+                        path.display(),
+                        endpoint
+                    ),
+                )
+                .with_path(path.display().to_string()),
+            ))
+        }
+        Err(e) => {
+            return Err(ToolResult::from(
+                McpError::new(error_kind(&e), format!("{:#}", e)).with_path(path.display().to_string()),
+            ))
+        }
+    };
+    let query_embedding = match EmbeddingClient::new(endpoint).and_then(|client| client.embed(query)) {
+        Ok(embedding) => embedding,
+        Err(e) => return Err(ToolResult::error(McpErrorKind::IoError, format!("Failed to embed query: {:#}", e))),
+    };
+    embeddings::semantic_search(store, &cache, query, &query_embedding, limit, cursor, options)
+        .map_err(|e| ToolResult::error(error_kind(&e), format!("Failed to search sessions: {}", e)))
+}
+
+/// `build-embeddings`: embed every session through the configured endpoint
+/// and save the vectors for semantic search
+fn build_embeddings() -> Result<()> {
+    let endpoint = CONFIG
+        .get()
+        .and_then(|config| config.embedding_endpoint.as_deref())
+        .ok_or_else(|| anyhow::anyhow!("No embedding endpoint; pass --embedding-endpoint or set embedding_endpoint in config.toml"))?;
+    let path = embeddings::cache_path().ok_or_else(|| anyhow::anyhow!("Home directory not found"))?;
+    let client = EmbeddingClient::new(endpoint)?;
+    let cache = embeddings::build_cache(&session_store()?, &client, |done, total| {
+        if config::log_enabled(LogLevel::Info) {
+            eprintln!("info: embedded {}/{} sessions", done, total);
+        }
+    })?;
+    cache.save(&path)?;
+    eprintln!("Saved embeddings of {} sessions to {}", cache.len(), path.display());
+    Ok(())
+}
+
+/// Check a `.jsonl` path a client asked to read or write: it must be absolute
+/// and stay under the user's home directory (see `security::is_safe_path`)
+fn client_jsonl_path<'a>(param: &str, file_path: &'a str) -> Result<&'a Path, ToolResult> {
+    let path = Path::new(file_path);
+    if !path.is_absolute() {
+        return Err(ToolResult::error(McpErrorKind::InvalidArgument, format!("{} must be absolute", param)));
+    }
+    if path.extension().is_none_or(|ext| ext != "jsonl") {
+        return Err(ToolResult::error(McpErrorKind::InvalidArgument, format!("{} must end in .jsonl", param)));
+    }
+    let Some(home) = dirs::home_dir() else {
+        return Err(ToolResult::error(McpErrorKind::IoError, "Could not determine home directory"));
+    };
+    if !security::is_safe_path(path, &[&home]) {
+        return Err(ToolResult::error(
+            McpErrorKind::InvalidArgument,
+            format!("{} must be under {} without .. components", param, home.display()),
+        ));
+    }
+    Ok(path)
+}
+
+/// Put `text` on the system clipboard. Fails where there is none, such as a
+/// headless machine without a display, or when `clipboard` is configured off.
+fn copy_to_clipboard(text: &str) -> Result<()> {
+    if CONFIG.get().is_some_and(|config| !config.clipboard) {
+        anyhow::bail!("the clipboard is turned off by the clipboard setting");
+    }
+    let mut clipboard = CLIPBOARD.lock().unwrap_or_else(|e| e.into_inner());
+    if clipboard.is_none() {
+        *clipboard = Some(arboard::Clipboard::new()?);
+    }
+    if let Some(clipboard) = clipboard.as_mut() {
+        clipboard.set_text(text)?;
+    }
+    Ok(())
+}
+
+/// The tools available under `config`, leaving out its `disabled_tools`
+fn get_tools(config: &Config) -> Vec<Tool> {
+    let mut tools = all_tools();
+    tools.retain(|tool| !config.disabled_tools.contains(&tool.name));
+    tools
+}
+
+/// The tools `tools/list` and `list_tools` report: the enabled ones, or none
+/// with `show_tools = false`
+fn listed_tools() -> Vec<Tool> {
+    let config = CONFIG.get().cloned().unwrap_or_default();
+    if config.show_tools {
+        get_tools(&config)
+    } else {
+        Vec::new()
+    }
+}
+
+/// Every tool the server implements, disabled or not
+fn all_tools() -> Vec<Tool> {
     vec![
         Tool {
             name: "list_sessions".to_string(),
-            description: "List recent Claude Code CLI sessions. Returns session IDs, timestamps, and previews.".to_string(),
+            description: "List recent Claude Code CLI sessions. Returns session IDs, timestamps, and previews, plus a next_cursor when more sessions are available. With group_by_project, returns {groups, total_groups} instead: one group per project ({project_path, session_count, sessions}), most recently active first.".to_string(),
             input_schema: json!({
                 "type": "object",
                 "properties": {
                     "limit": {
                         "type": "integer",
-                        "description": "Maximum number of sessions to return (default: 20, max: 100)",
+                        "description": "Maximum number of sessions to return, or per project with group_by_project (default: 20, max: 100)",
+                        "default": 20,
+                        "minimum": 1,
+                        "maximum": 100
+                    },
+                    "cursor": {
+                        "type": "string",
+                        "description": "Opaque cursor from a previous response's next_cursor to fetch the next page (not with group_by_project)"
+                    },
+                    "group_by_project": {
+                        "type": "boolean",
+                        "description": "Group sessions by project, each group and the sessions in it most recently updated first (default: false)",
+                        "default": false
+                    },
+                    "project_limit": {
+                        "type": "integer",
+                        "description": "Maximum number of projects to return with group_by_project (default: 10, max: 100)",
+                        "default": 10,
+                        "minimum": 1,
+                        "maximum": 100
+                    },
+                    "min_messages": {
+                        "type": "integer",
+                        "description": "Leave out sessions with fewer messages than this. Not set by default, so trivial sessions such as a single message from an accidental open are listed; 2 hides them.",
+                        "minimum": 0
+                    },
+                    "include_agents": {
+                        "type": "boolean",
+                        "description": "Also list agent sub-sessions (default: false)",
+                        "default": false
+                    },
+                    "sort_by": {
+                        "type": "string",
+                        "enum": ["updated_at", "created_at", "message_count", "project_path"],
+                        "description": "Field to sort sessions by (default: updated_at). Sessions without a project path sort last by project_path. Ignored with group_by_project.",
+                        "default": "updated_at"
+                    },
+                    "sort_order": {
+                        "type": "string",
+                        "enum": ["desc", "asc"],
+                        "description": "Sort direction (default: desc). Ignored with group_by_project.",
+                        "default": "desc"
+                    },
+                    "fields": {
+                        "type": "array",
+                        "items": { "type": "string", "enum": sessions::SUMMARY_FIELDS },
+                        "minItems": 1,
+                        "description": "Only include these fields in each session (default: all). Leaving out preview skips copying message text."
+                    },
+                    "preview_length": {
+                        "type": "integer",
+                        "description": "Characters of the first user message to show as each session's preview, followed by ... if it's longer; 0 leaves preview empty and skips copying message text (default: 200)",
+                        "default": 200,
+                        "minimum": 0,
+                        "maximum": 1000
+                    },
+                    "full_content": {
+                        "type": "boolean",
+                        "description": "Return whole sessions with every message instead of summaries, at most 10 per page, with total_bytes giving the size of the page. Pages over 1 MB are refused. Not with group_by_project or fields (default: false)",
+                        "default": false
+                    }
+                }
+            }),
+        },
+        Tool {
+            name: "list_agent_sessions".to_string(),
+            description: "List agent sub-sessions (the transcripts of sub-tasks handed to agents), each with the ID of the session that spawned it.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "parent_session_id": {
+                        "type": "string",
+                        "description": "Only list agent sessions spawned by this session"
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": "Maximum number of agent sessions to return (default: 20, max: 100)",
                         "default": 20
                     }
                 }
@@ -34,7 +661,7 @@ fn get_tools() -> Vec<Tool> {
         },
         Tool {
             name: "search_sessions".to_string(),
-            description: "Search Claude Code CLI sessions by keyword. Finds sessions containing the search term in messages.".to_string(),
+            description: "Search Claude Code CLI sessions by keyword or regular expression. Finds sessions containing the search term in messages.".to_string(),
             input_schema: json!({
                 "type": "object",
                 "properties": {
@@ -42,259 +669,3239 @@ fn get_tools() -> Vec<Tool> {
                         "type": "string",
                         "description": "Search query to find in session content"
                     },
+                    "search_mode": {
+                        "type": "string",
+                        "enum": ["fuzzy", "regex", "boolean", "semantic"],
+                        "description": "\"fuzzy\" for typo-tolerant matching ranked by score, \"regex\" for regular expressions ranked by match count, \"boolean\" for case-insensitive terms combined with AND, OR, NOT and parentheses, e.g. \"rust AND async NOT tokio\", \"semantic\" for sessions closest in meaning to the query, which needs an embeddings cache built with `claude-code-mcp build-embeddings` (default: fuzzy; also accepted as query_mode)",
+                        "default": "fuzzy"
+                    },
                     "limit": {
                         "type": "integer",
                         "description": "Maximum number of results (default: 10, max: 50)",
                         "default": 10
+                    },
+                    "highlight": {
+                        "type": "boolean",
+                        "description": "Include a match_context snippet with the match in **bold** (default: false)",
+                        "default": false
+                    },
+                    "cursor": {
+                        "type": "string",
+                        "description": "Opaque cursor from a previous response's next_cursor to fetch the next page of results for the same query"
+                    },
+                    "min_score": {
+                        "type": "integer",
+                        "description": "Leave out results whose score is below this. Each result carries its score. Fuzzy scores typically range from 0 to 1000 and more; 100 is a reasonable start for somewhat relevant results. Regex and boolean scores are match counts."
+                    },
+                    "search_tool_inputs": {
+                        "type": "boolean",
+                        "description": "Also search the raw input of tool calls, such as the file paths and shell commands they were given, not just message text (default: false)",
+                        "default": false
+                    },
+                    "role_filter": {
+                        "type": "string",
+                        "description": "Only search messages with this role, \"user\" or \"assistant\". Case-sensitive. With \"assistant\", the input and output of Claude's tool calls are searched too."
+                    },
+                    "since": {
+                        "type": "string",
+                        "description": "Only sessions last updated at or after this RFC 3339 timestamp or YYYY-MM-DD date"
+                    },
+                    "until": {
+                        "type": "string",
+                        "description": "Only sessions last updated at or before this RFC 3339 timestamp or YYYY-MM-DD date (inclusive of the whole day)"
                     }
                 },
                 "required": ["query"]
             }),
         },
+        Tool {
+            name: "suggestions_for".to_string(),
+            description: "Complete a search query prefix to key terms that occur in Claude Code sessions, most frequent first, to avoid searching for words no session uses.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "prefix": {
+                        "type": "string",
+                        "description": "Start of a term, ignoring case; empty for the most frequent terms overall"
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": "Maximum number of suggestions (default: 5)",
+                        "default": 5,
+                        "minimum": 1,
+                        "maximum": 100
+                    }
+                },
+                "required": ["prefix"]
+            }),
+        },
         Tool {
             name: "get_session".to_string(),
-            description: "Get the full content of a specific Claude Code session by ID. Returns all messages in the session.".to_string(),
+            description: "Get the full content of a specific Claude Code session by ID. Returns all messages in the session, or the first max_messages.".to_string(),
             input_schema: json!({
                 "type": "object",
                 "properties": {
                     "session_id": {
                         "type": "string",
-                        "description": "The session ID to retrieve"
+                        "description": "The session ID to retrieve, or a prefix of at least 4 characters matching only that session"
+                    },
+                    "max_messages": {
+                        "type": "integer",
+                        "description": "Only return the first N messages, without reading the rest of a long session"
+                    },
+                    "format": {
+                        "type": "string",
+                        "enum": ["json", "markdown", "plain_text"],
+                        "description": "\"json\" for the session as JSON, \"markdown\" for the export_session_markdown rendering, \"plain_text\" for \"Role: content\" lines (default: json)",
+                        "default": "json"
+                    },
+                    "separator": {
+                        "type": "string",
+                        "description": "Line placed between messages with the plain_text format (default: \"---\")",
+                        "default": "---"
+                    },
+                    "raw": {
+                        "type": "boolean",
+                        "description": "Also return the file's lines as stored, in raw_lines ({index, content, parsed}), with parsed false for lines that didn't become messages. Up to 100 KB, with truncated set when cut. json format only (default: false)",
+                        "default": false
+                    },
+                    "content_format": {
+                        "type": "string",
+                        "enum": ["flat", "typed"],
+                        "description": "\"flat\" for each message's content as one string, \"typed\" for parts instead: text, tool_call ({name, input_summary, tool_use_id}), tool_result ({tool_use_id, output_preview, is_error}) and image ({description}), with inputs and outputs cut to 200 characters. json format only (default: flat)",
+                        "default": "flat"
+                    },
+                    "embed_resource": {
+                        "type": "boolean",
+                        "description": "Also return the session as an embedded claude-session:// resource holding the JSONL file, for clients that keep resources by reference (default: false)",
+                        "default": false
+                    },
+                    "summary_only": {
+                        "type": "boolean",
+                        "description": "Return a few sentences on the session instead of its messages: the first request, the message count, the three files and five key terms mentioned most. Not combinable with raw, content_format or embed_resource (default: false)",
+                        "default": false
                     }
                 },
                 "required": ["session_id"]
             }),
         },
+        Tool {
+            name: "inspect_jsonl".to_string(),
+            description: "Parse a Claude Code session file anywhere under your home directory, not just in ~/.claude/projects, and return the full session. For debugging session files kept elsewhere.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "file_path": {
+                        "type": "string",
+                        "description": "Absolute path of a .jsonl file under your home directory"
+                    }
+                },
+                "required": ["file_path"]
+            }),
+        },
+        Tool {
+            name: "get_most_recent_session".to_string(),
+            description: "Get the full content of the Claude Code session written to most recently, i.e. what you were last working on. Same output as get_session.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "project_filter": {
+                        "type": "string",
+                        "description": "Only consider sessions in projects at or under this path"
+                    },
+                    "format": {
+                        "type": "string",
+                        "enum": ["json", "markdown", "plain_text"],
+                        "description": "\"json\" for the session as JSON, \"markdown\" for the export_session_markdown rendering, \"plain_text\" for \"Role: content\" lines (default: json)",
+                        "default": "json"
+                    }
+                }
+            }),
+        },
         Tool {
             name: "get_session_context".to_string(),
-            description: "Get a condensed context summary of a Claude Code session, suitable for understanding what was worked on without full message history.".to_string(),
+            description: "Get a condensed context summary of a Claude Code session, suitable for understanding what was worked on without full message history. Pass session_ids to combine several sessions into one context.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "session_id": {
+                        "type": "string",
+                        "description": "The session ID to get context for, or a prefix of at least 4 characters matching only that session"
+                    },
+                    "session_ids": {
+                        "type": "array",
+                        "items": {"type": "string"},
+                        "description": "Several session IDs to combine into one context, instead of session_id. IDs that aren't found are listed in missing_ids."
+                    },
+                    "format": {
+                        "type": "string",
+                        "enum": ["json", "prompt"],
+                        "description": "\"json\" for the context as JSON, \"prompt\" for a paragraph of at most 1000 characters to paste into another prompt (default: json)",
+                        "default": "json"
+                    },
+                    "depth": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "description": "Only take files, key terms and commands from this many messages (default: all messages)"
+                    },
+                    "depth_mode": {
+                        "type": "string",
+                        "enum": ["last_n", "first_n", "all"],
+                        "description": "Which messages depth counts: \"last_n\" for the most recent, to resume a session, \"first_n\" for the earliest, to see how it was set up, \"all\" to ignore depth (default: last_n)"
+                    },
+                    "include_tool_summary": {
+                        "type": "boolean",
+                        "description": "Add a tool_summary listing each tool Claude called, with its call count, the files it touched and the shell commands it ran (default: false)",
+                        "default": false
+                    },
+                    "include_token_estimate": {
+                        "type": "boolean",
+                        "description": "Add token_estimate, the approximate tokens of all the session's messages, and token_estimate_by_role with those of the user's and Claude's. Estimated as get_session_token_estimate does, from about 4 bytes per token for prose down to 3 for code-heavy text, not with a tokenizer; expect them to be off by up to 30% for non-English text or heavy code (default: true)",
+                        "default": true
+                    },
+                    "max_files": {
+                        "type": "integer",
+                        "description": "Maximum number of files_mentioned per session, 0 for all of them (default: 20)",
+                        "default": 20,
+                        "minimum": 0,
+                        "maximum": 200
+                    },
+                    "max_terms": {
+                        "type": "integer",
+                        "description": "Maximum number of key_terms per session, 0 for all of them (default: 15)",
+                        "default": 15,
+                        "minimum": 0,
+                        "maximum": 200
+                    },
+                    "initial_request_length": {
+                        "type": "integer",
+                        "description": "Characters of the first user message to keep as initial_request, followed by ... if it's longer; 0 keeps all of it (default: 500)",
+                        "default": 500,
+                        "minimum": 0,
+                        "maximum": 10000
+                    },
+                    "streaming": {
+                        "type": "boolean",
+                        "description": "For a session of more than 200 messages, answer straight away without files_mentioned and key_terms, listed in pending, and send them afterwards as {requestId, partial} in a notifications/tools/progress notification. Only over stdio, with session_id and the json format; otherwise the context is answered whole (default: false)",
+                        "default": false
+                    }
+                }
+            }),
+        },
+        Tool {
+            name: "get_session_timeline".to_string(),
+            description: "Get a Claude Code session as a chronological event log: each user message, assistant message, tool call and tool result, with the milliseconds since the previous event as a rough measure of response latency.".to_string(),
             input_schema: json!({
                 "type": "object",
                 "properties": {
                     "session_id": {
                         "type": "string",
-                        "description": "The session ID to get context for"
+                        "description": "The session ID to get the timeline of"
                     }
                 },
                 "required": ["session_id"]
             }),
         },
-    ]
-}
+        Tool {
+            name: "find_sessions_by_cwd".to_string(),
+            description: "Find Claude Code sessions whose working directory or project is at or under a directory, most recent first.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "cwd_prefix": {
+                        "type": "string",
+                        "description": "Directory to match, e.g. \"~/projects/myapp\". A leading ~ is expanded to the home directory. Matches whole path components, so /a/proj does not match /a/project."
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": "Maximum number of sessions to return (default: 20, max: 100)",
+                        "default": 20
+                    }
+                },
+                "required": ["cwd_prefix"]
+            }),
+        },
+        Tool {
+            name: "get_related_sessions".to_string(),
+            description: "Find Claude Code sessions that worked on the same files as a given session, ranked by the overlap of the files they mention.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "session_id": {
+                        "type": "string",
+                        "description": "The session ID to find related sessions for"
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": "Maximum number of related sessions (default: 10)",
+                        "default": 10
+                    }
+                },
+                "required": ["session_id"]
+            }),
+        },
+        Tool {
+            name: "find_duplicate_sessions".to_string(),
+            description: "Find Claude Code sessions that look like the same task started more than once: sessions in the same working directory whose key terms occur in nearly the same proportions. Returns groups of duplicates, most similar first.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "similarity_threshold": {
+                        "type": "number",
+                        "minimum": 0,
+                        "maximum": 1,
+                        "description": "Cosine similarity of key term frequencies at which two sessions count as duplicates (default: 0.9)",
+                        "default": 0.9
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": "Maximum number of groups (default: 10)",
+                        "default": 10
+                    }
+                }
+            }),
+        },
+        Tool {
+            name: "find_sessions_by_language".to_string(),
+            description: "Find Claude Code sessions mainly about code in a programming language, judged from tagged code blocks, file extensions and keywords. Most confident first.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "language": {
+                        "type": "string",
+                        "description": "Language name or file extension, e.g. \"rust\", \"python\", \"typescript\" or \"go\""
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": "Maximum number of sessions (default: 20, max: 100)",
+                        "default": 20
+                    }
+                },
+                "required": ["language"]
+            }),
+        },
+        Tool {
+            name: "find_sessions_without_assistant_response".to_string(),
+            description: "Find Claude Code sessions left waiting on Claude, e.g. after a dropped connection or a crash: the last message is the user's, Claude never replied, or there is only one message. Most recently updated first, each with last_message_role.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "limit": {
+                        "type": "integer",
+                        "description": "Maximum number of sessions (default: 20, max: 100)",
+                        "default": 20
+                    },
+                    "abandoned_only": {
+                        "type": "boolean",
+                        "description": "Only sessions where Claude had replied before the user's last message, suggesting one abandoned midway (default: false)",
+                        "default": false
+                    }
+                }
+            }),
+        },
+        Tool {
+            name: "find_sessions_by_git_repo".to_string(),
+            description: "Find Claude Code sessions run in a git repository, from the gitRepo and gitBranch recorded in their entries. Matches any part of the repository URL or name, ignoring case. Most recently updated first.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "repo": {
+                        "type": "string",
+                        "description": "Repository URL or name, or any part of it"
+                    },
+                    "branch": {
+                        "type": "string",
+                        "description": "Only sessions on this branch"
+                    },
+                    "resolve_git": {
+                        "type": "boolean",
+                        "description": "For sessions that don't record a repository, run git in their cwd to find its top-level directory and current branch (default: false). Their git_repo is then that local path, not a remote URL, so match it by path or directory name",
+                        "default": false
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": "Maximum number of sessions (default: 20, max: 100)",
+                        "default": 20
+                    }
+                },
+                "required": ["repo"]
+            }),
+        },
+        Tool {
+            name: "compare_sessions".to_string(),
+            description: "Compare two Claude Code sessions: the files, key terms and shell commands only one of them has, those they share, and how much longer one took.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "session_id_a": {
+                        "type": "string",
+                        "description": "The first session ID"
+                    },
+                    "session_id_b": {
+                        "type": "string",
+                        "description": "The second session ID"
+                    }
+                },
+                "required": ["session_id_a", "session_id_b"]
+            }),
+        },
+        Tool {
+            name: "list_projects".to_string(),
+            description: "List all projects that have Claude Code CLI sessions. Returns each project path with its session count and last activity time.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "include_cwd": {
+                        "type": "boolean",
+                        "description": "Also return the working directories seen across sessions in each project (default: false)",
+                        "default": false
+                    }
+                }
+            }),
+        },
+        Tool {
+            name: "get_session_messages".to_string(),
+            description: "Get a range of messages from a Claude Code session, optionally filtered by role. Indices are 0-based and to_index is exclusive.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "session_id": {
+                        "type": "string",
+                        "description": "The session ID to retrieve messages from"
+                    },
+                    "role": {
+                        "type": "string",
+                        "description": "Only return messages with this role (e.g. \"user\" or \"assistant\")"
+                    },
+                    "from_index": {
+                        "type": "integer",
+                        "description": "Index of the first message to return (default: 0)"
+                    },
+                    "to_index": {
+                        "type": "integer",
+                        "description": "Index one past the last message to return (default: end of session)"
+                    }
+                },
+                "required": ["session_id"]
+            }),
+        },
+        Tool {
+            name: "get_session_diff".to_string(),
+            description: "Get the messages a Claude Code session gained since an earlier call, with the cost and duration of the new turns. To tail a session that is still running, call with since_message_index 0 (or the message count from get_session), then poll with the to_index of the previous response as since_message_index. no_new_messages is true when nothing was added.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "session_id": {
+                        "type": "string",
+                        "description": "The session ID to diff"
+                    },
+                    "since_message_index": {
+                        "type": "integer",
+                        "description": "0-based index of the first message to return, usually the to_index of the previous call"
+                    }
+                },
+                "required": ["session_id", "since_message_index"]
+            }),
+        },
+        Tool {
+            name: "watch_session".to_string(),
+            description: "Wait for a Claude Code session that is still running to gain messages, and return them as soon as they are written, or timed_out after timeout_ms. Returns new_messages, timed_out and current_message_count. Waits end before the server's tool timeout: a second before it, or halfway through it if that is later.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "session_id": {
+                        "type": "string",
+                        "description": "The session ID to watch"
+                    },
+                    "poll_interval_ms": {
+                        "type": "integer",
+                        "description": "How often to check the session file, at least 100 (default: 2000)",
+                        "default": 2000,
+                        "minimum": 0,
+                        "maximum": 30000
+                    },
+                    "timeout_ms": {
+                        "type": "integer",
+                        "description": "How long to wait for new messages (default: 30000)",
+                        "default": 30000,
+                        "minimum": 0
+                    }
+                },
+                "required": ["session_id"]
+            }),
+        },
+        Tool {
+            name: "merge_sessions".to_string(),
+            description: "Merge two Claude Code sessions, e.g. one long task split across sessions, into a new JSONL session file under a new session ID. Messages are interleaved by timestamp and repeated messages dropped.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "session_id_a": {
+                        "type": "string",
+                        "description": "The earlier session"
+                    },
+                    "session_id_b": {
+                        "type": "string",
+                        "description": "The later session"
+                    },
+                    "output_path": {
+                        "type": "string",
+                        "description": "Absolute path of the .jsonl file to create, under your home directory. Must not exist yet."
+                    }
+                },
+                "required": ["session_id_a", "session_id_b", "output_path"]
+            }),
+        },
+        Tool {
+            name: "get_session_token_estimate".to_string(),
+            description: "Estimate how many tokens a Claude Code session's messages take up, to judge whether it fits in a prompt's context window. Warns above 100K tokens.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "session_id": {
+                        "type": "string",
+                        "description": "The session ID to estimate"
+                    },
+                    "role_filter": {
+                        "type": "string",
+                        "description": "Count only messages with this role (e.g. \"user\" or \"assistant\") in estimated_tokens"
+                    }
+                },
+                "required": ["session_id"]
+            }),
+        },
+        Tool {
+            name: "get_session_cost".to_string(),
+            description: "Get what a Claude Code session cost to run: total cost in USD and duration in milliseconds, summed over its result entries, and the cost per message. Fields are null when the session recorded no cost.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "session_id": {
+                        "type": "string",
+                        "description": "The session ID to get the cost of"
+                    }
+                },
+                "required": ["session_id"]
+            }),
+        },
+        Tool {
+            name: "get_session_commands".to_string(),
+            description: "Get the shell commands executed during a Claude Code session, with timestamps and message indices.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "session_id": {
+                        "type": "string",
+                        "description": "The session ID to get commands for"
+                    },
+                    "unique": {
+                        "type": "boolean",
+                        "description": "Drop repeated identical commands, keeping the first occurrence (default: false)",
+                        "default": false
+                    }
+                },
+                "required": ["session_id"]
+            }),
+        },
+        Tool {
+            name: "get_command_history".to_string(),
+            description: "Get the shell commands Claude Code ran across all sessions, as a command history to refer back to. By default each distinct command is listed once, most often run first.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "limit": {
+                        "type": "integer",
+                        "description": "Maximum number of commands to return (default: 50)",
+                        "default": 50,
+                        "minimum": 1
+                    },
+                    "project_filter": {
+                        "type": "string",
+                        "description": "Only include sessions in projects at or under this path"
+                    },
+                    "unique": {
+                        "type": "boolean",
+                        "description": "List each distinct command once with its execution_count and last_seen_in_session, most often run first (default: true). With false, list every run with its session_id and timestamp, most recent first.",
+                        "default": true
+                    }
+                }
+            }),
+        },
+        Tool {
+            name: "extract_todos".to_string(),
+            description: "Find TODO, FIXME and similar markers in a Claude Code session's messages, in prose and code blocks alike, e.g. to collect follow-up tasks left at the end of a long session. A comment continued on indented lines is returned as one todo.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "session_id": {
+                        "type": "string",
+                        "description": "The session ID to scan"
+                    },
+                    "patterns": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Case-sensitive markers to look for as whole words (default: [\"TODO\", \"FIXME\", \"HACK\", \"NOTE\", \"XXX\"])"
+                    }
+                },
+                "required": ["session_id"]
+            }),
+        },
+        Tool {
+            name: "find_todos_across_sessions".to_string(),
+            description: "Find TODO, FIXME and similar markers across all Claude Code sessions, most recent first, each with the session it was found in.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "patterns": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Case-sensitive markers to look for as whole words (default: [\"TODO\", \"FIXME\", \"HACK\", \"NOTE\", \"XXX\"])"
+                    },
+                    "project_filter": {
+                        "type": "string",
+                        "description": "Only include sessions in projects at or under this path"
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": "Maximum number of todos to return (default: 50)",
+                        "default": 50,
+                        "minimum": 1
+                    }
+                }
+            }),
+        },
+        Tool {
+            name: "list_session_files".to_string(),
+            description: "List the files a Claude Code session read or changed through tool calls (Read, Write, Edit, ...), with the tool, message index and timestamp of each operation.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "session_id": {
+                        "type": "string",
+                        "description": "The session ID to list files for"
+                    },
+                    "operations": {
+                        "type": "array",
+                        "items": {"type": "string"},
+                        "description": "Only operations by these tools, e.g. [\"Write\", \"Edit\"] (default: all)"
+                    },
+                    "unique": {
+                        "type": "boolean",
+                        "description": "List each file once, at its first operation; false lists every operation (default: true)",
+                        "default": true
+                    }
+                },
+                "required": ["session_id"]
+            }),
+        },
+        Tool {
+            name: "find_sessions_touching_file".to_string(),
+            description: "Find Claude Code sessions that mentioned or operated on a file path, most recently updated first.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "file_path": {
+                        "type": "string",
+                        "description": "File path to look for, e.g. \"src/main.rs\""
+                    },
+                    "match_mode": {
+                        "type": "string",
+                        "enum": ["exact", "suffix", "contains"],
+                        "description": "\"exact\" for identical paths, \"suffix\" to match trailing path components (\"main.rs\" matches \"/project/src/main.rs\"), \"contains\" for any substring (default: suffix)",
+                        "default": "suffix"
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": "Maximum number of sessions to return (default: 20, max: 100)",
+                        "default": 20
+                    }
+                },
+                "required": ["file_path"]
+            }),
+        },
+        Tool {
+            name: "find_sessions_with_errors".to_string(),
+            description: "Find Claude Code sessions where something went wrong: tool output or assistant replies mentioning errors, failed tests or non-zero exit codes. Most recently updated first.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "error_patterns": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Case-sensitive substrings (not regexes) that signal an error (default: [\"error:\", \"Error:\", \"failed\", \"FAILED\", \"panic\", \"exit code 1\"])"
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": "Maximum number of sessions to return (default: 20, max: 100)",
+                        "default": 20
+                    }
+                }
+            }),
+        },
+        Tool {
+            name: "extract_code_blocks".to_string(),
+            description: "Extract the fenced code blocks from a Claude Code session's messages and tool output.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "session_id": {
+                        "type": "string",
+                        "description": "The session ID to extract code from"
+                    },
+                    "language": {
+                        "type": "string",
+                        "description": "Only return blocks fenced with this language, case-insensitive (e.g. \"rust\")"
+                    }
+                },
+                "required": ["session_id"]
+            }),
+        },
+        Tool {
+            name: "export_sessions_csv".to_string(),
+            description: "Export Claude Code session summaries as CSV for spreadsheets or SQL, most recently updated first.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "limit": {
+                        "type": "integer",
+                        "description": "Maximum number of sessions to export (default: 100, max: 1000)",
+                        "default": 100
+                    },
+                    "since": {
+                        "type": "string",
+                        "description": "Only sessions last updated at or after this RFC 3339 timestamp or YYYY-MM-DD date"
+                    },
+                    "until": {
+                        "type": "string",
+                        "description": "Only sessions last updated at or before this RFC 3339 timestamp or YYYY-MM-DD date (inclusive of the whole day)"
+                    }
+                }
+            }),
+        },
+        Tool {
+            name: "export_session_markdown".to_string(),
+            description: "Export a Claude Code session as Markdown, with a YAML front-matter header and a section per message.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "session_id": {
+                        "type": "string",
+                        "description": "The session ID to export"
+                    },
+                    "include_tool_calls": {
+                        "type": "boolean",
+                        "description": "Render tool calls as fenced code blocks of their input (default: false)",
+                        "default": false
+                    }
+                },
+                "required": ["session_id"]
+            }),
+        },
+        Tool {
+            name: "copy_session_to_clipboard".to_string(),
+            description: "Copy a Claude Code session to the system clipboard. Returns whether the copy worked (\"copied\" or \"clipboard_unavailable\", e.g. with no display) followed by the formatted session, so it can be copied by hand.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "session_id": {
+                        "type": "string",
+                        "description": "The session ID to copy"
+                    },
+                    "format": {
+                        "type": "string",
+                        "enum": ["json", "markdown", "plain_text"],
+                        "description": "\"json\" for the session as JSON, \"markdown\" for the export_session_markdown rendering, \"plain_text\" for \"Role: content\" lines (default: markdown)",
+                        "default": "markdown"
+                    }
+                },
+                "required": ["session_id"]
+            }),
+        },
+        Tool {
+            name: "validate_session".to_string(),
+            description: "Check a Claude Code session file for corruption: invalid JSON or UTF-8, unknown entry types, bad timestamps, mixed session IDs, duplicate timestamps and missing agent sub-sessions.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "session_id": {
+                        "type": "string",
+                        "description": "The session ID to validate"
+                    }
+                },
+                "required": ["session_id"]
+            }),
+        },
+        Tool {
+            name: "get_tool_usage_stats".to_string(),
+            description: "Count how often each Claude Code tool (Bash, Read, Write, ...) was called across sessions, most used first.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "limit": {
+                        "type": "integer",
+                        "description": "Maximum number of tools to return (default: all)"
+                    },
+                    "session_filter": {
+                        "type": "string",
+                        "description": "Only count sessions in projects at or under this path"
+                    }
+                }
+            }),
+        },
+        Tool {
+            name: "get_activity_heatmap".to_string(),
+            description: "Count Claude Code sessions by the hour of day, day of week and date they started (UTC), to show when work happens.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "project_filter": {
+                        "type": "string",
+                        "description": "Only count sessions in projects at or under this path"
+                    },
+                    "since": {
+                        "type": "string",
+                        "description": "Only sessions started at or after this RFC 3339 timestamp or YYYY-MM-DD date"
+                    },
+                    "until": {
+                        "type": "string",
+                        "description": "Only sessions started at or before this RFC 3339 timestamp or YYYY-MM-DD date (inclusive of the whole day)"
+                    }
+                }
+            }),
+        },
+        Tool {
+            name: "get_project_timeline".to_string(),
+            description: "Show the arc of work on a project: its Claude Code sessions counted by the day, week or month they started (UTC), with their messages and the files first mentioned in each period. Only periods with sessions are listed, so gaps show as jumps in date.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "project_path": {
+                        "type": "string",
+                        "description": "Project path; projects under it are included"
+                    },
+                    "bucket": {
+                        "type": "string",
+                        "enum": ["day", "week", "month"],
+                        "description": "Period to count sessions by; weeks start on Monday (default: day)",
+                        "default": "day"
+                    }
+                },
+                "required": ["project_path"]
+            }),
+        },
+        Tool {
+            name: "get_sessions_by_hour".to_string(),
+            description: "Count Claude Code sessions and their messages by the hour of day they started, in the local time zone, to show which hours you use Claude Code most.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "project_filter": {
+                        "type": "string",
+                        "description": "Only count sessions in projects at or under this path"
+                    },
+                    "since": {
+                        "type": "string",
+                        "description": "Only sessions started at or after this RFC 3339 timestamp or YYYY-MM-DD date"
+                    },
+                    "until": {
+                        "type": "string",
+                        "description": "Only sessions started at or before this RFC 3339 timestamp or YYYY-MM-DD date (inclusive of the whole day)"
+                    }
+                }
+            }),
+        },
+        Tool {
+            name: "get_longest_sessions".to_string(),
+            description: "Find the Claude Code sessions with the most messages, which are often the ones that tackled hard problems. Sessions with fewer than 2 messages are excluded unless include_trivial is true.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "limit": {
+                        "type": "integer",
+                        "description": "Maximum number of sessions to return (default: 10)",
+                        "default": 10,
+                        "minimum": 1,
+                        "maximum": 100
+                    },
+                    "project_filter": {
+                        "type": "string",
+                        "description": "Only sessions in projects at or under this path"
+                    },
+                    "include_trivial": {
+                        "type": "boolean",
+                        "description": "Include sessions with fewer than 2 messages, which are excluded by default",
+                        "default": false
+                    }
+                }
+            }),
+        },
+        Tool {
+            name: "get_shortest_sessions".to_string(),
+            description: "Find the Claude Code sessions with the fewest messages, such as quick one-off questions. Sessions with fewer than 2 messages are excluded unless include_trivial is true.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "limit": {
+                        "type": "integer",
+                        "description": "Maximum number of sessions to return (default: 10)",
+                        "default": 10,
+                        "minimum": 1,
+                        "maximum": 100
+                    },
+                    "project_filter": {
+                        "type": "string",
+                        "description": "Only sessions in projects at or under this path"
+                    },
+                    "include_trivial": {
+                        "type": "boolean",
+                        "description": "Include sessions with fewer than 2 messages, which are excluded by default",
+                        "default": false
+                    }
+                }
+            }),
+        },
+        Tool {
+            name: "get_sessions_statistics".to_string(),
+            description: "Aggregate metrics across all Claude Code sessions: totals, average session length, busiest day, most active project, date range, and total and average cost in USD.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {}
+            }),
+        },
+        Tool {
+            name: "rebuild_index".to_string(),
+            description: "Rescan every Claude Code session file and rebuild the session cache and, with --index-path, the search index, for when they seem stale. Returns how many sessions were found, which files failed to parse and why, and how long it took.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {}
+            }),
+        },
+        Tool {
+            name: "show_config".to_string(),
+            description: "Show the configuration the server is running with, after combining the config file, CLAUDE_CODE_MCP_* environment variables and command-line flags. Credentials in URLs are masked.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {}
+            }),
+        },
+        Tool {
+            name: "list_tools".to_string(),
+            description: "List the tools this server offers, with the name, description and inputSchema of each, itself included. The same as the tools/list method, for agent frameworks that only discover tools by calling one.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {}
+            }),
+        },
+        Tool {
+            name: "tag_session".to_string(),
+            description: "Add a tag to a Claude Code session. Tags persist across server restarts.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "session_id": {
+                        "type": "string",
+                        "description": "The session ID to tag"
+                    },
+                    "tag": {
+                        "type": "string",
+                        "description": "The tag to add"
+                    }
+                },
+                "required": ["session_id", "tag"]
+            }),
+        },
+        Tool {
+            name: "untag_session".to_string(),
+            description: "Remove a tag from a Claude Code session.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "session_id": {
+                        "type": "string",
+                        "description": "The session ID to remove the tag from"
+                    },
+                    "tag": {
+                        "type": "string",
+                        "description": "The tag to remove"
+                    }
+                },
+                "required": ["session_id", "tag"]
+            }),
+        },
+        Tool {
+            name: "list_tags".to_string(),
+            description: "List all distinct tags applied to Claude Code sessions.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {}
+            }),
+        },
+        Tool {
+            name: "find_sessions_by_tag".to_string(),
+            description: "Find Claude Code sessions carrying a tag, most recently updated first.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "tag": {
+                        "type": "string",
+                        "description": "The tag to look for"
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": "Maximum number of sessions to return (default: 20, max: 100)",
+                        "default": 20
+                    }
+                },
+                "required": ["tag"]
+            }),
+        },
+        Tool {
+            name: "add_session_note".to_string(),
+            description: "Write a plain-text note on a Claude Code session, e.g. \"the session where I fixed the auth bug\". Notes persist across server restarts.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "session_id": {
+                        "type": "string",
+                        "description": "The session ID to annotate"
+                    },
+                    "note": {
+                        "type": "string",
+                        "description": "The note text (at most 2000 characters)"
+                    }
+                },
+                "required": ["session_id", "note"]
+            }),
+        },
+        Tool {
+            name: "get_session_notes".to_string(),
+            description: "Get the notes written on a Claude Code session, oldest first.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "session_id": {
+                        "type": "string",
+                        "description": "The session ID to get notes for"
+                    }
+                },
+                "required": ["session_id"]
+            }),
+        },
+        Tool {
+            name: "delete_session_note".to_string(),
+            description: "Delete a note from a Claude Code session.".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "session_id": {
+                        "type": "string",
+                        "description": "The session ID the note is on"
+                    },
+                    "note_id": {
+                        "type": "string",
+                        "description": "The note's id, as returned by add_session_note or get_session_notes"
+                    }
+                },
+                "required": ["session_id", "note_id"]
+            }),
+        },
+    ]
+}
+
+/// Handle an incoming JSON-RPC request
+async fn handle_request(state: &ServerState, request: JsonRpcRequest) -> JsonRpcResponse {
+    let method = request.method.clone();
+    // Notifications get no response, so there is nothing to audit
+    let audit = state.logger.clone().filter(|_| !request.is_notification()).map(|logger| {
+        let tool = (method == "tools/call")
+            .then(|| request.params.as_ref()?.get("name")?.as_str().map(str::to_string))
+            .flatten();
+        (logger, chrono::Utc::now(), Instant::now(), tool)
+    });
+
+    let response = dispatch_request(state, request).await;
+    let status = if response.error.is_some() { Status::Error } else { Status::Ok };
+    state.metrics.record_request(&method, status);
+
+    if let Some((logger, ts, started, tool)) = audit {
+        // A tool call that fails still gets a result, flagged with isError
+        let tool_failed = response.result.as_ref().and_then(|r| r.get("isError")) == Some(&Value::Bool(true));
+        logger.log(RequestRecord {
+            ts,
+            request_id: response.id.clone(),
+            method,
+            duration_ms: started.elapsed().as_millis() as u64,
+            status: if response.error.is_some() || tool_failed { RecordStatus::Error } else { RecordStatus::Success },
+            tool,
+            error_code: response.error.as_ref().map(|e| e.code),
+        });
+    }
+    response
+}
+
+/// Run the tool a `tools/call` request names, under the concurrency and rate
+/// limits and the tool timeout, giving up early if `cancel` fires
+async fn call_tool(state: &ServerState, id: Value, params: Option<Value>, cancel: CancellationToken) -> JsonRpcResponse {
+    let params = params.unwrap_or(json!({}));
+    let tool_name = params
+        .get("name")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+    let arguments = params.get("arguments").cloned().unwrap_or(json!({}));
+
+    // Held by the call's thread, so a call abandoned after a timeout
+    // keeps its permit until it really finishes
+    let permit = match &state.concurrency {
+        Some(limiter) => match limiter.try_acquire() {
+            Some(permit) => Some(permit),
+            None => return JsonRpcResponse::error(id, -32000, "Server overloaded, retry later"),
+        },
+        None => None,
+    };
+
+    if let Some(limiter) = &state.rate_limiter {
+        if let Err(retry_after) = limiter.acquire().await {
+            return JsonRpcResponse::error(id, -32000, "Rate limit exceeded, retry later")
+                .with_data(json!({ "retry_after_ms": retry_after.as_millis().max(1) as u64 }));
+        }
+    }
+
+    // The session store does blocking file I/O, so run the call on the
+    // blocking pool. A call that outlives the timeout is abandoned: its
+    // thread finishes on its own and the result is dropped.
+    let name = tool_name.to_string();
+    let started = Instant::now();
+    let mut progress = state.notifications.clone().filter(|_| state.tool_progress).map(|notifications| ToolProgress {
+        request_id: id.clone(),
+        notifications,
+        deadline: started + state.tool_timeout,
+        deferred: None,
+    });
+
+    // Cancellation abandons the call like a timeout does. The answer comes
+    // back before the thread finishes anything the call deferred.
+    let (answer_tx, answer) = tokio::sync::oneshot::channel();
+    let call = tokio::task::spawn_blocking({
+        let cancel = cancel.clone();
+        move || {
+            let _permit = permit;
+            let _ = answer_tx.send(handle_tool_call(&name, arguments, progress.as_mut(), &cancel));
+            if let Some(progress) = progress {
+                progress.finish(&cancel);
+            }
+        }
+    });
+    let result = tokio::select! {
+        outcome = tokio::time::timeout(state.tool_timeout, answer) => match outcome {
+            Ok(Ok(result)) => result,
+            // Dropped without an answer, so the call panicked
+            Ok(Err(_)) => {
+                let error = call.await.err().map_or_else(|| "no result".to_string(), |e| e.to_string());
+                ToolResult::error(McpErrorKind::IoError, format!("Tool call failed: {}", error))
+            }
+            Err(_) => ToolResult::error(
+                McpErrorKind::Timeout,
+                format!("Tool call timed out after {} seconds", state.tool_timeout.as_secs()),
+            ),
+        },
+        _ = cancel.cancelled() => ToolResult::error(McpErrorKind::Cancelled, "Request cancelled by client"),
+    };
+    let status = if result.is_error == Some(true) { Status::Error } else { Status::Ok };
+    state.metrics.record_tool_call(tool_name, status, started.elapsed());
+    if config::log_enabled(LogLevel::Debug) {
+        eprintln!("debug: {} took {:?} ({:?})", tool_name, started.elapsed(), status);
+    }
+    JsonRpcResponse::success(id, serde_json::to_value(result).unwrap())
+}
+
+async fn dispatch_request(state: &ServerState, request: JsonRpcRequest) -> JsonRpcResponse {
+    let id = request.id.clone().unwrap_or(Value::Null);
+
+    match request.method.as_str() {
+        "initialize" => {
+            let requested = request
+                .params
+                .as_ref()
+                .and_then(|p| p.get("protocolVersion"))
+                .and_then(|v| v.as_str());
+            let Some((protocol_version, downgraded)) = protocol::negotiate_protocol_version(requested) else {
+                return JsonRpcResponse::mcp_error(
+                    id,
+                    -32602,
+                    McpError::new(
+                        McpErrorKind::InvalidArgument,
+                        format!(
+                            "Unsupported protocol version: {} (supported: {})",
+                            requested.unwrap_or_default(),
+                            SUPPORTED_PROTOCOL_VERSIONS.join(", ")
+                        ),
+                    ),
+                );
+            };
+
+            let result = InitializeResult {
+                protocol_version: protocol_version.to_string(),
+                downgraded,
+                capabilities: ServerCapabilities {
+                    tools: ToolsCapability {
+                        list_changed: watcher::is_active() || state.tools_changed,
+                    },
+                    resources: ResourcesCapability {
+                        list_changed: false,
+                        // Updates come from the same watcher
+                        subscribe: watcher::is_active(),
+                    },
+                    prompts: PromptsCapability { list_changed: false },
+                },
+                server_info: ServerInfo {
+                    name: "claude-code-mcp".to_string(),
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                },
+            };
+            JsonRpcResponse::success(id, serde_json::to_value(result).unwrap())
+        }
+
+        "notifications/initialized" | "initialized" => {
+            // The client may still have the tool list of an older version
+            if let Some(notifications) = state.notifications.as_ref().filter(|_| state.tools_changed) {
+                let notification = JsonRpcNotification::new("notifications/tools/list_changed", json!({}));
+                if let Ok(message) = serde_json::to_string(&notification) {
+                    let _ = notifications.send(message).await;
+                }
+            }
+            // Normally sent as a notification, in which case the main loop drops
+            // this; acknowledge it if a client sent it with an id
+            JsonRpcResponse::success(id, json!({}))
+        }
+
+        "notifications/cancelled" => {
+            // Cancelling a request that already finished, or never existed, is a no-op
+            let request_id = request.params.as_ref().and_then(|p| p.get("requestId"));
+            if let Some(request_id) = request_id {
+                if let Some(token) = state.in_flight().get(&request_id.to_string()) {
+                    token.cancel();
+                }
+            }
+            JsonRpcResponse::success(id, json!({}))
+        }
+
+        "ping" => {
+            // Liveness probe: answered right here, without the session store
+            // or the tool-call limits
+            JsonRpcResponse::success(id, json!({}))
+        }
+
+        "tools/list" => JsonRpcResponse::success(id, json!({ "tools": listed_tools() })),
+
+        "tools/call" => {
+            // Registered already if the transport read the call ahead; see `register_call`
+            let key = id.to_string();
+            let cancel = state.in_flight().entry(key.clone()).or_default().clone();
+            let response = call_tool(state, id, request.params, cancel).await;
+            state.in_flight().remove(&key);
+            response
+        }
+
+        "resources/list" => {
+            let params = request.params.unwrap_or(json!({}));
+            let cursor = params.get("cursor").and_then(|v| v.as_str());
+
+            let store = match session_store() {
+                Ok(s) => s,
+                Err(e) => {
+                    return JsonRpcResponse::mcp_error(id, -32603, McpError::new(error_kind(&e), e.to_string()))
+                }
+            };
+
+            match store.list_sessions(100, cursor, ListOptions::default(), None) {
+                Ok(page) => {
+                    let resources: Vec<McpResource> = page
+                        .items
+                        .into_iter()
+                        .map(|s| McpResource {
+                            uri: format!("{}{}", SESSION_URI_SCHEME, s.id),
+                            name: s.id,
+                            description: Some(s.preview),
+                            mime_type: "application/jsonl".to_string(),
+                        })
+                        .collect();
+                    JsonRpcResponse::success(
+                        id,
+                        json!({ "resources": resources, "nextCursor": page.next_cursor }),
+                    )
+                }
+                Err(e) => JsonRpcResponse::mcp_error(id, -32602, McpError::new(error_kind(&e), e.to_string())),
+            }
+        }
+
+        "resources/read" => {
+            let params = request.params.unwrap_or(json!({}));
+            let uri = params.get("uri").and_then(|v| v.as_str()).unwrap_or("");
+
+            let Some(resource) = parse_session_uri(uri) else {
+                return JsonRpcResponse::mcp_error(
+                    id,
+                    -32602,
+                    McpError::new(McpErrorKind::InvalidArgument, format!("Unsupported resource URI: {}", uri)),
+                );
+            };
+
+            let store = match session_store() {
+                Ok(s) => s,
+                Err(e) => {
+                    return JsonRpcResponse::mcp_error(id, -32603, McpError::new(error_kind(&e), e.to_string()))
+                }
+            };
+
+            let session_id = match &resource {
+                SessionUriRequest::Session(session_id) => session_id,
+                view => {
+                    return match read_session_view(&store, uri, view) {
+                        Ok(Some(content)) => JsonRpcResponse::success(id, json!({ "contents": [content] })),
+                        Ok(None) => JsonRpcResponse::mcp_error(
+                            id,
+                            -32002,
+                            McpError::new(McpErrorKind::NotFound, format!("Resource not found: {}", uri)),
+                        ),
+                        Err(e) => JsonRpcResponse::mcp_error(
+                            id,
+                            -32603,
+                            McpError::new(error_kind(&e), format!("Failed to read {}: {}", uri, e)),
+                        ),
+                    }
+                }
+            };
+
+            let path = match store.find_session_file(session_id) {
+                Ok(Some(path)) => path,
+                Ok(None) => {
+                    return JsonRpcResponse::mcp_error(
+                        id,
+                        -32002,
+                        McpError::new(McpErrorKind::NotFound, format!("Resource not found: {}", uri)),
+                    )
+                }
+                Err(e) => {
+                    return JsonRpcResponse::mcp_error(id, -32603, McpError::new(error_kind(&e), e.to_string()))
+                }
+            };
+
+            match read_session_resource(uri, &path) {
+                Ok(content) => JsonRpcResponse::success(id, json!({ "contents": [content] })),
+                Err(e) => JsonRpcResponse::mcp_error(
+                    id,
+                    -32603,
+                    McpError::new(error_kind(&e), format!("Failed to read {}: {}", uri, e))
+                        .with_path(path.display().to_string()),
+                ),
+            }
+        }
+
+        "resources/subscribe" | "resources/unsubscribe" => {
+            let params = request.params.unwrap_or(json!({}));
+            let uri = params.get("uri").and_then(|v| v.as_str()).unwrap_or("");
+
+            // Updates are only sent for session files, not the views derived from them
+            if !matches!(parse_session_uri(uri), Some(SessionUriRequest::Session(_))) {
+                return JsonRpcResponse::mcp_error(
+                    id,
+                    -32602,
+                    McpError::new(McpErrorKind::InvalidArgument, format!("Unsupported resource URI: {}", uri)),
+                );
+            }
+
+            if request.method == "resources/subscribe" {
+                state.subscriptions().insert(uri.to_string());
+            } else {
+                state.subscriptions().remove(uri);
+            }
+            JsonRpcResponse::success(id, json!({}))
+        }
+
+        "prompts/list" => {
+            let prompts = prompts::get_prompts();
+            JsonRpcResponse::success(id, json!({ "prompts": prompts }))
+        }
+
+        "prompts/get" => {
+            let params = request.params.unwrap_or(json!({}));
+            let name = params.get("name").and_then(|v| v.as_str()).unwrap_or("");
+            let arguments = params.get("arguments").cloned().unwrap_or(json!({}));
+
+            let Some(prompt) = prompts::get_prompts().into_iter().find(|p| p.name == name) else {
+                return JsonRpcResponse::mcp_error(
+                    id,
+                    -32602,
+                    McpError::new(McpErrorKind::InvalidArgument, format!("Unknown prompt: {}", name)),
+                );
+            };
+            if let Some(missing) = prompt
+                .arguments
+                .iter()
+                .find(|a| a.required && arguments.get(&a.name).and_then(|v| v.as_str()).is_none())
+            {
+                return JsonRpcResponse::mcp_error(
+                    id,
+                    -32602,
+                    McpError::new(
+                        McpErrorKind::InvalidArgument,
+                        format!("Missing required argument: {}", missing.name),
+                    ),
+                );
+            }
+
+            let store = match session_store() {
+                Ok(s) => s,
+                Err(e) => {
+                    return JsonRpcResponse::mcp_error(id, -32603, McpError::new(error_kind(&e), e.to_string()))
+                }
+            };
+
+            match prompts::get_prompt(&store, name, &arguments) {
+                Ok(result) => JsonRpcResponse::success(id, serde_json::to_value(result).unwrap()),
+                Err(e) => JsonRpcResponse::mcp_error(
+                    id,
+                    -32603,
+                    McpError::new(error_kind(&e), format!("Failed to build prompt: {}", e)),
+                ),
+            }
+        }
+
+        _ => JsonRpcResponse::error(
+            id,
+            -32601,
+            format!("Method not found: {}", request.method),
+        ),
+    }
+}
+
+/// Validators for each tool's input schema, disabled tools included
+fn tool_validators() -> &'static HashMap<String, jsonschema::Validator> {
+    TOOL_VALIDATORS.get_or_init(|| {
+        all_tools()
+            .into_iter()
+            .map(|tool| {
+                let validator = jsonschema::validator_for(&tool.input_schema)
+                    .unwrap_or_else(|e| panic!("invalid input schema for {}: {}", tool.name, e));
+                (tool.name, validator)
+            })
+            .collect()
+    })
+}
+
+/// Check tool arguments against the tool's input schema, returning an error
+/// naming every offending argument
+fn validate_arguments(name: &str, arguments: &Value) -> Option<ToolResult> {
+    // Unknown tools are reported by the caller
+    let validator = tool_validators().get(name)?;
+
+    let errors: Vec<String> = validator
+        .iter_errors(arguments)
+        .map(|e| {
+            let path = e.instance_path.to_string();
+            format!("- {}: {}", if path.is_empty() { "/" } else { &path }, e)
+        })
+        .collect();
+    (!errors.is_empty()).then(|| {
+        ToolResult::error(
+            McpErrorKind::InvalidArgument,
+            format!("Invalid arguments for {}:\n{}", name, errors.join("\n")),
+        )
+    })
+}
+
+/// Handle a tool call. Blocks on file I/O. Tools that answer early send the
+/// rest of their result through `progress`, when there is somewhere to send it.
+/// Tools that wait stop once `cancel` fires.
+fn handle_tool_call(
+    name: &str,
+    arguments: Value,
+    progress: Option<&mut ToolProgress>,
+    cancel: &CancellationToken,
+) -> ToolResult {
+    // Disabled tools are refused like ones that don't exist, before anything
+    // else could give away that they do
+    let disabled = CONFIG.get().is_some_and(|config| config.disabled_tools.iter().any(|tool| tool == name));
+    if disabled || !tool_validators().contains_key(name) {
+        return ToolResult::error(McpErrorKind::InvalidArgument, format!("Unknown tool: {}", name));
+    }
+    if let Some(error) = validate_arguments(name, &arguments) {
+        return error;
+    }
+
+    // Discovery works whether or not there are sessions to open
+    if name == "list_tools" {
+        let json = serde_json::to_string_pretty(&listed_tools()).unwrap_or_else(|_| "[]".to_string());
+        return ToolResult::text(json);
+    }
+
+    let store = match session_store() {
+        Ok(s) => s,
+        Err(e) => return ToolResult::error(error_kind(&e), format!("Failed to initialize session store: {}", e)),
+    };
+
+    match name {
+        "list_sessions" => {
+            let limit = arguments
+                .get("limit")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(20) as usize;
+            let cursor = arguments.get("cursor").and_then(|v| v.as_str());
+            let include_agents = arguments
+                .get("include_agents")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+            let group_by_project = arguments
+                .get("group_by_project")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+            let project_limit = arguments
+                .get("project_limit")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(10) as usize;
+            let min_messages = arguments
+                .get("min_messages")
+                .and_then(|v| v.as_u64())
+                .map(|v| v as usize);
+            let sort = match list_sort_options(&arguments) {
+                Ok(sort) => sort,
+                Err(e) => return ToolResult::error(McpErrorKind::InvalidArgument, e.to_string()),
+            };
+            let fields: Option<Vec<String>> = arguments
+                .get("fields")
+                .and_then(|v| v.as_array())
+                .map(|a| a.iter().filter_map(|v| v.as_str()).map(str::to_string).collect());
+            let preview = fields.as_ref().is_none_or(|f| f.iter().any(|f| f == "preview"));
+            let preview_length = arguments
+                .get("preview_length")
+                .and_then(|v| v.as_u64())
+                .map_or(sessions::DEFAULT_PREVIEW_CHARS, |v| v as usize);
+            let full_content = arguments
+                .get("full_content")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+            // Tags are a nice-to-have here; list without them if the database is unavailable
+            let metadata = MetadataStore::open_existing_in(store.base_path()).unwrap_or_else(|e| {
+                warning!("{}", e);
+                None
+            });
+
+            if group_by_project && cursor.is_some() {
+                return ToolResult::error(
+                    McpErrorKind::InvalidArgument,
+                    "cursor can't be combined with group_by_project",
+                );
+            }
+            if full_content && (group_by_project || fields.is_some()) {
+                return ToolResult::error(
+                    McpErrorKind::InvalidArgument,
+                    "full_content can't be combined with group_by_project or fields",
+                );
+            }
+
+            let snapshot = match SESSION_CACHE.get() {
+                Some(cache) => cache.snapshot(),
+                None => Arc::new(Snapshot::load(&store)),
+            };
+            let summaries = snapshot.summaries(
+                include_agents,
+                min_messages,
+                if preview && !full_content { preview_length } else { 0 },
+            );
+            let cache_age = snapshot.age();
+
+            let listed = if group_by_project {
+                sessions::group_summaries(summaries, limit, project_limit, metadata.as_ref())
+                    .and_then(|grouped| Ok(serde_json::to_value(WithCacheMeta::new(grouped, cache_age))?))
+            } else if full_content {
+                let limit = limit.min(sessions::MAX_FULL_CONTENT_SESSIONS);
+                let page = match sessions::paginate_summaries(summaries, limit, cursor, sort, None) {
+                    Ok(page) => page,
+                    Err(e) => return ToolResult::error(error_kind(&e), format!("Failed to list sessions: {}", e)),
+                };
+                let items: Vec<sessions::Session> =
+                    page.items.iter().filter_map(|s| snapshot.session(&s.id)).cloned().collect();
+                let total_bytes = serde_json::to_vec(&items).map_or(0, |bytes| bytes.len());
+                if total_bytes > sessions::MAX_FULL_CONTENT_BYTES {
+                    return ToolResult::error(
+                        McpErrorKind::InvalidArgument,
+                        format!(
+                            "These sessions take {} bytes, more than the 1 MB full_content allows. \
+                             Lower limit to fetch fewer sessions per page, or use get_session for specific sessions.",
+                            total_bytes
+                        ),
+                    );
+                }
+                let full = sessions::FullSessionPage {
+                    items,
+                    next_cursor: page.next_cursor,
+                    total_bytes,
+                };
+                serde_json::to_value(WithCacheMeta::new(full, cache_age)).map_err(Into::into)
+            } else {
+                sessions::paginate_summaries(summaries, limit, cursor, sort, metadata.as_ref())
+                    .and_then(|page| Ok(serde_json::to_value(WithCacheMeta::new(page, cache_age))?))
+            };
+
+            match listed {
+                Ok(mut listed) => {
+                    if let Some(fields) = &fields {
+                        for summary in listed_summaries(&mut listed) {
+                            sessions::retain_fields(summary, fields);
+                        }
+                    }
+                    let json = serde_json::to_string_pretty(&listed)
+                        .unwrap_or_else(|_| "{}".to_string());
+                    ToolResult::text(json)
+                }
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to list sessions: {}", e)),
+            }
+        }
+
+        "list_agent_sessions" => {
+            let parent_session_id = arguments
+                .get("parent_session_id")
+                .and_then(|v| v.as_str())
+                .filter(|id| !id.is_empty());
+            let limit = arguments
+                .get("limit")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(20) as usize;
+
+            match store.list_agent_sessions(parent_session_id, limit) {
+                Ok(agents) => {
+                    let json = serde_json::to_string_pretty(&agents)
+                        .unwrap_or_else(|_| "[]".to_string());
+                    ToolResult::text(json)
+                }
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to list agent sessions: {}", e)),
+            }
+        }
+
+        "search_sessions" => {
+            let query = arguments
+                .get("query")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let limit = arguments
+                .get("limit")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(10) as usize;
+            let mode = match arguments
+                .get("search_mode")
+                .or_else(|| arguments.get("query_mode"))
+                .and_then(|v| v.as_str())
+                .map(str::parse::<SearchMode>)
+                .transpose()
+            {
+                Ok(mode) => mode.unwrap_or_default(),
+                Err(e) => return ToolResult::error(McpErrorKind::InvalidArgument, e.to_string()),
+            };
+            let highlight = arguments
+                .get("highlight")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+            let cursor = arguments.get("cursor").and_then(|v| v.as_str());
+            let (since, until) = match date_range_args(&arguments) {
+                Ok(range) => range,
+                Err(e) => return ToolResult::error(McpErrorKind::InvalidArgument, e.to_string()),
+            };
+            let options = SearchOptions {
+                highlight,
+                min_score: arguments.get("min_score").and_then(|v| v.as_i64()),
+                include_tool_inputs: arguments
+                    .get("search_tool_inputs")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false),
+                role_filter: arguments
+                    .get("role_filter")
+                    .and_then(|v| v.as_str())
+                    .map(str::to_string),
+                since,
+                until,
+            };
+
+            if query.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "Query parameter is required");
+            }
+
+            let results = match SEARCH_INDEX.get() {
+                _ if mode == SearchMode::Semantic => match semantic_search(&store, query, limit, cursor, options) {
+                    Ok(results) => Ok(results),
+                    Err(result) => return result,
+                },
+                Some(indexed) => indexed.search_sessions(query, limit, cursor, mode, options),
+                None => store.search_sessions(query, limit, cursor, mode, options),
+            };
+            match results {
+                Ok(results) => {
+                    let json = serde_json::to_string_pretty(&results)
+                        .unwrap_or_else(|_| "{}".to_string());
+                    ToolResult::text(json)
+                }
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to search sessions: {}", e)),
+            }
+        }
+
+        "suggestions_for" => {
+            let prefix = arguments
+                .get("prefix")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let limit = arguments
+                .get("limit")
+                .and_then(|v| v.as_u64())
+                .map_or(suggestions::DEFAULT_SUGGESTIONS, |v| v as usize);
+
+            let snapshot = match SESSION_CACHE.get() {
+                Some(cache) => cache.snapshot(),
+                None => Arc::new(Snapshot::load(&store)),
+            };
+            let suggestions = snapshot.term_index().suggestions_for(prefix, limit);
+            let json = serde_json::to_string_pretty(&json!({
+                "prefix": prefix,
+                "suggestions": suggestions,
+            }))
+            .unwrap_or_else(|_| "{}".to_string());
+            ToolResult::text(json)
+        }
+
+        "get_session" => {
+            let session_id = arguments
+                .get("session_id")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let max_messages = arguments
+                .get("max_messages")
+                .and_then(|v| v.as_u64())
+                .map(|v| v as usize);
+            let format = match arguments
+                .get("format")
+                .and_then(|v| v.as_str())
+                .map(str::parse::<SessionFormat>)
+                .transpose()
+            {
+                Ok(format) => format.unwrap_or_default(),
+                Err(e) => return ToolResult::error(McpErrorKind::InvalidArgument, e.to_string()),
+            };
+            let separator = arguments
+                .get("separator")
+                .and_then(|v| v.as_str())
+                .unwrap_or("---");
+            let raw = arguments.get("raw").and_then(|v| v.as_bool()).unwrap_or(false);
+            let embed_resource = arguments
+                .get("embed_resource")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+            let typed = match arguments
+                .get("content_format")
+                .and_then(|v| v.as_str())
+                .map(str::parse::<ContentFormat>)
+                .transpose()
+            {
+                Ok(content_format) => content_format == Some(ContentFormat::Typed),
+                Err(e) => return ToolResult::error(McpErrorKind::InvalidArgument, e.to_string()),
+            };
+            let summary_only = arguments
+                .get("summary_only")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+
+            if session_id.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "session_id parameter is required");
+            }
+            if let Err(e) = security::validate_session_id(session_id) {
+                return ToolResult::error(McpErrorKind::InvalidArgument, e.to_string());
+            }
+            if summary_only && (raw || typed || embed_resource) {
+                return ToolResult::error(
+                    McpErrorKind::InvalidArgument,
+                    "summary_only can't be combined with raw, content_format typed or embed_resource",
+                );
+            }
+            if raw && format != SessionFormat::Json {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "raw is only supported with the json format");
+            }
+            if typed && format != SessionFormat::Json {
+                return ToolResult::error(
+                    McpErrorKind::InvalidArgument,
+                    "content_format typed is only supported with the json format",
+                );
+            }
+
+            let session = match store.get_session_head(session_id, max_messages) {
+                Ok(Some(session)) => session,
+                Ok(None) => return ToolResult::error(McpErrorKind::NotFound, format!("Session not found: {}", session_id)),
+                Err(e) => return ToolResult::error(error_kind(&e), format!("Failed to get session: {}", e)),
+            };
+            if summary_only {
+                return ToolResult::text(sessions::generate_session_summary(&session));
+            }
+            let read_error = |e: anyhow::Error| {
+                ToolResult::from(
+                    McpError::new(error_kind(&e), format!("Failed to read session file: {}", e))
+                        .with_path(session.file_path.display().to_string()),
+                )
+            };
+
+            let result = if raw || typed {
+                let mut result = sessions::session_to_json(&session);
+                if typed {
+                    match sessions::read_typed_messages(&session.file_path, max_messages) {
+                        Ok(messages) => result["messages"] = json!(messages),
+                        Err(e) => return read_error(e),
+                    }
+                }
+                if raw {
+                    let raw_lines = match sessions::read_raw_lines(&session.file_path, RAW_LINES_MAX_BYTES) {
+                        Ok(lines) => lines,
+                        Err(e) => return read_error(e),
+                    };
+                    result["raw_lines"] = json!(raw_lines.raw_lines);
+                    result["truncated"] = json!(raw_lines.truncated);
+                }
+                ToolResult::text(serde_json::to_string_pretty(&result).unwrap_or_else(|_| "{}".to_string()))
+            } else {
+                ToolResult::text(sessions::format_session(&session, format, separator))
+            };
+
+            match session_uri(&session.file_path) {
+                Some(uri) if embed_resource => match read_session_resource(&uri, &session.file_path) {
+                    Ok(resource) => result.with_resource(resource),
+                    Err(e) => read_error(e),
+                },
+                _ => result,
+            }
+        }
+
+        "inspect_jsonl" => {
+            let file_path = arguments
+                .get("file_path")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+
+            if file_path.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "file_path parameter is required");
+            }
+            let path = match client_jsonl_path("file_path", file_path) {
+                Ok(path) => path,
+                Err(result) => return result,
+            };
+
+            match store.try_parse_jsonl_session(path) {
+                Ok(Some(session)) => ToolResult::text(sessions::format_session(&session, SessionFormat::Json, "---")),
+                Ok(None) => ToolResult::from(
+                    McpError::new(McpErrorKind::ParseError, format!("No session messages in {}", file_path))
+                        .with_path(file_path),
+                ),
+                Err(e) => ToolResult::from(
+                    McpError::new(error_kind(&e), format!("Failed to parse {}: {}", file_path, e)).with_path(file_path),
+                ),
+            }
+        }
+
+        "get_most_recent_session" => {
+            let project_filter = arguments.get("project_filter").and_then(|v| v.as_str());
+            let format = match arguments
+                .get("format")
+                .and_then(|v| v.as_str())
+                .map(str::parse::<SessionFormat>)
+                .transpose()
+            {
+                Ok(format) => format.unwrap_or_default(),
+                Err(e) => return ToolResult::error(McpErrorKind::InvalidArgument, e.to_string()),
+            };
+
+            match store.most_recent_session(project_filter) {
+                Ok(Some(session)) => ToolResult::text(sessions::format_session(&session, format, "---")),
+                Ok(None) => ToolResult::error(McpErrorKind::NotFound, "No sessions found"),
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to get session: {}", e)),
+            }
+        }
+
+        "get_session_context" => {
+            let session_id = arguments
+                .get("session_id")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let session_ids: Vec<String> = arguments
+                .get("session_ids")
+                .and_then(|v| v.as_array())
+                .map(|ids| ids.iter().filter_map(|id| id.as_str()).map(str::to_string).collect())
+                .unwrap_or_default();
+            let as_prompt = match arguments.get("format").and_then(|v| v.as_str()).unwrap_or("json") {
+                "json" => false,
+                "prompt" => true,
+                other => {
+                    return ToolResult::error(
+                        McpErrorKind::InvalidArgument,
+                        format!("Unknown format: {} (expected \"json\" or \"prompt\")", other),
+                    )
+                }
+            };
+            let depth = arguments.get("depth").and_then(|v| v.as_u64()).map(|v| v as usize);
+            let depth_mode = arguments.get("depth_mode").and_then(|v| v.as_str());
+            let depth = match sessions::ContextDepth::from_args(depth, depth_mode) {
+                Ok(depth) => depth,
+                Err(e) => return ToolResult::error(McpErrorKind::InvalidArgument, e.to_string()),
+            };
+            let include_tool_summary = arguments
+                .get("include_tool_summary")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+            let include_token_estimate = arguments
+                .get("include_token_estimate")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(true);
+            let limits = sessions::ContextLimits {
+                max_files: arguments
+                    .get("max_files")
+                    .and_then(|v| v.as_u64())
+                    .map_or(sessions::ContextLimits::DEFAULT_MAX_FILES, |v| v as usize),
+                max_terms: arguments
+                    .get("max_terms")
+                    .and_then(|v| v.as_u64())
+                    .map_or(sessions::ContextLimits::DEFAULT_MAX_TERMS, |v| v as usize),
+                initial_request_chars: arguments
+                    .get("initial_request_length")
+                    .and_then(|v| v.as_u64())
+                    .map_or(sessions::ContextLimits::DEFAULT_INITIAL_REQUEST_CHARS, |v| v as usize),
+            };
+            let streaming = arguments
+                .get("streaming")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+            let render = |context: &sessions::SessionContext| {
+                if as_prompt {
+                    ToolResult::text(sessions::context_to_prompt(context, CONTEXT_PROMPT_CHARS))
+                } else {
+                    let json = serde_json::to_string_pretty(context)
+                        .unwrap_or_else(|_| "{}".to_string());
+                    ToolResult::text(json)
+                }
+            };
+
+            if let Some(Err(e)) = session_ids
+                .iter()
+                .map(String::as_str)
+                .chain(Some(session_id).filter(|id| !id.is_empty()))
+                .map(security::validate_session_id)
+                .find(Result::is_err)
+            {
+                return ToolResult::error(McpErrorKind::InvalidArgument, e.to_string());
+            }
+
+            if !session_ids.is_empty() {
+                return match store.get_multi_session_context(&session_ids, depth, include_tool_summary, include_token_estimate, limits) {
+                    Ok(Some(context)) => render(&context),
+                    Ok(None) => ToolResult::error(McpErrorKind::NotFound, format!("Sessions not found: {}", session_ids.join(", "))),
+                    Err(e) => ToolResult::error(error_kind(&e), format!("Failed to get session context: {}", e)),
+                };
+            }
+
+            if session_id.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "session_id or session_ids parameter is required");
+            }
+
+            let session = match store.get_session(session_id) {
+                Ok(Some(session)) => session,
+                Ok(None) => return ToolResult::error(McpErrorKind::NotFound, format!("Session not found: {}", session_id)),
+                Err(e) => return ToolResult::error(error_kind(&e), format!("Failed to get session context: {}", e)),
+            };
+            // Short sessions are quick enough to answer whole
+            if streaming && !as_prompt && session.messages.len() > STREAMING_CONTEXT_MESSAGES {
+                if let Some(progress) = progress {
+                    return stream_session_context(
+                        session,
+                        depth,
+                        include_tool_summary,
+                        include_token_estimate,
+                        limits,
+                        progress,
+                    );
+                }
+            }
+            render(&sessions::session_context(&session, depth, include_tool_summary, include_token_estimate, limits))
+        }
+
+        "get_session_timeline" => {
+            let session_id = arguments
+                .get("session_id")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+
+            if session_id.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "session_id parameter is required");
+            }
+
+            match store.get_session_timeline(session_id) {
+                Ok(Some(timeline)) => {
+                    let json = serde_json::to_string_pretty(&timeline)
+                        .unwrap_or_else(|_| "{}".to_string());
+                    ToolResult::text(json)
+                }
+                Ok(None) => ToolResult::error(McpErrorKind::NotFound, format!("Session not found: {}", session_id)),
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to get session timeline: {}", e)),
+            }
+        }
+
+        "find_sessions_by_cwd" => {
+            let cwd_prefix = arguments
+                .get("cwd_prefix")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let limit = arguments
+                .get("limit")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(20)
+                .min(100) as usize;
+
+            if cwd_prefix.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "cwd_prefix parameter is required");
+            }
+
+            match store.sessions_by_cwd(cwd_prefix, limit) {
+                Ok(sessions) => {
+                    let json = serde_json::to_string_pretty(&sessions)
+                        .unwrap_or_else(|_| "[]".to_string());
+                    ToolResult::text(json)
+                }
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to find sessions: {}", e)),
+            }
+        }
+
+        "get_related_sessions" => {
+            let session_id = arguments
+                .get("session_id")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let limit = arguments
+                .get("limit")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(10) as usize;
+
+            if session_id.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "session_id parameter is required");
+            }
+
+            match store.find_related_sessions(session_id, limit) {
+                Ok(Some(related)) => {
+                    let json = serde_json::to_string_pretty(&related)
+                        .unwrap_or_else(|_| "[]".to_string());
+                    ToolResult::text(json)
+                }
+                Ok(None) => ToolResult::error(McpErrorKind::NotFound, format!("Session not found: {}", session_id)),
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to find related sessions: {}", e)),
+            }
+        }
+
+        "find_duplicate_sessions" => {
+            let threshold = arguments
+                .get("similarity_threshold")
+                .and_then(|v| v.as_f64())
+                .unwrap_or(0.9);
+            let limit = arguments
+                .get("limit")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(10) as usize;
+
+            match store.find_duplicate_sessions(threshold, limit) {
+                Ok(groups) => {
+                    let json = serde_json::to_string_pretty(&groups)
+                        .unwrap_or_else(|_| "[]".to_string());
+                    ToolResult::text(json)
+                }
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to find duplicate sessions: {}", e)),
+            }
+        }
+
+        "find_sessions_by_language" => {
+            let language = arguments
+                .get("language")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let limit = arguments
+                .get("limit")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(20) as usize;
+
+            if language.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "language parameter is required");
+            }
+            let Some(language) = sessions::canonical_language(language) else {
+                return ToolResult::error(McpErrorKind::InvalidArgument, format!("Unknown language: {}", language));
+            };
+
+            match store.find_sessions_by_language(language, limit.min(100)) {
+                Ok(sessions) => {
+                    let json = serde_json::to_string_pretty(&sessions)
+                        .unwrap_or_else(|_| "[]".to_string());
+                    ToolResult::text(json)
+                }
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to find sessions: {}", e)),
+            }
+        }
+
+        "find_sessions_without_assistant_response" => {
+            let limit = arguments
+                .get("limit")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(20) as usize;
+            let abandoned_only = arguments
+                .get("abandoned_only")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+
+            match store.find_sessions_without_assistant_response(limit.min(100), abandoned_only) {
+                Ok(sessions) => {
+                    let json = serde_json::to_string_pretty(&sessions)
+                        .unwrap_or_else(|_| "[]".to_string());
+                    ToolResult::text(json)
+                }
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to find sessions: {}", e)),
+            }
+        }
+
+        "find_sessions_by_git_repo" => {
+            let repo = arguments
+                .get("repo")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            if repo.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "repo must not be empty");
+            }
+            let branch = arguments.get("branch").and_then(|v| v.as_str());
+            let resolve_git = arguments
+                .get("resolve_git")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+            let limit = arguments
+                .get("limit")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(20) as usize;
+
+            match store.find_sessions_by_git_repo(repo, branch, resolve_git, limit.min(100)) {
+                Ok(sessions) => {
+                    let json = serde_json::to_string_pretty(&sessions)
+                        .unwrap_or_else(|_| "[]".to_string());
+                    ToolResult::text(json)
+                }
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to find sessions: {}", e)),
+            }
+        }
+
+        "compare_sessions" => {
+            let session_id_a = arguments
+                .get("session_id_a")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let session_id_b = arguments
+                .get("session_id_b")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+
+            if session_id_a.is_empty() || session_id_b.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "session_id_a and session_id_b parameters are required");
+            }
+
+            let (a, b) = match (store.get_session(session_id_a), store.get_session(session_id_b)) {
+                (Ok(Some(a)), Ok(Some(b))) => (a, b),
+                (Ok(None), _) => {
+                    return ToolResult::error(McpErrorKind::NotFound, format!("Session not found: {} (session_id_a)", session_id_a))
+                }
+                (_, Ok(None)) => {
+                    return ToolResult::error(McpErrorKind::NotFound, format!("Session not found: {} (session_id_b)", session_id_b))
+                }
+                (Err(e), _) | (_, Err(e)) => {
+                    return ToolResult::error(error_kind(&e), format!("Failed to get session: {}", e))
+                }
+            };
+
+            let diff = sessions::diff_sessions(&a, &b);
+            let json = serde_json::to_string_pretty(&diff).unwrap_or_else(|_| "{}".to_string());
+            ToolResult::text(json)
+        }
+
+        "list_projects" => {
+            let include_cwd = arguments
+                .get("include_cwd")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+
+            match store.list_projects(include_cwd) {
+                Ok(projects) => {
+                    let json = serde_json::to_string_pretty(&projects)
+                        .unwrap_or_else(|_| "[]".to_string());
+                    ToolResult::text(json)
+                }
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to list projects: {}", e)),
+            }
+        }
+
+        "get_session_messages" => {
+            let session_id = arguments
+                .get("session_id")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let role = arguments.get("role").and_then(|v| v.as_str());
+            let from_index = arguments
+                .get("from_index")
+                .and_then(|v| v.as_u64())
+                .map(|v| v as usize);
+            let to_index = arguments
+                .get("to_index")
+                .and_then(|v| v.as_u64())
+                .map(|v| v as usize);
+
+            if session_id.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "session_id parameter is required");
+            }
+            if let (Some(from), Some(to)) = (from_index, to_index) {
+                if from > to {
+                    return ToolResult::error(McpErrorKind::InvalidArgument, format!(
+                        "from_index ({}) must not be greater than to_index ({})",
+                        from, to
+                    ));
+                }
+            }
+
+            match store.get_session_messages(session_id, role, from_index, to_index) {
+                Ok(Some(slice)) => {
+                    let json = serde_json::to_string_pretty(&slice)
+                        .unwrap_or_else(|_| "{}".to_string());
+                    ToolResult::text(json)
+                }
+                Ok(None) => ToolResult::error(McpErrorKind::NotFound, format!("Session not found: {}", session_id)),
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to get session messages: {}", e)),
+            }
+        }
+
+        "watch_session" => {
+            let session_id = arguments
+                .get("session_id")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let poll_interval = arguments
+                .get("poll_interval_ms")
+                .and_then(|v| v.as_u64())
+                .map_or(Duration::from_millis(2000), Duration::from_millis)
+                .max(MIN_WATCH_POLL_INTERVAL);
+            // Answer before the call itself times out: a second before it, or
+            // halfway through it when that's later, so a short tool timeout
+            // still leaves time to poll
+            let tool_timeout = CONFIG.get().map_or(config::DEFAULT_TOOL_TIMEOUT, Config::tool_timeout);
+            let max_wait = tool_timeout.saturating_sub(Duration::from_secs(1)).max(tool_timeout / 2);
+            let timeout = arguments
+                .get("timeout_ms")
+                .and_then(|v| v.as_u64())
+                .map_or(Duration::from_millis(30_000), Duration::from_millis)
+                .min(max_wait);
+
+            if session_id.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "session_id parameter is required");
+            }
+            let session = match store.get_session(session_id) {
+                Ok(Some(session)) => session,
+                Ok(None) => return ToolResult::error(McpErrorKind::NotFound, format!("Session not found: {}", session_id)),
+                Err(e) => return ToolResult::error(error_kind(&e), format!("Failed to get session: {}", e)),
+            };
+
+            match watch_session(&store, &session, poll_interval, timeout, cancel) {
+                Ok(result) => {
+                    let json = serde_json::to_string_pretty(&result)
+                        .unwrap_or_else(|_| "{}".to_string());
+                    ToolResult::text(json)
+                }
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to watch session: {}", e)),
+            }
+        }
+
+        "get_session_diff" => {
+            let session_id = arguments
+                .get("session_id")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let Some(since_message_index) = arguments
+                .get("since_message_index")
+                .and_then(|v| v.as_u64())
+                .map(|v| v as usize)
+            else {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "since_message_index parameter is required");
+            };
+
+            if session_id.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "session_id parameter is required");
+            }
+            if let Err(e) = security::validate_session_id(session_id) {
+                return ToolResult::error(McpErrorKind::InvalidArgument, e.to_string());
+            }
+
+            match store.get_session_diff(session_id, since_message_index) {
+                Ok(Some(diff)) => {
+                    let json = serde_json::to_string_pretty(&diff)
+                        .unwrap_or_else(|_| "{}".to_string());
+                    ToolResult::text(json)
+                }
+                Ok(None) => ToolResult::error(McpErrorKind::NotFound, format!("Session not found: {}", session_id)),
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to diff session: {}", e)),
+            }
+        }
+
+        "merge_sessions" => {
+            let session_id_a = arguments
+                .get("session_id_a")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let session_id_b = arguments
+                .get("session_id_b")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let output_path = arguments
+                .get("output_path")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+
+            if session_id_a.is_empty() || session_id_b.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "session_id_a and session_id_b parameters are required");
+            }
+            if output_path.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "output_path parameter is required");
+            }
+            let path = match client_jsonl_path("output_path", output_path) {
+                Ok(path) => path,
+                Err(result) => return result,
+            };
+
+            let (a, b) = match (store.get_session(session_id_a), store.get_session(session_id_b)) {
+                (Ok(Some(a)), Ok(Some(b))) => (a, b),
+                (Ok(None), _) => {
+                    return ToolResult::error(McpErrorKind::NotFound, format!("Session not found: {} (session_id_a)", session_id_a))
+                }
+                (_, Ok(None)) => {
+                    return ToolResult::error(McpErrorKind::NotFound, format!("Session not found: {} (session_id_b)", session_id_b))
+                }
+                (Err(e), _) | (_, Err(e)) => {
+                    return ToolResult::error(error_kind(&e), format!("Failed to get session: {}", e))
+                }
+            };
+
+            let merged = sessions::merge_sessions(&a, &b);
+            if let Err(e) = sessions::write_session_jsonl(&merged, path) {
+                return ToolResult::from(
+                    McpError::new(error_kind(&e), format!("Failed to write merged session: {:#}", e)).with_path(output_path),
+                );
+            }
+
+            let result = sessions::MergeResult {
+                output_path: output_path.to_string(),
+                session_id: merged.id.clone(),
+                total_messages: merged.messages.len(),
+                duplicates_removed: a.messages.len() + b.messages.len() - merged.messages.len(),
+            };
+            let json = serde_json::to_string_pretty(&result).unwrap_or_else(|_| "{}".to_string());
+            ToolResult::text(json)
+        }
+
+        "get_session_token_estimate" => {
+            let session_id = arguments
+                .get("session_id")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let role_filter = arguments.get("role_filter").and_then(|v| v.as_str());
+
+            if session_id.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "session_id parameter is required");
+            }
+
+            match store.get_session_token_estimate(session_id, role_filter) {
+                Ok(Some(estimate)) => {
+                    let json = serde_json::to_string_pretty(&estimate)
+                        .unwrap_or_else(|_| "{}".to_string());
+                    ToolResult::text(json)
+                }
+                Ok(None) => ToolResult::error(McpErrorKind::NotFound, format!("Session not found: {}", session_id)),
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to estimate tokens: {}", e)),
+            }
+        }
+
+        "get_session_cost" => {
+            let session_id = arguments
+                .get("session_id")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+
+            if session_id.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "session_id parameter is required");
+            }
+
+            match store.get_session_cost(session_id) {
+                Ok(Some(cost)) => {
+                    let json = serde_json::to_string_pretty(&cost)
+                        .unwrap_or_else(|_| "{}".to_string());
+                    ToolResult::text(json)
+                }
+                Ok(None) => ToolResult::error(McpErrorKind::NotFound, format!("Session not found: {}", session_id)),
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to get session cost: {}", e)),
+            }
+        }
+
+        "get_session_commands" => {
+            let session_id = arguments
+                .get("session_id")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let unique = arguments
+                .get("unique")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+
+            if session_id.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "session_id parameter is required");
+            }
+
+            match store.get_session_commands(session_id, unique) {
+                Ok(Some(commands)) => {
+                    let json = serde_json::to_string_pretty(&commands)
+                        .unwrap_or_else(|_| "{}".to_string());
+                    ToolResult::text(json)
+                }
+                Ok(None) => ToolResult::error(McpErrorKind::NotFound, format!("Session not found: {}", session_id)),
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to get session commands: {}", e)),
+            }
+        }
+
+        "get_command_history" => {
+            let limit = arguments
+                .get("limit")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(50) as usize;
+            let project_filter = arguments.get("project_filter").and_then(|v| v.as_str());
+            let unique = arguments
+                .get("unique")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(true);
+
+            match store.command_history(project_filter) {
+                Ok(mut history) => {
+                    let json = if unique {
+                        let mut items = sessions::command_frequencies(&history);
+                        items.truncate(limit);
+                        serde_json::to_string_pretty(&items)
+                    } else {
+                        history.truncate(limit);
+                        serde_json::to_string_pretty(&history)
+                    };
+                    ToolResult::text(json.unwrap_or_else(|_| "[]".to_string()))
+                }
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to get command history: {}", e)),
+            }
+        }
+
+        "list_session_files" => {
+            let session_id = arguments
+                .get("session_id")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let operations: Option<Vec<String>> = arguments.get("operations").and_then(|v| v.as_array()).map(|ops| {
+                ops.iter()
+                    .filter_map(|op| op.as_str().map(str::to_string))
+                    .collect()
+            });
+            let unique = arguments
+                .get("unique")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(true);
+
+            if session_id.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "session_id parameter is required");
+            }
+
+            match store.list_session_files(session_id, operations.as_deref(), unique) {
+                Ok(Some(files)) => {
+                    let json = serde_json::to_string_pretty(&files)
+                        .unwrap_or_else(|_| "{}".to_string());
+                    ToolResult::text(json)
+                }
+                Ok(None) => ToolResult::error(McpErrorKind::NotFound, format!("Session not found: {}", session_id)),
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to list session files: {}", e)),
+            }
+        }
+
+        "extract_todos" => {
+            let session_id = arguments
+                .get("session_id")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let patterns = match todo_patterns(&arguments) {
+                Ok(patterns) => patterns,
+                Err(result) => return result,
+            };
+
+            if session_id.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "session_id parameter is required");
+            }
+
+            let patterns: Vec<&str> = patterns.iter().map(String::as_str).collect();
+            match store.extract_todos(session_id, &patterns) {
+                Ok(Some(todos)) => {
+                    let json = serde_json::to_string_pretty(&todos)
+                        .unwrap_or_else(|_| "[]".to_string());
+                    ToolResult::text(json)
+                }
+                Ok(None) => ToolResult::error(McpErrorKind::NotFound, format!("Session not found: {}", session_id)),
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to extract todos: {}", e)),
+            }
+        }
+
+        "find_todos_across_sessions" => {
+            let limit = arguments
+                .get("limit")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(50) as usize;
+            let project_filter = arguments.get("project_filter").and_then(|v| v.as_str());
+            let patterns = match todo_patterns(&arguments) {
+                Ok(patterns) => patterns,
+                Err(result) => return result,
+            };
+
+            let patterns: Vec<&str> = patterns.iter().map(String::as_str).collect();
+            match store.find_todos(project_filter, &patterns) {
+                Ok(mut todos) => {
+                    todos.truncate(limit);
+                    let json = serde_json::to_string_pretty(&todos)
+                        .unwrap_or_else(|_| "[]".to_string());
+                    ToolResult::text(json)
+                }
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to find todos: {}", e)),
+            }
+        }
+
+        "find_sessions_touching_file" => {
+            let file_path = arguments
+                .get("file_path")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let limit = arguments
+                .get("limit")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(20) as usize;
+            let mode = match arguments
+                .get("match_mode")
+                .and_then(|v| v.as_str())
+                .map(str::parse::<FileMatchMode>)
+                .transpose()
+            {
+                Ok(mode) => mode.unwrap_or_default(),
+                Err(e) => return ToolResult::error(McpErrorKind::InvalidArgument, e.to_string()),
+            };
+
+            if file_path.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "file_path parameter is required");
+            }
+
+            match store.sessions_touching_file(file_path, mode, limit) {
+                Ok(sessions) => {
+                    let json = serde_json::to_string_pretty(&sessions)
+                        .unwrap_or_else(|_| "[]".to_string());
+                    ToolResult::text(json)
+                }
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to find sessions: {}", e)),
+            }
+        }
+
+        "find_sessions_with_errors" => {
+            let limit = arguments
+                .get("limit")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(20)
+                .min(100) as usize;
+            let patterns: Vec<&str> = match arguments.get("error_patterns").and_then(|v| v.as_array()) {
+                Some(values) => values.iter().filter_map(|v| v.as_str()).collect(),
+                None => sessions::DEFAULT_ERROR_PATTERNS.to_vec(),
+            };
+
+            if patterns.is_empty() || patterns.iter().any(|p| p.is_empty()) {
+                return ToolResult::error(
+                    McpErrorKind::InvalidArgument,
+                    "error_patterns must be non-empty strings",
+                );
+            }
+
+            match store.sessions_with_errors(&patterns, limit) {
+                Ok(sessions) => {
+                    let json = serde_json::to_string_pretty(&sessions)
+                        .unwrap_or_else(|_| "[]".to_string());
+                    ToolResult::text(json)
+                }
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to find sessions: {}", e)),
+            }
+        }
 
-/// Handle an incoming JSON-RPC request
-async fn handle_request(request: JsonRpcRequest) -> JsonRpcResponse {
-    let id = request.id.clone();
+        "extract_code_blocks" => {
+            let session_id = arguments
+                .get("session_id")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let language = arguments.get("language").and_then(|v| v.as_str());
 
-    match request.method.as_str() {
-        "initialize" => {
-            let result = InitializeResult {
-                protocol_version: "2024-11-05".to_string(),
-                capabilities: ServerCapabilities {
-                    tools: ToolsCapability { list_changed: false },
-                },
-                server_info: ServerInfo {
-                    name: "claude-code-mcp".to_string(),
-                    version: env!("CARGO_PKG_VERSION").to_string(),
-                },
-            };
-            JsonRpcResponse::success(id, serde_json::to_value(result).unwrap())
+            if session_id.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "session_id parameter is required");
+            }
+
+            match store.get_session(session_id) {
+                Ok(Some(session)) => {
+                    let mut blocks = sessions::extract_code_blocks(&session);
+                    if let Some(language) = language {
+                        blocks.retain(|b| {
+                            b.language.as_deref().is_some_and(|l| l.eq_ignore_ascii_case(language))
+                        });
+                    }
+                    let json = serde_json::to_string_pretty(&blocks)
+                        .unwrap_or_else(|_| "[]".to_string());
+                    ToolResult::text(json)
+                }
+                Ok(None) => ToolResult::error(McpErrorKind::NotFound, format!("Session not found: {}", session_id)),
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to get session: {}", e)),
+            }
         }
 
-        "notifications/initialized" | "initialized" => {
-            // Notifications don't get responses - but we need to return something
-            // Use a special marker that main loop can skip
-            return JsonRpcResponse {
-                jsonrpc: "2.0".to_string(),
-                id: Value::Null,
-                result: None,
-                error: None,
+        "export_sessions_csv" => {
+            let limit = arguments
+                .get("limit")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(100)
+                .min(1000) as usize;
+            let (since, until) = match date_range_args(&arguments) {
+                Ok(range) => range,
+                Err(e) => return ToolResult::error(McpErrorKind::InvalidArgument, e.to_string()),
             };
+
+            match store.sessions_updated_between(since, until, limit) {
+                Ok(sessions) => ToolResult::text(export::sessions_to_csv(&sessions)),
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to export sessions: {}", e)),
+            }
         }
 
-        "tools/list" => {
-            let tools = get_tools();
-            JsonRpcResponse::success(id, json!({ "tools": tools }))
+        "export_session_markdown" => {
+            let session_id = arguments
+                .get("session_id")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let include_tool_calls = arguments
+                .get("include_tool_calls")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+
+            if session_id.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "session_id parameter is required");
+            }
+
+            match store.get_session(session_id) {
+                Ok(Some(session)) => ToolResult::text(sessions::session_to_markdown(&session, include_tool_calls)),
+                Ok(None) => ToolResult::error(McpErrorKind::NotFound, format!("Session not found: {}", session_id)),
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to get session: {}", e)),
+            }
         }
 
-        "tools/call" => {
-            let params = request.params.unwrap_or(json!({}));
-            let tool_name = params
-                .get("name")
+        "copy_session_to_clipboard" => {
+            let session_id = arguments
+                .get("session_id")
                 .and_then(|v| v.as_str())
                 .unwrap_or("");
-            let arguments = params.get("arguments").cloned().unwrap_or(json!({}));
+            let format = match arguments
+                .get("format")
+                .and_then(|v| v.as_str())
+                .map(str::parse::<SessionFormat>)
+                .transpose()
+            {
+                Ok(format) => format.unwrap_or(SessionFormat::Markdown),
+                Err(e) => return ToolResult::error(McpErrorKind::InvalidArgument, e.to_string()),
+            };
 
-            let result = handle_tool_call(tool_name, arguments).await;
-            JsonRpcResponse::success(id, serde_json::to_value(result).unwrap())
+            if session_id.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "session_id parameter is required");
+            }
+
+            let session = match store.get_session(session_id) {
+                Ok(Some(session)) => session,
+                Ok(None) => return ToolResult::error(McpErrorKind::NotFound, format!("Session not found: {}", session_id)),
+                Err(e) => return ToolResult::error(error_kind(&e), format!("Failed to get session: {}", e)),
+            };
+
+            let content = sessions::format_session(&session, format, "---");
+            // A missing clipboard isn't a failed call: the content is returned either way
+            let status = match copy_to_clipboard(&content) {
+                Ok(()) => json!({ "status": "copied" }),
+                Err(e) => json!({ "status": "clipboard_unavailable", "reason": e.to_string() }),
+            };
+            ToolResult {
+                content: vec![
+                    ToolContent::Text { text: status.to_string() },
+                    ToolContent::Text { text: content },
+                ],
+                is_error: None,
+            }
         }
 
-        _ => JsonRpcResponse::error(
-            id,
-            -32601,
-            format!("Method not found: {}", request.method),
-        ),
-    }
-}
+        "validate_session" => {
+            let session_id = arguments
+                .get("session_id")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
 
-/// Handle a tool call
-async fn handle_tool_call(name: &str, arguments: Value) -> ToolResult {
-    let store = match SessionStore::new() {
-        Ok(s) => s,
-        Err(e) => return ToolResult::error(format!("Failed to initialize session store: {}", e)),
-    };
+            if session_id.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "session_id parameter is required");
+            }
 
-    match name {
-        "list_sessions" => {
+            match store.find_session_file(session_id) {
+                Ok(Some(path)) => {
+                    let report = sessions::validate_jsonl(&path);
+                    let json = serde_json::to_string_pretty(&report)
+                        .unwrap_or_else(|_| "{}".to_string());
+                    ToolResult::text(json)
+                }
+                Ok(None) => ToolResult::error(McpErrorKind::NotFound, format!("Session not found: {}", session_id)),
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to find session: {}", e)),
+            }
+        }
+
+        "get_tool_usage_stats" => {
             let limit = arguments
                 .get("limit")
                 .and_then(|v| v.as_u64())
-                .unwrap_or(20) as usize;
+                .map_or(usize::MAX, |v| v as usize);
+            let session_filter = arguments.get("session_filter").and_then(|v| v.as_str());
 
-            match store.list_sessions(limit) {
-                Ok(sessions) => {
-                    let json = serde_json::to_string_pretty(&sessions)
-                        .unwrap_or_else(|_| "[]".to_string());
+            match store.tool_usage_stats(session_filter, limit) {
+                Ok(stats) => {
+                    let json = serde_json::to_string_pretty(&stats)
+                        .unwrap_or_else(|_| "{}".to_string());
                     ToolResult::text(json)
                 }
-                Err(e) => ToolResult::error(format!("Failed to list sessions: {}", e)),
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to compute tool usage: {}", e)),
             }
         }
 
-        "search_sessions" => {
-            let query = arguments
-                .get("query")
+        "get_activity_heatmap" => {
+            let project_filter = arguments.get("project_filter").and_then(|v| v.as_str());
+            let (since, until) = match date_range_args(&arguments) {
+                Ok(range) => range,
+                Err(e) => return ToolResult::error(McpErrorKind::InvalidArgument, e.to_string()),
+            };
+
+            match store.activity_heatmap(project_filter, since, until) {
+                Ok(heatmap) => {
+                    let json = serde_json::to_string_pretty(&heatmap)
+                        .unwrap_or_else(|_| "{}".to_string());
+                    ToolResult::text(json)
+                }
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to compute activity heatmap: {}", e)),
+            }
+        }
+
+        "get_project_timeline" => {
+            let project_path = arguments
+                .get("project_path")
                 .and_then(|v| v.as_str())
                 .unwrap_or("");
+            let bucket = match arguments
+                .get("bucket")
+                .and_then(|v| v.as_str())
+                .map(str::parse::<sessions::BucketSize>)
+                .transpose()
+            {
+                Ok(bucket) => bucket.unwrap_or_default(),
+                Err(e) => return ToolResult::error(McpErrorKind::InvalidArgument, e.to_string()),
+            };
+
+            if project_path.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "project_path parameter is required");
+            }
+
+            match store.project_timeline(project_path, bucket) {
+                Ok(Some(timeline)) => {
+                    let json = serde_json::to_string_pretty(&timeline)
+                        .unwrap_or_else(|_| "{}".to_string());
+                    ToolResult::text(json)
+                }
+                Ok(None) => ToolResult::error(
+                    McpErrorKind::NotFound,
+                    format!("No sessions found for project: {}", project_path),
+                ),
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to build project timeline: {}", e)),
+            }
+        }
+
+        "get_sessions_by_hour" => {
+            let project_filter = arguments.get("project_filter").and_then(|v| v.as_str());
+            let (since, until) = match date_range_args(&arguments) {
+                Ok(range) => range,
+                Err(e) => return ToolResult::error(McpErrorKind::InvalidArgument, e.to_string()),
+            };
+            // Each session is converted at the offset the zone had when it
+            // started, so sessions either side of a DST change both count right
+            let usage = match local_timezone() {
+                Some(timezone) => store.hourly_usage(project_filter, since, until, &timezone, &chrono::Local),
+                None => store.hourly_usage(project_filter, since, until, "UTC", &chrono::Utc).map(|mut usage| {
+                    usage.note = Some(
+                        "The system time zone could not be detected, so hours are in UTC; set TZ to use another zone"
+                            .to_string(),
+                    );
+                    usage
+                }),
+            };
+
+            match usage {
+                Ok(usage) => {
+                    let json = serde_json::to_string_pretty(&usage)
+                        .unwrap_or_else(|_| "{}".to_string());
+                    ToolResult::text(json)
+                }
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to count sessions by hour: {}", e)),
+            }
+        }
+
+        "get_longest_sessions" | "get_shortest_sessions" => {
+            let order = if name == "get_longest_sessions" { SortOrder::Desc } else { SortOrder::Asc };
             let limit = arguments
                 .get("limit")
                 .and_then(|v| v.as_u64())
                 .unwrap_or(10) as usize;
+            let project_filter = arguments.get("project_filter").and_then(|v| v.as_str());
+            let include_trivial = arguments
+                .get("include_trivial")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
 
-            if query.is_empty() {
-                return ToolResult::error("Query parameter is required");
-            }
-
-            match store.search_sessions(query, limit) {
+            match store.sessions_by_message_count(order, limit, project_filter, include_trivial) {
                 Ok(sessions) => {
                     let json = serde_json::to_string_pretty(&sessions)
                         .unwrap_or_else(|_| "[]".to_string());
                     ToolResult::text(json)
                 }
-                Err(e) => ToolResult::error(format!("Failed to search sessions: {}", e)),
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to rank sessions: {}", e)),
             }
         }
 
-        "get_session" => {
+        "get_sessions_statistics" => match store.compute_statistics() {
+            Ok(stats) => {
+                let json = serde_json::to_string_pretty(&stats)
+                    .unwrap_or_else(|_| "{}".to_string());
+                ToolResult::text(json)
+            }
+            Err(e) => ToolResult::error(error_kind(&e), format!("Failed to compute statistics: {}", e)),
+        },
+
+        "rebuild_index" => {
+            let started = Instant::now();
+            let mut scan = sessions::SessionScan::default();
+            let mut sessions_found = 0;
+            match SESSION_CACHE.get() {
+                // Other calls keep using the cached sessions until this is done
+                Some(cache) => {
+                    cache.rebuild(|store| {
+                        scan = store.scan_sessions();
+                        sessions_found = scan.sessions.len();
+                        Snapshot::new(std::mem::take(&mut scan.sessions), store.agent_sessions())
+                    });
+                }
+                None => {
+                    scan = store.scan_sessions();
+                    sessions_found = scan.sessions.len();
+                }
+            }
+
+            let mut errors: Vec<String> =
+                scan.failed.iter().map(|(path, e)| format!("{}: {}", path.display(), e)).collect();
+            let indexed_sessions = SEARCH_INDEX.get().and_then(|indexed| match indexed.rebuild() {
+                Ok(count) => Some(count),
+                Err(e) => {
+                    errors.push(format!("search index: {:#}", e));
+                    None
+                }
+            });
+
+            let result = RebuildResult {
+                sessions_found,
+                sessions_failed: scan.failed.len(),
+                duration_ms: started.elapsed().as_millis() as u64,
+                indexed_sessions,
+                errors,
+            };
+            let json = serde_json::to_string_pretty(&result).unwrap_or_else(|_| "{}".to_string());
+            ToolResult::text(json)
+        }
+
+        "show_config" => {
+            let path = config::config_path();
+            let result = json!({
+                "config_file": path.as_ref().map(|p| p.display().to_string()),
+                "config_file_found": path.is_some_and(|p| p.exists()),
+                "config": CONFIG.get().map(Config::masked).unwrap_or_default(),
+            });
+            let json = serde_json::to_string_pretty(&result).unwrap_or_else(|_| "{}".to_string());
+            ToolResult::text(json)
+        }
+
+        "tag_session" | "untag_session" => {
             let session_id = arguments
                 .get("session_id")
                 .and_then(|v| v.as_str())
                 .unwrap_or("");
+            let tag = arguments
+                .get("tag")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
 
             if session_id.is_empty() {
-                return ToolResult::error("session_id parameter is required");
+                return ToolResult::error(McpErrorKind::InvalidArgument, "session_id parameter is required");
+            }
+            if tag.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "tag parameter is required");
             }
 
-            match store.get_session(session_id) {
-                Ok(Some(session)) => {
-                    // Format messages for readability
-                    let formatted: Vec<_> = session
-                        .messages
-                        .iter()
-                        .map(|m| {
-                            json!({
-                                "role": m.role,
-                                "content": m.content,
-                                "timestamp": m.timestamp
-                            })
-                        })
-                        .collect();
+            // A prefix finds the session, but tags are kept under its full ID
+            let session_id = match store.get_session(session_id) {
+                Ok(Some(session)) => session.id,
+                Ok(None) => return ToolResult::error(McpErrorKind::NotFound, format!("Session not found: {}", session_id)),
+                Err(e) => return ToolResult::error(error_kind(&e), format!("Failed to get session: {}", e)),
+            };
 
-                    let result = json!({
-                        "id": session.id,
-                        "project_path": session.project_path,
-                        "messages": formatted
-                    });
+            let metadata = match MetadataStore::open_in(store.base_path()) {
+                Ok(m) => m,
+                Err(e) => return ToolResult::error(error_kind(&e), format!("Failed to open metadata store: {}", e)),
+            };
+            let result = if name == "tag_session" {
+                metadata.tag_session(&session_id, tag)
+            } else {
+                metadata.untag_session(&session_id, tag)
+            };
 
+            match result {
+                Ok(tags) => {
+                    let result = json!({ "session_id": session_id, "tags": tags });
                     ToolResult::text(
                         serde_json::to_string_pretty(&result).unwrap_or_else(|_| "{}".to_string()),
                     )
                 }
-                Ok(None) => ToolResult::error(format!("Session not found: {}", session_id)),
-                Err(e) => ToolResult::error(format!("Failed to get session: {}", e)),
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to update tags: {}", e)),
             }
         }
 
-        "get_session_context" => {
+        "list_tags" => {
+            let tags = match MetadataStore::open_existing_in(store.base_path()) {
+                Ok(Some(metadata)) => metadata.list_tags(),
+                Ok(None) => Ok(Vec::new()),
+                Err(e) => Err(e),
+            };
+
+            match tags {
+                Ok(tags) => {
+                    let json = serde_json::to_string_pretty(&tags)
+                        .unwrap_or_else(|_| "[]".to_string());
+                    ToolResult::text(json)
+                }
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to list tags: {}", e)),
+            }
+        }
+
+        "find_sessions_by_tag" => {
+            let tag = arguments
+                .get("tag")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let limit = arguments
+                .get("limit")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(20) as usize;
+
+            if tag.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "tag parameter is required");
+            }
+
+            let metadata = match MetadataStore::open_existing_in(store.base_path()) {
+                Ok(Some(m)) => m,
+                Ok(None) => return ToolResult::text("[]"),
+                Err(e) => return ToolResult::error(error_kind(&e), format!("Failed to open metadata store: {}", e)),
+            };
+
+            let sessions = metadata.sessions_with_tag(tag).and_then(|ids| {
+                let mut sessions = store.sessions_by_ids(&ids)?;
+                sessions.truncate(limit.min(100));
+                for summary in &mut sessions {
+                    summary.tags = Some(metadata.tags_for(&summary.id)?);
+                }
+                Ok(sessions)
+            });
+
+            match sessions {
+                Ok(sessions) => {
+                    let json = serde_json::to_string_pretty(&sessions)
+                        .unwrap_or_else(|_| "[]".to_string());
+                    ToolResult::text(json)
+                }
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to find sessions: {}", e)),
+            }
+        }
+
+        "add_session_note" => {
             let session_id = arguments
                 .get("session_id")
                 .and_then(|v| v.as_str())
                 .unwrap_or("");
+            let note = arguments
+                .get("note")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
 
             if session_id.is_empty() {
-                return ToolResult::error("session_id parameter is required");
+                return ToolResult::error(McpErrorKind::InvalidArgument, "session_id parameter is required");
+            }
+            if note.trim().is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "note parameter is required");
             }
+            if note.chars().count() > metadata::MAX_NOTE_CHARS {
+                return ToolResult::error(
+                    McpErrorKind::InvalidArgument,
+                    format!("note is longer than {} characters", metadata::MAX_NOTE_CHARS),
+                );
+            }
+
+            // A prefix finds the session, but notes are kept under its full ID
+            let session_id = match store.get_session(session_id) {
+                Ok(Some(session)) => session.id,
+                Ok(None) => return ToolResult::error(McpErrorKind::NotFound, format!("Session not found: {}", session_id)),
+                Err(e) => return ToolResult::error(error_kind(&e), format!("Failed to get session: {}", e)),
+            };
 
-            match store.get_session_context(session_id) {
-                Ok(Some(context)) => {
-                    let json = serde_json::to_string_pretty(&context)
+            match NotesLog::in_dir(store.base_path()).add_note(&session_id, note) {
+                Ok(note) => {
+                    let json = serde_json::to_string_pretty(&note)
                         .unwrap_or_else(|_| "{}".to_string());
                     ToolResult::text(json)
                 }
-                Ok(None) => ToolResult::error(format!("Session not found: {}", session_id)),
-                Err(e) => ToolResult::error(format!("Failed to get session context: {}", e)),
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to add note: {}", e)),
+            }
+        }
+
+        "get_session_notes" => {
+            let session_id = arguments
+                .get("session_id")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+
+            if session_id.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "session_id parameter is required");
+            }
+            // Notes are kept under the full ID, which a prefix resolves to
+            let session_id = match store.get_session(session_id) {
+                Ok(Some(session)) => session.id,
+                Ok(None) => return ToolResult::error(McpErrorKind::NotFound, format!("Session not found: {}", session_id)),
+                Err(e) => return ToolResult::error(error_kind(&e), format!("Failed to get session: {}", e)),
+            };
+
+            match NotesLog::in_dir(store.base_path()).notes_for(&session_id) {
+                Ok(notes) => {
+                    let json = serde_json::to_string_pretty(&notes)
+                        .unwrap_or_else(|_| "[]".to_string());
+                    ToolResult::text(json)
+                }
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to get notes: {}", e)),
+            }
+        }
+
+        "delete_session_note" => {
+            let session_id = arguments
+                .get("session_id")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let note_id = arguments
+                .get("note_id")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+
+            if session_id.is_empty() {
+                return ToolResult::error(McpErrorKind::InvalidArgument, "session_id parameter is required");
+            }
+            let note_id = match note_id.parse::<uuid::Uuid>() {
+                Ok(id) => id,
+                Err(e) => return ToolResult::error(McpErrorKind::InvalidArgument, format!("Invalid note_id: {}", e)),
+            };
+            // Notes are kept under the full ID, which a prefix resolves to
+            let session_id = match store.get_session(session_id) {
+                Ok(Some(session)) => session.id,
+                Ok(None) => return ToolResult::error(McpErrorKind::NotFound, format!("Session not found: {}", session_id)),
+                Err(e) => return ToolResult::error(error_kind(&e), format!("Failed to get session: {}", e)),
+            };
+
+            match NotesLog::in_dir(store.base_path()).delete_note(&session_id, note_id) {
+                Ok(true) => {
+                    let result = json!({ "session_id": session_id, "deleted": note_id });
+                    ToolResult::text(
+                        serde_json::to_string_pretty(&result).unwrap_or_else(|_| "{}".to_string()),
+                    )
+                }
+                Ok(false) => ToolResult::error(
+                    McpErrorKind::NotFound,
+                    format!("Note {} not found on session {}", note_id, session_id),
+                ),
+                Err(e) => ToolResult::error(error_kind(&e), format!("Failed to delete note: {}", e)),
             }
         }
 
-        _ => ToolResult::error(format!("Unknown tool: {}", name)),
+        _ => ToolResult::error(McpErrorKind::InvalidArgument, format!("Unknown tool: {}", name)),
     }
 }
 
+/// Command-line arguments
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Port to listen on with the http transport
+    #[arg(long, default_value_t = 3000)]
+    port: u16,
+
+    /// Address to bind with the http transport
+    #[arg(long, default_value = "127.0.0.1")]
+    host: String,
+
+    /// Keep a full-text search index here to speed up search_sessions
+    #[arg(long)]
+    index_path: Option<PathBuf>,
+
+    /// Settings that can also come from the config file or environment
+    #[command(flatten)]
+    config: ConfigArgs,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Commands run instead of serving MCP
+#[derive(Debug, clap::Subcommand)]
+enum Command {
+    /// Embed every session through the embedding endpoint and save the
+    /// vectors to ~/.claude-code-mcp/embeddings.cache for semantic search
+    BuildEmbeddings,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    let stdin = tokio::io::stdin();
-    let mut stdout = tokio::io::stdout();
-    let reader = BufReader::new(stdin);
-    let mut lines = reader.lines();
+    let cli = Cli::parse();
+    let config = Config::load(&cli.config)?;
+    config::set_log_level(config.log_level);
+    let config = CONFIG.get_or_init(|| config);
+    if config::log_enabled(LogLevel::Info) {
+        if let Some(path) = config::config_path().filter(|path| path.exists()) {
+            eprintln!("info: loaded config from {}", path.display());
+        }
+    }
+    let known_tools: HashSet<String> = all_tools().into_iter().map(|t| t.name).collect();
+    for tool in config.disabled_tools.iter().filter(|tool| !known_tools.contains(*tool)) {
+        warning!("disabled_tools: there is no tool named {}", tool);
+    }
+    if let Some(Command::BuildEmbeddings) = cli.command {
+        // The embedding client blocks, which it must not do on the runtime
+        return tokio::task::spawn_blocking(build_embeddings).await?;
+    }
 
-    // MCP servers communicate via JSON-RPC over stdio
-    while let Ok(Some(line)) = lines.next_line().await {
-        if line.trim().is_empty() {
-            continue;
+    // Compared now, but only saved on clean shutdown, so a run that crashes
+    // doesn't keep the next one from telling clients
+    let (notifications_tx, notifications) = tokio::sync::mpsc::channel(16);
+    let manifest_path = manifest::manifest_path();
+    let tool_manifest = ToolManifest::current(get_tools(config).into_iter().map(|t| t.name));
+    let last_manifest = manifest_path.as_deref().and_then(|path| match ToolManifest::load(path) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            warning!("ignoring last tool manifest: {:#}", e);
+            None
         }
+    });
 
-        match serde_json::from_str::<JsonRpcRequest>(&line) {
-            Ok(request) => {
-                // Check if this is a notification (no id means notification)
-                let is_notification = request.id.is_null() ||
-                    request.method.starts_with("notifications/");
+    let state = ServerState {
+        tools_changed: last_manifest.is_some_and(|last| manifest::compare_tool_manifests(&last, &tool_manifest)),
+        notifications: Some(notifications_tx.clone()),
+        tool_progress: config.transport == Transport::Stdio,
+        rate_limiter: (config.rate_limit_per_sec > 0).then(|| Arc::new(RateLimiter::new(config.rate_limit_per_sec))),
+        concurrency: (config.max_concurrent_requests > 0)
+            .then(|| Arc::new(ConcurrencyLimiter::new(config.max_concurrent_requests))),
+        tool_timeout: config.tool_timeout(),
+        logger: config.log_file.clone().map(|path| Arc::new(RequestLogger::new(path))),
+        ..ServerState::default()
+    };
 
-                let response = handle_request(request).await;
+    // Notify clients when sessions appear or change; the server works fine without it
+    let (invalidate_tx, invalidate_rx) = tokio::sync::watch::channel(());
+    if let Ok(store) = session_store() {
+        // Nothing else writes the notes log yet, so this is the time to shrink it
+        let notes = NotesLog::in_dir(store.base_path());
+        if let Err(e) = notes.compact_if_larger_than(metadata::NOTES_COMPACT_THRESHOLD) {
+            warning!("failed to compact notes log: {:#}", e);
+        }
 
-                // Don't send response for notifications
-                if is_notification {
-                    continue;
-                }
+        // Without the watcher the cache is never fresh, and re-reads every time
+        if let Err(e) = watcher::spawn(&store.base_path().join("projects"), state.clone(), notifications_tx, invalidate_tx) {
+            warning!("session watcher disabled: {:#}", e);
+        }
+        SESSION_CACHE.get_or_init(|| CachedSessionStore::new(store, invalidate_rx, config.max_sessions_in_cache));
+    }
 
-                // Skip empty responses (for notifications that slipped through)
-                if response.result.is_none() && response.error.is_none() {
-                    continue;
-                }
+    if let Some(index_path) = &cli.index_path {
+        match session_store().and_then(|store| IndexedSessionStore::open(store, index_path)) {
+            Ok(indexed) => {
+                let indexed = SEARCH_INDEX.get_or_init(|| indexed);
+                // Catch up with sessions written while the server wasn't running
+                tokio::task::spawn_blocking(move || {
+                    if let Err(e) = indexed.refresh() {
+                        warning!("failed to update search index: {:#}", e);
+                    }
+                });
+            }
+            Err(e) => warning!("search index disabled: {:#}", e),
+        }
+    }
+
+    match config.transport {
+        Transport::Stdio => transport::run_stdio(state, notifications, shutdown_signal()).await?,
+        Transport::Http => transport::run_http(state, &cli.host, cli.port, notifications, shutdown_signal()).await?,
+    }
+
+    if let Some(path) = &manifest_path {
+        if let Err(e) = tool_manifest.save(path) {
+            warning!("failed to save tool manifest: {:#}", e);
+        }
+    }
+    // A read from stdin may still be pending on a blocking thread that the
+    // runtime would wait for on the way out
+    std::process::exit(0)
+}
+
+/// Resolves on the first SIGINT (Ctrl-C) or SIGTERM
+async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warning!("failed to listen for SIGINT: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
 
-                let response_json = serde_json::to_string(&response)?;
-                stdout.write_all(response_json.as_bytes()).await?;
-                stdout.write_all(b"\n").await?;
-                stdout.flush().await?;
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
             }
             Err(e) => {
-                let error = JsonRpcResponse::error(Value::Null, -32700, format!("Parse error: {}", e));
-                let error_json = serde_json::to_string(&error)?;
-                stdout.write_all(error_json.as_bytes()).await?;
-                stdout.write_all(b"\n").await?;
-                stdout.flush().await?;
+                warning!("failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
             }
         }
-    }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
 
-    Ok(())
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
 }
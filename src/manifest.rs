@@ -0,0 +1,104 @@
+//! Record of the tools the server offered when it last ran
+//!
+//! Clients may keep the result of `tools/list` between runs. On startup the
+//! server compares its tools with the manifest the previous run left in
+//! `~/.claude-code-mcp/last_tool_manifest.json`, and if they differ, as after
+//! an upgrade, it advertises `tools.listChanged` and sends
+//! `notifications/tools/list_changed` once the client is initialized. The
+//! manifest is written again on clean shutdown.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+/// The server version and the names of the tools it offers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolManifest {
+    pub version: String,
+    pub tool_names: Vec<String>,
+}
+
+impl ToolManifest {
+    /// Manifest of this build, which offers `tool_names`
+    pub fn current(tool_names: impl IntoIterator<Item = String>) -> Self {
+        let mut tool_names: Vec<String> = tool_names.into_iter().collect();
+        tool_names.sort();
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            tool_names,
+        }
+    }
+
+    /// The manifest saved at `path`, or `None` if there isn't one
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        let manifest = serde_json::from_str(&contents).with_context(|| format!("Invalid manifest {}", path.display()))?;
+        Ok(Some(manifest))
+    }
+
+    /// Write the manifest to `path`, creating its directory if needed
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let contents = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// Where the manifest of the last run is kept
+pub fn manifest_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".claude-code-mcp").join("last_tool_manifest.json"))
+}
+
+/// Whether a client holding the tool list of `old` has to fetch it again for
+/// `new`. Only the tool names count; a new version offering the same tools
+/// doesn't change the list.
+pub fn compare_tool_manifests(old: &ToolManifest, new: &ToolManifest) -> bool {
+    let old: BTreeSet<&String> = old.tool_names.iter().collect();
+    let new: BTreeSet<&String> = new.tool_names.iter().collect();
+    old != new
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(version: &str, tool_names: &[&str]) -> ToolManifest {
+        ToolManifest {
+            version: version.to_string(),
+            tool_names: tool_names.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_compare_tool_manifests() {
+        let old = manifest("0.1.0", &["get_session", "list_sessions"]);
+
+        assert!(!compare_tool_manifests(&old, &manifest("0.1.0", &["get_session", "list_sessions"])));
+        // Order and version alone don't matter
+        assert!(!compare_tool_manifests(&old, &manifest("0.2.0", &["list_sessions", "get_session"])));
+        assert!(compare_tool_manifests(&old, &manifest("0.2.0", &["get_session", "list_sessions", "ping_session"])));
+        assert!(compare_tool_manifests(&old, &manifest("0.2.0", &["list_sessions"])));
+    }
+
+    #[test]
+    fn test_manifest_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("last_tool_manifest.json");
+        assert_eq!(ToolManifest::load(&path).unwrap(), None);
+
+        let current = ToolManifest::current(["search_sessions".to_string(), "get_session".to_string()]);
+        assert_eq!(current.tool_names, vec!["get_session", "search_sessions"]);
+        current.save(&path).unwrap();
+        assert_eq!(ToolManifest::load(&path).unwrap(), Some(current));
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(ToolManifest::load(&path).is_err());
+    }
+}
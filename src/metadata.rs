@@ -0,0 +1,419 @@
+//! Persistent session metadata (tags and notes) stored alongside Claude Code's
+//! own data
+//!
+//! Tags are kept in a sled database at ~/.claude/mcp-metadata.sled. Keys are
+//! `tags/<session-id>` and values are JSON arrays of tag strings.
+//!
+//! Notes are kept in an append-only log at ~/.claude/mcp-notes.jsonl, one
+//! JSON entry per line recording a note being added or deleted. A crash can at
+//! worst cut off the last line, which is skipped when the log is read.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Name of the metadata database inside the Claude Code directory
+const METADATA_DB: &str = "mcp-metadata.sled";
+
+/// How often, and how long apart, to retry opening a locked database
+const LOCK_RETRIES: u32 = 10;
+const LOCK_RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// Key prefix for per-session tag lists
+const TAGS_PREFIX: &str = "tags/";
+
+/// Name of the notes log inside the Claude Code directory
+const NOTES_LOG: &str = "mcp-notes.jsonl";
+
+/// Longest note accepted, in characters
+pub const MAX_NOTE_CHARS: usize = 2000;
+
+/// Size past which the notes log is rewritten at startup without the entries
+/// of deleted notes
+pub const NOTES_COMPACT_THRESHOLD: u64 = 1024 * 1024;
+
+/// Tags and other annotations for sessions that survive server restarts
+pub struct MetadataStore {
+    db: sled::Db,
+}
+
+impl MetadataStore {
+    /// Open (or create) the metadata database inside `claude_dir`
+    pub fn open_in(claude_dir: &Path) -> Result<Self> {
+        Self::open(&Self::path_in(claude_dir))
+    }
+
+    /// Open the metadata database inside `claude_dir` only if it already exists
+    pub fn open_existing_in(claude_dir: &Path) -> Result<Option<Self>> {
+        let path = Self::path_in(claude_dir);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Self::open(&path).map(Some)
+    }
+
+    /// Open (or create) a metadata database at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        // A handle dropped moments ago (e.g. by the previous tool call) can still
+        // hold the lock while sled's background thread shuts down
+        let mut attempt = 0;
+        let result = loop {
+            match sled::open(path) {
+                Err(sled::Error::Io(e)) if is_lock_error(&e) && attempt < LOCK_RETRIES => {
+                    attempt += 1;
+                    std::thread::sleep(LOCK_RETRY_DELAY);
+                }
+                result => break result,
+            }
+        };
+
+        match result {
+            Ok(db) => Ok(Self { db }),
+            Err(sled::Error::Io(e)) if is_lock_error(&e) => {
+                anyhow::bail!(
+                    "Metadata database at {} is locked by another process. \
+                     Is another claude-code-mcp server running?",
+                    path.display()
+                )
+            }
+            Err(e) => Err(e).with_context(|| {
+                format!("Failed to open metadata database at {}", path.display())
+            }),
+        }
+    }
+
+    fn path_in(claude_dir: &Path) -> PathBuf {
+        claude_dir.join(METADATA_DB)
+    }
+
+    /// Tags attached to a session, sorted
+    pub fn tags_for(&self, session_id: &str) -> Result<Vec<String>> {
+        let key = format!("{}{}", TAGS_PREFIX, session_id);
+        match self.db.get(key)? {
+            Some(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Add a tag to a session, returning the session's tags afterwards
+    pub fn tag_session(&self, session_id: &str, tag: &str) -> Result<Vec<String>> {
+        let mut tags: BTreeSet<String> = self.tags_for(session_id)?.into_iter().collect();
+        tags.insert(tag.to_string());
+        self.set_tags(session_id, tags.into_iter().collect())
+    }
+
+    /// Remove a tag from a session, returning the session's tags afterwards
+    pub fn untag_session(&self, session_id: &str, tag: &str) -> Result<Vec<String>> {
+        let mut tags = self.tags_for(session_id)?;
+        tags.retain(|t| t != tag);
+        self.set_tags(session_id, tags)
+    }
+
+    fn set_tags(&self, session_id: &str, tags: Vec<String>) -> Result<Vec<String>> {
+        let key = format!("{}{}", TAGS_PREFIX, session_id);
+        if tags.is_empty() {
+            self.db.remove(key)?;
+        } else {
+            self.db.insert(key, serde_json::to_vec(&tags)?)?;
+        }
+        self.db.flush()?;
+        Ok(tags)
+    }
+
+    /// All distinct tags across sessions, sorted
+    pub fn list_tags(&self) -> Result<Vec<String>> {
+        let mut all = BTreeSet::new();
+        for entry in self.db.scan_prefix(TAGS_PREFIX) {
+            let (_, value) = entry?;
+            let tags: Vec<String> = serde_json::from_slice(&value)?;
+            all.extend(tags);
+        }
+        Ok(all.into_iter().collect())
+    }
+
+    /// IDs of sessions carrying `tag`
+    pub fn sessions_with_tag(&self, tag: &str) -> Result<Vec<String>> {
+        let mut ids = Vec::new();
+        for entry in self.db.scan_prefix(TAGS_PREFIX) {
+            let (key, value) = entry?;
+            let tags: Vec<String> = serde_json::from_slice(&value)?;
+            if tags.iter().any(|t| t == tag) {
+                let key = String::from_utf8_lossy(&key);
+                ids.push(key[TAGS_PREFIX.len()..].to_string());
+            }
+        }
+        Ok(ids)
+    }
+}
+
+/// A plain-text note on a session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Note {
+    pub id: Uuid,
+    pub text: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// One line of the notes log
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum NoteLogEntry {
+    Add { session_id: String, note: Note },
+    Delete { session_id: String, note_id: Uuid },
+}
+
+/// Notes on sessions, in an append-only log that is replayed on every read
+pub struct NotesLog {
+    path: PathBuf,
+}
+
+impl NotesLog {
+    /// The notes log inside `claude_dir`, created on the first write
+    pub fn in_dir(claude_dir: &Path) -> Self {
+        Self::at(claude_dir.join(NOTES_LOG))
+    }
+
+    /// The notes log at `path`
+    pub fn at(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Notes on a session, oldest first
+    pub fn notes_for(&self, session_id: &str) -> Result<Vec<Note>> {
+        Ok(self
+            .replay()?
+            .into_iter()
+            .filter(|(id, _)| id == session_id)
+            .map(|(_, note)| note)
+            .collect())
+    }
+
+    /// Add a note to a session
+    pub fn add_note(&self, session_id: &str, text: &str) -> Result<Note> {
+        let note = Note {
+            id: Uuid::new_v4(),
+            text: text.to_string(),
+            created_at: Utc::now(),
+        };
+        self.append(&NoteLogEntry::Add {
+            session_id: session_id.to_string(),
+            note: note.clone(),
+        })?;
+        Ok(note)
+    }
+
+    /// Delete a note from a session, returning whether it existed
+    pub fn delete_note(&self, session_id: &str, note_id: Uuid) -> Result<bool> {
+        let exists = self
+            .replay()?
+            .iter()
+            .any(|(id, note)| id == session_id && note.id == note_id);
+        if exists {
+            self.append(&NoteLogEntry::Delete {
+                session_id: session_id.to_string(),
+                note_id,
+            })?;
+        }
+        Ok(exists)
+    }
+
+    /// Rewrite the log with only the notes that still exist if it has grown
+    /// past `max_bytes`, returning whether it was rewritten. Only safe while
+    /// nothing else is writing to the log.
+    pub fn compact_if_larger_than(&self, max_bytes: u64) -> Result<bool> {
+        match std::fs::metadata(&self.path) {
+            Ok(metadata) if metadata.len() > max_bytes => {}
+            Ok(_) => return Ok(false),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", self.path.display())),
+        }
+
+        let mut compacted = String::new();
+        for (session_id, note) in self.replay()? {
+            compacted.push_str(&serde_json::to_string(&NoteLogEntry::Add { session_id, note })?);
+            compacted.push('\n');
+        }
+
+        // Swap the new log in whole, so a crash leaves one log or the other
+        let tmp = self.path.with_extension("jsonl.tmp");
+        let mut file = std::fs::File::create(&tmp)
+            .with_context(|| format!("Failed to create {}", tmp.display()))?;
+        file.write_all(compacted.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to replace {}", self.path.display()))?;
+        Ok(true)
+    }
+
+    /// Notes that haven't been deleted, with their session IDs, in the order
+    /// they were added
+    fn replay(&self) -> Result<Vec<(String, Note)>> {
+        let content = match std::fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", self.path.display())),
+        };
+
+        let mut notes: Vec<(String, Note)> = Vec::new();
+        // Lines that don't parse were cut off by a crash mid-write
+        for entry in content.lines().filter_map(|line| serde_json::from_str(line).ok()) {
+            match entry {
+                NoteLogEntry::Add { session_id, note } => notes.push((session_id, note)),
+                NoteLogEntry::Delete { session_id, note_id } => {
+                    notes.retain(|(id, note)| !(*id == session_id && note.id == note_id))
+                }
+            }
+        }
+        Ok(notes)
+    }
+
+    fn append(&self, entry: &NoteLogEntry) -> Result<()> {
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+
+        // Start on a fresh line if a crash cut the last entry short
+        let mut line = String::new();
+        if file.seek(SeekFrom::End(0))? > 0 {
+            let mut last = [0u8; 1];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                line.push('\n');
+            }
+        }
+        line.push_str(&serde_json::to_string(entry)?);
+        line.push('\n');
+
+        file.write_all(line.as_bytes())?;
+        file.sync_data()?;
+        Ok(())
+    }
+}
+
+/// Whether an error from `sled::open` means another process holds the database lock.
+/// sled wraps the `WouldBlock` from the file lock in an error of its own.
+fn is_lock_error(e: &std::io::Error) -> bool {
+    e.kind() == std::io::ErrorKind::WouldBlock
+        || e.get_ref()
+            .and_then(|inner| inner.downcast_ref::<std::io::Error>())
+            .is_some_and(|inner| inner.kind() == std::io::ErrorKind::WouldBlock)
+        || e.to_string().contains("could not acquire lock")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tag_and_untag() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = MetadataStore::open_in(dir.path()).unwrap();
+
+        store.tag_session("s1", "auth").unwrap();
+        store.tag_session("s1", "bugfix").unwrap();
+        store.tag_session("s1", "auth").unwrap();
+        store.tag_session("s2", "auth").unwrap();
+
+        assert_eq!(store.tags_for("s1").unwrap(), vec!["auth", "bugfix"]);
+        assert_eq!(store.list_tags().unwrap(), vec!["auth", "bugfix"]);
+
+        let mut tagged = store.sessions_with_tag("auth").unwrap();
+        tagged.sort();
+        assert_eq!(tagged, vec!["s1", "s2"]);
+
+        store.untag_session("s1", "bugfix").unwrap();
+        assert_eq!(store.tags_for("s1").unwrap(), vec!["auth"]);
+        assert_eq!(store.list_tags().unwrap(), vec!["auth"]);
+    }
+
+    #[test]
+    fn test_tags_persist_across_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let store = MetadataStore::open_in(dir.path()).unwrap();
+            store.tag_session("s1", "keep").unwrap();
+        }
+        let store = MetadataStore::open_existing_in(dir.path()).unwrap().unwrap();
+        assert_eq!(store.tags_for("s1").unwrap(), vec!["keep"]);
+    }
+
+    #[test]
+    fn test_locked_database_reports_clear_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let _held = MetadataStore::open_in(dir.path()).unwrap();
+
+        let err = MetadataStore::open_in(dir.path()).err().unwrap();
+        assert!(err.to_string().contains("locked by another process"));
+    }
+
+    #[test]
+    fn test_add_get_and_delete_notes() {
+        let dir = tempfile::tempdir().unwrap();
+        let notes = NotesLog::in_dir(dir.path());
+        assert!(notes.notes_for("s1").unwrap().is_empty());
+
+        let first = notes.add_note("s1", "fixed the auth bug here").unwrap();
+        let second = notes.add_note("s1", "token refresh still flaky").unwrap();
+        notes.add_note("s2", "unrelated").unwrap();
+
+        let texts: Vec<String> = notes.notes_for("s1").unwrap().into_iter().map(|n| n.text).collect();
+        assert_eq!(texts, vec!["fixed the auth bug here", "token refresh still flaky"]);
+
+        assert!(notes.delete_note("s1", first.id).unwrap());
+        assert!(!notes.delete_note("s1", first.id).unwrap());
+        // A note is only deleted from its own session
+        assert!(!notes.delete_note("s2", second.id).unwrap());
+
+        let reopened = NotesLog::in_dir(dir.path());
+        assert_eq!(reopened.notes_for("s1").unwrap(), vec![second]);
+        assert_eq!(reopened.notes_for("s2").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_notes_skip_truncated_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let notes = NotesLog::in_dir(dir.path());
+        notes.add_note("s1", "kept").unwrap();
+
+        // Simulate a crash partway through writing an entry
+        let path = dir.path().join(NOTES_LOG);
+        let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"op":"add","session_id":"s1","no"#).unwrap();
+
+        notes.add_note("s1", "after the crash").unwrap();
+        let texts: Vec<String> = notes.notes_for("s1").unwrap().into_iter().map(|n| n.text).collect();
+        assert_eq!(texts, vec!["kept", "after the crash"]);
+    }
+
+    #[test]
+    fn test_notes_compaction() {
+        let dir = tempfile::tempdir().unwrap();
+        let notes = NotesLog::in_dir(dir.path());
+        let path = dir.path().join(NOTES_LOG);
+        assert!(!notes.compact_if_larger_than(0).unwrap());
+
+        let kept = notes.add_note("s1", "keep me").unwrap();
+        for _ in 0..20 {
+            let note = notes.add_note("s1", &"x".repeat(100)).unwrap();
+            notes.delete_note("s1", note.id).unwrap();
+        }
+        let size = std::fs::metadata(&path).unwrap().len();
+
+        // Below the threshold the log is left alone
+        assert!(!notes.compact_if_larger_than(size).unwrap());
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), size);
+
+        assert!(notes.compact_if_larger_than(size - 1).unwrap());
+        assert!(std::fs::metadata(&path).unwrap().len() < size);
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 1);
+        assert_eq!(notes.notes_for("s1").unwrap(), vec![kept]);
+    }
+}
@@ -0,0 +1,203 @@
+//! Request and tool call metrics, served as Prometheus text from `GET /metrics`
+//! by the HTTP transport
+//!
+//! Label values are fixed when the collector is created, so counting is a
+//! lookup and an atomic add. Methods and tools outside those sets are counted
+//! as `other` and `unknown`.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// JSON-RPC methods counted under their own name
+const METHODS: &[&str] = &[
+    "initialize",
+    "initialized",
+    "notifications/initialized",
+    "notifications/cancelled",
+    "tools/list",
+    "tools/call",
+    "resources/list",
+    "resources/read",
+    "resources/subscribe",
+    "resources/unsubscribe",
+    "prompts/list",
+    "prompts/get",
+];
+
+/// Label for methods not in `METHODS`
+const OTHER_METHOD: &str = "other";
+
+/// Label for tool names the server doesn't offer
+const UNKNOWN_TOOL: &str = "unknown";
+
+/// Upper bounds (seconds) of the tool duration histogram buckets
+const DURATION_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// Whether a request or tool call succeeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Status {
+    Ok,
+    Error,
+}
+
+impl Status {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Error => "error",
+        }
+    }
+}
+
+/// Counts per status for one label value
+#[derive(Default)]
+struct StatusCounters {
+    ok: AtomicU64,
+    error: AtomicU64,
+}
+
+impl StatusCounters {
+    fn increment(&self, status: Status) {
+        let counter = match status {
+            Status::Ok => &self.ok,
+            Status::Error => &self.error,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn get(&self, status: Status) -> u64 {
+        match status {
+            Status::Ok => self.ok.load(Ordering::Relaxed),
+            Status::Error => self.error.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Tool durations: observations per bucket of `DURATION_BUCKETS` (not
+/// cumulative), then the sum of all observations
+type Histogram = Mutex<Vec<f64>>;
+
+pub struct MetricsCollector {
+    requests: HashMap<&'static str, StatusCounters>,
+    tool_calls: HashMap<String, StatusCounters>,
+    tool_durations: HashMap<String, Histogram>,
+    sessions_cached: AtomicU64,
+}
+
+impl MetricsCollector {
+    /// A collector with a series for each of `tools`
+    pub fn new<'a>(tools: impl IntoIterator<Item = &'a str>) -> Self {
+        let tools: Vec<&str> = tools.into_iter().chain([UNKNOWN_TOOL]).collect();
+        Self {
+            requests: METHODS
+                .iter()
+                .chain(&[OTHER_METHOD])
+                .map(|&method| (method, StatusCounters::default()))
+                .collect(),
+            tool_calls: tools.iter().map(|&t| (t.to_string(), StatusCounters::default())).collect(),
+            tool_durations: tools
+                .iter()
+                .map(|&t| (t.to_string(), Mutex::new(vec![0.0; DURATION_BUCKETS.len() + 2])))
+                .collect(),
+            sessions_cached: AtomicU64::new(0),
+        }
+    }
+
+    /// Count a handled JSON-RPC request
+    pub fn record_request(&self, method: &str, status: Status) {
+        let counters = self.requests.get(method).or_else(|| self.requests.get(OTHER_METHOD));
+        if let Some(counters) = counters {
+            counters.increment(status);
+        }
+    }
+
+    /// Count a tool call and how long it took
+    pub fn record_tool_call(&self, tool: &str, status: Status, duration: Duration) {
+        let tool = if self.tool_calls.contains_key(tool) { tool } else { UNKNOWN_TOOL };
+        self.tool_calls[tool].increment(status);
+
+        let seconds = duration.as_secs_f64();
+        let mut histogram = self.tool_durations[tool].lock().unwrap_or_else(|e| e.into_inner());
+        let bucket = DURATION_BUCKETS
+            .iter()
+            .position(|&bound| seconds <= bound)
+            .unwrap_or(DURATION_BUCKETS.len());
+        histogram[bucket] += 1.0;
+        histogram[DURATION_BUCKETS.len() + 1] += seconds;
+    }
+
+    /// Set the number of sessions in the search index
+    pub fn set_sessions_cached(&self, count: u64) {
+        self.sessions_cached.store(count, Ordering::Relaxed);
+    }
+
+    /// All metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let statuses = [Status::Ok, Status::Error];
+
+        out.push_str("# HELP mcp_requests_total JSON-RPC requests handled, by method and status.\n");
+        out.push_str("# TYPE mcp_requests_total counter\n");
+        for method in METHODS.iter().chain(&[OTHER_METHOD]) {
+            for status in statuses {
+                let _ = writeln!(
+                    out,
+                    "mcp_requests_total{{method=\"{}\",status=\"{}\"}} {}",
+                    method,
+                    status.label(),
+                    self.requests[method].get(status)
+                );
+            }
+        }
+
+        let mut tools: Vec<&String> = self.tool_calls.keys().collect();
+        tools.sort();
+
+        out.push_str("# HELP mcp_tool_calls_total Tool calls, by tool and status.\n");
+        out.push_str("# TYPE mcp_tool_calls_total counter\n");
+        for &tool in &tools {
+            for status in statuses {
+                let _ = writeln!(
+                    out,
+                    "mcp_tool_calls_total{{tool=\"{}\",status=\"{}\"}} {}",
+                    tool,
+                    status.label(),
+                    self.tool_calls[tool].get(status)
+                );
+            }
+        }
+
+        out.push_str("# HELP mcp_tool_duration_seconds How long tool calls took, by tool.\n");
+        out.push_str("# TYPE mcp_tool_duration_seconds histogram\n");
+        for &tool in &tools {
+            let histogram = self.tool_durations[tool].lock().unwrap_or_else(|e| e.into_inner());
+            let mut cumulative = 0.0;
+            for (bound, count) in DURATION_BUCKETS.iter().zip(histogram.iter()) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "mcp_tool_duration_seconds_bucket{{tool=\"{}\",le=\"{}\"}} {}",
+                    tool, bound, cumulative
+                );
+            }
+            cumulative += histogram[DURATION_BUCKETS.len()];
+            let _ = writeln!(out, "mcp_tool_duration_seconds_bucket{{tool=\"{}\",le=\"+Inf\"}} {}", tool, cumulative);
+            let _ = writeln!(
+                out,
+                "mcp_tool_duration_seconds_sum{{tool=\"{}\"}} {}",
+                tool,
+                histogram[DURATION_BUCKETS.len() + 1]
+            );
+            let _ = writeln!(out, "mcp_tool_duration_seconds_count{{tool=\"{}\"}} {}", tool, cumulative);
+        }
+
+        out.push_str("# HELP mcp_sessions_cached Sessions held in the search index.\n");
+        out.push_str("# TYPE mcp_sessions_cached gauge\n");
+        let _ = writeln!(out, "mcp_sessions_cached {}", self.sessions_cached.load(Ordering::Relaxed));
+
+        out
+    }
+}
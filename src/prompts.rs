@@ -0,0 +1,172 @@
+//! Built-in prompt templates served through `prompts/list` and `prompts/get`
+//!
+//! Each template pulls real session data from the `SessionStore` into its
+//! messages, so the client gets a ready-to-send prompt rather than a recipe.
+
+use anyhow::Result;
+use serde_json::Value;
+use std::fmt::Write;
+
+use crate::protocol::{McpPrompt, McpPromptArgument, McpPromptResult, PromptMessage};
+use crate::sessions::{
+    ContextDepth, ContextLimits, FileMatchMode, ListOptions, SearchMode, SearchOptions, SessionStore, SessionSummary,
+};
+
+/// Sessions summarized by `recent-work-summary` when `count` is not given
+const DEFAULT_RECENT_COUNT: usize = 5;
+
+/// Most sessions any template pulls into a prompt
+const MAX_PROMPT_SESSIONS: usize = 20;
+
+/// Define available prompts
+pub fn get_prompts() -> Vec<McpPrompt> {
+    vec![
+        McpPrompt {
+            name: "recent-work-summary".to_string(),
+            description: Some("Summarize the work done in the most recent Claude Code sessions".to_string()),
+            arguments: vec![argument(
+                "count",
+                "Number of recent sessions to summarize (default: 5, max: 20)",
+                false,
+            )],
+        },
+        McpPrompt {
+            name: "find-related-sessions".to_string(),
+            description: Some("Find past sessions related to a topic and explain how they connect".to_string()),
+            arguments: vec![argument("topic", "Topic to look for in past sessions", true)],
+        },
+        McpPrompt {
+            name: "code-archaeology".to_string(),
+            description: Some("Trace how a file evolved across the sessions that touched it".to_string()),
+            arguments: vec![argument(
+                "file_path",
+                "File to trace, matched as a path suffix (e.g. \"src/auth.rs\")",
+                true,
+            )],
+        },
+    ]
+}
+
+fn argument(name: &str, description: &str, required: bool) -> McpPromptArgument {
+    McpPromptArgument {
+        name: name.to_string(),
+        description: Some(description.to_string()),
+        required,
+    }
+}
+
+/// Fill in the prompt `name` with session data. Required arguments are
+/// validated by the caller against `get_prompts()`.
+pub fn get_prompt(store: &SessionStore, name: &str, arguments: &Value) -> Result<McpPromptResult> {
+    let arg = |key: &str| arguments.get(key).and_then(|v| v.as_str()).unwrap_or("");
+
+    match name {
+        "recent-work-summary" => {
+            let count = arg("count")
+                .parse::<usize>()
+                .unwrap_or(DEFAULT_RECENT_COUNT)
+                .clamp(1, MAX_PROMPT_SESSIONS);
+            let sessions = store.list_sessions(count, None, ListOptions::default(), None)?.items;
+
+            let mut text = format!(
+                "Summarize the work done in my {} most recent Claude Code sessions. \
+                 For each, say what was attempted and whether it looks finished, \
+                 then list any open threads worth picking back up.\n",
+                sessions.len()
+            );
+            for session in &sessions {
+                text.push('\n');
+                text.push_str(&describe_session(store, session)?);
+            }
+
+            Ok(single_message("Summary of recent Claude Code work", text))
+        }
+
+        "find-related-sessions" => {
+            let topic = arg("topic");
+            let options = SearchOptions {
+                highlight: true,
+                ..Default::default()
+            };
+            let sessions = store.search_sessions(topic, MAX_PROMPT_SESSIONS, None, SearchMode::Fuzzy, options)?.items;
+
+            let mut text = format!(
+                "Here are past Claude Code sessions that may relate to \"{}\". \
+                 Identify which are actually relevant, explain how they connect to \
+                 the topic, and point out decisions or code worth reusing.\n",
+                topic
+            );
+            if sessions.is_empty() {
+                text.push_str("\nNo matching sessions were found.\n");
+            }
+            for session in &sessions {
+                text.push('\n');
+                text.push_str(&describe_session(store, session)?);
+                if let Some(context) = &session.match_context {
+                    writeln!(text, "  Match: {}", context)?;
+                }
+            }
+
+            Ok(single_message(format!("Sessions related to {}", topic), text))
+        }
+
+        "code-archaeology" => {
+            let file_path = arg("file_path");
+            let mut sessions =
+                store.sessions_touching_file(file_path, FileMatchMode::Suffix, MAX_PROMPT_SESSIONS)?;
+            // Oldest first, so the history reads in order
+            sessions.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+
+            let mut text = format!(
+                "Trace how `{}` evolved across these Claude Code sessions, listed oldest \
+                 first. Describe what changed in each session and why, and summarize \
+                 the file's current design and its history.\n",
+                file_path
+            );
+            if sessions.is_empty() {
+                text.push_str("\nNo sessions touching this file were found.\n");
+            }
+            for session in &sessions {
+                text.push('\n');
+                text.push_str(&describe_session(store, session)?);
+            }
+
+            Ok(single_message(format!("History of {}", file_path), text))
+        }
+
+        _ => anyhow::bail!("Unknown prompt: {}", name),
+    }
+}
+
+/// A Markdown bullet describing a session, with its opening request and files
+fn describe_session(store: &SessionStore, session: &SessionSummary) -> Result<String> {
+    let mut text = format!(
+        "- Session {} ({}, {} messages)\n",
+        session.id,
+        session.updated_at.as_deref().unwrap_or("unknown date"),
+        session.message_count
+    );
+    if let Some(project) = &session.project_path {
+        writeln!(text, "  Project: {}", project)?;
+    }
+
+    match store.get_session_context(&session.id, ContextDepth::All, false, false, ContextLimits::default())? {
+        Some(context) => {
+            if let Some(request) = &context.initial_request {
+                writeln!(text, "  Request: {}", request)?;
+            }
+            if !context.files_mentioned.is_empty() {
+                writeln!(text, "  Files: {}", context.files_mentioned.join(", "))?;
+            }
+        }
+        None => writeln!(text, "  Preview: {}", session.preview)?,
+    }
+    Ok(text)
+}
+
+fn single_message(description: impl Into<String>, text: String) -> McpPromptResult {
+    McpPromptResult {
+        description: Some(description.into()),
+        messages: vec![PromptMessage::user(text)],
+    }
+}
@@ -1,6 +1,6 @@
 //! MCP Protocol types (JSON-RPC 2.0 based)
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value;
 
 /// JSON-RPC 2.0 Request
@@ -8,13 +8,30 @@ use serde_json::Value;
 #[allow(dead_code)]
 pub struct JsonRpcRequest {
     pub jsonrpc: String,
-    #[serde(default)]
-    pub id: Value,
+    /// `None` when the `id` member is absent (a notification); an explicit
+    /// `"id": null` is kept as `Some(Value::Null)`.
+    #[serde(default, deserialize_with = "deserialize_present")]
+    pub id: Option<Value>,
     pub method: String,
     #[serde(default)]
     pub params: Option<Value>,
 }
 
+impl JsonRpcRequest {
+    /// Notifications are requests without an `id` member and never get a response
+    pub fn is_notification(&self) -> bool {
+        self.id.is_none()
+    }
+}
+
+/// Distinguish a present-but-null `id` from a missing one
+fn deserialize_present<'de, D>(deserializer: D) -> Result<Option<Value>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Value::deserialize(deserializer).map(Some)
+}
+
 /// JSON-RPC 2.0 Response
 #[derive(Debug, Serialize)]
 pub struct JsonRpcResponse {
@@ -26,6 +43,36 @@ pub struct JsonRpcResponse {
     pub error: Option<JsonRpcError>,
 }
 
+/// JSON-RPC 2.0 Notification sent from the server to the client
+#[derive(Debug, Serialize)]
+pub struct JsonRpcNotification {
+    pub jsonrpc: String,
+    pub method: String,
+    pub params: Value,
+}
+
+impl JsonRpcNotification {
+    pub fn new(method: impl Into<String>, params: Value) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            method: method.into(),
+            params,
+        }
+    }
+}
+
+/// Method of the notification carrying the rest of a streamed tool result
+pub const TOOL_PROGRESS_METHOD: &str = "notifications/tools/progress";
+
+/// Params of `notifications/tools/progress`: fields of the result of the
+/// `tools/call` request `request_id` that were left out of its response
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProgressNotification {
+    pub request_id: Value,
+    pub partial: Value,
+}
+
 #[derive(Debug, Serialize)]
 pub struct JsonRpcError {
     pub code: i32,
@@ -56,6 +103,88 @@ impl JsonRpcResponse {
             }),
         }
     }
+
+    /// Attach `data` to an error response
+    pub fn with_data(mut self, data: Value) -> Self {
+        if let Some(error) = &mut self.error {
+            error.data = Some(data);
+        }
+        self
+    }
+
+    /// An error whose `data` carries `error` for clients to inspect
+    pub fn mcp_error(id: Value, code: i32, error: McpError) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: None,
+            error: Some(JsonRpcError {
+                code,
+                message: error.detail.clone(),
+                data: serde_json::to_value(&error).ok(),
+            }),
+        }
+    }
+}
+
+/// What kind of failure an error reports, so clients can tell a missing
+/// session from a corrupt or unreadable file without parsing the message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum McpErrorKind {
+    NotFound,
+    IoError,
+    ParseError,
+    InvalidArgument,
+    Timeout,
+    Cancelled,
+}
+
+/// Structured error details, sent as JSON-RPC error `data` and alongside
+/// the message of failed tool calls
+#[derive(Debug, Clone, Serialize)]
+pub struct McpError {
+    pub error_kind: McpErrorKind,
+    /// File the error concerns, if any
+    pub path: Option<String>,
+    pub detail: String,
+}
+
+impl McpError {
+    pub fn new(error_kind: McpErrorKind, detail: impl Into<String>) -> Self {
+        Self {
+            error_kind,
+            path: None,
+            detail: detail.into(),
+        }
+    }
+
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+}
+
+/// MCP protocol versions this server speaks, oldest first
+pub const SUPPORTED_PROTOCOL_VERSIONS: &[&str] = &["2024-11-05"];
+
+/// Pick the protocol version to answer `initialize` with. A supported
+/// requested version is echoed back; any other date-formatted version gets the
+/// newest supported one, flagged as downgraded (`true`). Returns `None` for a
+/// version that isn't an MCP version string at all.
+pub fn negotiate_protocol_version(requested: Option<&str>) -> Option<(&'static str, bool)> {
+    let latest = SUPPORTED_PROTOCOL_VERSIONS[SUPPORTED_PROTOCOL_VERSIONS.len() - 1];
+    let Some(requested) = requested else {
+        return Some((latest, false));
+    };
+
+    if let Some(version) = SUPPORTED_PROTOCOL_VERSIONS.iter().find(|v| **v == requested) {
+        return Some((version, false));
+    }
+    // MCP versions are release dates
+    chrono::NaiveDate::parse_from_str(requested, "%Y-%m-%d")
+        .ok()
+        .map(|_| (latest, true))
 }
 
 /// MCP Server Info
@@ -70,6 +199,9 @@ pub struct ServerInfo {
 #[serde(rename_all = "camelCase")]
 pub struct InitializeResult {
     pub protocol_version: String,
+    /// Set when the client asked for a version this server doesn't speak
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub downgraded: bool,
     pub capabilities: ServerCapabilities,
     pub server_info: ServerInfo,
 }
@@ -77,6 +209,8 @@ pub struct InitializeResult {
 #[derive(Debug, Serialize)]
 pub struct ServerCapabilities {
     pub tools: ToolsCapability,
+    pub resources: ResourcesCapability,
+    pub prompts: PromptsCapability,
 }
 
 #[derive(Debug, Serialize)]
@@ -85,6 +219,130 @@ pub struct ToolsCapability {
     pub list_changed: bool,
 }
 
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourcesCapability {
+    pub list_changed: bool,
+    pub subscribe: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptsCapability {
+    pub list_changed: bool,
+}
+
+/// URI scheme under which sessions are exposed as MCP resources
+pub const SESSION_URI_SCHEME: &str = "claude-session://";
+
+/// What a `claude-session://` URI refers to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionUriRequest {
+    /// `claude-session://{id}`: the session file
+    Session(String),
+    /// `claude-session://{id}/context`: the session's context summary
+    Context(String),
+    /// `claude-session://{id}/commands`: the shell commands the session ran
+    Commands(String),
+}
+
+impl SessionUriRequest {
+    pub fn session_id(&self) -> &str {
+        match self {
+            Self::Session(id) | Self::Context(id) | Self::Commands(id) => id,
+        }
+    }
+}
+
+/// Parse a `claude-session://` URI, or `None` if it isn't one this server serves
+pub fn parse_session_uri(uri: &str) -> Option<SessionUriRequest> {
+    let rest = uri.strip_prefix(SESSION_URI_SCHEME)?;
+    let (id, view) = match rest.split_once('/') {
+        Some((id, view)) => (id, Some(view)),
+        None => (rest, None),
+    };
+    if id.is_empty() {
+        return None;
+    }
+    let id = id.to_string();
+    match view {
+        None => Some(SessionUriRequest::Session(id)),
+        Some("context") => Some(SessionUriRequest::Context(id)),
+        Some("commands") => Some(SessionUriRequest::Commands(id)),
+        Some(_) => None,
+    }
+}
+
+/// A resource advertised by `resources/list`
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpResource {
+    pub uri: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub mime_type: String,
+}
+
+/// Contents of a resource returned by `resources/read`
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceContent {
+    pub uri: String,
+    pub mime_type: String,
+    pub text: String,
+}
+
+/// Params of `notifications/resources/updated`, sent when a subscribed
+/// resource changes, with its new contents
+#[derive(Debug, Serialize)]
+pub struct ResourceUpdateNotification {
+    pub uri: String,
+    pub contents: Vec<ResourceContent>,
+}
+
+/// A prompt template advertised by `prompts/list`
+#[derive(Debug, Serialize)]
+pub struct McpPrompt {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub arguments: Vec<McpPromptArgument>,
+}
+
+/// An argument accepted by a prompt template
+#[derive(Debug, Serialize)]
+pub struct McpPromptArgument {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub required: bool,
+}
+
+/// A filled-in prompt returned by `prompts/get`
+#[derive(Debug, Serialize)]
+pub struct McpPromptResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub messages: Vec<PromptMessage>,
+}
+
+/// One message of a filled-in prompt
+#[derive(Debug, Serialize)]
+pub struct PromptMessage {
+    pub role: String,
+    pub content: ToolContent,
+}
+
+impl PromptMessage {
+    pub fn user(text: impl Into<String>) -> Self {
+        Self {
+            role: "user".to_string(),
+            content: ToolContent::Text { text: text.into() },
+        }
+    }
+}
+
 /// Tool definition
 #[derive(Debug, Serialize)]
 pub struct Tool {
@@ -94,6 +352,32 @@ pub struct Tool {
     pub input_schema: Value,
 }
 
+/// A content block of a session message, for `get_session` with
+/// `content_format: "typed"`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MessagePart {
+    Text {
+        text: String,
+    },
+    /// A tool_use block; `input_summary` is the start of its input as JSON
+    ToolCall {
+        name: String,
+        input_summary: String,
+        tool_use_id: Option<String>,
+    },
+    /// A tool_result block; `output_preview` is the start of its output
+    ToolResult {
+        tool_use_id: Option<String>,
+        output_preview: String,
+        is_error: bool,
+    },
+    /// An image block, described rather than included
+    Image {
+        description: String,
+    },
+}
+
 /// Tool call result
 #[derive(Debug, Serialize)]
 pub struct ToolResult {
@@ -107,6 +391,9 @@ pub struct ToolResult {
 pub enum ToolContent {
     #[serde(rename = "text")]
     Text { text: String },
+    /// A resource the result refers to, with its contents
+    #[serde(rename = "resource")]
+    EmbeddedResource { resource: ResourceContent },
 }
 
 impl ToolResult {
@@ -117,10 +404,68 @@ impl ToolResult {
         }
     }
 
-    pub fn error(message: impl Into<String>) -> Self {
+    /// A failed call: the message, followed by the structured `McpError` as JSON
+    pub fn error(kind: McpErrorKind, message: impl Into<String>) -> Self {
+        Self::from(McpError::new(kind, message))
+    }
+
+    /// Add `resource` after the content so far
+    pub fn with_resource(mut self, resource: ResourceContent) -> Self {
+        self.content.push(ToolContent::EmbeddedResource { resource });
+        self
+    }
+}
+
+impl From<McpError> for ToolResult {
+    fn from(error: McpError) -> Self {
+        let data = serde_json::to_string(&error).unwrap_or_default();
         Self {
-            content: vec![ToolContent::Text { text: message.into() }],
+            content: vec![
+                ToolContent::Text { text: error.detail },
+                ToolContent::Text { text: data },
+            ],
             is_error: Some(true),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_session_uri() {
+        assert_eq!(
+            parse_session_uri("claude-session://abc123"),
+            Some(SessionUriRequest::Session("abc123".to_string()))
+        );
+        assert_eq!(
+            parse_session_uri("claude-session://abc123/context"),
+            Some(SessionUriRequest::Context("abc123".to_string()))
+        );
+        assert_eq!(
+            parse_session_uri("claude-session://abc123/commands"),
+            Some(SessionUriRequest::Commands("abc123".to_string()))
+        );
+        for uri in ["claude-session://", "claude-session:///context", "claude-session://abc123/files", "file:///abc123"] {
+            assert_eq!(parse_session_uri(uri), None, "{}", uri);
+        }
+    }
+
+    #[test]
+    fn test_embedded_resource_content() {
+        let resource = ResourceContent {
+            uri: "claude-session://abc123".to_string(),
+            mime_type: "application/jsonl".to_string(),
+            text: "{}".to_string(),
+        };
+        let result = serde_json::to_value(ToolResult::text("session").with_resource(resource)).unwrap();
+        assert_eq!(
+            result["content"],
+            serde_json::json!([
+                {"type": "text", "text": "session"},
+                {"type": "resource", "resource": {"uri": "claude-session://abc123", "mimeType": "application/jsonl", "text": "{}"}}
+            ])
+        );
+    }
+}
@@ -0,0 +1,303 @@
+//! Boolean search queries such as `rust AND (async OR tokio) NOT wasm`
+//!
+//! `NOT` binds tightest, then `AND`, then `OR`; adjacent terms without an
+//! operator are ANDed. Operators must be uppercase, so a lowercase "and" is an
+//! ordinary term, and a double-quoted phrase is a single term.
+
+/// A parsed boolean query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryNode {
+    And(Box<[QueryNode]>),
+    Or(Box<[QueryNode]>),
+    Not(Box<QueryNode>),
+    /// Lowercased term, matched as a substring
+    Term(String),
+}
+
+/// A query that can't be parsed
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid query: {0}")]
+pub struct QueryError(String);
+
+impl QueryNode {
+    /// Whether `text` satisfies the query, ignoring case
+    pub fn matches(&self, text: &str) -> bool {
+        self.eval(&text.to_lowercase())
+    }
+
+    fn eval(&self, text: &str) -> bool {
+        match self {
+            Self::And(nodes) => nodes.iter().all(|n| n.eval(text)),
+            Self::Or(nodes) => nodes.iter().any(|n| n.eval(text)),
+            Self::Not(node) => !node.eval(text),
+            Self::Term(term) => text.contains(term.as_str()),
+        }
+    }
+
+    /// Terms that count towards a match, i.e. those not under a `NOT`
+    pub fn positive_terms(&self) -> Vec<&str> {
+        match self {
+            Self::And(nodes) | Self::Or(nodes) => nodes.iter().flat_map(|n| n.positive_terms()).collect(),
+            Self::Not(_) => Vec::new(),
+            Self::Term(term) => vec![term.as_str()],
+        }
+    }
+
+    /// Whether every match must contain some positive term. Without one the
+    /// query would match nearly every session, e.g. `NOT tokio` or `a OR NOT b`.
+    fn requires_positive(&self) -> bool {
+        match self {
+            Self::And(nodes) => nodes.iter().any(|n| n.requires_positive()),
+            Self::Or(nodes) => nodes.iter().all(|n| n.requires_positive()),
+            Self::Not(_) => false,
+            Self::Term(_) => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+    Term(String),
+}
+
+/// Recursive-descent parser over the tokens of a query
+pub struct QueryParser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl QueryParser {
+    /// Parse `query` into a `QueryNode`
+    pub fn parse(query: &str) -> Result<QueryNode, QueryError> {
+        let mut parser = Self {
+            tokens: tokenize(query)?,
+            pos: 0,
+        };
+        if parser.tokens.is_empty() {
+            return Err(QueryError("query is empty".to_string()));
+        }
+
+        let node = parser.or_expr()?;
+        if let Some(token) = parser.peek() {
+            return Err(QueryError(format!("unexpected {}", describe(token))));
+        }
+        if !node.requires_positive() {
+            return Err(QueryError(
+                "at least one term must be required without NOT".to_string(),
+            ));
+        }
+        Ok(node)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// or_expr := and_expr (OR and_expr)*
+    fn or_expr(&mut self) -> Result<QueryNode, QueryError> {
+        let mut nodes = vec![self.and_expr()?];
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            nodes.push(self.and_expr()?);
+        }
+        Ok(combine(nodes, QueryNode::Or))
+    }
+
+    /// and_expr := unary ([AND] unary)*
+    fn and_expr(&mut self) -> Result<QueryNode, QueryError> {
+        let mut nodes = vec![self.unary()?];
+        loop {
+            match self.peek() {
+                Some(Token::And) => {
+                    self.pos += 1;
+                    nodes.push(self.unary()?);
+                }
+                // Adjacent operands are implicitly ANDed
+                Some(Token::Not | Token::Open | Token::Term(_)) => nodes.push(self.unary()?),
+                _ => break,
+            }
+        }
+        Ok(combine(nodes, QueryNode::And))
+    }
+
+    /// unary := NOT unary | '(' or_expr ')' | term
+    fn unary(&mut self) -> Result<QueryNode, QueryError> {
+        match self.next() {
+            Some(Token::Not) => Ok(QueryNode::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let node = self.or_expr()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(node),
+                    _ => Err(QueryError("missing closing parenthesis".to_string())),
+                }
+            }
+            Some(Token::Term(term)) => Ok(QueryNode::Term(term)),
+            Some(token) => Err(QueryError(format!("expected a term, found {}", describe(&token)))),
+            None => Err(QueryError("expected a term, found the end of the query".to_string())),
+        }
+    }
+}
+
+/// A single node stays as is; several are joined with `op`
+fn combine(mut nodes: Vec<QueryNode>, op: fn(Box<[QueryNode]>) -> QueryNode) -> QueryNode {
+    if nodes.len() == 1 {
+        nodes.remove(0)
+    } else {
+        op(nodes.into_boxed_slice())
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::And => "AND".to_string(),
+        Token::Or => "OR".to_string(),
+        Token::Not => "NOT".to_string(),
+        Token::Open => "'('".to_string(),
+        Token::Close => "')'".to_string(),
+        Token::Term(term) => format!("\"{}\"", term),
+    }
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' => {
+                chars.next();
+                tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            }
+            '"' => {
+                chars.next();
+                let phrase: String = chars.by_ref().take_while(|&c| c != '"').collect();
+                if phrase.trim().is_empty() {
+                    return Err(QueryError("empty quoted phrase".to_string()));
+                }
+                tokens.push(Token::Term(phrase.to_lowercase()));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' || c == '"' {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Term(word.to_lowercase()),
+                });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(t: &str) -> QueryNode {
+        QueryNode::Term(t.to_string())
+    }
+
+    fn matches(query: &str, text: &str) -> bool {
+        QueryParser::parse(query).unwrap().matches(text)
+    }
+
+    #[test]
+    fn test_and() {
+        assert_eq!(
+            QueryParser::parse("Rust AND async").unwrap(),
+            QueryNode::And(Box::new([term("rust"), term("async")]))
+        );
+        // Adjacent terms are ANDed too
+        assert_eq!(QueryParser::parse("rust async").unwrap(), QueryParser::parse("rust AND async").unwrap());
+        assert!(matches("rust AND async", "Async code in RUST"));
+        assert!(!matches("rust AND async", "rust only"));
+    }
+
+    #[test]
+    fn test_or() {
+        assert!(matches("tokio OR smol", "we use smol"));
+        assert!(!matches("tokio OR smol", "we use async-std"));
+    }
+
+    #[test]
+    fn test_not() {
+        assert!(matches("rust NOT tokio", "rust with smol"));
+        assert!(!matches("rust NOT tokio", "rust with tokio"));
+        assert!(matches("rust AND NOT tokio", "rust with smol"));
+    }
+
+    #[test]
+    fn test_precedence() {
+        // NOT binds tighter than AND, which binds tighter than OR
+        assert_eq!(
+            QueryParser::parse("a OR b AND NOT c").unwrap(),
+            QueryNode::Or(Box::new([
+                term("a"),
+                QueryNode::And(Box::new([term("b"), QueryNode::Not(Box::new(term("c")))])),
+            ]))
+        );
+        assert!(matches("a OR b AND NOT c", "a c"));
+        assert!(!matches("a OR b AND NOT c", "b c"));
+
+        // Parentheses override it
+        assert!(!matches("(a OR b) AND NOT c", "a c"));
+        assert!(matches("(a OR b) AND NOT c", "b d"));
+        assert!(matches("rust AND (async OR threads) NOT tokio", "rust threads"));
+        assert!(!matches("rust AND (async OR threads) NOT tokio", "rust threads tokio"));
+    }
+
+    #[test]
+    fn test_phrases_and_lowercase_operators() {
+        assert!(matches("\"dark mode\" NOT light", "Added Dark Mode"));
+        assert!(!matches("\"dark mode\"", "dark theme mode"));
+        // Lowercase operators are plain terms
+        assert_eq!(
+            QueryParser::parse("cats and dogs").unwrap(),
+            QueryNode::And(Box::new([term("cats"), term("and"), term("dogs")]))
+        );
+    }
+
+    #[test]
+    fn test_requires_positive_term() {
+        assert!(QueryParser::parse("NOT tokio").is_err());
+        assert!(QueryParser::parse("NOT (a OR b)").is_err());
+        assert!(QueryParser::parse("rust OR NOT tokio").is_err());
+        assert!(QueryParser::parse("rust OR (async NOT tokio)").is_ok());
+    }
+
+    #[test]
+    fn test_invalid_queries() {
+        for query in ["", "   ", "rust AND", "OR rust", "(rust", "rust)", "NOT", "\"\""] {
+            assert!(QueryParser::parse(query).is_err(), "{:?} should not parse", query);
+        }
+    }
+
+    #[test]
+    fn test_positive_terms() {
+        let query = QueryParser::parse("rust AND (async OR threads) NOT tokio").unwrap();
+        assert_eq!(query.positive_terms(), vec!["rust", "async", "threads"]);
+    }
+}
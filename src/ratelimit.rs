@@ -0,0 +1,45 @@
+//! Token-bucket rate limiting for tool calls
+//!
+//! Each tool call takes a token from the bucket, which refills continuously at
+//! the configured rate and holds at most one second's worth of tokens. A client
+//! calling tools in a tight loop is turned away instead of keeping the disk busy.
+
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+pub struct RateLimiter {
+    /// Tokens added per second, which is also the bucket's capacity
+    rate: f64,
+    tokens: Mutex<f64>,
+    last_refill: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// A limiter allowing `rate` calls per second, starting with a full bucket
+    pub fn new(rate: u32) -> Self {
+        Self {
+            rate: rate as f64,
+            tokens: Mutex::new(rate as f64),
+            last_refill: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Take a token, or return how long until one will be available
+    pub async fn acquire(&self) -> Result<(), Duration> {
+        let mut tokens = self.tokens.lock().await;
+        let mut last_refill = self.last_refill.lock().await;
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(*last_refill).as_secs_f64();
+        *tokens = (*tokens + elapsed * self.rate).min(self.rate);
+        *last_refill = now;
+
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - *tokens) / self.rate))
+        }
+    }
+}
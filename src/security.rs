@@ -0,0 +1,158 @@
+//! Checks on file paths and session IDs supplied by clients
+//!
+//! Most tools only read files they find under `~/.claude/projects/`. Tools
+//! that take a path from the client check it here first, so a request can't
+//! read arbitrary files through `..` components, symlinks or embedded NUL
+//! bytes. Session IDs are checked too, since they are matched against file
+//! names and repeated in error messages.
+
+use anyhow::Result;
+use std::path::{Component, Path, PathBuf};
+
+/// Refuse a session ID that isn't made of ASCII letters, digits, `-` and `_`,
+/// as UUIDs and their prefixes are. Anything else, such as a path separator,
+/// `.`, a NUL byte or a line break, can't name a session file and could smuggle
+/// text into logs, so the error doesn't repeat the ID.
+pub fn validate_session_id(id: &str) -> Result<()> {
+    let valid = !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        anyhow::bail!("Invalid session_id format: must be alphanumeric with hyphens and underscores only");
+    }
+    Ok(())
+}
+
+/// Whether `path` is an absolute path that stays under one of
+/// `allowed_prefixes`. Paths containing `..` components or NUL bytes are
+/// refused outright; otherwise symlinks are resolved on both sides, so a link
+/// pointing out of every prefix is refused too.
+pub fn is_safe_path(path: &Path, allowed_prefixes: &[&Path]) -> bool {
+    if path.as_os_str().as_encoded_bytes().contains(&0) {
+        return false;
+    }
+    if !path.is_absolute() || path.components().any(|c| c == Component::ParentDir) {
+        return false;
+    }
+
+    let resolved = resolve(path);
+    allowed_prefixes
+        .iter()
+        .filter(|prefix| prefix.is_absolute())
+        .any(|prefix| resolved.starts_with(resolve(prefix)))
+}
+
+/// `path` with symlinks resolved. A path that doesn't exist yet, such as a
+/// file about to be written, is resolved through its nearest existing parent.
+fn resolve(path: &Path) -> PathBuf {
+    if let Ok(resolved) = path.canonicalize() {
+        return resolved;
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => resolve(parent).join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_session_ids() {
+        assert!(validate_session_id("3f2a9c1e-7b4d-4e8a-9c2f-1a2b3c4d5e6f").is_ok());
+        assert!(validate_session_id("3F2A9C1E").is_ok());
+        assert!(validate_session_id("agent_a1b2").is_ok());
+    }
+
+    #[test]
+    fn test_rejected_session_ids() {
+        for id in [
+            "",
+            "../../etc/passwd",
+            "a/b",
+            "a\\b",
+            "session.jsonl",
+            "..",
+            "abc\0def",
+            "abc\ndef",
+            "abc def",
+            "abc%2e",
+            "séance",
+            "会话",
+        ] {
+            let error = validate_session_id(id).unwrap_err().to_string();
+            assert_eq!(error, "Invalid session_id format: must be alphanumeric with hyphens and underscores only");
+        }
+    }
+
+    #[test]
+    fn test_paths_under_prefix() {
+        let home = tempfile::tempdir().unwrap();
+        let file = home.path().join("exports").join("session.jsonl");
+        std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+        std::fs::write(&file, "").unwrap();
+
+        assert!(is_safe_path(&file, &[home.path()]));
+        // Files that don't exist yet are judged by the path alone
+        assert!(is_safe_path(&home.path().join("missing.jsonl"), &[home.path()]));
+        assert!(!is_safe_path(Path::new("exports/session.jsonl"), &[home.path()]));
+    }
+
+    #[test]
+    fn test_parent_dir_components() {
+        let home = tempfile::tempdir().unwrap();
+        let escaping = home.path().join("..").join("..").join("etc").join("passwd");
+        assert!(!is_safe_path(&escaping, &[home.path()]));
+
+        // Refused even when it would land back inside the prefix
+        let round_trip = home.path().join("a").join("..").join("b.jsonl");
+        assert!(!is_safe_path(&round_trip, &[home.path()]));
+    }
+
+    #[test]
+    fn test_paths_outside_prefixes() {
+        let home = tempfile::tempdir().unwrap();
+        let elsewhere = tempfile::tempdir().unwrap();
+        let file = elsewhere.path().join("session.jsonl");
+        std::fs::write(&file, "").unwrap();
+
+        assert!(!is_safe_path(&file, &[home.path()]));
+        assert!(is_safe_path(&file, &[home.path(), elsewhere.path()]));
+        assert!(!is_safe_path(&file, &[]));
+        // A sibling directory sharing the prefix's name is not under it
+        let sibling = PathBuf::from(format!("{}-other", home.path().display())).join("s.jsonl");
+        assert!(!is_safe_path(&sibling, &[home.path()]));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks_escaping_prefix() {
+        let home = tempfile::tempdir().unwrap();
+        let elsewhere = tempfile::tempdir().unwrap();
+        let target = elsewhere.path().join("secret.jsonl");
+        std::fs::write(&target, "").unwrap();
+
+        let file_link = home.path().join("link.jsonl");
+        std::os::unix::fs::symlink(&target, &file_link).unwrap();
+        assert!(!is_safe_path(&file_link, &[home.path()]));
+
+        let dir_link = home.path().join("linked-dir");
+        std::os::unix::fs::symlink(elsewhere.path(), &dir_link).unwrap();
+        assert!(!is_safe_path(&dir_link.join("secret.jsonl"), &[home.path()]));
+        // Nor can a file be created through the link
+        assert!(!is_safe_path(&dir_link.join("new").join("file.jsonl"), &[home.path()]));
+
+        // Links that stay inside the prefix are fine
+        let inside = home.path().join("real.jsonl");
+        std::fs::write(&inside, "").unwrap();
+        let inner_link = home.path().join("inner-link.jsonl");
+        std::os::unix::fs::symlink(&inside, &inner_link).unwrap();
+        assert!(is_safe_path(&inner_link, &[home.path()]));
+    }
+
+    #[test]
+    fn test_null_bytes() {
+        let home = tempfile::tempdir().unwrap();
+        let path = PathBuf::from(format!("{}/session\0.jsonl", home.path().display()));
+        assert!(!is_safe_path(&path, &[home.path()]));
+    }
+}
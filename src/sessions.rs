@@ -4,13 +4,23 @@
 //! Each line is a JSON object with type, message, timestamp, sessionId fields.
 
 use anyhow::{Context, Result};
-use chrono::{DateTime, Utc};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use flate2::read::GzDecoder;
 use fuzzy_matcher::skim::SkimMatcherV2;
 use fuzzy_matcher::FuzzyMatcher;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, IsTerminal, Read};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use walkdir::WalkDir;
 
+use crate::metadata::MetadataStore;
+use crate::protocol::MessagePart;
+use crate::query::QueryParser;
+
 /// A Claude Code session
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Session {
@@ -21,6 +31,30 @@ pub struct Session {
     pub updated_at: Option<DateTime<Utc>>,
     pub messages: Vec<Message>,
     pub file_path: PathBuf,
+    /// Sum of `costUSD` over the session's result entries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_cost_usd: Option<f64>,
+    /// Sum of `durationMs` over the session's result entries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_duration_ms: Option<u64>,
+    /// The session's result entries, in file order
+    #[serde(skip)]
+    pub result_entries: Vec<ResultEntry>,
+    /// Repository the session ran in, from the `gitRepo` of its entries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_repo: Option<String>,
+    /// Branch checked out, from the `gitBranch` of its entries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_branch: Option<String>,
+}
+
+/// The cost and duration of one turn, from a result entry
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResultEntry {
+    /// Number of messages before the entry in the session file
+    pub message_count: usize,
+    pub cost_usd: Option<f64>,
+    pub duration_ms: Option<u64>,
 }
 
 /// A message in a session
@@ -30,8 +64,173 @@ pub struct Message {
     pub content: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub timestamp: Option<DateTime<Utc>>,
+    /// Raw tool_use blocks; `content` only carries a short rendering of these
+    #[serde(skip)]
+    pub tool_calls: Vec<ToolCall>,
+    /// Full output of tool_result blocks; `content` only carries a short preview
+    #[serde(skip)]
+    pub tool_results: Vec<String>,
+}
+
+/// A message with its content split into blocks
+#[derive(Debug, Serialize)]
+pub struct TypedMessage {
+    pub role: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<DateTime<Utc>>,
+    pub parts: Vec<MessagePart>,
+}
+
+/// A tool_use block from an assistant message
+#[derive(Debug, Clone)]
+pub struct ToolCall {
+    pub name: String,
+    pub input: serde_json::Value,
+}
+
+/// Tool names whose `command` input is a shell command
+const SHELL_TOOLS: &[&str] = &["Bash", "Run", "Execute", "Shell", "Terminal"];
+
+/// Length of a full session ID (a UUID); shorter IDs are looked up as prefixes
+const SESSION_ID_LEN: usize = 36;
+
+/// Shortest session ID prefix accepted, so a stray character doesn't match
+/// an arbitrary session
+const MIN_SESSION_ID_PREFIX_LEN: usize = 4;
+
+/// A session ID prefix that doesn't identify a single session
+#[derive(Debug, thiserror::Error)]
+pub enum SessionLookupError {
+    #[error("Session ID prefix \"{0}\" is too short, minimum {MIN_SESSION_ID_PREFIX_LEN} characters")]
+    PrefixTooShort(String),
+    #[error("Session ID prefix \"{prefix}\" matches several sessions: {}", .matches.join(", "))]
+    AmbiguousPrefix { prefix: String, matches: Vec<String> },
+}
+
+/// Substrings `find_sessions_with_errors` looks for when none are given
+pub const DEFAULT_ERROR_PATTERNS: &[&str] = &["error:", "Error:", "failed", "FAILED", "panic", "exit code 1"];
+
+/// A shell command executed during a session
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandEntry {
+    pub command: String,
+    pub timestamp: Option<DateTime<Utc>>,
+    pub message_index: usize,
+}
+
+/// A shell command from `command_history`, with the session that ran it
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandHistoryEntry {
+    pub session_id: String,
+    #[serde(flatten)]
+    pub entry: CommandEntry,
+}
+
+/// A TODO-style marker found in a session's messages
+#[derive(Debug, Clone, Serialize)]
+pub struct TodoItem {
+    /// The line from the marker on, with any indented continuation lines
+    /// joined on
+    pub text: String,
+    /// The marker that matched
+    pub pattern: String,
+    pub role: String,
+    pub message_index: usize,
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+/// A todo from `find_todos`, with the session it was found in
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionTodo {
+    pub session_id: String,
+    #[serde(flatten)]
+    pub todo: TodoItem,
+}
+
+/// Markers `extract_todos` looks for when not given any
+pub const DEFAULT_TODO_PATTERNS: [&str; 5] = ["TODO", "FIXME", "HACK", "NOTE", "XXX"];
+
+/// A distinct shell command and how often it was run across sessions
+#[derive(Debug, Serialize, PartialEq)]
+pub struct CommandHistoryItem {
+    pub command: String,
+    pub execution_count: usize,
+    /// Session of the most recent run
+    pub last_seen_in_session: String,
+}
+
+/// A fenced code block found in a session
+#[derive(Debug, Serialize)]
+pub struct CodeBlock {
+    pub language: Option<String>,
+    pub content: String,
+    /// Role of the message the block came from, or "tool_result" for tool output
+    pub role: String,
+    pub message_index: usize,
+}
+
+/// Roughly how many tokens a session's messages take up
+#[derive(Debug, Serialize)]
+pub struct TokenEstimate {
+    pub session_id: String,
+    /// All messages, or only those with the requested role
+    pub estimated_tokens: usize,
+    pub estimated_tokens_user_only: usize,
+    pub estimated_tokens_assistant_only: usize,
+    /// Set when the session is too big to paste into a prompt comfortably
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+/// What a session cost to run, as recorded in its result entries
+#[derive(Debug, Serialize)]
+pub struct SessionCost {
+    pub session_id: String,
+    pub cost_usd: Option<f64>,
+    pub duration_ms: Option<u64>,
+    /// `cost_usd` spread over every message in the session
+    pub cost_per_message: Option<f64>,
+}
+
+/// Estimates above this many tokens come with a warning
+pub const TOKEN_WARNING_THRESHOLD: usize = 100_000;
+
+/// Bytes per token of English prose
+const PROSE_BYTES_PER_TOKEN: f64 = 4.0;
+
+/// Bytes per token of code, whose symbols and identifiers split into more tokens
+const CODE_BYTES_PER_TOKEN: f64 = 3.0;
+
+/// Share of symbol characters at and above which text counts as pure code
+const CODE_SYMBOL_SHARE: f64 = 0.1;
+
+/// Shell commands executed during a session
+#[derive(Debug, Serialize)]
+pub struct SessionCommands {
+    pub session_id: String,
+    pub commands: Vec<CommandEntry>,
+}
+
+/// A tool call that read or changed a file
+#[derive(Debug, Clone, Serialize)]
+pub struct FileOperation {
+    pub file_path: String,
+    /// Name of the tool, e.g. "Read", "Write" or "Edit"
+    pub operation: String,
+    pub message_index: usize,
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+/// Files operated on during a session
+#[derive(Debug, Serialize)]
+pub struct SessionFiles {
+    pub session_id: String,
+    pub files: Vec<FileOperation>,
 }
 
+/// How long a statistics scan runs before it starts reporting progress
+const STATISTICS_PROGRESS_DELAY: std::time::Duration = std::time::Duration::from_secs(2);
+
 /// Summary of a session for listing
 #[derive(Debug, Serialize)]
 pub struct SessionSummary {
@@ -41,490 +240,6777 @@ pub struct SessionSummary {
     pub created_at: Option<String>,
     pub updated_at: Option<String>,
     pub message_count: usize,
+    pub user_messages: usize,
+    pub assistant_messages: usize,
     pub preview: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    /// Matched text in context, with the match in `**bold**` (search with `highlight`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub match_context: Option<String>,
+    /// How well the session matched a search, higher is better
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_cost_usd: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_duration_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git_repo: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git_branch: Option<String>,
+}
+
+/// An agent sub-session with the session that spawned it
+#[derive(Debug, Serialize)]
+pub struct AgentSessionSummary {
+    #[serde(flatten)]
+    pub summary: SessionSummary,
+    pub parent_session_id: Option<String>,
+}
+
+/// A session file with its line count, an upper bound on its message count
+/// that is found without parsing any JSON
+#[derive(Debug, Clone)]
+struct SessionScanResult {
+    path: PathBuf,
+    fast_message_count: usize,
+}
+
+/// Sessions with fewer messages than this are left out of
+/// `sessions_by_message_count` unless trivial sessions are asked for
+const MIN_NONTRIVIAL_MESSAGES: usize = 2;
+
+/// A session whose tool output or assistant replies contain error signals
+#[derive(Debug, Serialize)]
+pub struct ErrorSessionSummary {
+    #[serde(flatten)]
+    pub summary: SessionSummary,
+    /// Distinct patterns found, in the order they were given
+    pub matched_patterns: Vec<String>,
+    /// Number of tool results and assistant messages matching each pattern, summed
+    pub error_count: usize,
+}
+
+/// ID, creation and last update time of a top-level session
+type SessionSpan = (String, Option<DateTime<Utc>>, Option<DateTime<Utc>>);
+
+/// Weight of each term in a session (see `term_frequency_vector`)
+type TermVector = std::collections::HashMap<String, f64>;
+
+/// Which messages `get_session_context` draws files, key terms and commands
+/// from. The initial request and message count always cover the whole session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ContextDepth {
+    #[default]
+    All,
+    /// The first N messages, for how a session was set up
+    FirstN(usize),
+    /// The last N messages, for where a session left off
+    LastN(usize),
+}
+
+impl ContextDepth {
+    /// Parse the `depth` and `depth_mode` tool arguments. `depth` alone means
+    /// the last `depth` messages; neither means all of them.
+    pub fn from_args(depth: Option<usize>, depth_mode: Option<&str>) -> Result<Self> {
+        match (depth_mode, depth) {
+            (None, None) | (Some("all"), _) => Ok(Self::All),
+            (None | Some("last_n"), Some(n)) => Ok(Self::LastN(n)),
+            (Some("first_n"), Some(n)) => Ok(Self::FirstN(n)),
+            (Some(mode @ ("last_n" | "first_n")), None) => anyhow::bail!("depth_mode {} requires depth", mode),
+            (Some(other), _) => anyhow::bail!(
+                "Unknown depth_mode: {} (expected \"last_n\", \"first_n\" or \"all\")",
+                other
+            ),
+        }
+    }
+
+    /// The messages to analyze, all of them if there are no more than N
+    pub fn window(self, messages: &[Message]) -> &[Message] {
+        match self {
+            Self::All => messages,
+            Self::FirstN(n) => &messages[..n.min(messages.len())],
+            Self::LastN(n) => &messages[messages.len().saturating_sub(n)..],
+        }
+    }
+}
+
+/// How many files and key terms `get_session_context` lists per session, and
+/// how many characters of the initial request it keeps. A limit of 0 lists
+/// them all, or keeps the whole request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContextLimits {
+    pub max_files: usize,
+    pub max_terms: usize,
+    pub initial_request_chars: usize,
+}
+
+impl ContextLimits {
+    pub const DEFAULT_MAX_FILES: usize = 20;
+    pub const DEFAULT_MAX_TERMS: usize = 15;
+    pub const DEFAULT_INITIAL_REQUEST_CHARS: usize = 500;
+}
+
+impl Default for ContextLimits {
+    fn default() -> Self {
+        Self {
+            max_files: Self::DEFAULT_MAX_FILES,
+            max_terms: Self::DEFAULT_MAX_TERMS,
+            initial_request_chars: Self::DEFAULT_INITIAL_REQUEST_CHARS,
+        }
+    }
+}
+
+/// The parts of a session context that take longest to extract
+#[derive(Debug, Serialize)]
+pub struct ContextTerms {
+    pub files_mentioned: Vec<String>,
+    pub key_terms: Vec<String>,
 }
 
 /// Context summary of a session
 #[derive(Debug, Serialize)]
 pub struct SessionContext {
     pub id: String,
+    /// When the session started; the earliest start when aggregated
+    pub created_at: Option<DateTime<Utc>>,
     pub cwd: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git_repo: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git_branch: Option<String>,
     pub initial_request: Option<String>,
     pub message_count: usize,
+    /// Estimated tokens of all the session's messages, when asked for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_estimate: Option<usize>,
+    /// Estimated tokens of the user's and Claude's messages alone
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_estimate_by_role: Option<RoleTokens>,
     pub files_mentioned: Vec<String>,
     pub key_terms: Vec<String>,
+    /// Distinct shell commands run, in the order first run
+    pub commands: Vec<String>,
+    /// `file_path` input of the last tool call that had one, i.e. the file
+    /// being worked on when the session ended
+    pub most_recent_file: Option<String>,
+    /// Name of the last tool called
+    pub most_recent_tool: Option<String>,
+    /// Tool calls per tool, most used first, when asked for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_summary: Option<Vec<ToolCallSummary>>,
+    /// Whether this combines several sessions (see `get_multi_session_context`)
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub aggregated: bool,
+    /// Requested sessions that weren't found, when aggregated
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub missing_ids: Vec<String>,
 }
 
-/// Claude Code session storage handler
-pub struct SessionStore {
-    base_path: PathBuf,
+/// Estimated tokens per role
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct RoleTokens {
+    pub user: usize,
+    pub assistant: usize,
 }
 
-impl SessionStore {
-    /// Create a new session store, finding the Claude Code directory
-    pub fn new() -> Result<Self> {
-        let home = dirs::home_dir().context("Could not find home directory")?;
-        let claude_dir = home.join(".claude");
+/// How one tool was used in a session
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ToolCallSummary {
+    pub tool_name: String,
+    pub call_count: usize,
+    /// Distinct `file_path` inputs, in the order first used
+    pub files_touched: Vec<String>,
+    /// Distinct shell commands, cut to `COMMAND_PREVIEW_CHARS`, in the order
+    /// first run
+    pub commands_run: Vec<String>,
+}
 
-        if !claude_dir.exists() {
-            anyhow::bail!(
-                "Claude Code directory not found at ~/.claude. \
-                 Make sure Claude Code CLI is installed and has been used at least once."
-            );
-        }
+/// Characters of a shell command kept in a `ToolCallSummary`
+const COMMAND_PREVIEW_CHARS: usize = 80;
 
-        Ok(Self {
-            base_path: claude_dir,
-        })
-    }
+/// What happened in a session, in order
+#[derive(Debug, Serialize)]
+pub struct SessionTimeline {
+    pub session_id: String,
+    /// Time from the first to the last timestamped message
+    pub duration_seconds: Option<f64>,
+    pub events: Vec<TimelineEvent>,
+}
 
-    /// List all sessions, sorted by recency
-    pub fn list_sessions(&self, limit: usize) -> Result<Vec<SessionSummary>> {
-        let mut sessions = Vec::new();
-        let projects_dir = self.base_path.join("projects");
+/// One message, tool call or tool result in a session's timeline
+#[derive(Debug, Serialize)]
+pub struct TimelineEvent {
+    pub event_type: TimelineEventType,
+    /// Timestamp of the message the event belongs to
+    pub timestamp: Option<DateTime<Utc>>,
+    /// Milliseconds since the previous event; `None` unless both are timestamped
+    pub delta_ms: Option<i64>,
+    pub summary: String,
+}
 
-        if !projects_dir.exists() {
-            return Ok(sessions);
-        }
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimelineEventType {
+    UserMessage,
+    AssistantMessage,
+    ToolCall,
+    ToolResult,
+}
 
-        // Walk through the projects directory looking for .jsonl session files
-        for entry in WalkDir::new(&projects_dir)
-            .max_depth(3)
-            .follow_links(true)
-            .into_iter()
-            .filter_map(|e| e.ok())
-        {
-            let path = entry.path();
-            if path.is_file() && path.extension().is_some_and(|e| e == "jsonl") {
-                // Skip agent files (subagent sessions)
-                if path.file_name().map_or(false, |n| n.to_string_lossy().starts_with("agent-")) {
-                    continue;
-                }
-                if let Ok(Some(session)) = self.try_parse_jsonl_session(path) {
-                    sessions.push(session_to_summary(&session));
-                }
+/// Longest `summary` of a timeline event
+const TIMELINE_SUMMARY_CHARS: usize = 200;
+
+/// Where two sessions were merged to, and what went into the result
+#[derive(Debug, Serialize)]
+pub struct MergeResult {
+    pub output_path: String,
+    pub session_id: String,
+    pub total_messages: usize,
+    pub duplicates_removed: usize,
+}
+
+/// Differences between two sessions' files, key terms and commands
+#[derive(Debug, Serialize)]
+pub struct SessionDiff {
+    pub only_in_a: SessionContext,
+    pub only_in_b: SessionContext,
+    /// What both sessions share. Its `id` names both sessions, and `cwd` is
+    /// only set when the two agree.
+    pub in_both: SessionContext,
+    /// Duration of session B minus duration of session A
+    pub duration_delta_seconds: Option<i64>,
+}
+
+/// A session related to another through the files both mention
+#[derive(Debug, Serialize)]
+pub struct RelatedSession {
+    #[serde(flatten)]
+    pub summary: SessionSummary,
+    /// Jaccard similarity of the two sessions' mentioned files (0-1)
+    pub similarity_score: f64,
+}
+
+/// Sessions in the same directory that look like the same task started over
+#[derive(Debug, Serialize)]
+pub struct DuplicateGroup {
+    pub cwd: String,
+    /// Lowest cosine similarity (0-1) of the pairs that put sessions in this
+    /// group, each at least the threshold asked for
+    pub min_similarity: f64,
+    /// Most recently updated first
+    pub sessions: Vec<SessionSummary>,
+}
+
+/// A session whose dominant programming language was looked for
+#[derive(Debug, Serialize)]
+pub struct LanguageSession {
+    #[serde(flatten)]
+    pub summary: SessionSummary,
+    pub language: String,
+    /// The language's share of all language evidence in the session (0-1)
+    pub confidence: f64,
+}
+
+/// A session Claude left without a reply, from
+/// `find_sessions_without_assistant_response`
+#[derive(Debug, Serialize)]
+pub struct UnansweredSession {
+    #[serde(flatten)]
+    pub summary: SessionSummary,
+    pub last_message_role: String,
+}
+
+/// A filtered range of messages from a session
+#[derive(Debug, Serialize)]
+pub struct MessageSlice {
+    pub session_id: String,
+    pub total_messages: usize,
+    pub returned_messages: usize,
+    pub messages: Vec<Message>,
+}
+
+/// The messages added to a session since a given index
+#[derive(Debug, Serialize)]
+pub struct SessionDelta {
+    pub session_id: String,
+    /// Index of the first new message
+    pub from_index: usize,
+    /// Number of messages in the session; the `since_message_index` of the next poll
+    pub to_index: usize,
+    pub new_messages: Vec<Message>,
+    /// Sum of `costUSD` over the result entries after `from_index`
+    pub new_cost_usd: Option<f64>,
+    /// Sum of `durationMs` over the result entries after `from_index`
+    pub new_duration_ms: Option<u64>,
+    pub no_new_messages: bool,
+}
+
+/// Messages added to a session while `watch_session` waited
+#[derive(Debug, Serialize)]
+pub struct WatchResult {
+    pub new_messages: Vec<Message>,
+    /// Whether the wait ended without new messages
+    pub timed_out: bool,
+    pub current_message_count: usize,
+}
+
+/// Usage count for a single tool
+#[derive(Debug, Serialize)]
+pub struct ToolUsage {
+    pub name: String,
+    pub count: usize,
+    pub percentage: f64,
+}
+
+/// Tool call frequency across sessions
+#[derive(Debug, Serialize)]
+pub struct ToolUsageStats {
+    pub total_sessions_scanned: usize,
+    pub total_tool_calls: usize,
+    pub tools: Vec<ToolUsage>,
+}
+
+/// Number of sessions started on a day
+#[derive(Debug, Serialize, PartialEq)]
+pub struct DateCount {
+    /// `YYYY-MM-DD`, in UTC
+    pub date: String,
+    pub count: usize,
+}
+
+/// When sessions were started, bucketed by their `created_at` in UTC
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ActivityHeatmap {
+    pub by_hour: [usize; 24],
+    /// Monday first
+    pub by_day_of_week: [usize; 7],
+    /// Days with at least one session, oldest first
+    pub by_date: Vec<DateCount>,
+    /// Sessions left out because they have no `created_at`
+    pub skipped_sessions: usize,
+}
+
+/// Sessions of one project over time, oldest first
+#[derive(Debug, Serialize)]
+pub struct ProjectTimeline {
+    pub project_path: String,
+    /// Periods with at least one session, oldest first
+    pub buckets: Vec<TimelineBucket>,
+    pub total_sessions: usize,
+    pub total_messages: usize,
+}
+
+/// Sessions started in one day, week or month
+#[derive(Debug, Serialize, PartialEq)]
+pub struct TimelineBucket {
+    /// `YYYY-MM-DD` for a day, the Monday starting a week, `YYYY-MM` for a month
+    pub date: String,
+    pub session_count: usize,
+    pub message_count: usize,
+    /// Files mentioned in this period's sessions but none before, in order
+    pub new_files: Vec<String>,
+}
+
+/// Period `bucket_sessions_by_date` groups sessions by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BucketSize {
+    #[default]
+    Day,
+    /// Monday to Sunday
+    Week,
+    Month,
+}
+
+impl BucketSize {
+    /// Label of the period containing `date`
+    fn label(self, date: chrono::NaiveDate) -> String {
+        match self {
+            Self::Day => date.format("%Y-%m-%d").to_string(),
+            Self::Week => {
+                let monday = date - chrono::Duration::days(date.weekday().num_days_from_monday().into());
+                monday.format("%Y-%m-%d").to_string()
             }
+            Self::Month => date.format("%Y-%m").to_string(),
         }
+    }
+}
 
-        // Sort by updated_at descending (most recent first)
-        sessions.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
-
-        // Apply limit
-        sessions.truncate(limit.min(100));
+impl std::str::FromStr for BucketSize {
+    type Err = anyhow::Error;
 
-        Ok(sessions)
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "day" => Ok(Self::Day),
+            "week" => Ok(Self::Week),
+            "month" => Ok(Self::Month),
+            other => anyhow::bail!("Unknown bucket: {} (expected \"day\", \"week\" or \"month\")", other),
+        }
     }
+}
 
-    /// Search sessions by keyword
-    pub fn search_sessions(&self, query: &str, limit: usize) -> Result<Vec<SessionSummary>> {
-        let matcher = SkimMatcherV2::default();
-        let mut results: Vec<(i64, SessionSummary)> = Vec::new();
-        let projects_dir = self.base_path.join("projects");
+/// Sessions started in one hour of the day
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+pub struct HourStats {
+    pub hour: u8,
+    pub session_count: usize,
+    pub total_messages: usize,
+    pub avg_messages: f64,
+}
 
-        if !projects_dir.exists() {
-            return Ok(vec![]);
+/// Sessions by the local hour of day they started
+#[derive(Debug, Serialize, PartialEq)]
+pub struct HourlyUsage {
+    /// Midnight first
+    pub hours: [HourStats; 24],
+    /// Zone the hours are in, e.g. `Europe/Berlin` or `UTC+02:00`
+    pub timezone: String,
+    /// Sessions left out because they have no `created_at`
+    pub unknown_time: usize,
+    /// Why the hours aren't in the zone the user expects, if they might not be
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// Aggregate metrics across all sessions
+#[derive(Debug, Serialize, PartialEq)]
+pub struct SessionStatistics {
+    pub total_sessions: usize,
+    pub total_messages: usize,
+    pub avg_messages_per_session: f64,
+    pub total_user_messages: usize,
+    pub total_assistant_messages: usize,
+    /// Day (YYYY-MM-DD) on which the most sessions were active
+    pub busiest_day: Option<String>,
+    /// Project with the most sessions
+    pub most_active_project: Option<String>,
+    /// Earliest and latest activity across all sessions
+    pub date_range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    pub total_cost_usd: f64,
+    /// Average over the sessions that recorded a cost
+    pub avg_cost_usd: f64,
+}
+
+/// Running totals for `SessionStatistics`, fed one session at a time so a
+/// full scan never holds every session in memory
+#[derive(Default)]
+struct StatisticsBuilder {
+    total_sessions: usize,
+    total_messages: usize,
+    total_user_messages: usize,
+    total_assistant_messages: usize,
+    sessions_per_day: std::collections::BTreeMap<String, usize>,
+    sessions_per_project: std::collections::BTreeMap<String, usize>,
+    earliest: Option<DateTime<Utc>>,
+    latest: Option<DateTime<Utc>>,
+    total_cost_usd: f64,
+    sessions_with_cost: usize,
+}
+
+impl StatisticsBuilder {
+    fn add(&mut self, session: &Session) {
+        self.total_sessions += 1;
+        self.total_messages += session.messages.len();
+        self.total_user_messages += session.messages.iter().filter(|m| m.role == "user").count();
+        self.total_assistant_messages +=
+            session.messages.iter().filter(|m| m.role == "assistant").count();
+
+        // A session counts once for every day it has activity on
+        let days: std::collections::BTreeSet<String> = session
+            .messages
+            .iter()
+            .filter_map(|m| m.timestamp)
+            .chain(session.created_at)
+            .chain(session.updated_at)
+            .map(|t| t.format("%Y-%m-%d").to_string())
+            .collect();
+        for day in days {
+            *self.sessions_per_day.entry(day).or_insert(0) += 1;
         }
 
-        for entry in WalkDir::new(&projects_dir)
-            .max_depth(3)
-            .follow_links(true)
-            .into_iter()
-            .filter_map(|e| e.ok())
-        {
-            let path = entry.path();
-            if path.is_file() && path.extension().is_some_and(|e| e == "jsonl") {
-                if path.file_name().map_or(false, |n| n.to_string_lossy().starts_with("agent-")) {
-                    continue;
-                }
-                if let Ok(Some(session)) = self.try_parse_jsonl_session(path) {
-                    // Search through all message content
-                    let full_text: String = session
-                        .messages
-                        .iter()
-                        .map(|m| m.content.as_str())
-                        .collect::<Vec<_>>()
-                        .join(" ");
-
-                    if let Some(score) = matcher.fuzzy_match(&full_text, query) {
-                        results.push((score, session_to_summary(&session)));
-                    }
-                }
-            }
+        if let Some(project) = &session.project_path {
+            *self.sessions_per_project.entry(project.clone()).or_insert(0) += 1;
         }
 
-        // Sort by match score descending
-        results.sort_by(|a, b| b.0.cmp(&a.0));
+        if let Some(cost) = session.total_cost_usd {
+            self.total_cost_usd += cost;
+            self.sessions_with_cost += 1;
+        }
 
-        // Apply limit and extract just the summaries
-        Ok(results
-            .into_iter()
-            .take(limit.min(50))
-            .map(|(_, s)| s)
-            .collect())
+        let first = session.created_at.or(session.updated_at);
+        let last = session.updated_at.or(session.created_at);
+        self.earliest = match (self.earliest, first) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.latest = match (self.latest, last) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        };
     }
 
-    /// Get full session by ID
-    pub fn get_session(&self, session_id: &str) -> Result<Option<Session>> {
-        let projects_dir = self.base_path.join("projects");
+    fn finish(self) -> SessionStatistics {
+        // Ties go to the earliest day / first project alphabetically
+        let most = |counts: std::collections::BTreeMap<String, usize>| {
+            counts
+                .into_iter()
+                .fold(None, |best: Option<(String, usize)>, (key, count)| match best {
+                    Some((_, best_count)) if best_count >= count => best,
+                    _ => Some((key, count)),
+                })
+                .map(|(key, _)| key)
+        };
 
-        if !projects_dir.exists() {
-            return Ok(None);
+        SessionStatistics {
+            total_sessions: self.total_sessions,
+            total_messages: self.total_messages,
+            avg_messages_per_session: if self.total_sessions == 0 {
+                0.0
+            } else {
+                self.total_messages as f64 / self.total_sessions as f64
+            },
+            total_user_messages: self.total_user_messages,
+            total_assistant_messages: self.total_assistant_messages,
+            busiest_day: most(self.sessions_per_day),
+            most_active_project: most(self.sessions_per_project),
+            date_range: self.earliest.zip(self.latest),
+            total_cost_usd: self.total_cost_usd,
+            avg_cost_usd: if self.sessions_with_cost == 0 {
+                0.0
+            } else {
+                self.total_cost_usd / self.sessions_with_cost as f64
+            },
         }
+    }
+}
 
-        for entry in WalkDir::new(&projects_dir)
-            .max_depth(3)
-            .follow_links(true)
-            .into_iter()
-            .filter_map(|e| e.ok())
-        {
-            let path = entry.path();
-            if path.is_file() && path.extension().is_some_and(|e| e == "jsonl") {
-                // Check if filename matches session_id
-                if let Some(stem) = path.file_stem() {
-                    if stem.to_string_lossy() == session_id {
-                        if let Ok(Some(session)) = self.try_parse_jsonl_session(path) {
-                            return Ok(Some(session));
-                        }
-                    }
-                }
-            }
+/// How `search_sessions` matches the query against session content
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchMode {
+    /// Typo-tolerant fuzzy matching, ranked by match score
+    #[default]
+    Fuzzy,
+    /// Regular expression matching, ranked by number of matches
+    Regex,
+    /// AND/OR/NOT over case-insensitive terms (see `query`), ranked by how
+    /// often the required terms occur
+    Boolean,
+    /// Cosine similarity of embedding vectors (see `embeddings`), ranked by
+    /// similarity
+    Semantic,
+}
+
+impl std::str::FromStr for SearchMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "fuzzy" => Ok(Self::Fuzzy),
+            "regex" => Ok(Self::Regex),
+            "boolean" => Ok(Self::Boolean),
+            "semantic" => Ok(Self::Semantic),
+            other => anyhow::bail!(
+                "Unknown search_mode: {} (expected \"fuzzy\", \"regex\", \"boolean\" or \"semantic\")",
+                other
+            ),
         }
-        Ok(None)
     }
+}
 
-    /// Get context summary of a session
-    pub fn get_session_context(&self, session_id: &str) -> Result<Option<SessionContext>> {
-        if let Some(session) = self.get_session(session_id)? {
-            let initial_request = session
-                .messages
-                .iter()
-                .find(|m| m.role == "user")
-                .map(|m| {
-                    let content: String = m.content.chars().take(500).collect();
-                    if m.content.len() > 500 {
-                        format!("{}...", content)
-                    } else {
-                        content
-                    }
-                });
+/// What else `search_sessions` does besides matching message text
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SearchOptions {
+    /// Attach a `match_context` snippet to each result
+    pub highlight: bool,
+    /// Leave out matches scoring below this
+    pub min_score: Option<i64>,
+    /// Also match the raw JSON input of tool calls, such as file paths and
+    /// shell commands
+    pub include_tool_inputs: bool,
+    /// Only match messages with this role (case-sensitive)
+    pub role_filter: Option<String>,
+    /// Only match sessions last updated at or after this
+    pub since: Option<DateTime<Utc>>,
+    /// Only match sessions last updated at or before this
+    pub until: Option<DateTime<Utc>>,
+}
 
-            // Extract file paths mentioned
-            let files_mentioned = extract_file_paths(&session);
+/// Field `list_sessions` orders sessions by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortField {
+    #[default]
+    UpdatedAt,
+    CreatedAt,
+    MessageCount,
+    /// Sessions without a project path come last, ordered by `updated_at`
+    ProjectPath,
+}
 
-            // Extract key terms (simple word frequency)
-            let key_terms = extract_key_terms(&session);
+impl std::str::FromStr for SortField {
+    type Err = anyhow::Error;
 
-            return Ok(Some(SessionContext {
-                id: session.id,
-                cwd: session.cwd,
-                initial_request,
-                message_count: session.messages.len(),
-                files_mentioned,
-                key_terms,
-            }));
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "updated_at" => Ok(Self::UpdatedAt),
+            "created_at" => Ok(Self::CreatedAt),
+            "message_count" => Ok(Self::MessageCount),
+            "project_path" => Ok(Self::ProjectPath),
+            other => anyhow::bail!(
+                "Unknown sort_by: {} (expected \"updated_at\", \"created_at\", \"message_count\" or \"project_path\")",
+                other
+            ),
         }
-        Ok(None)
     }
+}
 
-    /// Parse a JSONL session file (Claude Code's actual format)
-    fn try_parse_jsonl_session(&self, path: &Path) -> Result<Option<Session>> {
-        let content = std::fs::read_to_string(path)?;
-        let lines: Vec<&str> = content.lines().collect();
+/// Direction `list_sessions` orders sessions in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortOrder {
+    #[default]
+    Desc,
+    Asc,
+}
 
-        if lines.is_empty() {
-            return Ok(None);
+impl SortOrder {
+    fn apply(self, ordering: std::cmp::Ordering) -> std::cmp::Ordering {
+        match self {
+            Self::Desc => ordering.reverse(),
+            Self::Asc => ordering,
         }
+    }
+}
 
-        let mut messages = Vec::new();
-        let mut session_id: Option<String> = None;
-        let mut cwd: Option<String> = None;
-        let mut first_timestamp: Option<DateTime<Utc>> = None;
-        let mut last_timestamp: Option<DateTime<Utc>> = None;
+impl std::str::FromStr for SortOrder {
+    type Err = anyhow::Error;
 
-        for line in lines {
-            if line.trim().is_empty() {
-                continue;
-            }
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "desc" => Ok(Self::Desc),
+            "asc" => Ok(Self::Asc),
+            other => anyhow::bail!("Unknown sort_order: {} (expected \"desc\" or \"asc\")", other),
+        }
+    }
+}
 
-            let value: serde_json::Value = match serde_json::from_str(line) {
-                Ok(v) => v,
-                Err(_) => continue,
-            };
+/// How `list_sessions` orders sessions. Ties on the field are broken by
+/// `updated_at`, then ID, in the same direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SortOptions {
+    pub field: SortField,
+    pub order: SortOrder,
+}
 
-            // Get session ID from any entry
-            if session_id.is_none() {
-                session_id = value.get("sessionId")
-                    .and_then(|v| v.as_str())
-                    .map(|s| s.to_string());
-            }
+impl SortOptions {
+    /// Sort `sessions` in place
+    pub fn sort(&self, sessions: &mut [SessionSummary]) {
+        sessions.sort_by(|a, b| self.compare(&SortKey::from(a), &SortKey::from(b)));
+    }
 
-            // Get cwd from any entry
-            if cwd.is_none() {
-                cwd = value.get("cwd")
-                    .and_then(|v| v.as_str())
-                    .map(|s| s.to_string());
-            }
+    fn compare(&self, a: &SortKey, b: &SortKey) -> std::cmp::Ordering {
+        let tie_break = || self.order.apply((a.updated_at, a.id).cmp(&(b.updated_at, b.id)));
+        match self.field {
+            SortField::UpdatedAt => tie_break(),
+            SortField::CreatedAt => self.order.apply(a.created_at.cmp(&b.created_at)).then_with(tie_break),
+            SortField::MessageCount => self.order.apply(a.message_count.cmp(&b.message_count)).then_with(tie_break),
+            // Kept apart so the order stays total when only some sessions have a path
+            SortField::ProjectPath => match (a.project_path, b.project_path) {
+                (Some(a_path), Some(b_path)) => self.order.apply(a_path.cmp(b_path)).then_with(tie_break),
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (None, None) => tie_break(),
+            },
+        }
+    }
+}
 
-            // Parse timestamp
-            let timestamp = value.get("timestamp")
-                .and_then(|v| v.as_str())
-                .and_then(|s| s.parse::<DateTime<Utc>>().ok());
+/// The fields of a session summary that `SortOptions` compares
+struct SortKey<'a> {
+    updated_at: Option<&'a str>,
+    created_at: Option<&'a str>,
+    message_count: usize,
+    project_path: Option<&'a str>,
+    id: &'a str,
+}
 
-            if let Some(ts) = timestamp {
-                if first_timestamp.is_none() || Some(ts) < first_timestamp {
-                    first_timestamp = Some(ts);
-                }
-                if last_timestamp.is_none() || Some(ts) > last_timestamp {
-                    last_timestamp = Some(ts);
-                }
-            }
+impl<'a> From<&'a SessionSummary> for SortKey<'a> {
+    fn from(s: &'a SessionSummary) -> Self {
+        Self {
+            updated_at: s.updated_at.as_deref(),
+            created_at: s.created_at.as_deref(),
+            message_count: s.message_count,
+            project_path: s.project_path.as_deref(),
+            id: &s.id,
+        }
+    }
+}
 
-            // Only process user and assistant messages
-            let msg_type = value.get("type").and_then(|v| v.as_str()).unwrap_or("");
-            if msg_type != "user" && msg_type != "assistant" {
-                continue;
-            }
+/// Which sessions `list_sessions` lists, and in what order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ListOptions {
+    /// Also list agent sub-sessions
+    pub include_agents: bool,
+    /// Leave out sessions with fewer messages than this
+    pub min_messages: Option<usize>,
+    pub sort: SortOptions,
+}
 
-            // Extract role and content from the message field
-            if let Some(message) = value.get("message") {
-                let role = message.get("role")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or(msg_type)
-                    .to_string();
+/// Output format of `get_session` and `copy_session_to_clipboard`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SessionFormat {
+    /// Session ID, project and messages as JSON
+    #[default]
+    Json,
+    /// `session_to_markdown`
+    Markdown,
+    /// `session_to_plain_text`
+    PlainText,
+}
 
-                let content = extract_message_content(message);
+impl std::str::FromStr for SessionFormat {
+    type Err = anyhow::Error;
 
-                if !content.is_empty() {
-                    messages.push(Message {
-                        role,
-                        content,
-                        timestamp,
-                    });
-                }
-            }
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "json" => Ok(Self::Json),
+            "markdown" => Ok(Self::Markdown),
+            "plain_text" => Ok(Self::PlainText),
+            other => anyhow::bail!(
+                "Unknown format: {} (expected \"json\", \"markdown\" or \"plain_text\")",
+                other
+            ),
         }
+    }
+}
 
-        if messages.is_empty() {
-            return Ok(None);
-        }
+/// How `get_session` lays out each message's content
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ContentFormat {
+    /// `content` as one string, tool calls and results summarized in line
+    #[default]
+    Flat,
+    /// `parts`, one per content block (see `MessagePart`)
+    Typed,
+}
 
-        // Use filename as session ID if not found in content
-        let id = session_id.unwrap_or_else(|| {
-            path.file_stem()
-                .map(|s| s.to_string_lossy().to_string())
-                .unwrap_or_else(|| uuid_from_path(path))
-        });
+impl std::str::FromStr for ContentFormat {
+    type Err = anyhow::Error;
 
-        Ok(Some(Session {
-            id,
-            project_path: extract_project_path(path),
-            cwd,
-            created_at: first_timestamp,
-            updated_at: last_timestamp.or_else(|| {
-                path.metadata()
-                    .ok()
-                    .and_then(|m| m.modified().ok())
-                    .map(DateTime::from)
-            }),
-            messages,
-            file_path: path.to_path_buf(),
-        }))
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "flat" => Ok(Self::Flat),
+            "typed" => Ok(Self::Typed),
+            other => anyhow::bail!("Unknown content_format: {} (expected \"flat\" or \"typed\")", other),
+        }
     }
 }
 
-/// Extract content from a message object (handles both string and array content formats)
-fn extract_message_content(message: &serde_json::Value) -> String {
-    if let Some(content) = message.get("content") {
-        // String content
-        if let Some(s) = content.as_str() {
-            return s.to_string();
-        }
+/// How `sessions_touching_file` compares a query path against paths in a session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FileMatchMode {
+    /// The paths are identical
+    Exact,
+    /// The query matches the trailing components of the path (`main.rs` matches `src/main.rs`)
+    #[default]
+    Suffix,
+    /// The query appears anywhere in the path
+    Contains,
+}
 
-        // Array content (assistant messages with tool_use, text blocks, etc.)
-        if let Some(arr) = content.as_array() {
-            let mut parts = Vec::new();
-            for item in arr {
-                // Text block: {"type": "text", "text": "..."}
-                if let Some(text) = item.get("text").and_then(|t| t.as_str()) {
-                    parts.push(text.to_string());
-                }
-                // Tool use block: extract tool name and input summary
-                else if item.get("type").and_then(|t| t.as_str()) == Some("tool_use") {
-                    if let Some(name) = item.get("name").and_then(|n| n.as_str()) {
-                        let input_summary = item.get("input")
-                            .map(|i| {
-                                if let Some(fp) = i.get("file_path").and_then(|f| f.as_str()) {
-                                    format!(" on {}", fp)
-                                } else if let Some(cmd) = i.get("command").and_then(|c| c.as_str()) {
-                                    let cmd_preview: String = cmd.chars().take(50).collect();
-                                    format!(": {}", cmd_preview)
-                                } else {
-                                    String::new()
-                                }
-                            })
-                            .unwrap_or_default();
-                        parts.push(format!("[Tool: {}{}]", name, input_summary));
-                    }
-                }
-            }
-            return parts.join("\n");
+impl FileMatchMode {
+    fn matches(self, candidate: &str, query: &str) -> bool {
+        match self {
+            Self::Exact => candidate == query,
+            Self::Suffix => Path::new(candidate).ends_with(query),
+            Self::Contains => candidate.contains(query),
         }
     }
-    String::new()
 }
 
-/// Extract project path from session file path
-fn extract_project_path(path: &Path) -> Option<String> {
-    let components: Vec<_> = path.components().collect();
-    for (i, comp) in components.iter().enumerate() {
-        if comp.as_os_str() == "projects" && i + 1 < components.len() {
-            let project_hash = components[i + 1].as_os_str().to_string_lossy().to_string();
-            // Convert hash back to readable path if it starts with -
-            if project_hash.starts_with('-') {
-                return Some(project_hash.replace('-', "/"));
-            }
-            return Some(project_hash);
+impl std::str::FromStr for FileMatchMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "exact" => Ok(Self::Exact),
+            "suffix" => Ok(Self::Suffix),
+            "contains" => Ok(Self::Contains),
+            other => anyhow::bail!(
+                "Unknown match_mode: {} (expected \"exact\", \"suffix\" or \"contains\")",
+                other
+            ),
         }
     }
-    None
 }
 
-/// Generate a UUID-like string from path for sessions without explicit ID
-fn uuid_from_path(path: &Path) -> String {
-    use std::hash::{Hash, Hasher};
-    let mut hasher = std::collections::hash_map::DefaultHasher::new();
-    path.hash(&mut hasher);
-    format!("{:016x}", hasher.finish())
+/// Summary of a project directory and the sessions it contains
+#[derive(Debug, Serialize)]
+pub struct ProjectSummary {
+    pub project_path: String,
+    pub session_count: usize,
+    pub last_active: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cwds: Option<Vec<String>>,
 }
 
-/// Convert session to summary
-fn session_to_summary(session: &Session) -> SessionSummary {
-    let preview = session
-        .messages
-        .iter()
-        .find(|m| m.role == "user")
-        .map(|m| {
-            let content: String = m.content.chars().take(200).collect();
-            if m.content.len() > 200 {
-                format!("{}...", content)
-            } else {
-                content
-            }
-        })
-        .unwrap_or_else(|| "No preview available".to_string());
+/// A line of a session file as stored, for `get_session` with `raw`
+#[derive(Debug, Serialize)]
+pub struct RawLine {
+    /// 1-based line number, as in `validate_session` reports
+    pub index: usize,
+    pub content: String,
+    /// Whether the line became a message. Invalid JSON, entries that aren't
+    /// user or assistant messages, and empty messages are all skipped.
+    pub parsed: bool,
+}
 
-    SessionSummary {
-        id: session.id.clone(),
-        project_path: session.project_path.clone(),
-        cwd: session.cwd.clone(),
-        created_at: session.created_at.map(|dt| dt.to_rfc3339()),
-        updated_at: session.updated_at.map(|dt| dt.to_rfc3339()),
-        message_count: session.messages.len(),
-        preview,
-    }
+/// The non-blank lines of a session file, up to a size limit
+#[derive(Debug, Serialize)]
+pub struct RawLines {
+    pub raw_lines: Vec<RawLine>,
+    /// Whether lines were left out, or the last one cut short, to stay within the limit
+    pub truncated: bool,
 }
 
-/// Extract file paths mentioned in session
-fn extract_file_paths(session: &Session) -> Vec<String> {
-    use std::collections::HashSet;
-    let mut paths = HashSet::new();
+/// Result of checking a session file for structural problems
+#[derive(Debug, Serialize)]
+pub struct ValidationReport {
+    pub valid: bool,
+    pub warnings: Vec<String>,
+    pub errors: Vec<String>,
+    pub line_count: usize,
+    pub parseable_lines: usize,
+}
 
-    for msg in &session.messages {
-        for word in msg.content.split_whitespace() {
-            if (word.contains('/') || word.contains('\\'))
-                && (word.contains('.') || word.ends_with('/'))
-            {
-                let cleaned = word.trim_matches(|c: char| {
-                    !c.is_alphanumeric() && c != '/' && c != '\\' && c != '.' && c != '_' && c != '-'
-                });
-                if cleaned.len() > 3 {
-                    paths.insert(cleaned.to_string());
-                }
-            }
+/// Entry `type` values Claude Code writes to session files
+const KNOWN_ENTRY_TYPES: &[&str] = &[
+    "user",
+    "assistant",
+    "system",
+    "summary",
+    "attachment",
+    "progress",
+    "file-history-snapshot",
+    "queue-operation",
+    "last-prompt",
+];
+
+/// A page of results with an opaque cursor for fetching the next page
+#[derive(Debug, Serialize)]
+pub struct PaginatedResult<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+/// A page of whole sessions, from `list_sessions` with `full_content`
+#[derive(Debug, Serialize)]
+pub struct FullSessionPage {
+    pub items: Vec<Session>,
+    pub next_cursor: Option<String>,
+    /// Size of `items` serialized as JSON
+    pub total_bytes: usize,
+}
+
+/// Most sessions on a `FullSessionPage`
+pub const MAX_FULL_CONTENT_SESSIONS: usize = 10;
+
+/// Largest `FullSessionPage::total_bytes` returned; bigger pages are refused
+pub const MAX_FULL_CONTENT_BYTES: usize = 1024 * 1024;
+
+/// Sessions of one project, most recently updated first
+#[derive(Debug, Serialize)]
+pub struct ProjectGroup {
+    pub project_path: Option<String>,
+    /// Sessions in the project, including any cut by the per-group limit
+    pub session_count: usize,
+    pub sessions: Vec<SessionSummary>,
+}
+
+/// Sessions grouped by project, most recently active project first
+#[derive(Debug, Serialize)]
+pub struct GroupedSessionsResult {
+    pub groups: Vec<ProjectGroup>,
+    /// Projects with sessions, including any cut by the project limit
+    pub total_groups: usize,
+}
+
+/// A page of search results, like `PaginatedResult` plus what was searched
+/// for and how many sessions matched across all pages
+#[derive(Debug, Serialize)]
+pub struct SearchResult<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+    pub query: String,
+    pub search_mode: SearchMode,
+    pub total_matched: usize,
+}
+
+/// Position of the last search result returned in a page, encoded as base64
+/// JSON. Results are ordered by score, best first, then by session ID, then
+/// in regex mode by session file path.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct SearchCursor {
+    score: i64,
+    session_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+}
+
+impl SearchCursor {
+    pub(crate) fn new(score: i64, session_id: &str, path: Option<&Path>) -> Self {
+        Self {
+            score,
+            session_id: session_id.to_string(),
+            path: path.map(|p| p.to_string_lossy().into_owned()),
         }
     }
 
-    let mut result: Vec<_> = paths.into_iter().collect();
-    result.sort();
-    result.truncate(20);
-    result
+    fn sort_key(&self) -> (std::cmp::Reverse<i64>, &str, Option<&str>) {
+        (std::cmp::Reverse(self.score), &self.session_id, self.path.as_deref())
+    }
 }
 
-/// Extract key terms from session (simple word frequency)
-fn extract_key_terms(session: &Session) -> Vec<String> {
-    use std::collections::HashMap;
+/// Order search matches best first and cut out the page of up to `limit`
+/// that follows `cursor`. Returns the page, the cursor for the next one and
+/// the number of matches across all pages.
+pub(crate) fn paginate_matches<T>(
+    mut matches: Vec<(SearchCursor, T)>,
+    cursor: Option<&str>,
+    limit: usize,
+) -> Result<(PaginatedResult<T>, usize)> {
+    let cursor: Option<SearchCursor> = cursor.map(decode_cursor).transpose()?;
+    matches.sort_by(|a, b| a.0.sort_key().cmp(&b.0.sort_key()));
+    let total_matched = matches.len();
 
-    let stop_words: std::collections::HashSet<&str> = [
-        "the", "a", "an", "is", "are", "was", "were", "be", "been", "being",
-        "have", "has", "had", "do", "does", "did", "will", "would", "could",
-        "should", "may", "might", "must", "shall", "can", "need", "dare",
-        "ought", "used", "to", "of", "in", "for", "on", "with", "at", "by",
-        "from", "as", "into", "through", "during", "before", "after", "above",
-        "below", "between", "under", "again", "further", "then", "once", "here",
-        "there", "when", "where", "why", "how", "all", "each", "few", "more",
-        "most", "other", "some", "such", "no", "nor", "not", "only", "own",
-        "same", "so", "than", "too", "very", "just", "and", "but", "if", "or",
-        "because", "until", "while", "this", "that", "these", "those", "i", "you",
-        "he", "she", "it", "we", "they", "what", "which", "who", "whom", "its",
-        "his", "her", "their", "my", "your", "our", "tool", "file", "path",
-    ]
-    .iter()
-    .copied()
-    .collect();
+    // Resume strictly after the last result of the previous page
+    if let Some(cursor) = &cursor {
+        matches.retain(|(position, _)| position.sort_key() > cursor.sort_key());
+    }
 
-    let mut word_counts: HashMap<String, usize> = HashMap::new();
+    let next_cursor = if matches.len() > limit {
+        matches.truncate(limit);
+        matches.last().map(|(position, _)| encode_cursor(position))
+    } else {
+        None
+    };
+    let page = PaginatedResult {
+        items: matches.into_iter().map(|(_, item)| item).collect(),
+        next_cursor,
+    };
+    Ok((page, total_matched))
+}
 
-    for msg in &session.messages {
-        for word in msg.content.split_whitespace() {
-            let cleaned: String = word
-                .chars()
-                .filter(|c| c.is_alphanumeric())
-                .collect::<String>()
-                .to_lowercase();
+fn encode_cursor<T: Serialize>(cursor: &T) -> String {
+    URL_SAFE_NO_PAD.encode(serde_json::to_vec(cursor).unwrap_or_default())
+}
 
-            if cleaned.len() > 3 && !stop_words.contains(cleaned.as_str()) {
-                *word_counts.entry(cleaned).or_insert(0) += 1;
+fn decode_cursor<T: serde::de::DeserializeOwned>(cursor: &str) -> Result<T> {
+    let bytes = URL_SAFE_NO_PAD
+        .decode(cursor)
+        .context("Invalid cursor: not base64")?;
+    serde_json::from_slice(&bytes).context("Invalid cursor: malformed payload")
+}
+
+/// Position of the last session returned in a page, encoded as base64 JSON.
+/// Carries every field a page can be sorted by.
+#[derive(Debug, Serialize, Deserialize)]
+struct SessionCursor {
+    updated_at: Option<String>,
+    id: String,
+    #[serde(default)]
+    created_at: Option<String>,
+    #[serde(default)]
+    message_count: usize,
+    #[serde(default)]
+    project_path: Option<String>,
+}
+
+impl<'a> From<&'a SessionCursor> for SortKey<'a> {
+    fn from(c: &'a SessionCursor) -> Self {
+        Self {
+            updated_at: c.updated_at.as_deref(),
+            created_at: c.created_at.as_deref(),
+            message_count: c.message_count,
+            project_path: c.project_path.as_deref(),
+            id: &c.id,
+        }
+    }
+}
+
+/// Session files by the decoded path of the project they belong to, found
+/// from directory listings alone so no session has to be parsed
+#[derive(Debug, Default)]
+pub struct ProjectIndex {
+    projects: std::collections::HashMap<String, Vec<PathBuf>>,
+}
+
+impl ProjectIndex {
+    /// Index the top-level session files (agent sub-sessions excluded) of
+    /// every project directory under `projects_dir`
+    pub fn build(projects_dir: &Path) -> Self {
+        let mut projects = std::collections::HashMap::new();
+        let Ok(entries) = std::fs::read_dir(projects_dir) else {
+            return Self { projects };
+        };
+
+        for entry in entries.filter_map(|e| e.ok()) {
+            let dir = entry.path();
+            if !dir.is_dir() {
+                continue;
             }
+            let project_path = decode_project_hash(&entry.file_name().to_string_lossy());
+            // Same depth as `SessionStore::session_files`, measured from the project
+            let files: Vec<PathBuf> = WalkDir::new(&dir)
+                .min_depth(1)
+                .max_depth(2)
+                .follow_links(true)
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .map(|e| e.into_path())
+                .filter(|path| {
+                    path.is_file()
+                        && is_session_file(path)
+                        && !path.file_name().is_some_and(|n| n.to_string_lossy().starts_with("agent-"))
+                })
+                .collect();
+            projects.entry(project_path).or_insert_with(Vec::new).extend(files);
         }
+
+        Self { projects }
     }
 
-    let mut sorted: Vec<_> = word_counts.into_iter().collect();
-    sorted.sort_by(|a, b| b.1.cmp(&a.1));
+    /// Session files of projects at or under `prefix`, comparing whole path components
+    pub fn files_under(&self, prefix: &str) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = self
+            .projects
+            .iter()
+            .filter(|(project, _)| Path::new(project).starts_with(prefix))
+            .flat_map(|(_, files)| files.iter().cloned())
+            .collect();
+        files.sort();
+        files
+    }
 
-    sorted.into_iter().take(15).map(|(word, _)| word).collect()
+    /// Session files whose ID starts with `prefix`, sorted
+    pub fn files_with_id_prefix(&self, prefix: &str) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = self
+            .projects
+            .values()
+            .flatten()
+            .filter(|path| session_file_stem(path).is_some_and(|stem| stem.starts_with(prefix)))
+            .cloned()
+            .collect();
+        files.sort();
+        files
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Top-level sessions parsed by a full rescan, and the files that couldn't be
+#[derive(Debug, Default)]
+pub struct SessionScan {
+    pub sessions: Vec<Session>,
+    /// Each file that failed to parse, with the reason
+    pub failed: Vec<(PathBuf, String)>,
+}
 
-    #[test]
-    fn test_extract_message_content_string() {
-        let message = serde_json::json!({
-            "role": "user",
-            "content": "Hello world"
-        });
-        assert_eq!(extract_message_content(&message), "Hello world");
+/// Claude Code session storage handler. Clones share one project index.
+#[derive(Clone)]
+pub struct SessionStore {
+    base_path: PathBuf,
+    /// Built on first use by a project-filtered query; see `invalidate_index`
+    project_index: Arc<Mutex<Option<Arc<ProjectIndex>>>>,
+}
+
+impl SessionStore {
+    /// Create a new session store over the Claude Code directory at
+    /// `base_path`, or at `~/.claude` when none is given
+    pub fn new(base_path: Option<PathBuf>) -> Result<Self> {
+        let claude_dir = match base_path {
+            Some(path) => path,
+            None => dirs::home_dir().context("Could not find home directory")?.join(".claude"),
+        };
+
+        if !claude_dir.exists() {
+            anyhow::bail!(
+                "Claude Code directory not found at {}. \
+                 Make sure Claude Code CLI is installed and has been used at least once.",
+                claude_dir.display()
+            );
+        }
+
+        Ok(Self::with_path(claude_dir))
     }
 
-    #[test]
-    fn test_extract_message_content_array() {
-        let message = serde_json::json!({
-            "role": "assistant",
-            "content": [
-                {"type": "text", "text": "Here is the result"},
-                {"type": "tool_use", "name": "Write", "input": {"file_path": "/test/file.rs"}}
-            ]
-        });
-        let content = extract_message_content(&message);
-        assert!(content.contains("Here is the result"));
-        assert!(content.contains("[Tool: Write on /test/file.rs]"));
+    /// Create a session store reading from the Claude Code directory at `base_path`
+    pub fn with_path(base_path: PathBuf) -> Self {
+        Self {
+            base_path,
+            project_index: Arc::new(Mutex::new(None)),
+        }
     }
 
-    #[test]
-    fn test_extract_project_path() {
+    /// The Claude Code directory this store reads from
+    pub fn base_path(&self) -> &Path {
+        &self.base_path
+    }
+
+    /// Drop the project index, so the next query that needs it builds it
+    /// again with the projects and session files created since
+    pub fn invalidate_index(&self) {
+        *self.project_index.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    /// The project index, built now if no query has needed it yet
+    fn project_index(&self) -> Arc<ProjectIndex> {
+        let mut index = self.project_index.lock().unwrap_or_else(|e| e.into_inner());
+        index
+            .get_or_insert_with(|| Arc::new(ProjectIndex::build(&self.base_path.join("projects"))))
+            .clone()
+    }
+
+    /// IDs of the sessions in projects at or under `prefix`, taken from file
+    /// names without parsing any session
+    pub fn session_ids_for_project_prefix(&self, prefix: &str) -> Vec<String> {
+        self.project_index()
+            .files_under(prefix)
+            .iter()
+            .filter_map(|path| session_file_stem(path))
+            .collect()
+    }
+
+    /// Top-level session files, only those in projects at or under
+    /// `project_prefix` if given. Filtered lookups skip other projects'
+    /// directories entirely.
+    fn session_files_in(&self, project_prefix: Option<&str>) -> Vec<PathBuf> {
+        match project_prefix {
+            Some(prefix) => self.project_index().files_under(prefix),
+            None => self.session_files(),
+        }
+    }
+
+    /// Parse every top-level session file like `sessions_in_project(None)`,
+    /// but keeping the reason each file that fails to parse does
+    pub fn scan_sessions(&self) -> SessionScan {
+        let mut scan = SessionScan::default();
+        for path in self.session_files() {
+            match self.try_parse_jsonl_session(&path) {
+                Ok(Some(session)) => scan.sessions.push(session),
+                // Files without messages aren't broken, just not sessions
+                Ok(None) => {}
+                Err(e) => scan.failed.push((path, format!("{:#}", e))),
+            }
+        }
+        scan
+    }
+
+    /// Parse every (non-agent) session in projects at or under
+    /// `project_prefix`, skipping files that fail to parse
+    pub fn sessions_in_project(&self, project_prefix: Option<&str>) -> Vec<Session> {
+        self.session_files_in(project_prefix)
+            .iter()
+            .filter_map(|path| self.try_parse_jsonl_session(path).ok().flatten())
+            .collect()
+    }
+
+    /// List the sessions `options` selects in its order, starting after
+    /// `cursor` if given. When `metadata` is available, each summary carries
+    /// its tags.
+    pub fn list_sessions(
+        &self,
+        limit: usize,
+        cursor: Option<&str>,
+        options: ListOptions,
+        metadata: Option<&MetadataStore>,
+    ) -> Result<PaginatedResult<SessionSummary>> {
+        let summaries = self.session_summaries(options.include_agents, options.min_messages, DEFAULT_PREVIEW_CHARS);
+        paginate_summaries(summaries, limit, cursor, options.sort, metadata)
+    }
+
+    /// List sessions grouped by project: the `project_limit` most recently
+    /// active projects, each with its `limit` most recently updated sessions
+    pub fn list_sessions_grouped(
+        &self,
+        limit: usize,
+        project_limit: usize,
+        include_agents: bool,
+        min_messages: Option<usize>,
+        metadata: Option<&MetadataStore>,
+    ) -> Result<GroupedSessionsResult> {
+        group_summaries(self.session_summaries(include_agents, min_messages, DEFAULT_PREVIEW_CHARS), limit, project_limit, metadata)
+    }
+
+    /// Summaries of every session that parses, agent sub-sessions included
+    /// only with `include_agents`, in no particular order, with previews of
+    /// `preview_length` characters. With a `preview_length` of 0 they are
+    /// `fast_summary`s.
+    pub fn session_summaries(
+        &self,
+        include_agents: bool,
+        min_messages: Option<usize>,
+        preview_length: usize,
+    ) -> Vec<SessionSummary> {
+        let summarize = |session: &Session| session_to_summary(session, preview_length);
+        let mut sessions: Vec<SessionSummary> = self
+            .session_files()
+            .iter()
+            .filter_map(|path| self.try_parse_jsonl_session(path).ok().flatten())
+            .map(|session| summarize(&session))
+            .collect();
+
+        if include_agents {
+            sessions.extend(self.agent_sessions().iter().map(summarize));
+        }
+        // Message counts are only known once the files are parsed
+        if let Some(min_messages) = min_messages {
+            sessions.retain(|s| s.message_count >= min_messages);
+        }
+        sessions
+    }
+
+    /// Parse every agent sub-session, identified by its file name, skipping
+    /// files that fail to parse
+    pub fn agent_sessions(&self) -> Vec<Session> {
+        self.agent_session_files()
+            .iter()
+            .filter_map(|path| self.parse_agent_session(path))
+            .map(|(session, _)| session)
+            .collect()
+    }
+
+    /// List agent sub-sessions, most recently updated first, optionally only
+    /// those spawned by `parent_session_id`
+    pub fn list_agent_sessions(
+        &self,
+        parent_session_id: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<AgentSessionSummary>> {
+        let agent_files = self.agent_session_files();
+        if agent_files.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Top-level sessions by the directory they sit in, to find parents among
+        let mut spans: std::collections::HashMap<PathBuf, Vec<SessionSpan>> =
+            std::collections::HashMap::new();
+        for path in self.session_files() {
+            if let (Some(dir), Ok(Some(session))) = (path.parent(), self.try_parse_jsonl_session(&path)) {
+                spans
+                    .entry(dir.to_path_buf())
+                    .or_default()
+                    .push((session.id, session.created_at, session.updated_at));
+            }
+        }
+
+        let mut agents = Vec::new();
+        for path in agent_files {
+            let Some((session, logged_id)) = self.parse_agent_session(&path) else {
+                continue;
+            };
+            let parent = agent_parent(&path, &session, &logged_id, &spans);
+            if parent_session_id.is_some_and(|id| parent.as_deref() != Some(id)) {
+                continue;
+            }
+            agents.push(AgentSessionSummary {
+                summary: session_to_summary(&session, DEFAULT_PREVIEW_CHARS),
+                parent_session_id: parent,
+            });
+        }
+
+        agents.sort_by(|a, b| {
+            (&b.summary.updated_at, &b.summary.id).cmp(&(&a.summary.updated_at, &a.summary.id))
+        });
+        agents.truncate(limit.min(100));
+        Ok(agents)
+    }
+
+    /// Summaries for the sessions with the given IDs, most recently updated first
+    pub fn sessions_by_ids(&self, ids: &[String]) -> Result<Vec<SessionSummary>> {
+        let mut sessions = Vec::new();
+        let projects_dir = self.base_path.join("projects");
+
+        if ids.is_empty() || !projects_dir.exists() {
+            return Ok(sessions);
+        }
+
+        for entry in WalkDir::new(&projects_dir)
+            .max_depth(3)
+            .follow_links(true)
+            .into_iter()
+            .filter_map(|e| e.ok())
+        {
+            let path = entry.path();
+            if path.is_file() && is_session_file(path) {
+                let Some(stem) = session_file_stem(path) else {
+                    continue;
+                };
+                if !ids.contains(&stem) {
+                    continue;
+                }
+                if let Ok(Some(session)) = self.try_parse_jsonl_session(path) {
+                    sessions.push(session_to_summary(&session, DEFAULT_PREVIEW_CHARS));
+                }
+            }
+        }
+
+        sessions.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+        Ok(sessions)
+    }
+
+    /// Search sessions by keyword or regular expression
+    pub fn search_sessions(
+        &self,
+        query: &str,
+        limit: usize,
+        cursor: Option<&str>,
+        mode: SearchMode,
+        options: SearchOptions,
+    ) -> Result<SearchResult<SessionSummary>> {
+        let SearchOptions {
+            highlight,
+            min_score,
+            include_tool_inputs,
+            role_filter,
+            since,
+            until,
+        } = options;
+        if mode == SearchMode::Semantic {
+            anyhow::bail!("Semantic search needs the query's embedding; use embeddings::semantic_search");
+        }
+        let matcher = SkimMatcherV2::default();
+        let boolean = match mode {
+            SearchMode::Boolean => Some(QueryParser::parse(query)?),
+            _ => None,
+        };
+        let regex = match mode {
+            SearchMode::Regex => Some(Regex::new(query).map_err(|e| {
+                // Keep the regex error in the chain so callers can tell it's bad input
+                let message = format!("Invalid regex: {}", e);
+                anyhow::Error::new(e).context(message)
+            })?),
+            // Boolean matches are ranked and highlighted by their required terms
+            SearchMode::Boolean => boolean.as_ref().map(|q| {
+                let terms: Vec<String> = q.positive_terms().into_iter().map(regex::escape).collect();
+                Regex::new(&format!("(?i){}", terms.join("|"))).expect("escaped terms form a valid regex")
+            }),
+            SearchMode::Fuzzy | SearchMode::Semantic => None,
+        };
+        let mut results: Vec<(SearchCursor, SessionSummary)> = Vec::new();
+        let projects_dir = self.base_path.join("projects");
+
+        for entry in WalkDir::new(&projects_dir)
+            .max_depth(3)
+            .follow_links(true)
+            .into_iter()
+            .filter_map(|e| e.ok())
+        {
+            let path = entry.path();
+            if path.is_file() && is_session_file(path) {
+                if path.file_name().is_some_and(|n| n.to_string_lossy().starts_with("agent-")) {
+                    continue;
+                }
+                if entry.metadata().is_ok_and(|m| !modified_since(&m, since)) {
+                    continue;
+                }
+                if let Ok(Some(session)) = self.try_parse_jsonl_session(path) {
+                    if !updated_in_range(&session, since, until) {
+                        continue;
+                    }
+                    // Search through all message content
+                    let full_text =
+                        extract_full_text_for_search(&session, include_tool_inputs, role_filter.as_deref());
+
+                    let score = match (&boolean, &regex) {
+                        (Some(q), _) if !q.matches(&full_text) => None,
+                        // Matching guarantees a required term, though not every one counted
+                        (Some(_), Some(re)) => Some(re.find_iter(&full_text).count().max(1) as i64),
+                        (_, Some(re)) => match re.find_iter(&full_text).count() {
+                            0 => None,
+                            count => Some(count as i64),
+                        },
+                        _ => matcher.fuzzy_match(&full_text, query),
+                    };
+
+                    if let Some(score) = score.filter(|&score| min_score.is_none_or(|min| score >= min)) {
+                        let mut summary = session_to_summary(&session, DEFAULT_PREVIEW_CHARS);
+                        summary.score = Some(score);
+                        if highlight {
+                            let span = match &regex {
+                                Some(re) => re.find(&full_text).map(|m| (m.start(), m.end())),
+                                None => fuzzy_match_span(&matcher, &full_text, query),
+                            };
+                            summary.match_context = span.map(|(start, end)| {
+                                highlight_match(&full_text, start, end, MATCH_CONTEXT_CHARS)
+                            });
+                        }
+                        // Regex cursors also carry the file, keeping the order stable
+                        // between sessions that share an ID
+                        let file = (mode == SearchMode::Regex).then_some(path);
+                        results.push((SearchCursor::new(score, &summary.id, file), summary));
+                    }
+                }
+            }
+        }
+
+        // Everything matching was collected above; only now cut out the page
+        let (page, total_matched) = paginate_matches(results, cursor, limit.min(50))?;
+        Ok(SearchResult {
+            items: page.items,
+            next_cursor: page.next_cursor,
+            query: query.to_string(),
+            search_mode: mode,
+            total_matched,
+        })
+    }
+
+    /// List all projects with session counts, sorted by most recent activity
+    pub fn list_projects(&self, include_cwd: bool) -> Result<Vec<ProjectSummary>> {
+        let mut projects = Vec::new();
+        let projects_dir = self.base_path.join("projects");
+
+        if !projects_dir.exists() {
+            return Ok(projects);
+        }
+
+        for project_entry in std::fs::read_dir(&projects_dir)?.filter_map(|e| e.ok()) {
+            let project_dir = project_entry.path();
+            if !project_dir.is_dir() {
+                continue;
+            }
+            let Some(project_path) = extract_project_path(&project_dir) else {
+                continue;
+            };
+
+            let mut session_count = 0;
+            let mut last_active: Option<DateTime<Utc>> = None;
+            let mut cwds = std::collections::BTreeSet::new();
+
+            for entry in WalkDir::new(&project_dir)
+                .max_depth(2)
+                .follow_links(true)
+                .into_iter()
+                .filter_map(|e| e.ok())
+            {
+                let path = entry.path();
+                if !path.is_file() || !is_session_file(path) {
+                    continue;
+                }
+                if path.file_name().is_some_and(|n| n.to_string_lossy().starts_with("agent-")) {
+                    continue;
+                }
+
+                match self.try_parse_jsonl_session(path) {
+                    Ok(Some(session)) => {
+                        session_count += 1;
+                        if session.updated_at > last_active {
+                            last_active = session.updated_at;
+                        }
+                        if let Some(cwd) = session.cwd {
+                            cwds.insert(cwd);
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        eprintln!("warning: skipping unreadable session file {}: {}", path.display(), e);
+                    }
+                }
+            }
+
+            if session_count == 0 {
+                continue;
+            }
+
+            projects.push(ProjectSummary {
+                project_path,
+                session_count,
+                last_active: last_active.map(|dt| dt.to_rfc3339()),
+                cwds: include_cwd.then(|| cwds.into_iter().collect()),
+            });
+        }
+
+        // Sort by last_active descending (most recent first)
+        projects.sort_by(|a, b| b.last_active.cmp(&a.last_active));
+
+        Ok(projects)
+    }
+
+    /// Find sessions that mention a file path, most recently updated first
+    pub fn sessions_touching_file(
+        &self,
+        file_path: &str,
+        mode: FileMatchMode,
+        limit: usize,
+    ) -> Result<Vec<SessionSummary>> {
+        let mut sessions = Vec::new();
+        let projects_dir = self.base_path.join("projects");
+
+        if !projects_dir.exists() {
+            return Ok(sessions);
+        }
+
+        // In every mode the query is a substring of a matching path, so a file that
+        // doesn't contain it (JSON-escaped) anywhere can be skipped without parsing
+        let escaped = serde_json::to_string(file_path)?;
+        let needle = &escaped[1..escaped.len() - 1];
+
+        for entry in WalkDir::new(&projects_dir)
+            .max_depth(3)
+            .follow_links(true)
+            .into_iter()
+            .filter_map(|e| e.ok())
+        {
+            let path = entry.path();
+            if path.is_file() && is_session_file(path) {
+                if path.file_name().is_some_and(|n| n.to_string_lossy().starts_with("agent-")) {
+                    continue;
+                }
+                let Ok(mut reader) = open_session_file(path) else {
+                    continue;
+                };
+                let mut bytes = Vec::new();
+                if reader.read_to_end(&mut bytes).is_err() {
+                    continue;
+                }
+                if !String::from_utf8_lossy(&bytes).contains(needle) {
+                    continue;
+                }
+                if let Ok(Some(session)) = self.try_parse_jsonl_session(path) {
+                    if session_touches_file(&session, file_path, mode) {
+                        sessions.push(session_to_summary(&session, DEFAULT_PREVIEW_CHARS));
+                    }
+                }
+            }
+        }
+
+        sessions.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+        sessions.truncate(limit.min(100));
+
+        Ok(sessions)
+    }
+
+    /// Get full session by ID, or by a prefix of its ID matching only that session
+    pub fn get_session(&self, session_id: &str) -> Result<Option<Session>> {
+        self.get_session_head(session_id, None)
+    }
+
+    /// The session most recently written to, optionally only among projects at
+    /// or under `project_prefix`. Files are tried newest modification time
+    /// first, so usually only one is parsed.
+    pub fn most_recent_session(&self, project_prefix: Option<&str>) -> Result<Option<Session>> {
+        let mut files: Vec<(std::time::SystemTime, PathBuf)> = self
+            .session_files_in(project_prefix)
+            .into_iter()
+            .filter_map(|path| Some((path.metadata().ok()?.modified().ok()?, path)))
+            .collect();
+        files.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+
+        Ok(files
+            .iter()
+            .find_map(|(_, path)| self.try_parse_jsonl_session(path).ok().flatten()))
+    }
+
+    /// Get a session, parsing no more than its first `max_messages` messages
+    pub fn get_session_head(&self, session_id: &str, max_messages: Option<usize>) -> Result<Option<Session>> {
+        let projects_dir = self.base_path.join("projects");
+
+        if !projects_dir.exists() {
+            return Ok(None);
+        }
+
+        for entry in WalkDir::new(&projects_dir)
+            .max_depth(3)
+            .follow_links(true)
+            .into_iter()
+            .filter_map(|e| e.ok())
+        {
+            // Compare file names first; they are already in memory, unlike file metadata
+            if session_file_stem(entry.path()).is_none_or(|stem| stem != session_id) {
+                continue;
+            }
+            let path = entry.path();
+            if entry.file_type().is_file() && is_session_file(path) {
+                if let Ok(Some(session)) = self.parse_jsonl_session(path, max_messages) {
+                    return Ok(Some(session));
+                }
+            }
+        }
+
+        if session_id.len() >= SESSION_ID_LEN {
+            return Ok(None);
+        }
+        if session_id.chars().count() < MIN_SESSION_ID_PREFIX_LEN {
+            return Err(SessionLookupError::PrefixTooShort(session_id.to_string()).into());
+        }
+
+        // Matched on file names, so only the one session found is parsed
+        let files = self.project_index().files_with_id_prefix(session_id);
+        let mut matches: Vec<String> = files.iter().filter_map(|path| session_file_stem(path)).collect();
+        matches.dedup();
+        if matches.len() > 1 {
+            return Err(SessionLookupError::AmbiguousPrefix {
+                prefix: session_id.to_string(),
+                matches,
+            }
+            .into());
+        }
+        for path in &files {
+            if let Ok(Some(session)) = self.parse_jsonl_session(path, max_messages) {
+                return Ok(Some(session));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Count tool calls by tool name, optionally only in projects under `project_prefix`
+    pub fn tool_usage_stats(&self, project_prefix: Option<&str>, limit: usize) -> Result<ToolUsageStats> {
+        let sessions = self.sessions_in_project(project_prefix);
+
+        let counts = aggregate_tool_usage(&sessions);
+        let total_tool_calls: usize = counts.values().sum();
+
+        let mut tools: Vec<ToolUsage> = counts
+            .into_iter()
+            .map(|(name, count)| ToolUsage {
+                name,
+                count,
+                percentage: count as f64 * 100.0 / total_tool_calls as f64,
+            })
+            .collect();
+        tools.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+        tools.truncate(limit);
+
+        Ok(ToolUsageStats {
+            total_sessions_scanned: sessions.len(),
+            total_tool_calls,
+            tools,
+        })
+    }
+
+    /// Bucket when sessions started, optionally only those in projects under
+    /// `project_prefix` and started within `since..=until`
+    pub fn activity_heatmap(
+        &self,
+        project_prefix: Option<&str>,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<ActivityHeatmap> {
+        let mut sessions = self.sessions_in_project(project_prefix);
+        // Sessions without a start time are kept so they're counted as skipped
+        sessions.retain(|s| {
+            s.created_at.is_none_or(|created| {
+                since.is_none_or(|since| created >= since) && until.is_none_or(|until| created <= until)
+            })
+        });
+        Ok(compute_heatmap(&sessions))
+    }
+
+    /// Sessions in projects at or under `project_path` by the period they
+    /// started in, or `None` if there are none
+    pub fn project_timeline(&self, project_path: &str, bucket: BucketSize) -> Result<Option<ProjectTimeline>> {
+        let sessions = self.sessions_in_project(Some(project_path));
+        if sessions.is_empty() {
+            return Ok(None);
+        }
+        let buckets = bucket_sessions_by_date(&sessions, bucket);
+        Ok(Some(ProjectTimeline {
+            project_path: project_path.to_string(),
+            total_sessions: buckets.iter().map(|b| b.session_count).sum(),
+            total_messages: buckets.iter().map(|b| b.message_count).sum(),
+            buckets,
+        }))
+    }
+
+    /// Count sessions, optionally only those in projects under
+    /// `project_prefix` and started within `since..=until`, by the hour they
+    /// started in `tz`, reported as named `timezone`
+    pub fn hourly_usage<Tz: chrono::TimeZone>(
+        &self,
+        project_prefix: Option<&str>,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        timezone: &str,
+        tz: &Tz,
+    ) -> Result<HourlyUsage> {
+        let mut sessions = self.sessions_in_project(project_prefix);
+        // Sessions without a start time are kept so they're counted as unknown
+        sessions.retain(|s| {
+            s.created_at.is_none_or(|created| {
+                since.is_none_or(|since| created >= since) && until.is_none_or(|until| created <= until)
+            })
+        });
+        Ok(HourlyUsage {
+            hours: aggregate_by_hour(&sessions, tz),
+            timezone: timezone.to_string(),
+            unknown_time: sessions.iter().filter(|s| s.created_at.is_none()).count(),
+            note: None,
+        })
+    }
+
+    /// Aggregate metrics across all sessions in a single pass. Prints progress
+    /// to stderr when it is a terminal and the scan runs longer than a moment.
+    pub fn compute_statistics(&self) -> Result<SessionStatistics> {
+        let mut builder = StatisticsBuilder::default();
+        let projects_dir = self.base_path.join("projects");
+
+        if !projects_dir.exists() {
+            return Ok(builder.finish());
+        }
+
+        let show_progress = std::io::stderr().is_terminal();
+        let started = std::time::Instant::now();
+        let mut last_report = started;
+
+        for entry in WalkDir::new(&projects_dir)
+            .max_depth(3)
+            .follow_links(true)
+            .into_iter()
+            .filter_map(|e| e.ok())
+        {
+            let path = entry.path();
+            if path.is_file() && is_session_file(path) {
+                if path.file_name().is_some_and(|n| n.to_string_lossy().starts_with("agent-")) {
+                    continue;
+                }
+                if let Ok(Some(session)) = self.try_parse_jsonl_session(path) {
+                    builder.add(&session);
+                }
+
+                if show_progress
+                    && started.elapsed() > STATISTICS_PROGRESS_DELAY
+                    && last_report.elapsed() > std::time::Duration::from_secs(1)
+                {
+                    eprint!("\rScanning sessions: {} so far...", builder.total_sessions);
+                    last_report = std::time::Instant::now();
+                }
+            }
+        }
+
+        if show_progress && started.elapsed() > STATISTICS_PROGRESS_DELAY {
+            eprintln!("\rScanned {} sessions.              ", builder.total_sessions);
+        }
+
+        Ok(builder.finish())
+    }
+
+    /// Paths of all top-level session files (agent sub-sessions excluded)
+    pub(crate) fn session_files(&self) -> Vec<PathBuf> {
+        let projects_dir = self.base_path.join("projects");
+
+        if !projects_dir.exists() {
+            return Vec::new();
+        }
+
+        WalkDir::new(&projects_dir)
+            .max_depth(3)
+            .follow_links(true)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .map(|e| e.into_path())
+            .filter(|path| {
+                path.is_file()
+                    && is_session_file(path)
+                    && !path.file_name().is_some_and(|n| n.to_string_lossy().starts_with("agent-"))
+            })
+            .collect()
+    }
+
+    /// Paths of agent sub-session files, whether next to their parent
+    /// session or under `<parent>/subagents/`
+    fn agent_session_files(&self) -> Vec<PathBuf> {
+        let projects_dir = self.base_path.join("projects");
+
+        if !projects_dir.exists() {
+            return Vec::new();
+        }
+
+        WalkDir::new(&projects_dir)
+            .max_depth(4)
+            .follow_links(true)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .map(|e| e.into_path())
+            .filter(|path| {
+                path.is_file()
+                    && is_session_file(path)
+                    && path.file_name().is_some_and(|n| n.to_string_lossy().starts_with("agent-"))
+            })
+            .collect()
+    }
+
+    /// Parse an agent sub-session. Its entries are logged under the parent's
+    /// `sessionId`, so the session is identified by its file name instead;
+    /// the logged ID is returned alongside.
+    fn parse_agent_session(&self, path: &Path) -> Option<(Session, String)> {
+        let mut session = self.try_parse_jsonl_session(path).ok()??;
+        let logged_id = std::mem::replace(&mut session.id, session_file_stem(path)?);
+        Some((session, logged_id))
+    }
+
+    /// Find the JSONL file backing a session without parsing it
+    pub fn find_session_file(&self, session_id: &str) -> Result<Option<PathBuf>> {
+        let projects_dir = self.base_path.join("projects");
+
+        if !projects_dir.exists() {
+            return Ok(None);
+        }
+
+        for entry in WalkDir::new(&projects_dir)
+            .max_depth(3)
+            .follow_links(true)
+            .into_iter()
+            .filter_map(|e| e.ok())
+        {
+            let path = entry.path();
+            if path.is_file()
+                && is_session_file(path)
+                && session_file_stem(path).is_some_and(|stem| stem == session_id)
+            {
+                return Ok(Some(path.to_path_buf()));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Get messages in `[from_index, to_index)` of a session, optionally filtered by role
+    pub fn get_session_messages(
+        &self,
+        session_id: &str,
+        role: Option<&str>,
+        from_index: Option<usize>,
+        to_index: Option<usize>,
+    ) -> Result<Option<MessageSlice>> {
+        let Some(session) = self.get_session(session_id)? else {
+            return Ok(None);
+        };
+
+        let total_messages = session.messages.len();
+        let end = to_index.unwrap_or(total_messages).min(total_messages);
+        let start = from_index.unwrap_or(0).min(end);
+
+        let messages: Vec<Message> = session.messages[start..end]
+            .iter()
+            .filter(|m| role.is_none_or(|r| m.role == r))
+            .cloned()
+            .collect();
+
+        Ok(Some(MessageSlice {
+            session_id: session.id,
+            total_messages,
+            returned_messages: messages.len(),
+            messages,
+        }))
+    }
+
+    /// The messages a session gained after its first `since_message_index`
+    pub fn get_session_diff(&self, session_id: &str, since_message_index: usize) -> Result<Option<SessionDelta>> {
+        Ok(self
+            .get_session(session_id)?
+            .map(|session| session_delta(&session, since_message_index)))
+    }
+
+    /// Estimate the tokens in a session's messages, counting only messages
+    /// with role `role_filter` in the total if given
+    pub fn get_session_token_estimate(
+        &self,
+        session_id: &str,
+        role_filter: Option<&str>,
+    ) -> Result<Option<TokenEstimate>> {
+        let Some(session) = self.get_session(session_id)? else {
+            return Ok(None);
+        };
+
+        let tokens_for = |role: Option<&str>| -> usize {
+            session
+                .messages
+                .iter()
+                .filter(|m| role.is_none_or(|role| m.role == role))
+                .map(|m| estimate_tokens(&m.content))
+                .sum()
+        };
+        let estimated_tokens = tokens_for(role_filter);
+        let warning = (estimated_tokens > TOKEN_WARNING_THRESHOLD).then(|| {
+            format!(
+                "About {} tokens is more than most prompts can hold; \
+                 get_session_context gives a compact summary instead",
+                estimated_tokens
+            )
+        });
+
+        Ok(Some(TokenEstimate {
+            session_id: session.id.clone(),
+            estimated_tokens,
+            estimated_tokens_user_only: tokens_for(Some("user")),
+            estimated_tokens_assistant_only: tokens_for(Some("assistant")),
+            warning,
+        }))
+    }
+
+    /// Get what a session cost to run, from its result entries
+    pub fn get_session_cost(&self, session_id: &str) -> Result<Option<SessionCost>> {
+        let Some(session) = self.get_session(session_id)? else {
+            return Ok(None);
+        };
+
+        let messages = session.messages.len();
+        Ok(Some(SessionCost {
+            session_id: session.id.clone(),
+            cost_usd: session.total_cost_usd,
+            duration_ms: session.total_duration_ms,
+            cost_per_message: session
+                .total_cost_usd
+                .filter(|_| messages > 0)
+                .map(|cost| cost / messages as f64),
+        }))
+    }
+
+    /// Get the shell commands run in a session, optionally deduplicated
+    pub fn get_session_commands(&self, session_id: &str, unique: bool) -> Result<Option<SessionCommands>> {
+        let Some(session) = self.get_session(session_id)? else {
+            return Ok(None);
+        };
+
+        let mut commands = extract_commands(&session);
+        if unique {
+            let mut seen = std::collections::HashSet::new();
+            commands.retain(|c| seen.insert(c.command.clone()));
+        }
+
+        Ok(Some(SessionCommands {
+            session_id: session.id,
+            commands,
+        }))
+    }
+
+    /// Every shell command run in sessions in projects under
+    /// `project_prefix`, or in all sessions, most recently run first.
+    /// Commands without a timestamp come last.
+    pub fn command_history(&self, project_prefix: Option<&str>) -> Result<Vec<CommandHistoryEntry>> {
+        let mut history: Vec<CommandHistoryEntry> = self
+            .sessions_in_project(project_prefix)
+            .iter()
+            .flat_map(|session| {
+                extract_commands(session).into_iter().map(|entry| CommandHistoryEntry {
+                    session_id: session.id.clone(),
+                    entry,
+                })
+            })
+            .collect();
+        history.sort_by_key(|run| std::cmp::Reverse(run.entry.timestamp));
+        Ok(history)
+    }
+
+    /// Get the todos in a session's messages
+    pub fn extract_todos(&self, session_id: &str, patterns: &[&str]) -> Result<Option<Vec<TodoItem>>> {
+        Ok(self
+            .get_session(session_id)?
+            .map(|session| extract_todos_from_session(&session, patterns)))
+    }
+
+    /// Every todo in sessions in projects under `project_prefix`, or in all
+    /// sessions, most recent first. Todos without a timestamp come last.
+    pub fn find_todos(&self, project_prefix: Option<&str>, patterns: &[&str]) -> Result<Vec<SessionTodo>> {
+        let mut todos: Vec<SessionTodo> = self
+            .sessions_in_project(project_prefix)
+            .iter()
+            .flat_map(|session| {
+                extract_todos_from_session(session, patterns)
+                    .into_iter()
+                    .map(|todo| SessionTodo {
+                        session_id: session.id.clone(),
+                        todo,
+                    })
+            })
+            .collect();
+        todos.sort_by_key(|found| std::cmp::Reverse(found.todo.timestamp));
+        Ok(todos)
+    }
+
+    /// Get the files a session's tool calls operated on, only through the tools
+    /// in `operations` (case-insensitive) if given. With `unique`, each path is
+    /// listed once, at its first operation.
+    pub fn list_session_files(
+        &self,
+        session_id: &str,
+        operations: Option<&[String]>,
+        unique: bool,
+    ) -> Result<Option<SessionFiles>> {
+        let Some(session) = self.get_session(session_id)? else {
+            return Ok(None);
+        };
+
+        let mut files = extract_file_operations(&session);
+        if let Some(operations) = operations {
+            files.retain(|f| operations.iter().any(|op| op.eq_ignore_ascii_case(&f.operation)));
+        }
+        if unique {
+            let mut seen = std::collections::HashSet::new();
+            files.retain(|f| seen.insert(f.file_path.clone()));
+        }
+
+        Ok(Some(SessionFiles {
+            session_id: session.id,
+            files,
+        }))
+    }
+
+    /// Sessions whose working directory or project is at or under `cwd_prefix`
+    /// (`~` expanded), most recently updated first
+    pub fn sessions_by_cwd(&self, cwd_prefix: &str, limit: usize) -> Result<Vec<SessionSummary>> {
+        let prefix = expand_home(cwd_prefix);
+        let mut sessions = Vec::new();
+
+        for path in self.session_files() {
+            if let Ok(Some(session)) = self.try_parse_jsonl_session(&path) {
+                if in_directory(&session, &prefix) {
+                    sessions.push(session_to_summary(&session, DEFAULT_PREVIEW_CHARS));
+                }
+            }
+        }
+
+        sessions.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+        sessions.truncate(limit);
+        Ok(sessions)
+    }
+
+    /// Sessions last updated within `[since, until]`, most recent first. Either
+    /// bound may be left open; sessions without a timestamp only match when
+    /// both are.
+    pub fn sessions_updated_between(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> Result<Vec<SessionSummary>> {
+        let mut sessions = Vec::new();
+
+        for path in self.session_files() {
+            if std::fs::metadata(&path).is_ok_and(|m| !modified_since(&m, since)) {
+                continue;
+            }
+            let Ok(Some(session)) = self.try_parse_jsonl_session(&path) else {
+                continue;
+            };
+            if updated_in_range(&session, since, until) {
+                sessions.push(session_to_summary(&session, DEFAULT_PREVIEW_CHARS));
+            }
+        }
+
+        sessions.sort_by(|a, b| (&b.updated_at, &b.id).cmp(&(&a.updated_at, &a.id)));
+        sessions.truncate(limit);
+        Ok(sessions)
+    }
+
+    /// Sessions ranked by message count, most messages first with
+    /// `SortOrder::Desc`. Sessions with fewer than two messages are skipped
+    /// unless `include_trivial` is set.
+    pub fn sessions_by_message_count(
+        &self,
+        order: SortOrder,
+        limit: usize,
+        project_prefix: Option<&str>,
+        include_trivial: bool,
+    ) -> Result<Vec<SessionSummary>> {
+        let min_messages = if include_trivial { 0 } else { MIN_NONTRIVIAL_MESSAGES };
+        let mut scans: Vec<SessionScanResult> = self
+            .session_files_in(project_prefix)
+            .into_iter()
+            .filter_map(|path| scan_session_file(&path))
+            // Line counts never undercount messages, so these can't qualify
+            .filter(|scan| scan.fast_message_count >= min_messages)
+            .collect();
+        scans.sort_by(|a, b| order.apply(a.fast_message_count.cmp(&b.fast_message_count)));
+
+        let rank = |a: &SessionSummary, b: &SessionSummary| {
+            order
+                .apply(a.message_count.cmp(&b.message_count))
+                .then_with(|| b.updated_at.cmp(&a.updated_at))
+        };
+        let mut sessions: Vec<SessionSummary> = Vec::new();
+        for scan in scans {
+            // Longest first: once a file has fewer lines than the shortest
+            // session kept has messages, no later file can displace it
+            if order == SortOrder::Desc
+                && sessions.len() >= limit
+                && sessions.last().is_some_and(|s| scan.fast_message_count < s.message_count)
+            {
+                break;
+            }
+            let Ok(Some(session)) = self.try_parse_jsonl_session(&scan.path) else {
+                continue;
+            };
+            if session.messages.len() < min_messages {
+                continue;
+            }
+            sessions.push(session_to_summary(&session, DEFAULT_PREVIEW_CHARS));
+            sessions.sort_by(rank);
+            sessions.truncate(limit);
+        }
+        Ok(sessions)
+    }
+
+    /// Sessions whose tool results or assistant messages contain any of
+    /// `patterns`, most recently updated first
+    pub fn sessions_with_errors(&self, patterns: &[&str], limit: usize) -> Result<Vec<ErrorSessionSummary>> {
+        let mut sessions = Vec::new();
+        for path in self.session_files() {
+            let Ok(Some(session)) = self.try_parse_jsonl_session(&path) else {
+                continue;
+            };
+            let signals = count_error_signals(&session, patterns);
+            if signals.is_empty() {
+                continue;
+            }
+            let matched_patterns = patterns
+                .iter()
+                .filter(|p| signals.iter().any(|s| s == *p))
+                .map(|p| p.to_string())
+                .collect();
+            sessions.push(ErrorSessionSummary {
+                summary: session_to_summary(&session, DEFAULT_PREVIEW_CHARS),
+                matched_patterns,
+                error_count: signals.len(),
+            });
+        }
+
+        sessions.sort_by(|a, b| b.summary.updated_at.cmp(&a.summary.updated_at));
+        sessions.truncate(limit);
+        Ok(sessions)
+    }
+
+    /// Sessions mentioning files in common with `session_id`, most similar first.
+    /// Returns `None` if the session doesn't exist.
+    pub fn find_related_sessions(
+        &self,
+        session_id: &str,
+        limit: usize,
+    ) -> Result<Option<Vec<RelatedSession>>> {
+        let Some(target) = self.get_session(session_id)? else {
+            return Ok(None);
+        };
+        let max_files = ContextLimits::DEFAULT_MAX_FILES;
+        let target_files = extract_file_paths(&target, max_files);
+        if target_files.is_empty() {
+            return Ok(Some(Vec::new()));
+        }
+
+        let mut related = Vec::new();
+        for path in self.session_files() {
+            let Ok(Some(session)) = self.try_parse_jsonl_session(&path) else {
+                continue;
+            };
+            if session.id == target.id {
+                continue;
+            }
+            let similarity_score = jaccard_similarity(&target_files, &extract_file_paths(&session, max_files));
+            if similarity_score > 0.0 {
+                related.push(RelatedSession {
+                    summary: session_to_summary(&session, DEFAULT_PREVIEW_CHARS),
+                    similarity_score,
+                });
+            }
+        }
+
+        related.sort_by(|a, b| {
+            b.similarity_score
+                .total_cmp(&a.similarity_score)
+                .then_with(|| b.summary.updated_at.cmp(&a.summary.updated_at))
+        });
+        related.truncate(limit);
+        Ok(Some(related))
+    }
+
+    /// Groups of sessions that ran in the same directory and whose key term
+    /// frequencies have a cosine similarity of at least `threshold`. Groups
+    /// are joined pair by pair, so A and C share a group if both are close to
+    /// B. The most similar groups come first.
+    pub fn find_duplicate_sessions(&self, threshold: f64, limit: usize) -> Result<Vec<DuplicateGroup>> {
+        // Sessions in different directories are never duplicates, so pairs are
+        // only compared within a directory
+        let mut by_cwd: std::collections::HashMap<String, Vec<(SessionSummary, TermVector)>> =
+            std::collections::HashMap::new();
+        for path in self.session_files() {
+            let Ok(Some(session)) = self.try_parse_jsonl_session(&path) else {
+                continue;
+            };
+            let Some(cwd) = session.cwd.clone() else {
+                continue;
+            };
+            let vector = term_frequency_vector(&session);
+            if !vector.is_empty() {
+                by_cwd.entry(cwd).or_default().push((session_to_summary(&session, DEFAULT_PREVIEW_CHARS), vector));
+            }
+        }
+
+        let mut groups = Vec::new();
+        for (cwd, sessions) in by_cwd {
+            let mut pairs = Vec::new();
+            for i in 0..sessions.len() {
+                for j in i + 1..sessions.len() {
+                    let similarity = cosine_similarity(&sessions[i].1, &sessions[j].1);
+                    if similarity >= threshold {
+                        pairs.push((i, j, similarity));
+                    }
+                }
+            }
+
+            // Union-find over the sessions, by index
+            let mut parent: Vec<usize> = (0..sessions.len()).collect();
+            for &(i, j, _) in &pairs {
+                let (a, b) = (find_root(&mut parent, i), find_root(&mut parent, j));
+                parent[a] = b;
+            }
+            let mut found: std::collections::HashMap<usize, (f64, Vec<usize>)> = std::collections::HashMap::new();
+            for &(i, _, similarity) in &pairs {
+                let group = found.entry(find_root(&mut parent, i)).or_insert((similarity, Vec::new()));
+                group.0 = group.0.min(similarity);
+            }
+            for i in 0..sessions.len() {
+                if let Some(group) = found.get_mut(&find_root(&mut parent, i)) {
+                    group.1.push(i);
+                }
+            }
+
+            let mut summaries: Vec<Option<SessionSummary>> = sessions.into_iter().map(|(s, _)| Some(s)).collect();
+            for (min_similarity, members) in found.into_values() {
+                let mut sessions: Vec<SessionSummary> = members.iter().filter_map(|&i| summaries[i].take()).collect();
+                sessions.sort_by(|a, b| (&b.updated_at, &b.id).cmp(&(&a.updated_at, &a.id)));
+                groups.push(DuplicateGroup {
+                    cwd: cwd.clone(),
+                    min_similarity,
+                    sessions,
+                });
+            }
+        }
+
+        groups.sort_by(|a, b| {
+            b.min_similarity
+                .total_cmp(&a.min_similarity)
+                .then_with(|| b.sessions[0].updated_at.cmp(&a.sessions[0].updated_at))
+        });
+        groups.truncate(limit);
+        Ok(groups)
+    }
+
+    /// Sessions whose dominant language is `language` (a name returned by
+    /// `canonical_language`), most confident first
+    pub fn find_sessions_by_language(&self, language: &str, limit: usize) -> Result<Vec<LanguageSession>> {
+        let mut found = Vec::new();
+        for path in self.session_files() {
+            let Ok(Some(session)) = self.try_parse_jsonl_session(&path) else {
+                continue;
+            };
+            let scores = detect_language(&session);
+            if let Some((dominant, confidence)) = dominant_language(&scores) {
+                if dominant == language {
+                    found.push(LanguageSession {
+                        summary: session_to_summary(&session, DEFAULT_PREVIEW_CHARS),
+                        language: dominant.to_string(),
+                        confidence,
+                    });
+                }
+            }
+        }
+
+        found.sort_by(|a, b| {
+            b.confidence
+                .total_cmp(&a.confidence)
+                .then_with(|| b.summary.updated_at.cmp(&a.summary.updated_at))
+        });
+        found.truncate(limit);
+        Ok(found)
+    }
+
+    /// Sessions that end waiting on Claude, most recently updated first: the
+    /// last message is the user's, Claude never replied, or there is only
+    /// one message. With `abandoned_only`, just those where Claude had
+    /// replied earlier, as when a session was dropped midway.
+    pub fn find_sessions_without_assistant_response(
+        &self,
+        limit: usize,
+        abandoned_only: bool,
+    ) -> Result<Vec<UnansweredSession>> {
+        let mut found: Vec<UnansweredSession> = self
+            .session_files()
+            .iter()
+            .filter_map(|path| self.try_parse_jsonl_session(path).ok().flatten())
+            .filter(|session| is_unanswered(session, abandoned_only))
+            .map(|session| UnansweredSession {
+                last_message_role: session.messages.last().map(|m| m.role.clone()).unwrap_or_default(),
+                summary: session_to_summary(&session, DEFAULT_PREVIEW_CHARS),
+            })
+            .collect();
+
+        found.sort_by(|a, b| b.summary.updated_at.cmp(&a.summary.updated_at));
+        found.truncate(limit);
+        Ok(found)
+    }
+
+    /// Sessions whose `git_repo` contains `repo`, ignoring case, on `branch`
+    /// if given, most recently updated first. With `resolve_git`, sessions
+    /// that don't record their repository take it and the branch from the
+    /// checkout at their `cwd`, as it is now.
+    pub fn find_sessions_by_git_repo(
+        &self,
+        repo: &str,
+        branch: Option<&str>,
+        resolve_git: bool,
+        limit: usize,
+    ) -> Result<Vec<SessionSummary>> {
+        let repo = repo.to_lowercase();
+        let mut resolved: std::collections::HashMap<String, GitCheckout> = std::collections::HashMap::new();
+        let mut found: Vec<SessionSummary> = Vec::new();
+        for path in self.session_files() {
+            let Some(mut session) = self.try_parse_jsonl_session(&path).ok().flatten() else {
+                continue;
+            };
+            if resolve_git && session.git_repo.is_none() {
+                if let Some(cwd) = session.cwd.clone() {
+                    let checkout = resolved.entry(cwd).or_insert_with_key(|cwd| resolve_git_checkout(Path::new(cwd)));
+                    session.git_repo = checkout.repo.clone();
+                    session.git_branch = session.git_branch.or_else(|| checkout.branch.clone());
+                }
+            }
+
+            let repo_matches = session.git_repo.as_ref().is_some_and(|r| r.to_lowercase().contains(&repo));
+            let branch_matches = branch.is_none_or(|b| session.git_branch.as_deref() == Some(b));
+            if repo_matches && branch_matches {
+                found.push(session_to_summary(&session, DEFAULT_PREVIEW_CHARS));
+            }
+        }
+
+        found.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+        found.truncate(limit);
+        Ok(found)
+    }
+
+    /// Get context summary of a session, with files, key terms and commands
+    /// taken from the messages `depth` selects
+    pub fn get_session_context(
+        &self,
+        session_id: &str,
+        depth: ContextDepth,
+        include_tool_summary: bool,
+        include_token_estimate: bool,
+        limits: ContextLimits,
+    ) -> Result<Option<SessionContext>> {
+        Ok(self
+            .get_session(session_id)?
+            .map(|session| session_context(&session, depth, include_tool_summary, include_token_estimate, limits)))
+    }
+
+    /// Get the timeline of a session
+    pub fn get_session_timeline(&self, session_id: &str) -> Result<Option<SessionTimeline>> {
+        Ok(self.get_session(session_id)?.map(|session| session_timeline(&session)))
+    }
+
+    /// Combine the context of several sessions: files, key terms and commands
+    /// are unioned, initial requests concatenated and message counts summed.
+    /// `limits` apply to each session before the union. IDs that aren't found
+    /// are listed in `missing_ids`; returns `None` only if none of the
+    /// sessions exist.
+    pub fn get_multi_session_context(
+        &self,
+        session_ids: &[String],
+        depth: ContextDepth,
+        include_tool_summary: bool,
+        include_token_estimate: bool,
+        limits: ContextLimits,
+    ) -> Result<Option<SessionContext>> {
+        let mut contexts = Vec::new();
+        let mut missing_ids = Vec::new();
+        for id in session_ids {
+            match self.get_session_context(id, depth, include_tool_summary, include_token_estimate, limits)? {
+                Some(context) => contexts.push(context),
+                None => missing_ids.push(id.clone()),
+            }
+        }
+        let Some(first) = contexts.first() else {
+            return Ok(None);
+        };
+
+        let union = |field: fn(&SessionContext) -> &Vec<String>| {
+            let mut seen = std::collections::HashSet::new();
+            contexts
+                .iter()
+                .flat_map(|c| field(c).iter())
+                .filter(|item| seen.insert(item.as_str()))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        let requests: Vec<String> = contexts
+            .iter()
+            .filter_map(|c| c.initial_request.as_ref().map(|r| format!("[{}] {}", c.id, r)))
+            .collect();
+
+        Ok(Some(SessionContext {
+            id: contexts.iter().map(|c| c.id.as_str()).collect::<Vec<_>>().join(","),
+            created_at: contexts.iter().filter_map(|c| c.created_at).min(),
+            // Only kept when every session ran in the same directory
+            cwd: first.cwd.clone().filter(|cwd| contexts.iter().all(|c| c.cwd.as_ref() == Some(cwd))),
+            git_repo: first.git_repo.clone().filter(|repo| contexts.iter().all(|c| c.git_repo.as_ref() == Some(repo))),
+            git_branch: first
+                .git_branch
+                .clone()
+                .filter(|branch| contexts.iter().all(|c| c.git_branch.as_ref() == Some(branch))),
+            initial_request: (!requests.is_empty()).then(|| requests.join("\n\n")),
+            message_count: contexts.iter().map(|c| c.message_count).sum(),
+            token_estimate: include_token_estimate.then(|| contexts.iter().filter_map(|c| c.token_estimate).sum()),
+            token_estimate_by_role: include_token_estimate.then(|| {
+                contexts.iter().filter_map(|c| c.token_estimate_by_role).fold(RoleTokens::default(), |sum, tokens| {
+                    RoleTokens {
+                        user: sum.user + tokens.user,
+                        assistant: sum.assistant + tokens.assistant,
+                    }
+                })
+            }),
+            files_mentioned: union(|c| &c.files_mentioned),
+            key_terms: union(|c| &c.key_terms),
+            commands: union(|c| &c.commands),
+            // Taken from the last session listed that has them
+            most_recent_file: contexts.iter().rev().find_map(|c| c.most_recent_file.clone()),
+            most_recent_tool: contexts.iter().rev().find_map(|c| c.most_recent_tool.clone()),
+            tool_summary: include_tool_summary
+                .then(|| merge_tool_summaries(contexts.iter().filter_map(|c| c.tool_summary.as_deref()))),
+            aggregated: true,
+            missing_ids,
+        }))
+    }
+
+    /// Parse a JSONL session file (Claude Code's actual format)
+    pub fn try_parse_jsonl_session(&self, path: &Path) -> Result<Option<Session>> {
+        self.parse_jsonl_session(path, None)
+    }
+
+    /// Parse a JSONL session file line by line, stopping after `max_messages`
+    /// messages if given. A session cut short this way takes its `updated_at`
+    /// from the file's modification time.
+    fn parse_jsonl_session(&self, path: &Path, max_messages: Option<usize>) -> Result<Option<Session>> {
+        let mut messages = Vec::new();
+        let mut session_id: Option<String> = None;
+        let mut cwd: Option<String> = None;
+        let mut first_timestamp: Option<DateTime<Utc>> = None;
+        let mut last_timestamp: Option<DateTime<Utc>> = None;
+        let mut total_cost_usd: Option<f64> = None;
+        let mut total_duration_ms: Option<u64> = None;
+        let mut result_entries = Vec::new();
+        let mut git_repo: Option<String> = None;
+        let mut git_branch: Option<String> = None;
+        let mut truncated = false;
+
+        for line in open_session_file(path)?.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if max_messages.is_some_and(|max| messages.len() >= max) {
+                truncated = true;
+                break;
+            }
+
+            let value: serde_json::Value = match serde_json::from_str(&line) {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+
+            // Get session ID from any entry
+            if session_id.is_none() {
+                session_id = value.get("sessionId")
+                    .and_then(|v| v.as_str())
+                    .map(|s| s.to_string());
+            }
+
+            // Get cwd from any entry
+            if cwd.is_none() {
+                cwd = value.get("cwd")
+                    .and_then(|v| v.as_str())
+                    .map(|s| s.to_string());
+            }
+
+            // Git information may be on any entry, in either spelling
+            if git_repo.is_none() {
+                git_repo = entry_string(&value, &["gitRepo", "git_repo"]);
+            }
+            if git_branch.is_none() {
+                git_branch = entry_string(&value, &["gitBranch", "git_branch"]);
+            }
+
+            // Parse timestamp
+            let timestamp = value.get("timestamp")
+                .and_then(|v| v.as_str())
+                .and_then(|s| s.parse::<DateTime<Utc>>().ok());
+
+            if let Some(ts) = timestamp {
+                if first_timestamp.is_none() || Some(ts) < first_timestamp {
+                    first_timestamp = Some(ts);
+                }
+                if last_timestamp.is_none() || Some(ts) > last_timestamp {
+                    last_timestamp = Some(ts);
+                }
+            }
+
+            // Result entries record the cost and duration of one turn
+            if value.get("type").and_then(|v| v.as_str()) == Some("result") {
+                let cost = value.get("costUSD").and_then(|v| v.as_f64());
+                let duration = value.get("durationMs").and_then(|v| v.as_u64());
+                total_cost_usd = add_optional(total_cost_usd, cost);
+                total_duration_ms = add_optional(total_duration_ms, duration);
+                result_entries.push(ResultEntry {
+                    message_count: messages.len(),
+                    cost_usd: cost,
+                    duration_ms: duration,
+                });
+            }
+
+            if let Some(message) = entry_message(&value, timestamp) {
+                messages.push(message);
+            }
+        }
+
+        if messages.is_empty() {
+            return Ok(None);
+        }
+
+        // Use filename as session ID if not found in content
+        let id = session_id
+            .or_else(|| session_file_stem(path))
+            .unwrap_or_else(|| uuid_from_path(path));
+
+        let modified = || {
+            path.metadata()
+                .ok()
+                .and_then(|m| m.modified().ok())
+                .map(DateTime::from)
+        };
+
+        Ok(Some(Session {
+            id,
+            project_path: extract_project_path(path),
+            cwd,
+            created_at: first_timestamp,
+            updated_at: if truncated {
+                modified().or(last_timestamp)
+            } else {
+                last_timestamp.or_else(modified)
+            },
+            messages,
+            file_path: path.to_path_buf(),
+            total_cost_usd,
+            total_duration_ms,
+            result_entries,
+            git_repo,
+            git_branch,
+        }))
+    }
+}
+
+/// The repository and branch of a git checkout
+#[derive(Debug, Default, Clone)]
+struct GitCheckout {
+    repo: Option<String>,
+    branch: Option<String>,
+}
+
+/// Ask `git` for the top-level directory and current branch of the checkout
+/// at `dir`. Both are `None` if `dir` isn't in one or `git` can't be run.
+fn resolve_git_checkout(dir: &Path) -> GitCheckout {
+    let git = |args: &[&str]| {
+        let output = std::process::Command::new("git").arg("-C").arg(dir).args(args).output().ok()?;
+        let out = String::from_utf8(output.stdout).ok()?;
+        let out = out.trim();
+        (output.status.success() && !out.is_empty()).then(|| out.to_string())
+    };
+    if !dir.is_dir() {
+        return GitCheckout::default();
+    }
+    GitCheckout {
+        repo: git(&["rev-parse", "--show-toplevel"]),
+        // Fails on a detached HEAD, which has no branch
+        branch: git(&["symbolic-ref", "--short", "-q", "HEAD"]),
+    }
+}
+
+/// The first of `keys` that `value` has as a non-empty string
+fn entry_string(value: &serde_json::Value, keys: &[&str]) -> Option<String> {
+    keys.iter()
+        .filter_map(|key| value.get(*key).and_then(|v| v.as_str()))
+        .find(|s| !s.is_empty())
+        .map(str::to_string)
+}
+
+/// The messages of `session` from index `since_message_index` on, with the
+/// cost and duration of the turns recorded after them. An index at or past the
+/// end gives no messages and `no_new_messages`.
+pub fn session_delta(session: &Session, since_message_index: usize) -> SessionDelta {
+    let to_index = session.messages.len();
+    let from_index = since_message_index.min(to_index);
+    let new_messages = session.messages[from_index..].to_vec();
+
+    // A result entry closes the turn of the messages before it, so one after
+    // message `since_message_index` belongs to a new turn
+    let mut new_cost_usd = None;
+    let mut new_duration_ms = None;
+    for entry in session.result_entries.iter().filter(|e| e.message_count > since_message_index) {
+        new_cost_usd = add_optional(new_cost_usd, entry.cost_usd);
+        new_duration_ms = add_optional(new_duration_ms, entry.duration_ms);
+    }
+
+    SessionDelta {
+        session_id: session.id.clone(),
+        from_index,
+        to_index,
+        no_new_messages: new_messages.is_empty(),
+        new_messages,
+        new_cost_usd,
+        new_duration_ms,
+    }
+}
+
+/// Whether a session was last updated within `[since, until]`. Either bound
+/// may be open; a session without a timestamp only matches when both are.
+pub(crate) fn updated_in_range(session: &Session, since: Option<DateTime<Utc>>, until: Option<DateTime<Utc>>) -> bool {
+    match session.updated_at {
+        Some(updated) => since.is_none_or(|s| updated >= s) && until.is_none_or(|u| updated <= u),
+        None => since.is_none() && until.is_none(),
+    }
+}
+
+/// How far before its last message a session file's modification time may
+/// be, e.g. after a clock change, and still be considered for a date range
+const MTIME_GRACE: chrono::Duration = chrono::Duration::hours(24);
+
+/// Whether a session file modified at `metadata`'s mtime could hold a session
+/// last updated at or after `since`, give or take `MTIME_GRACE`. Used to skip
+/// parsing files that can't match; true if either is unknown.
+///
+/// Only the start of a date range can rule a file out: a file is last written
+/// with its last message, but copies and backup restores can leave it
+/// modified any time after that.
+pub fn modified_since(metadata: &std::fs::Metadata, since: Option<DateTime<Utc>>) -> bool {
+    let (Some(since), Ok(modified)) = (since, metadata.modified()) else {
+        return true;
+    };
+    DateTime::<Utc>::from(modified) + MTIME_GRACE >= since
+}
+
+/// Sum of two optional amounts, `None` only if both are
+fn add_optional<T: std::ops::Add<Output = T>>(a: Option<T>, b: Option<T>) -> Option<T> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a + b),
+        (a, b) => a.or(b),
+    }
+}
+
+/// The message a session file entry holds, if it is a user or assistant
+/// entry with any content
+fn entry_message(value: &serde_json::Value, timestamp: Option<DateTime<Utc>>) -> Option<Message> {
+    // Only process user and assistant messages
+    let msg_type = value.get("type").and_then(|v| v.as_str()).unwrap_or("");
+    if msg_type != "user" && msg_type != "assistant" {
+        return None;
+    }
+
+    // Extract role and content from the message field
+    let message = value.get("message")?;
+    let role = message.get("role")
+        .and_then(|v| v.as_str())
+        .unwrap_or(msg_type)
+        .to_string();
+
+    let content = extract_message_content(message);
+    if content.is_empty() {
+        return None;
+    }
+
+    Some(Message {
+        role,
+        content,
+        timestamp,
+        tool_calls: extract_tool_calls(message),
+        tool_results: extract_tool_results(message),
+    })
+}
+
+/// Characters of a tool call's input or a tool result's output kept in a
+/// `MessagePart`
+const PART_PREVIEW_CHARS: usize = 200;
+
+/// Split a message object's content into its blocks. String content is a
+/// single text part; blocks of unknown types are left out.
+pub fn parse_message_parts(message: &serde_json::Value) -> Vec<MessagePart> {
+    let preview = |text: &str| text.chars().take(PART_PREVIEW_CHARS).collect::<String>();
+    let id = |block: &serde_json::Value, key: &str| block.get(key).and_then(|v| v.as_str()).map(str::to_string);
+
+    match message.get("content") {
+        Some(serde_json::Value::String(text)) => vec![MessagePart::Text { text: text.clone() }],
+        Some(serde_json::Value::Array(blocks)) => blocks
+            .iter()
+            .filter_map(|block| match block.get("type").and_then(|t| t.as_str()) {
+                Some("text") => block.get("text").and_then(|t| t.as_str()).map(|text| MessagePart::Text {
+                    text: text.to_string(),
+                }),
+                Some("tool_use") => Some(MessagePart::ToolCall {
+                    name: block.get("name").and_then(|n| n.as_str()).unwrap_or_default().to_string(),
+                    input_summary: block.get("input").map(|input| preview(&input.to_string())).unwrap_or_default(),
+                    tool_use_id: id(block, "id"),
+                }),
+                Some("tool_result") => Some(MessagePart::ToolResult {
+                    tool_use_id: id(block, "tool_use_id"),
+                    output_preview: preview(&tool_result_text(block)),
+                    is_error: block.get("is_error").and_then(|e| e.as_bool()).unwrap_or(false),
+                }),
+                Some("image") => Some(MessagePart::Image {
+                    description: describe_image(block),
+                }),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// What an image block holds, e.g. "image/png image, 2048 bytes"
+fn describe_image(block: &serde_json::Value) -> String {
+    let source = block.get("source");
+    let field = |key: &str| source.and_then(|s| s.get(key)).and_then(|v| v.as_str());
+    let kind = field("media_type").map_or_else(|| "image".to_string(), |media_type| format!("{} image", media_type));
+    match (field("type"), field("data"), field("url")) {
+        (Some("base64"), Some(data), _) => {
+            let padding = data.bytes().rev().take_while(|&b| b == b'=').count();
+            format!("{}, {} bytes", kind, (data.len() * 3 / 4).saturating_sub(padding))
+        }
+        (_, _, Some(url)) => format!("{} at {}", kind, url),
+        _ => kind,
+    }
+}
+
+/// The first `max_messages` messages of the session file at `path`, or all of
+/// them, with their content blocks. Read from the file again rather than kept
+/// on every parsed `Message`, since only `get_session` asks for them. A
+/// message whose blocks are all of unknown types is a single text part.
+pub fn read_typed_messages(path: &Path, max_messages: Option<usize>) -> Result<Vec<TypedMessage>> {
+    let mut messages = Vec::new();
+    for line in open_session_file(path)?.lines() {
+        if max_messages.is_some_and(|max| messages.len() >= max) {
+            break;
+        }
+        let Ok(value) = serde_json::from_str::<serde_json::Value>(&line?) else {
+            continue;
+        };
+        let timestamp = value
+            .get("timestamp")
+            .and_then(|v| v.as_str())
+            .and_then(|s| s.parse::<DateTime<Utc>>().ok());
+        // The same entries `parse_jsonl_session` takes as messages
+        let Some(message) = entry_message(&value, timestamp) else {
+            continue;
+        };
+        let mut parts = value.get("message").map(parse_message_parts).unwrap_or_default();
+        if parts.is_empty() {
+            parts.push(MessagePart::Text { text: message.content });
+        }
+        messages.push(TypedMessage {
+            role: message.role,
+            timestamp,
+            parts,
+        });
+    }
+    Ok(messages)
+}
+
+/// Extract content from a message object (handles both string and array content formats)
+fn extract_message_content(message: &serde_json::Value) -> String {
+    if let Some(content) = message.get("content") {
+        // String content
+        if let Some(s) = content.as_str() {
+            return s.to_string();
+        }
+
+        // Array content (assistant messages with tool_use, text blocks, etc.)
+        if let Some(arr) = content.as_array() {
+            let mut parts = Vec::new();
+            for item in arr {
+                // Text block: {"type": "text", "text": "..."}
+                if let Some(text) = item.get("text").and_then(|t| t.as_str()) {
+                    parts.push(text.to_string());
+                }
+                // Tool use block: extract tool name and input summary
+                else if item.get("type").and_then(|t| t.as_str()) == Some("tool_use") {
+                    if let Some(name) = item.get("name").and_then(|n| n.as_str()) {
+                        parts.push(render_tool_use(name, item.get("input")));
+                    }
+                }
+                // Tool result block (in user messages): command output, file contents, ...
+                else if item.get("type").and_then(|t| t.as_str()) == Some("tool_result") {
+                    parts.push(render_tool_result(item));
+                }
+            }
+            return parts.join("\n");
+        }
+    }
+    String::new()
+}
+
+/// Render a tool_use block as the one-line summary used in message content
+fn render_tool_use(name: &str, input: Option<&serde_json::Value>) -> String {
+    let input_summary = input
+        .map(|i| {
+            if let Some(fp) = i.get("file_path").and_then(|f| f.as_str()) {
+                format!(" on {}", fp)
+            } else if let Some(cmd) = i.get("command").and_then(|c| c.as_str()) {
+                let cmd_preview: String = cmd.chars().take(50).collect();
+                format!(": {}", cmd_preview)
+            } else {
+                String::new()
+            }
+        })
+        .unwrap_or_default();
+    format!("[Tool: {}{}]", name, input_summary)
+}
+
+/// Render a tool_result block as a one-line summary of its output
+fn render_tool_result(block: &serde_json::Value) -> String {
+    let output = tool_result_text(block);
+
+    // Collapse to a single line so the summary stays on one line of content
+    let output = output.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut preview: String = output.chars().take(200).collect();
+    if output.chars().count() > 200 {
+        preview.push_str("...");
+    }
+
+    let is_error = block.get("is_error").and_then(|e| e.as_bool()).unwrap_or(false);
+    if is_error {
+        format!("[ToolResult error: {}]", preview)
+    } else {
+        format!("[ToolResult: {}]", preview)
+    }
+}
+
+/// Full output text of a tool_result block
+fn tool_result_text(block: &serde_json::Value) -> String {
+    // Like message content, the output is either a string or an array of text blocks
+    match block.get("content") {
+        Some(serde_json::Value::String(s)) => s.clone(),
+        Some(serde_json::Value::Array(items)) => items
+            .iter()
+            .filter_map(|i| i.get("text").and_then(|t| t.as_str()))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+/// Extract the output of tool_result blocks from a message's array content
+fn extract_tool_results(message: &serde_json::Value) -> Vec<String> {
+    let Some(arr) = message.get("content").and_then(|c| c.as_array()) else {
+        return Vec::new();
+    };
+
+    arr.iter()
+        .filter(|item| item.get("type").and_then(|t| t.as_str()) == Some("tool_result"))
+        .map(tool_result_text)
+        .collect()
+}
+
+/// Extract tool_use blocks from a message's array content
+fn extract_tool_calls(message: &serde_json::Value) -> Vec<ToolCall> {
+    let Some(arr) = message.get("content").and_then(|c| c.as_array()) else {
+        return Vec::new();
+    };
+
+    arr.iter()
+        .filter(|item| item.get("type").and_then(|t| t.as_str()) == Some("tool_use"))
+        .filter_map(|item| {
+            let name = item.get("name").and_then(|n| n.as_str())?;
+            Some(ToolCall {
+                name: name.to_string(),
+                input: item.get("input").cloned().unwrap_or(serde_json::Value::Null),
+            })
+        })
+        .collect()
+}
+
+/// Extract fenced code blocks from message text and tool output, in order
+pub fn extract_code_blocks(session: &Session) -> Vec<CodeBlock> {
+    let mut blocks = Vec::new();
+
+    for (index, msg) in session.messages.iter().enumerate() {
+        let sources = std::iter::once((msg.role.as_str(), msg.content.as_str()))
+            .chain(msg.tool_results.iter().map(|r| ("tool_result", r.as_str())));
+
+        for (role, text) in sources {
+            for (language, content) in fenced_blocks(text) {
+                blocks.push(CodeBlock {
+                    language,
+                    content,
+                    role: role.to_string(),
+                    message_index: index,
+                });
+            }
+        }
+    }
+
+    blocks
+}
+
+/// Fenced code blocks in `text` as `(language, content)` pairs. A block closes
+/// only on a bare fence at least as long as its opener, so shorter or
+/// info-string fences inside it are content. Unclosed blocks are dropped.
+fn fenced_blocks(text: &str) -> Vec<(Option<String>, String)> {
+    let mut blocks = Vec::new();
+    // Fence length, language and lines of the block being read
+    let mut open: Option<(usize, Option<String>, Vec<&str>)> = None;
+
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        let ticks = trimmed.chars().take_while(|&c| c == '`').count();
+
+        match &mut open {
+            None if ticks >= 3 => {
+                let info = trimmed[ticks..].trim();
+                let language = info.split_whitespace().next().map(str::to_string);
+                open = Some((ticks, language, Vec::new()));
+            }
+            None => {}
+            Some((fence, _, _)) if ticks >= *fence && trimmed.trim_end().len() == ticks => {
+                if let Some((_, language, lines)) = open.take() {
+                    blocks.push((language, lines.join("\n")));
+                }
+            }
+            Some((_, _, lines)) => lines.push(line),
+        }
+    }
+
+    blocks
+}
+
+/// File extensions and the languages they are written in
+const LANGUAGE_EXTENSIONS: &[(&str, &str)] = &[
+    ("rs", "rust"),
+    ("py", "python"),
+    ("pyi", "python"),
+    ("ts", "typescript"),
+    ("tsx", "typescript"),
+    ("js", "javascript"),
+    ("jsx", "javascript"),
+    ("mjs", "javascript"),
+    ("go", "go"),
+    ("java", "java"),
+    ("kt", "kotlin"),
+    ("rb", "ruby"),
+    ("c", "c"),
+    ("h", "c"),
+    ("cpp", "cpp"),
+    ("cc", "cpp"),
+    ("hpp", "cpp"),
+    ("cs", "csharp"),
+    ("swift", "swift"),
+    ("php", "php"),
+    ("sh", "shell"),
+];
+
+/// Other names code fences use for the languages above
+const LANGUAGE_ALIASES: &[(&str, &str)] = &[
+    ("golang", "go"),
+    ("c++", "cpp"),
+    ("c#", "csharp"),
+    ("bash", "shell"),
+    ("zsh", "shell"),
+];
+
+/// Words that show up in code of one language far more than in others
+const LANGUAGE_KEYWORDS: &[(&str, &[&str])] = &[
+    ("rust", &["fn", "impl", "let", "mut", "pub", "crate"]),
+    ("python", &["def", "import", "class", "self", "elif", "None"]),
+    ("typescript", &["interface", "readonly", "namespace", "implements"]),
+    ("javascript", &["function", "var", "require", "undefined"]),
+    ("go", &["func", "package", "chan", "defer"]),
+];
+
+/// Weight of a code block tagged with a language, which says the most
+const CODE_BLOCK_WEIGHT: usize = 10;
+
+/// Weight of each distinct file with a language's extension
+const FILE_EXTENSION_WEIGHT: usize = 5;
+
+/// The language `name` stands for, as a file extension, code fence tag or
+/// language name, or `None` for anything else, such as `json` or `text`
+pub fn canonical_language(name: &str) -> Option<&'static str> {
+    let name = name.trim().to_lowercase();
+    LANGUAGE_EXTENSIONS
+        .iter()
+        .chain(LANGUAGE_ALIASES)
+        .find(|(alias, _)| *alias == name)
+        .map(|(_, language)| *language)
+        .or_else(|| {
+            LANGUAGE_EXTENSIONS
+                .iter()
+                .map(|(_, language)| *language)
+                .find(|language| *language == name)
+        })
+}
+
+/// Score each programming language a session shows signs of: tagged code
+/// blocks, extensions of the files it mentions or touches, and keywords in
+/// its code blocks. Languages without any evidence are left out.
+pub fn detect_language(session: &Session) -> std::collections::HashMap<String, usize> {
+    let mut scores = std::collections::HashMap::new();
+    let code_blocks = extract_code_blocks(session);
+
+    for block in &code_blocks {
+        if let Some(language) = block.language.as_deref().and_then(canonical_language) {
+            *scores.entry(language.to_string()).or_insert(0) += CODE_BLOCK_WEIGHT;
+        }
+    }
+
+    let tool_paths = session
+        .messages
+        .iter()
+        .flat_map(|m| &m.tool_calls)
+        .filter_map(|c| c.input.get("file_path").and_then(|f| f.as_str()).map(str::to_string));
+    let files: std::collections::BTreeSet<String> = mentioned_file_paths(session).into_iter().chain(tool_paths).collect();
+    for file in &files {
+        let language = Path::new(file)
+            .extension()
+            .and_then(|ext| LANGUAGE_EXTENSIONS.iter().find(|(e, _)| ext == *e))
+            .map(|(_, language)| *language);
+        if let Some(language) = language {
+            *scores.entry(language.to_string()).or_insert(0) += FILE_EXTENSION_WEIGHT;
+        }
+    }
+
+    for block in &code_blocks {
+        for word in block.content.split(|c: char| !c.is_alphanumeric() && c != '_') {
+            for (language, keywords) in LANGUAGE_KEYWORDS {
+                if keywords.contains(&word) {
+                    *scores.entry(language.to_string()).or_insert(0) += 1;
+                }
+            }
+        }
+    }
+
+    scores
+}
+
+/// The highest-scoring language in `scores` with its share of the total
+/// score, breaking ties by name
+pub fn dominant_language(scores: &std::collections::HashMap<String, usize>) -> Option<(&str, f64)> {
+    let total: usize = scores.values().sum();
+    scores
+        .iter()
+        .filter(|(_, &score)| score > 0)
+        .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+        .map(|(language, &score)| (language.as_str(), score as f64 / total as f64))
+}
+
+/// Count the non-blank lines of a session file
+fn scan_session_file(path: &Path) -> Option<SessionScanResult> {
+    let reader = open_session_file(path).ok()?;
+    let fast_message_count = reader
+        .lines()
+        .map_while(|line| line.ok())
+        .filter(|line| !line.trim().is_empty())
+        .count();
+    Some(SessionScanResult {
+        path: path.to_path_buf(),
+        fast_message_count,
+    })
+}
+
+/// Whether a session's cwd or decoded project path is at or under `dir`.
+/// Whole path components are compared, so `/a/proj` is not under `/a/pro`.
+fn in_directory(session: &Session, dir: &Path) -> bool {
+    [session.cwd.as_deref(), session.project_path.as_deref()]
+        .into_iter()
+        .flatten()
+        .any(|p| expand_home(p).starts_with(dir))
+}
+
+/// Expand a leading `~` to the home directory
+pub fn expand_home(path: &str) -> PathBuf {
+    let home = || dirs::home_dir().unwrap_or_else(|| PathBuf::from("~"));
+    if path == "~" {
+        home()
+    } else if let Some(rest) = path.strip_prefix("~/") {
+        home().join(rest)
+    } else {
+        PathBuf::from(path)
+    }
+}
+
+/// IDs of the sessions whose ID starts with `prefix`, sorted
+pub fn find_by_prefix(sessions: &[Session], prefix: &str) -> Vec<String> {
+    let mut ids: Vec<String> = sessions
+        .iter()
+        .filter(|s| s.id.starts_with(prefix))
+        .map(|s| s.id.clone())
+        .collect();
+    ids.sort();
+    ids.dedup();
+    ids
+}
+
+/// Count tool calls by tool name across sessions
+pub fn aggregate_tool_usage(sessions: &[Session]) -> std::collections::HashMap<String, usize> {
+    let mut counts = std::collections::HashMap::new();
+    for call in sessions
+        .iter()
+        .flat_map(|s| &s.messages)
+        .filter(|m| m.role == "assistant")
+        .flat_map(|m| &m.tool_calls)
+    {
+        *counts.entry(call.name.clone()).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Count sessions by the hour, weekday and date (in UTC) they were created
+pub fn compute_heatmap(sessions: &[Session]) -> ActivityHeatmap {
+    let mut by_hour = [0; 24];
+    let mut by_day_of_week = [0; 7];
+    let mut by_date = std::collections::BTreeMap::new();
+    let mut skipped_sessions = 0;
+
+    for session in sessions {
+        let Some(created) = session.created_at else {
+            skipped_sessions += 1;
+            continue;
+        };
+        by_hour[created.hour() as usize] += 1;
+        by_day_of_week[created.weekday().num_days_from_monday() as usize] += 1;
+        *by_date.entry(created.date_naive()).or_insert(0) += 1;
+    }
+
+    ActivityHeatmap {
+        by_hour,
+        by_day_of_week,
+        by_date: by_date
+            .into_iter()
+            .map(|(date, count)| DateCount {
+                date: date.format("%Y-%m-%d").to_string(),
+                count,
+            })
+            .collect(),
+        skipped_sessions,
+    }
+}
+
+/// Count sessions and their messages by the day, week or month they were
+/// created in UTC, oldest first, noting which files each period's sessions
+/// mention first. Sessions without a `created_at` are left out.
+pub fn bucket_sessions_by_date(sessions: &[Session], bucket: BucketSize) -> Vec<TimelineBucket> {
+    let mut dated: Vec<(DateTime<Utc>, &Session)> =
+        sessions.iter().filter_map(|s| s.created_at.map(|created| (created, s))).collect();
+    dated.sort_by_key(|&(created, _)| created);
+
+    let mut buckets: Vec<TimelineBucket> = Vec::new();
+    let mut seen_files = std::collections::HashSet::new();
+    for (created, session) in dated {
+        let date = bucket.label(created.date_naive());
+        if buckets.last().is_none_or(|b| b.date != date) {
+            buckets.push(TimelineBucket {
+                date,
+                session_count: 0,
+                message_count: 0,
+                new_files: Vec::new(),
+            });
+        }
+        let current = buckets.last_mut().expect("a bucket was just pushed");
+        current.session_count += 1;
+        current.message_count += session.messages.len();
+        for file in extract_file_paths(session, 0) {
+            if seen_files.insert(file.clone()) {
+                current.new_files.push(file);
+            }
+        }
+    }
+    buckets
+}
+
+/// Count sessions and their messages by the hour they were created in `tz`,
+/// each at the offset the zone had then, so daylight saving time is followed.
+/// Sessions without a `created_at` are left out.
+pub fn aggregate_by_hour<Tz: chrono::TimeZone>(sessions: &[Session], tz: &Tz) -> [HourStats; 24] {
+    let mut hours: [HourStats; 24] = std::array::from_fn(|hour| HourStats {
+        hour: hour as u8,
+        session_count: 0,
+        total_messages: 0,
+        avg_messages: 0.0,
+    });
+    for session in sessions {
+        let Some(created) = session.created_at else {
+            continue;
+        };
+        let stats = &mut hours[created.with_timezone(tz).hour() as usize];
+        stats.session_count += 1;
+        stats.total_messages += session.messages.len();
+    }
+    for stats in hours.iter_mut().filter(|s| s.session_count > 0) {
+        stats.avg_messages = stats.total_messages as f64 / stats.session_count as f64;
+    }
+    hours
+}
+
+/// Context summary of a session, with a summary of its tool calls if
+/// `include_tool_summary`
+pub fn session_context(
+    session: &Session,
+    depth: ContextDepth,
+    include_tool_summary: bool,
+    include_token_estimate: bool,
+    limits: ContextLimits,
+) -> SessionContext {
+    let terms = context_terms(session, depth, limits);
+    SessionContext {
+        files_mentioned: terms.files_mentioned,
+        key_terms: terms.key_terms,
+        ..session_context_without_terms(session, depth, include_tool_summary, include_token_estimate, limits)
+    }
+}
+
+/// The messages of `session` that `depth` selects, as a session of their own
+fn context_window(session: &Session, depth: ContextDepth) -> std::borrow::Cow<'_, Session> {
+    match depth {
+        ContextDepth::All => std::borrow::Cow::Borrowed(session),
+        // Field by field, so only the selected messages are copied. Result
+        // entries count messages of the whole session, so none carry over.
+        _ => std::borrow::Cow::Owned(Session {
+            id: session.id.clone(),
+            project_path: session.project_path.clone(),
+            cwd: session.cwd.clone(),
+            created_at: session.created_at,
+            updated_at: session.updated_at,
+            messages: depth.window(&session.messages).to_vec(),
+            file_path: session.file_path.clone(),
+            total_cost_usd: session.total_cost_usd,
+            total_duration_ms: session.total_duration_ms,
+            result_entries: Vec::new(),
+            git_repo: session.git_repo.clone(),
+            git_branch: session.git_branch.clone(),
+        }),
+    }
+}
+
+/// The files and key terms of `session_context`, which scan every word of
+/// the messages `depth` selects
+pub fn context_terms(session: &Session, depth: ContextDepth, limits: ContextLimits) -> ContextTerms {
+    let analyzed = context_window(session, depth);
+    ContextTerms {
+        files_mentioned: extract_file_paths(&analyzed, limits.max_files),
+        key_terms: extract_key_terms(&analyzed, limits.max_terms),
+    }
+}
+
+/// `session_context` with `files_mentioned` and `key_terms` left empty, to
+/// answer quickly and fill them in from `context_terms` later
+pub fn session_context_without_terms(
+    session: &Session,
+    depth: ContextDepth,
+    include_tool_summary: bool,
+    include_token_estimate: bool,
+    limits: ContextLimits,
+) -> SessionContext {
+    let initial_request = session.messages.iter().find(|m| m.role == "user").map(|m| match limits.initial_request_chars {
+        0 => m.content.clone(),
+        max_chars => first_chars(&m.content, max_chars),
+    });
+
+    let analyzed = context_window(session, depth);
+
+    let mut seen = std::collections::HashSet::new();
+    let commands = extract_commands(&analyzed)
+        .into_iter()
+        .map(|c| c.command)
+        .filter(|c| seen.insert(c.clone()))
+        .collect();
+
+    // What the session was doing when it ended, whatever the depth
+    let mut recent_calls = session.messages.iter().rev().flat_map(|m| m.tool_calls.iter().rev());
+    let most_recent_tool = recent_calls.clone().next().map(|call| call.name.clone());
+    let most_recent_file = recent_calls.find_map(|call| {
+        call.input.get("file_path").and_then(|f| f.as_str()).map(str::to_string)
+    });
+
+    SessionContext {
+        id: session.id.clone(),
+        created_at: session.created_at,
+        cwd: session.cwd.clone(),
+        git_repo: session.git_repo.clone(),
+        git_branch: session.git_branch.clone(),
+        initial_request,
+        message_count: session.messages.len(),
+        token_estimate: include_token_estimate.then(|| role_token_estimate(session, None)),
+        token_estimate_by_role: include_token_estimate.then(|| RoleTokens {
+            user: role_token_estimate(session, Some("user")),
+            assistant: role_token_estimate(session, Some("assistant")),
+        }),
+        files_mentioned: Vec::new(),
+        key_terms: Vec::new(),
+        commands,
+        most_recent_file,
+        most_recent_tool,
+        tool_summary: include_tool_summary.then(|| summarize_tool_calls(&analyzed)),
+        aggregated: false,
+        missing_ids: Vec::new(),
+    }
+}
+
+/// Estimated tokens of the messages of `session` with role `role`, or of all
+/// of them, taken as one text
+fn role_token_estimate(session: &Session, role: Option<&str>) -> usize {
+    let text: Vec<&str> = session
+        .messages
+        .iter()
+        .filter(|m| role.is_none_or(|role| m.role == role))
+        .map(|m| m.content.as_str())
+        .collect();
+    estimate_tokens(&text.join("\n"))
+}
+
+/// Render a session context as a paragraph to paste into a prompt, at most
+/// `max_chars` characters long. An initial request longer than the whole
+/// budget is first cut to half of it so the rest of the context still fits;
+/// whatever is still over the budget is cut off, ending in `...`.
+pub fn context_to_prompt(ctx: &SessionContext, max_chars: usize) -> String {
+    let list = |items: &[String]| {
+        if items.is_empty() {
+            "none".to_string()
+        } else {
+            items.join(", ")
+        }
+    };
+    let date = ctx
+        .created_at
+        .map(|t| t.format("%Y-%m-%d").to_string())
+        .unwrap_or_else(|| "an unknown date".to_string());
+    let cwd = ctx.cwd.as_deref().unwrap_or("an unknown directory");
+
+    let mut text = format!("In session {} from {}, while working in {}, ", ctx.id, date, cwd);
+    match &ctx.initial_request {
+        Some(request) if request.chars().count() > max_chars => {
+            text.push_str(&format!("the user asked: '{}'.", truncate_chars(request, max_chars / 2)));
+        }
+        Some(request) => text.push_str(&format!("the user asked: '{}'.", request)),
+        None => text.push_str("the user made no request."),
+    }
+    text.push_str(&format!(
+        " The session involved {} messages and touched files: {}. Key technical terms: {}.",
+        ctx.message_count,
+        list(&ctx.files_mentioned),
+        list(&ctx.key_terms)
+    ));
+
+    truncate_chars(&text, max_chars)
+}
+
+/// Characters of the initial request quoted by `generate_session_summary`
+const SUMMARY_REQUEST_CHARS: usize = 150;
+
+/// A few sentences saying what a session was about: its first request, how
+/// long it ran, the three files and five key terms it mentions most
+pub fn generate_session_summary(session: &Session) -> String {
+    let mut text = match session.messages.iter().find(|m| m.role == "user") {
+        Some(m) => format!(
+            "In session {}, the user asked about '{}'.",
+            session.id,
+            truncate_chars(m.content.trim(), SUMMARY_REQUEST_CHARS)
+        ),
+        None => format!("In session {}, the user made no request.", session.id),
+    };
+
+    let count = session.messages.len();
+    let messages = format!("{} message{}", count, if count == 1 { "" } else { "s" });
+    let files = extract_file_paths(session, 3);
+    if files.is_empty() {
+        text.push_str(&format!(" The conversation involved {}.", messages));
+    } else {
+        text.push_str(&format!(" The conversation involved {} touching files {}.", messages, files.join(", ")));
+    }
+
+    let terms = extract_key_terms(session, 5);
+    if !terms.is_empty() {
+        text.push_str(&format!(" Key topics: {}.", terms.join(", ")));
+    }
+    text
+}
+
+/// `text` cut to at most `max_chars` characters, ending in `...` if cut
+fn truncate_chars(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(max_chars.saturating_sub(3)).collect();
+    truncated.push_str("...");
+    truncated
+}
+
+/// Lay a session out as events: each message, then the tool calls or tool
+/// results it carries. Tool calls and results share their message's timestamp.
+pub fn session_timeline(session: &Session) -> SessionTimeline {
+    let mut events = Vec::new();
+    for msg in &session.messages {
+        // Content repeats tool calls and results as `[Tool...]` lines; those get
+        // events of their own
+        let text: Vec<&str> = msg
+            .content
+            .lines()
+            .filter(|line| !line.starts_with("[Tool: ") && !line.starts_with("[ToolResult"))
+            .collect();
+        let text = text.join(" ");
+        let has_tools = !msg.tool_calls.is_empty() || !msg.tool_results.is_empty();
+        if !text.trim().is_empty() || !has_tools {
+            let event_type = if msg.role == "assistant" {
+                TimelineEventType::AssistantMessage
+            } else {
+                TimelineEventType::UserMessage
+            };
+            events.push((event_type, msg.timestamp, timeline_summary(&text)));
+        }
+
+        for call in &msg.tool_calls {
+            let summary = render_tool_use(&call.name, Some(&call.input));
+            events.push((TimelineEventType::ToolCall, msg.timestamp, summary));
+        }
+        for output in &msg.tool_results {
+            events.push((TimelineEventType::ToolResult, msg.timestamp, timeline_summary(output)));
+        }
+    }
+
+    let timestamps: Vec<_> = events.iter().map(|(_, timestamp, _)| *timestamp).collect();
+    let events = events
+        .into_iter()
+        .zip(timeline_deltas(&timestamps))
+        .map(|((event_type, timestamp, summary), delta_ms)| TimelineEvent {
+            event_type,
+            timestamp,
+            delta_ms,
+            summary,
+        })
+        .collect();
+
+    let duration_seconds = session
+        .created_at
+        .zip(session.updated_at)
+        .map(|(start, end)| (end - start).num_milliseconds() as f64 / 1000.0);
+
+    SessionTimeline {
+        session_id: session.id.clone(),
+        duration_seconds,
+        events,
+    }
+}
+
+/// Milliseconds between each timestamp and the one before it. The first
+/// entry, and any next to a missing timestamp, is `None`. Deltas can be
+/// negative when clocks were skewed.
+pub fn timeline_deltas(timestamps: &[Option<DateTime<Utc>>]) -> Vec<Option<i64>> {
+    let mut previous = None;
+    timestamps
+        .iter()
+        .map(|&timestamp| {
+            let delta = previous.zip(timestamp).map(|(prev, ts): (DateTime<Utc>, _)| (ts - prev).num_milliseconds());
+            previous = timestamp;
+            delta
+        })
+        .collect()
+}
+
+/// One line of at most `TIMELINE_SUMMARY_CHARS` characters
+fn timeline_summary(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    truncate_chars(&text, TIMELINE_SUMMARY_CHARS)
+}
+
+/// Whether Claude replied anywhere in `session`
+pub fn has_assistant_response(session: &Session) -> bool {
+    session.messages.iter().any(|m| m.role == "assistant")
+}
+
+/// Whether `session` ends waiting on Claude (see
+/// `find_sessions_without_assistant_response`)
+fn is_unanswered(session: &Session, abandoned_only: bool) -> bool {
+    let user_last = session.messages.last().is_some_and(|m| m.role == "user");
+    if abandoned_only {
+        return user_last && has_assistant_response(session);
+    }
+    user_last || !has_assistant_response(session) || session.messages.len() == 1
+}
+
+/// Combine two sessions into one under a new ID. Messages are ordered by
+/// timestamp when every message has one, and otherwise kept in order with all
+/// of `a` before all of `b`. A message with the same role, content and
+/// timestamp as an earlier one, as when a resumed session repeats its
+/// predecessor's history, is dropped.
+pub fn merge_sessions(a: &Session, b: &Session) -> Session {
+    use std::hash::{Hash, Hasher};
+
+    let mut messages: Vec<Message> = a.messages.iter().chain(&b.messages).cloned().collect();
+    if messages.iter().all(|m| m.timestamp.is_some()) {
+        // Stable, so messages with equal timestamps stay A-then-B
+        messages.sort_by_key(|m| m.timestamp);
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    messages.retain(|m| {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        (&m.role, &m.content, m.timestamp).hash(&mut hasher);
+        seen.insert(hasher.finish())
+    });
+
+    let timestamps = messages.iter().filter_map(|m| m.timestamp);
+    Session {
+        id: uuid::Uuid::new_v4().to_string(),
+        project_path: a.project_path.clone().or_else(|| b.project_path.clone()),
+        cwd: a.cwd.clone().or_else(|| b.cwd.clone()),
+        created_at: timestamps.clone().min().or(a.created_at).or(b.created_at),
+        updated_at: timestamps.max().or(b.updated_at).or(a.updated_at),
+        messages,
+        file_path: PathBuf::new(),
+        total_cost_usd: add_optional(a.total_cost_usd, b.total_cost_usd),
+        total_duration_ms: add_optional(a.total_duration_ms, b.total_duration_ms),
+        // Reordering and dropping messages leaves no place to put these
+        result_entries: Vec::new(),
+        git_repo: a.git_repo.clone().or_else(|| b.git_repo.clone()),
+        git_branch: a.git_branch.clone().or_else(|| b.git_branch.clone()),
+    }
+}
+
+/// Write `session` to a new file at `path` as Claude Code JSONL, one entry
+/// per message with its text as content. Fails if the file already exists.
+pub fn write_session_jsonl(session: &Session, path: &Path) -> Result<()> {
+    let mut out = String::new();
+    for message in &session.messages {
+        let mut entry = serde_json::json!({
+            "type": message.role,
+            "sessionId": session.id,
+            "message": {"role": message.role, "content": message.content},
+        });
+        if let Some(timestamp) = message.timestamp {
+            entry["timestamp"] = serde_json::json!(timestamp);
+        }
+        if let Some(cwd) = &session.cwd {
+            entry["cwd"] = serde_json::json!(cwd);
+        }
+        out.push_str(&entry.to_string());
+        out.push('\n');
+    }
+
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    std::io::Write::write_all(&mut file, out.as_bytes())?;
+    Ok(())
+}
+
+/// Compare the files, key terms and commands of two sessions
+pub fn diff_sessions(a: &Session, b: &Session) -> SessionDiff {
+    let mut only_in_a = session_context(a, ContextDepth::All, false, false, ContextLimits::default());
+    let mut only_in_b = session_context(b, ContextDepth::All, false, false, ContextLimits::default());
+
+    // Split each list of A into what B lacks and what both have, keeping A's order
+    let split = |a_items: &mut Vec<String>, b_items: &mut Vec<String>| {
+        let b_set: std::collections::HashSet<String> = b_items.iter().cloned().collect();
+        let (shared, unique): (Vec<_>, Vec<_>) =
+            std::mem::take(a_items).into_iter().partition(|item| b_set.contains(item));
+        *a_items = unique;
+        b_items.retain(|item| !shared.contains(item));
+        shared
+    };
+    let files_mentioned = split(&mut only_in_a.files_mentioned, &mut only_in_b.files_mentioned);
+    let key_terms = split(&mut only_in_a.key_terms, &mut only_in_b.key_terms);
+    let commands = split(&mut only_in_a.commands, &mut only_in_b.commands);
+
+    let in_both = SessionContext {
+        id: format!("{} & {}", a.id, b.id),
+        created_at: None,
+        cwd: a.cwd.clone().filter(|cwd| b.cwd.as_ref() == Some(cwd)),
+        git_repo: a.git_repo.clone().filter(|repo| b.git_repo.as_ref() == Some(repo)),
+        git_branch: a.git_branch.clone().filter(|branch| b.git_branch.as_ref() == Some(branch)),
+        initial_request: None,
+        message_count: 0,
+        token_estimate: None,
+        token_estimate_by_role: None,
+        files_mentioned,
+        key_terms,
+        commands,
+        most_recent_file: None,
+        most_recent_tool: None,
+        tool_summary: None,
+        aggregated: false,
+        missing_ids: Vec::new(),
+    };
+
+    let duration = |s: &Session| Some((s.updated_at? - s.created_at?).num_seconds());
+    let duration_delta_seconds = duration(b).zip(duration(a)).map(|(b, a)| b - a);
+
+    SessionDiff {
+        only_in_a,
+        only_in_b,
+        in_both,
+        duration_delta_seconds,
+    }
+}
+
+/// Extract shell commands from the tool calls in a session's assistant messages
+pub fn extract_commands(session: &Session) -> Vec<CommandEntry> {
+    let mut commands = Vec::new();
+
+    for (index, msg) in session.messages.iter().enumerate() {
+        if msg.role != "assistant" {
+            continue;
+        }
+        for call in &msg.tool_calls {
+            if !SHELL_TOOLS.contains(&call.name.as_str()) {
+                continue;
+            }
+            if let Some(command) = call.input.get("command").and_then(|c| c.as_str()) {
+                commands.push(CommandEntry {
+                    command: command.to_string(),
+                    timestamp: msg.timestamp,
+                    message_index: index,
+                });
+            }
+        }
+    }
+
+    commands
+}
+
+/// Characters that may come before a marker on a comment line
+const COMMENT_CHARS: &[char] = &['/', '#', '*', '-', ';', '%', '<', '!'];
+
+/// Find lines in a session's messages, prose and code blocks alike, that
+/// contain one of `patterns` as a word, e.g. `TODO` but not `TODOS`. Lines
+/// that continue a comment starting with the marker, with the same comment
+/// characters but indented past them, are joined onto its text.
+pub fn extract_todos_from_session(session: &Session, patterns: &[&str]) -> Vec<TodoItem> {
+    let mut todos = Vec::new();
+
+    for (index, msg) in session.messages.iter().enumerate() {
+        let lines: Vec<&str> = msg.content.lines().collect();
+        let mut i = 0;
+        while i < lines.len() {
+            let line = lines[i];
+            i += 1;
+            let Some((at, pattern)) = find_todo_marker(line, patterns) else {
+                continue;
+            };
+            let mut text = trim_comment_end(&line[at..]).to_string();
+
+            // Only a marker leading its line, after comment characters, can
+            // be continued on the next
+            let leader = &line[..at];
+            if leader.chars().all(|c| c.is_whitespace() || COMMENT_CHARS.contains(&c)) {
+                let comment = leader.trim_end();
+                let gap = leader.len() - comment.len();
+                while let Some(next) = lines.get(i) {
+                    let Some(rest) = next.strip_prefix(comment) else {
+                        break;
+                    };
+                    let continued = rest.trim_start();
+                    if continued.is_empty()
+                        || rest.len() - continued.len() <= gap
+                        || find_todo_marker(next, patterns).is_some()
+                    {
+                        break;
+                    }
+                    text.push(' ');
+                    text.push_str(trim_comment_end(continued));
+                    i += 1;
+                }
+            }
+
+            todos.push(TodoItem {
+                text,
+                pattern: pattern.to_string(),
+                role: msg.role.clone(),
+                message_index: index,
+                timestamp: msg.timestamp,
+            });
+        }
+    }
+
+    todos
+}
+
+/// The byte offset and pattern of the first of `patterns` in `line` that
+/// stands as a word of its own
+fn find_todo_marker<'a>(line: &str, patterns: &[&'a str]) -> Option<(usize, &'a str)> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    patterns
+        .iter()
+        .filter(|pattern| !pattern.is_empty())
+        .filter_map(|&pattern| {
+            line.match_indices(pattern)
+                .find(|&(at, _)| {
+                    !line[..at].chars().next_back().is_some_and(is_word)
+                        && !line[at + pattern.len()..].chars().next().is_some_and(is_word)
+                })
+                .map(|(at, _)| (at, pattern))
+        })
+        // Earliest first, the longer pattern where two start together
+        .min_by_key(|&(at, pattern)| (at, std::cmp::Reverse(pattern.len())))
+}
+
+/// `text` without a trailing block comment close
+fn trim_comment_end(text: &str) -> &str {
+    let text = text.trim_end();
+    text.strip_suffix("*/")
+        .or_else(|| text.strip_suffix("-->"))
+        .unwrap_or(text)
+        .trim_end()
+}
+
+/// How each tool was used across a session's assistant messages, most
+/// called first, ties by name
+pub fn summarize_tool_calls(session: &Session) -> Vec<ToolCallSummary> {
+    let mut summaries: Vec<ToolCallSummary> = Vec::new();
+    for call in session
+        .messages
+        .iter()
+        .filter(|m| m.role == "assistant")
+        .flat_map(|m| &m.tool_calls)
+    {
+        let summary = match summaries.iter().position(|s| s.tool_name == call.name) {
+            Some(i) => &mut summaries[i],
+            None => {
+                summaries.push(ToolCallSummary {
+                    tool_name: call.name.clone(),
+                    call_count: 0,
+                    files_touched: Vec::new(),
+                    commands_run: Vec::new(),
+                });
+                summaries.last_mut().expect("just pushed")
+            }
+        };
+        summary.call_count += 1;
+
+        if let Some(file_path) = call.input.get("file_path").and_then(|f| f.as_str()) {
+            if !summary.files_touched.iter().any(|f| f == file_path) {
+                summary.files_touched.push(file_path.to_string());
+            }
+        }
+        if SHELL_TOOLS.contains(&call.name.as_str()) {
+            if let Some(command) = call.input.get("command").and_then(|c| c.as_str()) {
+                let mut preview: String = command.chars().take(COMMAND_PREVIEW_CHARS).collect();
+                if preview.len() < command.len() {
+                    preview.push_str("...");
+                }
+                if !summary.commands_run.contains(&preview) {
+                    summary.commands_run.push(preview);
+                }
+            }
+        }
+    }
+    sort_tool_summaries(&mut summaries);
+    summaries
+}
+
+/// Combine per-session tool summaries: counts are summed, files and commands
+/// unioned in order
+fn merge_tool_summaries<'a>(per_session: impl Iterator<Item = &'a [ToolCallSummary]>) -> Vec<ToolCallSummary> {
+    let mut merged: Vec<ToolCallSummary> = Vec::new();
+    for summary in per_session.flatten() {
+        let Some(existing) = merged.iter_mut().find(|m| m.tool_name == summary.tool_name) else {
+            merged.push(summary.clone());
+            continue;
+        };
+        existing.call_count += summary.call_count;
+        for file in &summary.files_touched {
+            if !existing.files_touched.contains(file) {
+                existing.files_touched.push(file.clone());
+            }
+        }
+        for command in &summary.commands_run {
+            if !existing.commands_run.contains(command) {
+                existing.commands_run.push(command.clone());
+            }
+        }
+    }
+    sort_tool_summaries(&mut merged);
+    merged
+}
+
+fn sort_tool_summaries(summaries: &mut [ToolCallSummary]) {
+    summaries.sort_by(|a, b| b.call_count.cmp(&a.call_count).then_with(|| a.tool_name.cmp(&b.tool_name)));
+}
+
+/// Distinct commands in `history`, most often run first, ties in command
+/// order. `history` is most recent first, as `command_history` returns it.
+pub fn command_frequencies(history: &[CommandHistoryEntry]) -> Vec<CommandHistoryItem> {
+    let mut items: Vec<CommandHistoryItem> = Vec::new();
+    let mut index: std::collections::HashMap<&str, usize> = std::collections::HashMap::new();
+    for run in history {
+        match index.get(run.entry.command.as_str()) {
+            Some(&i) => items[i].execution_count += 1,
+            None => {
+                index.insert(&run.entry.command, items.len());
+                items.push(CommandHistoryItem {
+                    command: run.entry.command.clone(),
+                    execution_count: 1,
+                    last_seen_in_session: run.session_id.clone(),
+                });
+            }
+        }
+    }
+    items.sort_by(|a, b| b.execution_count.cmp(&a.execution_count).then_with(|| a.command.cmp(&b.command)));
+    items
+}
+
+/// Rough token count of `text`: about four bytes per token for prose, falling
+/// towards three as symbols typical of code make up more of it
+pub fn estimate_tokens(text: &str) -> usize {
+    if text.is_empty() {
+        return 0;
+    }
+    let symbols = text
+        .chars()
+        .filter(|c| c.is_ascii_punctuation() && !matches!(c, '.' | ',' | '\'' | '?' | '!'))
+        .count();
+    let code_share = (symbols as f64 / text.chars().count() as f64 / CODE_SYMBOL_SHARE).min(1.0);
+    let bytes_per_token = PROSE_BYTES_PER_TOKEN - code_share * (PROSE_BYTES_PER_TOKEN - CODE_BYTES_PER_TOKEN);
+    (text.len() as f64 / bytes_per_token).ceil() as usize
+}
+
+/// Every tool call with a `file_path` input, in order
+pub fn extract_file_operations(session: &Session) -> Vec<FileOperation> {
+    let mut operations = Vec::new();
+
+    for (index, msg) in session.messages.iter().enumerate() {
+        for call in &msg.tool_calls {
+            if let Some(file_path) = call.input.get("file_path").and_then(|f| f.as_str()) {
+                operations.push(FileOperation {
+                    file_path: file_path.to_string(),
+                    operation: call.name.clone(),
+                    message_index: index,
+                    timestamp: msg.timestamp,
+                });
+            }
+        }
+    }
+
+    operations
+}
+
+/// Patterns found in a session's tool results and assistant messages, as
+/// case-sensitive substrings. A pattern is listed once for each tool result or
+/// assistant message containing it.
+pub fn count_error_signals(session: &Session, patterns: &[&str]) -> Vec<String> {
+    let texts = session.messages.iter().flat_map(|msg| {
+        let reply = (msg.role == "assistant").then_some(msg.content.as_str());
+        reply.into_iter().chain(msg.tool_results.iter().map(String::as_str))
+    });
+
+    let mut signals = Vec::new();
+    for text in texts {
+        for pattern in patterns {
+            if text.contains(pattern) {
+                signals.push(pattern.to_string());
+            }
+        }
+    }
+    signals
+}
+
+/// Render a session as Markdown with a YAML front-matter header.
+///
+/// Message content is emitted as-is, except that a code fence left open by a
+/// message is closed so it can't swallow the following sections. Tool calls are
+/// rendered as fenced blocks whose fence is longer than any backtick run inside.
+pub fn session_to_markdown(session: &Session, include_tool_calls: bool) -> String {
+    let yaml_string = |value: Option<&str>| {
+        value
+            .map(|v| serde_json::to_string(v).unwrap_or_default())
+            .unwrap_or_else(|| "null".to_string())
+    };
+
+    let mut out = String::new();
+    out.push_str("---\n");
+    out.push_str(&format!("id: {}\n", yaml_string(Some(&session.id))));
+    out.push_str(&format!("cwd: {}\n", yaml_string(session.cwd.as_deref())));
+    out.push_str(&format!(
+        "created_at: {}\n",
+        yaml_string(session.created_at.map(|dt| dt.to_rfc3339()).as_deref())
+    ));
+    out.push_str(&format!(
+        "updated_at: {}\n",
+        yaml_string(session.updated_at.map(|dt| dt.to_rfc3339()).as_deref())
+    ));
+    out.push_str("---\n");
+
+    for msg in &session.messages {
+        // Drop the one-line tool summaries; they're either omitted or rendered in full below
+        let mut text = msg.content.clone();
+        for call in &msg.tool_calls {
+            text = text.replace(&render_tool_use(&call.name, Some(&call.input)), "");
+        }
+        // Tool output goes along with the tool calls
+        if !include_tool_calls {
+            text = text
+                .lines()
+                .filter(|line| !line.starts_with("[ToolResult"))
+                .collect::<Vec<_>>()
+                .join("\n");
+        }
+        let text = text.trim();
+
+        if text.is_empty() && (!include_tool_calls || msg.tool_calls.is_empty()) {
+            continue;
+        }
+
+        out.push_str(&format!("\n## {}\n\n", role_heading(&msg.role)));
+        if !text.is_empty() {
+            out.push_str(text);
+            out.push('\n');
+            if let Some(fence) = unclosed_fence(text) {
+                out.push_str(&fence);
+                out.push('\n');
+            }
+        }
+
+        if include_tool_calls {
+            for call in &msg.tool_calls {
+                let input = serde_json::to_string_pretty(&call.input).unwrap_or_default();
+                let fence = fence_for(&input);
+                out.push_str(&format!("\n{}{}\n{}\n{}\n", fence, call.name, input, fence));
+            }
+        }
+    }
+
+    out
+}
+
+/// Render a session as `Role: content` lines with `separator` on a line of its
+/// own between messages
+pub fn session_to_plain_text(session: &Session, separator: &str) -> String {
+    session
+        .messages
+        .iter()
+        .map(|msg| format!("{}: {}\n", role_heading(&msg.role), msg.content))
+        .collect::<Vec<_>>()
+        .join(&format!("{}\n", separator))
+}
+
+/// A session's ID, project and messages as the JSON `get_session` returns
+pub fn session_to_json(session: &Session) -> serde_json::Value {
+    let messages: Vec<_> = session
+        .messages
+        .iter()
+        .map(|m| {
+            serde_json::json!({
+                "role": m.role,
+                "content": m.content,
+                "timestamp": m.timestamp
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "id": session.id,
+        "project_path": session.project_path,
+        "messages": messages
+    })
+}
+
+/// Render a session in `format`; `separator` only applies to plain text
+pub fn format_session(session: &Session, format: SessionFormat, separator: &str) -> String {
+    match format {
+        SessionFormat::Json => {
+            serde_json::to_string_pretty(&session_to_json(session)).unwrap_or_else(|_| "{}".to_string())
+        }
+        SessionFormat::Markdown => session_to_markdown(session, false),
+        SessionFormat::PlainText => session_to_plain_text(session, separator),
+    }
+}
+
+/// Capitalize a role name for use as a section heading
+fn role_heading(role: &str) -> String {
+    let mut chars = role.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// A backtick fence long enough to wrap `content` without being closed by it
+fn fence_for(content: &str) -> String {
+    let mut longest = 0;
+    let mut run = 0;
+    for c in content.chars() {
+        if c == '`' {
+            run += 1;
+            longest = longest.max(run);
+        } else {
+            run = 0;
+        }
+    }
+    "`".repeat(longest.max(2) + 1)
+}
+
+/// If `text` leaves a code fence open, the fence needed to close it
+fn unclosed_fence(text: &str) -> Option<String> {
+    let mut open: Option<usize> = None;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        let ticks = trimmed.chars().take_while(|&c| c == '`').count();
+        if ticks < 3 {
+            continue;
+        }
+        match open {
+            None => open = Some(ticks),
+            // A closing fence is only backticks, at least as many as the opener
+            Some(n) if ticks >= n && trimmed.trim_end().len() == ticks => open = None,
+            Some(_) => {}
+        }
+    }
+    open.map(|n| "`".repeat(n))
+}
+
+/// Read a session file's non-blank lines in file order, noting which became
+/// messages, until their content reaches `max_bytes`
+pub fn read_raw_lines(path: &Path, max_bytes: usize) -> Result<RawLines> {
+    let mut bytes = Vec::new();
+    open_session_file(path)?.read_to_end(&mut bytes)?;
+
+    let mut raw = RawLines {
+        raw_lines: Vec::new(),
+        truncated: false,
+    };
+    let mut remaining = max_bytes;
+    for (index, line) in bytes.split(|&b| b == b'\n').enumerate() {
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        if remaining == 0 {
+            raw.truncated = true;
+            break;
+        }
+
+        let mut content = String::from_utf8_lossy(line).into_owned();
+        let parsed = serde_json::from_str::<serde_json::Value>(&content).is_ok_and(|value| {
+            let timestamp = value
+                .get("timestamp")
+                .and_then(|v| v.as_str())
+                .and_then(|s| s.parse::<DateTime<Utc>>().ok());
+            entry_message(&value, timestamp).is_some()
+        });
+        if content.len() > remaining {
+            let mut cut = remaining;
+            while !content.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            content.truncate(cut);
+            raw.truncated = true;
+        }
+        remaining -= content.len();
+
+        raw.raw_lines.push(RawLine {
+            index: index + 1,
+            content,
+            parsed,
+        });
+        if raw.truncated {
+            break;
+        }
+    }
+    Ok(raw)
+}
+
+/// Check a session JSONL file for corruption: unparseable lines, unknown entry
+/// types, bad timestamps, mixed session IDs and duplicated timestamps. Missing
+/// agent sub-session files are reported as warnings.
+pub fn validate_jsonl(path: &Path) -> ValidationReport {
+    let mut report = ValidationReport {
+        valid: false,
+        warnings: Vec::new(),
+        errors: Vec::new(),
+        line_count: 0,
+        parseable_lines: 0,
+    };
+
+    let mut bytes = Vec::new();
+    if let Err(e) = open_session_file(path).and_then(|mut r| Ok(r.read_to_end(&mut bytes)?)) {
+        report.errors.push(format!("Failed to read {}: {:#}", path.display(), e));
+        return report;
+    }
+
+    let mut session_id: Option<(String, usize)> = None;
+    let mut timestamps: Vec<(DateTime<Utc>, usize)> = Vec::new();
+    let mut agent_ids = std::collections::BTreeSet::new();
+    let lines: Vec<&[u8]> = bytes.split(|&b| b == b'\n').collect();
+
+    for (index, raw) in lines.iter().enumerate() {
+        let number = index + 1;
+        if raw.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        report.line_count += 1;
+
+        let Ok(line) = std::str::from_utf8(raw) else {
+            report.errors.push(format!("Line {}: invalid UTF-8", number));
+            continue;
+        };
+        let value: serde_json::Value = match serde_json::from_str(line) {
+            Ok(value) => value,
+            Err(e) if index == lines.len() - 1 && e.is_eof() => {
+                report.errors.push(format!("Line {}: truncated JSON (incomplete write?)", number));
+                continue;
+            }
+            Err(e) => {
+                report.errors.push(format!("Line {}: invalid JSON: {}", number, e));
+                continue;
+            }
+        };
+        report.parseable_lines += 1;
+
+        if let Some(entry_type) = value.get("type").and_then(|v| v.as_str()) {
+            if !KNOWN_ENTRY_TYPES.contains(&entry_type) {
+                report.errors.push(format!("Line {}: unrecognized type \"{}\"", number, entry_type));
+            }
+        }
+
+        if let Some(timestamp) = value.get("timestamp").and_then(|v| v.as_str()) {
+            match timestamp.parse::<DateTime<Utc>>() {
+                Ok(ts) => timestamps.push((ts, number)),
+                Err(_) => report.errors.push(format!(
+                    "Line {}: timestamp \"{}\" is not ISO-8601",
+                    number, timestamp
+                )),
+            }
+        }
+
+        if let Some(id) = value.get("sessionId").and_then(|v| v.as_str()) {
+            match &session_id {
+                None => session_id = Some((id.to_string(), number)),
+                Some((first, first_line)) if first != id => report.errors.push(format!(
+                    "Line {}: sessionId \"{}\" differs from \"{}\" on line {}",
+                    number, id, first, first_line
+                )),
+                Some(_) => {}
+            }
+        }
+
+        for agent_id in [value.get("agentId"), value.pointer("/toolUseResult/agentId")]
+            .into_iter()
+            .flatten()
+            .filter_map(|v| v.as_str())
+        {
+            agent_ids.insert(agent_id.to_string());
+        }
+    }
+
+    timestamps.sort();
+    for pair in timestamps.windows(2) {
+        let ((a, line_a), (b, line_b)) = (pair[0], pair[1]);
+        if b - a < chrono::Duration::milliseconds(1) {
+            report.errors.push(format!(
+                "Lines {} and {}: duplicate timestamp {}",
+                line_a.min(line_b),
+                line_a.max(line_b),
+                a.to_rfc3339()
+            ));
+        }
+    }
+
+    // Sub-agent transcripts sit next to the session or under <session>/subagents/
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    for agent_id in agent_ids {
+        let file_name = format!("agent-{}.jsonl", agent_id);
+        if !dir.join(&file_name).exists() && !dir.join(&stem).join("subagents").join(&file_name).exists() {
+            report.warnings.push(format!("Agent sub-session {} is referenced but {} is missing", agent_id, file_name));
+        }
+    }
+
+    report.valid = report.errors.is_empty();
+    report
+}
+
+/// Whether `path` names a session file, plain (`.jsonl`) or gzipped (`.jsonl.gz`)
+pub fn is_session_file(path: &Path) -> bool {
+    path.file_name()
+        .map(|n| n.to_string_lossy())
+        .is_some_and(|n| n.ends_with(".jsonl") || n.ends_with(".jsonl.gz"))
+}
+
+/// File name of a session file without its `.jsonl` or `.jsonl.gz` extension
+pub fn session_file_stem(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_string_lossy();
+    let stem = name.strip_suffix(".gz").unwrap_or(&name);
+    Some(stem.strip_suffix(".jsonl").unwrap_or(stem).to_string())
+}
+
+/// Open a session file for reading, decompressing `.gz` files on the fly
+pub fn open_session_file(path: &Path) -> Result<Box<dyn BufRead>> {
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    if path.extension().is_some_and(|e| e == "gz") {
+        Ok(Box::new(BufReader::new(GzDecoder::new(file))))
+    } else {
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
+
+/// The session that spawned an agent sub-session: the directory it sits in
+/// (`<parent>/subagents/`), else the `sessionId` it was logged under, else
+/// the session in the same project that was running when the agent started
+fn agent_parent(
+    path: &Path,
+    agent: &Session,
+    logged_id: &str,
+    spans: &std::collections::HashMap<PathBuf, Vec<SessionSpan>>,
+) -> Option<String> {
+    let dir = path.parent()?;
+    if dir.file_name().is_some_and(|n| n == "subagents") {
+        return dir.parent()?.file_name().map(|n| n.to_string_lossy().to_string());
+    }
+
+    let candidates = spans.get(dir)?;
+    if candidates.iter().any(|(id, _, _)| id == logged_id) {
+        return Some(logged_id.to_string());
+    }
+
+    // The latest-started session whose span covers the agent's start
+    let started = agent.created_at?;
+    candidates
+        .iter()
+        .filter(|(_, created, updated)| {
+            created.is_some_and(|c| c <= started) && updated.is_some_and(|u| started <= u)
+        })
+        .max_by_key(|(_, created, _)| *created)
+        .map(|(id, _, _)| id.clone())
+}
+
+/// Decode the directory name Claude Code stores a project's sessions under
+/// back into the project's path.
+///
+/// Separators become `-` and a literal `-` is doubled, so `/home/alice/my-project`
+/// is stored as `-home-alice-my--project`. A Windows drive is stored as `C--`
+/// and decodes to `C:\` with `\` separators. Names that are neither are
+/// returned unchanged.
+pub fn decode_project_hash(hash: &str) -> String {
+    let bytes = hash.as_bytes();
+    let (prefix, rest, separator) = if let Some(rest) = hash.strip_prefix('-') {
+        ("/".to_string(), rest, '/')
+    } else if bytes.len() >= 3 && bytes[0].is_ascii_alphabetic() && &bytes[1..3] == b"--" {
+        (format!("{}:\\", &hash[..1]), &hash[3..], '\\')
+    } else {
+        return hash.to_string();
+    };
+
+    let mut path = prefix;
+    let mut chars = rest.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '-' {
+            path.push(c);
+        } else if chars.peek() == Some(&'-') {
+            chars.next();
+            path.push('-');
+        } else {
+            path.push(separator);
+        }
+    }
+    path
+}
+
+/// Extract project path from session file path
+fn extract_project_path(path: &Path) -> Option<String> {
+    let components: Vec<_> = path.components().collect();
+    for (i, comp) in components.iter().enumerate() {
+        if comp.as_os_str() == "projects" && i + 1 < components.len() {
+            let project_hash = components[i + 1].as_os_str().to_string_lossy();
+            return Some(decode_project_hash(&project_hash));
+        }
+    }
+    None
+}
+
+/// Generate a UUID-like string from path for sessions without explicit ID
+fn uuid_from_path(path: &Path) -> String {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    path.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Characters of surrounding text shown on each side of a highlighted match
+const MATCH_CONTEXT_CHARS: usize = 50;
+
+/// Longest run of matched text shown in a highlight before it is cut off
+const MAX_MATCH_CHARS: usize = 150;
+
+/// The text `search_sessions` matches: all message text of a session and,
+/// with `include_tool_inputs`, the raw JSON input of each tool call after the
+/// text of its message. Message text only mentions a tool call briefly, e.g.
+/// `[Tool: Write on path]`, while its input has the full path or command.
+pub fn extract_full_text_for_search(
+    session: &Session,
+    include_tool_inputs: bool,
+    role_filter: Option<&str>,
+) -> String {
+    // Searching only Claude's side covers everything its tool calls did too.
+    // Tool results come back in user entries, but are Claude's all the same.
+    let assistant_only = role_filter == Some("assistant");
+    let mut parts: Vec<std::borrow::Cow<str>> = Vec::new();
+    for message in &session.messages {
+        if role_filter.is_none_or(|role| message.role == role) {
+            parts.push(message.content.as_str().into());
+            if include_tool_inputs || assistant_only {
+                parts.extend(message.tool_calls.iter().map(|call| call.input.to_string().into()));
+            }
+        }
+        if assistant_only {
+            parts.extend(message.tool_results.iter().map(|result| result.as_str().into()));
+        }
+    }
+    parts.join(" ")
+}
+
+/// `match_context` snippet for a fuzzy match of `query` in `text`
+pub(crate) fn fuzzy_match_context(text: &str, query: &str) -> Option<String> {
+    let (start, end) = fuzzy_match_span(&SkimMatcherV2::default(), text, query)?;
+    Some(highlight_match(text, start, end, MATCH_CONTEXT_CHARS))
+}
+
+/// Byte range from the first to the last character a fuzzy match hit
+fn fuzzy_match_span(matcher: &SkimMatcherV2, text: &str, query: &str) -> Option<(usize, usize)> {
+    // fuzzy_indices reports char positions; convert them to byte offsets
+    let (_, indices) = matcher.fuzzy_indices(text, query)?;
+    let byte_offset = |char_index: usize| text.char_indices().nth(char_index).map(|(i, _)| i);
+    let start = byte_offset(*indices.first()?)?;
+    let end = byte_offset(*indices.last()? + 1).unwrap_or(text.len());
+    Some((start, end))
+}
+
+/// Render `text[start..end]` in Markdown bold with up to `context_chars`
+/// characters of surrounding text on each side. `start` and `end` are byte
+/// offsets on char boundaries; elided text is marked with `...`.
+pub fn highlight_match(text: &str, start: usize, end: usize, context_chars: usize) -> String {
+    let before: Vec<char> = text[..start].chars().collect();
+    let skip = before.len().saturating_sub(context_chars);
+    let before: String = before[skip..].iter().collect();
+
+    let mut matched: String = text[start..end].chars().take(MAX_MATCH_CHARS).collect();
+    if matched.len() < end - start {
+        matched.push_str("...");
+    }
+
+    let mut after_chars = text[end..].chars();
+    let after: String = after_chars.by_ref().take(context_chars).collect();
+
+    format!(
+        "{}{}**{}**{}{}",
+        if skip > 0 { "..." } else { "" },
+        before,
+        matched,
+        after,
+        if after_chars.next().is_some() { "..." } else { "" },
+    )
+}
+
+/// The page of `sessions` in `sort` order that starts after `cursor`, as
+/// returned by `SessionStore::list_sessions`
+pub fn paginate_summaries(
+    mut sessions: Vec<SessionSummary>,
+    limit: usize,
+    cursor: Option<&str>,
+    sort: SortOptions,
+    metadata: Option<&MetadataStore>,
+) -> Result<PaginatedResult<SessionSummary>> {
+    let cursor: Option<SessionCursor> = cursor.map(decode_cursor).transpose()?;
+    sort.sort(&mut sessions);
+
+    // Resume strictly after the last session of the previous page
+    if let Some(cursor) = &cursor {
+        let cursor = SortKey::from(cursor);
+        sessions.retain(|s| sort.compare(&SortKey::from(s), &cursor).is_gt());
+    }
+
+    // Apply limit
+    let limit = limit.min(100);
+    let next_cursor = if sessions.len() > limit {
+        sessions.truncate(limit);
+        sessions.last().map(|s| {
+            encode_cursor(&SessionCursor {
+                updated_at: s.updated_at.clone(),
+                id: s.id.clone(),
+                created_at: s.created_at.clone(),
+                message_count: s.message_count,
+                project_path: s.project_path.clone(),
+            })
+        })
+    } else {
+        None
+    };
+
+    if let Some(metadata) = metadata {
+        for summary in &mut sessions {
+            summary.tags = Some(metadata.tags_for(&summary.id)?);
+        }
+    }
+
+    Ok(PaginatedResult {
+        items: sessions,
+        next_cursor,
+    })
+}
+
+/// `sessions` grouped by project, as returned by
+/// `SessionStore::list_sessions_grouped`
+pub fn group_summaries(
+    sessions: Vec<SessionSummary>,
+    limit: usize,
+    project_limit: usize,
+    metadata: Option<&MetadataStore>,
+) -> Result<GroupedSessionsResult> {
+    let mut by_project: std::collections::HashMap<Option<String>, Vec<SessionSummary>> =
+        std::collections::HashMap::new();
+    for summary in sessions {
+        by_project.entry(summary.project_path.clone()).or_default().push(summary);
+    }
+
+    let mut groups: Vec<ProjectGroup> = by_project
+        .into_iter()
+        .map(|(project_path, mut sessions)| {
+            sessions.sort_by(|a, b| (&b.updated_at, &b.id).cmp(&(&a.updated_at, &a.id)));
+            ProjectGroup {
+                project_path,
+                session_count: sessions.len(),
+                sessions,
+            }
+        })
+        .collect();
+    // Sessions are newest first, so each group's first is its most recent
+    groups.sort_by(|a, b| {
+        let latest = |g: &ProjectGroup| g.sessions.first().and_then(|s| s.updated_at.clone());
+        latest(b).cmp(&latest(a)).then_with(|| a.project_path.cmp(&b.project_path))
+    });
+
+    let total_groups = groups.len();
+    groups.truncate(project_limit.min(100));
+    for group in &mut groups {
+        group.sessions.truncate(limit.min(100));
+        if let Some(metadata) = metadata {
+            for summary in &mut group.sessions {
+                summary.tags = Some(metadata.tags_for(&summary.id)?);
+            }
+        }
+    }
+
+    Ok(GroupedSessionsResult { groups, total_groups })
+}
+
+/// Fields of a serialized `SessionSummary` that `list_sessions` can return
+pub const SUMMARY_FIELDS: &[&str] = &[
+    "id",
+    "project_path",
+    "cwd",
+    "created_at",
+    "updated_at",
+    "message_count",
+    "user_messages",
+    "assistant_messages",
+    "preview",
+    "tags",
+    "total_cost_usd",
+    "total_duration_ms",
+    "git_repo",
+    "git_branch",
+];
+
+/// Characters of the first user message `session_to_summary` keeps as the
+/// preview unless asked for more or fewer
+pub const DEFAULT_PREVIEW_CHARS: usize = 200;
+
+/// The summary of `session`, with the first `preview_length` characters of its
+/// first user message as the preview. A `preview_length` of 0 gives a
+/// `fast_summary`, with no preview.
+pub fn session_to_summary(session: &Session, preview_length: usize) -> SessionSummary {
+    if preview_length == 0 {
+        return fast_summary(session);
+    }
+    let preview = session
+        .messages
+        .iter()
+        .find(|m| m.role == "user")
+        .map(|m| first_chars(&m.content, preview_length))
+        .unwrap_or_else(|| "No preview available".to_string());
+
+    SessionSummary {
+        preview,
+        ..fast_summary(session)
+    }
+}
+
+/// The first `max_chars` characters of `text`, followed by `...` if that
+/// leaves some out
+fn first_chars(text: &str, max_chars: usize) -> String {
+    let mut chars = text.chars();
+    let mut first: String = chars.by_ref().take(max_chars).collect();
+    if chars.next().is_some() {
+        first.push_str("...");
+    }
+    first
+}
+
+/// A summary without the `preview`, which is left empty, so no message text
+/// is copied. For listings that don't show the preview.
+pub fn fast_summary(session: &Session) -> SessionSummary {
+    SessionSummary {
+        id: session.id.clone(),
+        project_path: session.project_path.clone(),
+        cwd: session.cwd.clone(),
+        created_at: session.created_at.map(|dt| dt.to_rfc3339()),
+        updated_at: session.updated_at.map(|dt| dt.to_rfc3339()),
+        message_count: session.messages.len(),
+        user_messages: session.messages.iter().filter(|m| m.role == "user").count(),
+        assistant_messages: session.messages.iter().filter(|m| m.role == "assistant").count(),
+        preview: String::new(),
+        tags: None,
+        match_context: None,
+        score: None,
+        total_cost_usd: session.total_cost_usd,
+        total_duration_ms: session.total_duration_ms,
+        git_repo: session.git_repo.clone(),
+        git_branch: session.git_branch.clone(),
+    }
+}
+
+/// Drop every key of a serialized summary that isn't in `fields`
+pub fn retain_fields(summary: &mut serde_json::Value, fields: &[String]) {
+    if let Some(object) = summary.as_object_mut() {
+        object.retain(|key, _| fields.contains(key));
+    }
+}
+
+/// Extract file paths mentioned in session, at most `max` of them unless
+/// `max` is 0
+fn extract_file_paths(session: &Session, max: usize) -> Vec<String> {
+    let mut result: Vec<_> = mentioned_file_paths(session).into_iter().collect();
+    if max > 0 {
+        result.truncate(max);
+    }
+    result
+}
+
+/// Jaccard similarity of two sets of strings: shared items over all distinct
+/// items, from 0 (nothing shared, or both empty) to 1 (identical)
+pub fn jaccard_similarity(a: &[String], b: &[String]) -> f64 {
+    let a: std::collections::HashSet<&String> = a.iter().collect();
+    let b: std::collections::HashSet<&String> = b.iter().collect();
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(&b).count() as f64 / union as f64
+}
+
+/// How often each key term (as picked by `extract_key_terms`) occurs in a
+/// session, as a share of all key term occurrences
+pub fn term_frequency_vector(session: &Session) -> std::collections::HashMap<String, f64> {
+    let counts = key_term_counts(session);
+    let total: usize = counts.values().sum();
+    counts
+        .into_iter()
+        .map(|(term, count)| (term, count as f64 / total as f64))
+        .collect()
+}
+
+/// Cosine of the angle between two sparse vectors, from 0 (no terms in
+/// common, or either is empty) to 1 (same proportions)
+pub fn cosine_similarity(
+    a: &std::collections::HashMap<String, f64>,
+    b: &std::collections::HashMap<String, f64>,
+) -> f64 {
+    let norm = |v: &std::collections::HashMap<String, f64>| v.values().map(|x| x * x).sum::<f64>().sqrt();
+    let (norm_a, norm_b) = (norm(a), norm(b));
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    let (small, large) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    let dot: f64 = small
+        .iter()
+        .filter_map(|(term, x)| large.get(term).map(|y| x * y))
+        .sum();
+    (dot / (norm_a * norm_b)).min(1.0)
+}
+
+/// Representative of `i`'s set in a union-find forest, halving paths on the way
+fn find_root(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+/// All file paths mentioned in free text, sorted and deduplicated
+fn mentioned_file_paths(session: &Session) -> std::collections::BTreeSet<String> {
+    let mut paths = std::collections::BTreeSet::new();
+
+    for msg in &session.messages {
+        for word in msg.content.split_whitespace() {
+            if (word.contains('/') || word.contains('\\'))
+                && (word.contains('.') || word.ends_with('/'))
+            {
+                let cleaned = word.trim_matches(|c: char| {
+                    !c.is_alphanumeric() && c != '/' && c != '\\' && c != '.' && c != '_' && c != '-'
+                });
+                if cleaned.len() > 3 {
+                    paths.insert(cleaned.to_string());
+                }
+            }
+        }
+    }
+
+    paths
+}
+
+/// Whether a session mentions `file_path`, either in text or as a tool_use `file_path` input
+fn session_touches_file(session: &Session, file_path: &str, mode: FileMatchMode) -> bool {
+    let tool_paths = session
+        .messages
+        .iter()
+        .flat_map(|m| &m.tool_calls)
+        .filter_map(|c| c.input.get("file_path").and_then(|f| f.as_str()));
+
+    tool_paths
+        .chain(mentioned_file_paths(session).iter().map(String::as_str))
+        .any(|candidate| mode.matches(candidate, file_path))
+}
+
+/// Extract key terms from session (simple word frequency), at most `max` of
+/// them unless `max` is 0
+fn extract_key_terms(session: &Session, max: usize) -> Vec<String> {
+    let mut sorted: Vec<_> = key_term_counts(session).into_iter().collect();
+    sorted.sort_by_key(|w| std::cmp::Reverse(w.1));
+    if max > 0 {
+        sorted.truncate(max);
+    }
+
+    sorted.into_iter().map(|(word, _)| word).collect()
+}
+
+/// Occurrences of each word in the session worth treating as a key term:
+/// longer than 3 characters and not a stop word, lowercased, without punctuation
+pub fn key_term_counts(session: &Session) -> std::collections::HashMap<String, usize> {
+    use std::collections::HashMap;
+
+    let stop_words: std::collections::HashSet<&str> = [
+        "the", "a", "an", "is", "are", "was", "were", "be", "been", "being",
+        "have", "has", "had", "do", "does", "did", "will", "would", "could",
+        "should", "may", "might", "must", "shall", "can", "need", "dare",
+        "ought", "used", "to", "of", "in", "for", "on", "with", "at", "by",
+        "from", "as", "into", "through", "during", "before", "after", "above",
+        "below", "between", "under", "again", "further", "then", "once", "here",
+        "there", "when", "where", "why", "how", "all", "each", "few", "more",
+        "most", "other", "some", "such", "no", "nor", "not", "only", "own",
+        "same", "so", "than", "too", "very", "just", "and", "but", "if", "or",
+        "because", "until", "while", "this", "that", "these", "those", "i", "you",
+        "he", "she", "it", "we", "they", "what", "which", "who", "whom", "its",
+        "his", "her", "their", "my", "your", "our", "tool", "file", "path",
+    ]
+    .iter()
+    .copied()
+    .collect();
+
+    let mut word_counts: HashMap<String, usize> = HashMap::new();
+
+    for msg in &session.messages {
+        for word in msg.content.split_whitespace() {
+            let cleaned: String = word
+                .chars()
+                .filter(|c| c.is_alphanumeric())
+                .collect::<String>()
+                .to_lowercase();
+
+            if cleaned.len() > 3 && !stop_words.contains(cleaned.as_str()) {
+                *word_counts.entry(cleaned).or_insert(0) += 1;
+            }
+        }
+    }
+
+    word_counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_message_content_string() {
+        let message = serde_json::json!({
+            "role": "user",
+            "content": "Hello world"
+        });
+        assert_eq!(extract_message_content(&message), "Hello world");
+    }
+
+    #[test]
+    fn test_extract_message_content_array() {
+        let message = serde_json::json!({
+            "role": "assistant",
+            "content": [
+                {"type": "text", "text": "Here is the result"},
+                {"type": "tool_use", "name": "Write", "input": {"file_path": "/test/file.rs"}}
+            ]
+        });
+        let content = extract_message_content(&message);
+        assert!(content.contains("Here is the result"));
+        assert!(content.contains("[Tool: Write on /test/file.rs]"));
+    }
+
+    #[test]
+    fn test_gzipped_session() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("projects").join("-home-user-archive");
+        std::fs::create_dir_all(&project).unwrap();
+        let path = project.join("old-session.jsonl.gz");
+
+        let lines = [
+            serde_json::json!({
+                "type": "user", "timestamp": "2024-06-01T09:00:00Z",
+                "message": {"role": "user", "content": "archived question"}
+            }),
+            serde_json::json!({
+                "type": "assistant", "timestamp": "2024-06-01T09:00:03Z",
+                "message": {"role": "assistant", "content": "archived answer"}
+            }),
+        ];
+        let content: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(content.join("\n").as_bytes()).unwrap();
+        std::fs::write(&path, encoder.finish().unwrap()).unwrap();
+
+        let store = SessionStore::with_path(dir.path().to_path_buf());
+        let session = store.try_parse_jsonl_session(&path).unwrap().unwrap();
+        // Without a sessionId the ID comes from the file name, minus both extensions
+        assert_eq!(session.id, "old-session");
+        assert_eq!(session.messages.len(), 2);
+        assert_eq!(session.messages[1].content, "archived answer");
+
+        let found = store.get_session("old-session").unwrap().unwrap();
+        assert_eq!(found.messages[0].content, "archived question");
+        let listed = store.list_sessions(10, None, ListOptions::default(), None).unwrap();
+        assert_eq!(listed.items.len(), 1);
+    }
+
+    #[test]
+    fn test_session_cost_from_result_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("paid.jsonl");
+        let lines = [
+            serde_json::json!({"type": "user", "message": {"role": "user", "content": "question"}}),
+            serde_json::json!({"type": "result", "costUSD": 0.5, "durationMs": 1500}),
+            serde_json::json!({"type": "assistant", "message": {"role": "assistant", "content": "answer"}}),
+            serde_json::json!({"type": "result", "costUSD": 0.25, "durationMs": 500}),
+            // Only result entries count
+            serde_json::json!({"type": "summary", "costUSD": 100.0, "durationMs": 100}),
+            serde_json::json!({"type": "result", "costUSD": 0.125}),
+        ];
+        let content: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+        std::fs::write(&path, content.join("\n")).unwrap();
+
+        let store = SessionStore::with_path(dir.path().to_path_buf());
+        let session = store.try_parse_jsonl_session(&path).unwrap().unwrap();
+        assert_eq!(session.messages.len(), 2);
+        assert_eq!(session.total_cost_usd, Some(0.875));
+        assert_eq!(session.total_duration_ms, Some(2000));
+
+        let summary = session_to_summary(&session, DEFAULT_PREVIEW_CHARS);
+        assert_eq!(summary.total_cost_usd, Some(0.875));
+        assert_eq!(summary.total_duration_ms, Some(2000));
+
+        std::fs::write(&path, lines[0].to_string()).unwrap();
+        let session = store.try_parse_jsonl_session(&path).unwrap().unwrap();
+        assert_eq!(session.total_cost_usd, None);
+        assert_eq!(session.total_duration_ms, None);
+    }
+
+    #[test]
+    fn test_agent_sessions() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("projects").join("-proj");
+        let write = |path: PathBuf, session_id: Option<&str>, times: &[&str]| {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            let lines: Vec<String> = times
+                .iter()
+                .map(|t| {
+                    let mut line = serde_json::json!({
+                        "type": "user", "timestamp": t,
+                        "message": {"role": "user", "content": "work"}
+                    });
+                    if let Some(id) = session_id {
+                        line["sessionId"] = serde_json::json!(id);
+                    }
+                    line.to_string()
+                })
+                .collect();
+            std::fs::write(path, lines.join("\n")).unwrap();
+        };
+        write(project.join("parent.jsonl"), Some("parent"), &["2025-01-01T10:00:00Z", "2025-01-01T10:30:00Z"]);
+        write(project.join("other.jsonl"), Some("other"), &["2025-01-02T10:00:00Z", "2025-01-02T10:30:00Z"]);
+        // Logged under the parent's sessionId
+        write(project.join("agent-a1.jsonl"), Some("parent"), &["2025-01-01T10:05:00Z"]);
+        // No sessionId, but started while "other" was running
+        write(project.join("agent-a2.jsonl"), None, &["2025-01-02T10:10:00Z"]);
+        // Stored under its parent's directory
+        write(project.join("other").join("subagents").join("agent-a3.jsonl"), None, &["2025-01-03T10:00:00Z"]);
+        // Started while nothing was running
+        write(project.join("agent-a4.jsonl"), None, &["2025-01-04T10:00:00Z"]);
+
+        let store = SessionStore::with_path(dir.path().to_path_buf());
+        let agents = store.list_agent_sessions(None, 10).unwrap();
+        let found: Vec<_> = agents
+            .iter()
+            .map(|a| (a.summary.id.as_str(), a.parent_session_id.as_deref()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("agent-a4", None),
+                ("agent-a3", Some("other")),
+                ("agent-a2", Some("other")),
+                ("agent-a1", Some("parent")),
+            ]
+        );
+
+        let children = store.list_agent_sessions(Some("other"), 10).unwrap();
+        let ids: Vec<_> = children.iter().map(|a| a.summary.id.as_str()).collect();
+        assert_eq!(ids, vec!["agent-a3", "agent-a2"]);
+
+        assert_eq!(store.list_sessions(10, None, ListOptions::default(), None).unwrap().items.len(), 2);
+        assert_eq!(store.list_sessions(10, None, ListOptions { include_agents: true, ..Default::default() }, None).unwrap().items.len(), 6);
+    }
+
+    #[test]
+    fn test_fast_summary() {
+        let session = session_with(vec![message("user", "hello there", vec![]), message("assistant", "hi", vec![])]);
+        let full = session_to_summary(&session, DEFAULT_PREVIEW_CHARS);
+        let fast = fast_summary(&session);
+        assert_eq!(full.preview, "hello there");
+        assert_eq!(fast.preview, "");
+        assert_eq!(fast.id, full.id);
+        assert_eq!(fast.message_count, 2);
+        assert_eq!(fast.user_messages, 1);
+
+        let mut value = serde_json::to_value(&full).unwrap();
+        retain_fields(&mut value, &["id".to_string(), "message_count".to_string(), "unknown".to_string()]);
+        assert_eq!(value, serde_json::json!({"id": "test-session", "message_count": 2}));
+    }
+
+    #[test]
+    fn test_preview_length_multibyte() {
+        // Three bytes per character, so a byte count would cut mid-character
+        let session = session_with(vec![message("user", "日本語のテキストです", vec![])]);
+        assert_eq!(session_to_summary(&session, 3).preview, "日本語...");
+        assert_eq!(session_to_summary(&session, 10).preview, "日本語のテキストです");
+        assert_eq!(session_to_summary(&session, 11).preview, "日本語のテキストです");
+        assert_eq!(session_to_summary(&session, 0).preview, "");
+
+        let limits = ContextLimits { initial_request_chars: 4, ..ContextLimits::default() };
+        let context = session_context(&session, ContextDepth::All, false, false, limits);
+        assert_eq!(context.initial_request.as_deref(), Some("日本語の..."));
+        let limits = ContextLimits { initial_request_chars: 0, ..ContextLimits::default() };
+        let context = session_context(&session, ContextDepth::All, false, false, limits);
+        assert_eq!(context.initial_request.as_deref(), Some("日本語のテキストです"));
+    }
+
+    fn vector(entries: &[(&str, f64)]) -> std::collections::HashMap<String, f64> {
+        entries.iter().map(|(term, x)| (term.to_string(), *x)).collect()
+    }
+
+    #[test]
+    fn test_cosine_similarity() {
+        let a = vector(&[("parser", 1.0), ("lexer", 2.0)]);
+        assert!((cosine_similarity(&a, &a) - 1.0).abs() < 1e-9);
+        // Only the proportions matter
+        assert!((cosine_similarity(&a, &vector(&[("parser", 3.0), ("lexer", 6.0)])) - 1.0).abs() < 1e-9);
+        // (1, 0) against (1, 1)
+        let b = vector(&[("parser", 1.0)]);
+        let c = vector(&[("parser", 1.0), ("tokens", 1.0)]);
+        assert!((cosine_similarity(&b, &c) - std::f64::consts::FRAC_1_SQRT_2).abs() < 1e-9);
+        // (1, 2, 0) against (0, 2, 3): 4 / (sqrt(5) * sqrt(13))
+        let d = vector(&[("lexer", 2.0), ("tokens", 3.0)]);
+        assert!((cosine_similarity(&a, &d) - 4.0 / (5.0f64.sqrt() * 13.0f64.sqrt())).abs() < 1e-9);
+        assert_eq!(cosine_similarity(&a, &vector(&[("tokens", 1.0)])), 0.0);
+        assert_eq!(cosine_similarity(&a, &vector(&[])), 0.0);
+    }
+
+    #[test]
+    fn test_term_frequency_vector() {
+        let session = session_with(vec![
+            message("user", "Refactor the parser", Vec::new()),
+            message("assistant", "The parser now uses tokens", Vec::new()),
+        ]);
+        let vector = term_frequency_vector(&session);
+        assert_eq!(vector.len(), 4);
+        assert_eq!(vector["parser"], 0.4);
+        assert_eq!(vector["refactor"], 0.2);
+        assert!(!vector.contains_key("the"));
+    }
+
+    #[test]
+    fn test_find_duplicate_sessions() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("projects").join("-proj");
+        std::fs::create_dir_all(&project).unwrap();
+        let write = |id: &str, cwd: &str, updated: &str, content: &str| {
+            let line = serde_json::json!({
+                "type": "user", "sessionId": id, "cwd": cwd, "timestamp": updated,
+                "message": {"role": "user", "content": content}
+            });
+            std::fs::write(project.join(format!("{}.jsonl", id)), line.to_string()).unwrap();
+        };
+        write("first", "/app", "2025-01-01T10:00:00Z", "migrate the billing schema to postgres and backfill invoices");
+        write("again", "/app", "2025-01-02T10:00:00Z", "migrate billing schema to postgres, backfill invoices please");
+        write("elsewhere", "/other", "2025-01-03T10:00:00Z", "migrate the billing schema to postgres and backfill invoices");
+        write("unrelated", "/app", "2025-01-04T10:00:00Z", "write release notes for version two");
+
+        let store = SessionStore::with_path(dir.path().to_path_buf());
+        let groups = store.find_duplicate_sessions(0.9, 10).unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].cwd, "/app");
+        let ids: Vec<&str> = groups[0].sessions.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, vec!["again", "first"]);
+        assert!(groups[0].min_similarity >= 0.9);
+
+        assert!(store.find_duplicate_sessions(0.9, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_list_sessions_min_messages() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("projects").join("-proj");
+        std::fs::create_dir_all(&project).unwrap();
+        let write = |id: &str, contents: &[&str]| {
+            let lines: Vec<String> = contents
+                .iter()
+                .map(|content| {
+                    serde_json::json!({
+                        "type": "user", "sessionId": id, "timestamp": "2025-01-01T10:00:00Z",
+                        "message": {"role": "user", "content": content}
+                    })
+                    .to_string()
+                })
+                .collect();
+            std::fs::write(project.join(format!("{}.jsonl", id)), lines.join("\n")).unwrap();
+        };
+        write("accidental", &["hi"]);
+        write("real", &["fix the build", "and run the tests"]);
+
+        let store = SessionStore::with_path(dir.path().to_path_buf());
+        let ids = |min_messages| -> Vec<String> {
+            let page = store.list_sessions(10, None, ListOptions { min_messages, ..Default::default() }, None).unwrap();
+            let mut ids: Vec<String> = page.items.into_iter().map(|s| s.id).collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids(None), vec!["accidental", "real"]);
+        assert_eq!(ids(Some(1)), vec!["accidental", "real"]);
+        assert_eq!(ids(Some(2)), vec!["real"]);
+        assert!(ids(Some(3)).is_empty());
+
+        let grouped = store.list_sessions_grouped(10, 10, false, Some(2), None).unwrap();
+        assert_eq!(grouped.groups[0].session_count, 1);
+    }
+
+    #[test]
+    fn test_get_session_head() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("projects").join("-proj");
+        std::fs::create_dir_all(&project).unwrap();
+        let lines: Vec<String> = (0..10)
+            .map(|n| {
+                serde_json::json!({
+                    "type": "user", "sessionId": "long", "timestamp": format!("2025-01-01T10:00:{:02}Z", n),
+                    "message": {"role": "user", "content": format!("message {}", n)}
+                })
+                .to_string()
+            })
+            .collect();
+        std::fs::write(project.join("long.jsonl"), lines.join("\n")).unwrap();
+        std::fs::write(project.join("other.jsonl"), &lines[0]).unwrap();
+
+        let store = SessionStore::with_path(dir.path().to_path_buf());
+        let head = store.get_session_head("long", Some(3)).unwrap().unwrap();
+        let contents: Vec<_> = head.messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, vec!["message 0", "message 1", "message 2"]);
+
+        let full = store.get_session_head("long", None).unwrap().unwrap();
+        assert_eq!(full.messages.len(), 10);
+        assert_eq!(full.updated_at, Some("2025-01-01T10:00:09Z".parse().unwrap()));
+        assert!(store.get_session_head("missing", None).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_extract_content_tool_result_string() {
+        let message = serde_json::json!({
+            "role": "user",
+            "content": [
+                {"type": "tool_result", "tool_use_id": "toolu_1", "content": "Compiling foo\n   Finished dev"}
+            ]
+        });
+        assert_eq!(extract_message_content(&message), "[ToolResult: Compiling foo Finished dev]");
+    }
+
+    #[test]
+    fn test_extract_content_tool_result_array() {
+        let long = "x".repeat(250);
+        let message = serde_json::json!({
+            "role": "user",
+            "content": [
+                {"type": "tool_result", "tool_use_id": "toolu_1", "content": [
+                    {"type": "text", "text": "fn main() {}"},
+                    {"type": "text", "text": long}
+                ]},
+                {"type": "text", "text": "looks good"}
+            ]
+        });
+        let content = extract_message_content(&message);
+        let (result, text) = content.split_once('\n').unwrap();
+        assert!(result.starts_with("[ToolResult: fn main() {} xxx"));
+        assert!(result.ends_with("...]"));
+        assert_eq!(result.chars().count(), "[ToolResult: ".len() + 200 + "...]".len());
+        assert_eq!(text, "looks good");
+    }
+
+    #[test]
+    fn test_extract_content_tool_result_error() {
+        let message = serde_json::json!({
+            "role": "user",
+            "content": [
+                {"type": "tool_result", "tool_use_id": "toolu_1", "is_error": true,
+                 "content": "No such file or directory"}
+            ]
+        });
+        assert_eq!(
+            extract_message_content(&message),
+            "[ToolResult error: No such file or directory]"
+        );
+    }
+
+    fn prefix_lookup_store(ids: &[&str]) -> (tempfile::TempDir, SessionStore) {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("projects").join("-proj");
+        std::fs::create_dir_all(&project).unwrap();
+        for id in ids {
+            let line = serde_json::json!({
+                "type": "user", "sessionId": id, "timestamp": "2024-06-01T09:00:00Z",
+                "message": {"role": "user", "content": format!("question in {}", id)}
+            });
+            std::fs::write(project.join(format!("{}.jsonl", id)), line.to_string()).unwrap();
+        }
+        let store = SessionStore::with_path(dir.path().to_path_buf());
+        (dir, store)
+    }
+
+    #[test]
+    fn test_find_by_prefix() {
+        let mut a = session_with(Vec::new());
+        a.id = "a1b2c3d4-0000-0000-0000-000000000000".to_string();
+        let mut b = session_with(Vec::new());
+        b.id = "a1b2ffff-0000-0000-0000-000000000000".to_string();
+        let sessions = [a, b];
+
+        assert_eq!(find_by_prefix(&sessions, "a1b2c"), vec!["a1b2c3d4-0000-0000-0000-000000000000"]);
+        assert_eq!(find_by_prefix(&sessions, "a1b2").len(), 2);
+        assert!(find_by_prefix(&sessions, "ffff").is_empty());
+    }
+
+    #[test]
+    fn test_get_session_by_unambiguous_prefix() {
+        let (_dir, store) = prefix_lookup_store(&[
+            "a1b2c3d4-e5f6-7890-abcd-ef0123456789",
+            "ffff0000-e5f6-7890-abcd-ef0123456789",
+        ]);
+
+        let session = store.get_session("a1b2c3d4").unwrap().unwrap();
+        assert_eq!(session.id, "a1b2c3d4-e5f6-7890-abcd-ef0123456789");
+        assert!(store.get_session("0000ffff").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_get_session_by_ambiguous_prefix() {
+        let (_dir, store) = prefix_lookup_store(&[
+            "a1b2c3d4-e5f6-7890-abcd-ef0123456789",
+            "a1b2ffff-e5f6-7890-abcd-ef0123456789",
+        ]);
+
+        let err = store.get_session("a1b2").unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("a1b2c3d4-e5f6-7890-abcd-ef0123456789"));
+        assert!(message.contains("a1b2ffff-e5f6-7890-abcd-ef0123456789"));
+    }
+
+    #[test]
+    fn test_get_session_by_full_id() {
+        let (_dir, store) = prefix_lookup_store(&[
+            "a1b2c3d4-e5f6-7890-abcd-ef0123456789",
+            "a1b2c3d4-e5f6-7890-abcd-ef0123456780",
+        ]);
+
+        let session = store.get_session("a1b2c3d4-e5f6-7890-abcd-ef0123456789").unwrap().unwrap();
+        assert_eq!(session.id, "a1b2c3d4-e5f6-7890-abcd-ef0123456789");
+        assert!(store.get_session("00000000-e5f6-7890-abcd-ef0123456789").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_get_session_prefix_too_short() {
+        let (_dir, store) = prefix_lookup_store(&["a1b2c3d4-e5f6-7890-abcd-ef0123456789"]);
+
+        for prefix in ["", "a1b"] {
+            let err = store.get_session(prefix).unwrap_err();
+            assert!(err.to_string().contains("too short, minimum 4 characters"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_sessions_by_message_count() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |project: &str, id: &str, messages: usize, extra_lines: usize| {
+            let project = dir.path().join("projects").join(project);
+            std::fs::create_dir_all(&project).unwrap();
+            let mut lines: Vec<String> = (0..messages)
+                .map(|i| {
+                    serde_json::json!({
+                        "type": "user", "sessionId": id,
+                        "timestamp": format!("2024-06-01T09:00:{:02}Z", i),
+                        "message": {"role": "user", "content": format!("message {}", i)}
+                    })
+                    .to_string()
+                })
+                .collect();
+            // Entries that aren't messages inflate the line count
+            lines.extend((0..extra_lines).map(|_| r#"{"type":"summary","summary":"notes"}"#.to_string()));
+            std::fs::write(project.join(format!("{}.jsonl", id)), lines.join("\n")).unwrap();
+        };
+        write("-home-user-app", "one", 1, 0);
+        write("-home-user-app", "two", 2, 0);
+        write("-home-user-app", "padded", 3, 10);
+        write("-home-user-lib", "five", 5, 0);
+        let store = SessionStore::with_path(dir.path().to_path_buf());
+        let ids = |sessions: Vec<SessionSummary>| sessions.into_iter().map(|s| s.id).collect::<Vec<_>>();
+
+        let longest = store.sessions_by_message_count(SortOrder::Desc, 2, None, false).unwrap();
+        assert_eq!(ids(longest), vec!["five", "padded"]);
+
+        let shortest = store.sessions_by_message_count(SortOrder::Asc, 10, None, false).unwrap();
+        assert_eq!(ids(shortest), vec!["two", "padded", "five"]);
+
+        let shortest = store.sessions_by_message_count(SortOrder::Asc, 1, None, true).unwrap();
+        assert_eq!(ids(shortest), vec!["one"]);
+
+        let in_app = store.sessions_by_message_count(SortOrder::Desc, 10, Some("/home/user/app"), false).unwrap();
+        assert_eq!(ids(in_app), vec!["padded", "two"]);
+    }
+
+    #[test]
+    fn test_session_ids_for_project_prefix() {
+        let dir = tempfile::tempdir().unwrap();
+        let projects = dir.path().join("projects");
+        let touch = |project: &str, file: &str| {
+            std::fs::create_dir_all(projects.join(project)).unwrap();
+            std::fs::write(projects.join(project).join(file), "not parsed").unwrap();
+        };
+        touch("-home-user-app", "a1.jsonl");
+        touch("-home-user-app", "a2.jsonl.gz");
+        touch("-home-user-app", "agent-x.jsonl");
+        touch("-home-user-apple", "apple.jsonl");
+        touch("-home-user-app-api", "api.jsonl");
+        let store = SessionStore::with_path(dir.path().to_path_buf());
+
+        let sorted = |mut ids: Vec<String>| {
+            ids.sort();
+            ids
+        };
+        // Whole path components are compared, so /home/user/app doesn't match /home/user/apple
+        assert_eq!(sorted(store.session_ids_for_project_prefix("/home/user/app")), vec!["a1", "a2", "api"]);
+        assert_eq!(sorted(store.session_ids_for_project_prefix("/home/user")).len(), 4);
+        assert!(store.session_ids_for_project_prefix("/srv").is_empty());
+
+        // The index is cached, and shared with clones, until invalidated
+        touch("-srv-site", "site.jsonl");
+        let clone = store.clone();
+        assert!(clone.session_ids_for_project_prefix("/srv").is_empty());
+        clone.invalidate_index();
+        assert_eq!(store.session_ids_for_project_prefix("/srv"), vec!["site"]);
+    }
+
+    #[test]
+    fn test_store_without_projects_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(Some(dir.path().to_path_buf())).unwrap();
+
+        assert!(store.list_sessions(10, None, ListOptions::default(), None).unwrap().items.is_empty());
+        assert!(store.search_sessions("anything", 10, None, SearchMode::Fuzzy, SearchOptions::default()).unwrap().items.is_empty());
+        assert!(store.list_projects(false).unwrap().is_empty());
+        assert!(store.get_session("missing").unwrap().is_none());
+
+        assert!(SessionStore::new(Some(dir.path().join("absent"))).is_err());
+    }
+
+    #[test]
+    fn test_extract_code_blocks() {
+        let mut with_output = message("user", "[ToolResult: ...]", vec![]);
+        with_output.tool_results = vec!["Contents:\n```toml\n[package]\n```".to_string()];
+
+        let session = session_with(vec![
+            message("user", "Write a script\n```python\nprint('hi')\n```", vec![]),
+            message(
+                "assistant",
+                "Rust:\n```Rust\nfn main() {}\n```\nPlain:\n```\nplain text\n```",
+                vec![],
+            ),
+            // A longer outer fence keeps the inner fence as content
+            message("assistant", "````markdown\n```rust\nlet x = 1;\n```\n````", vec![]),
+            // Unclosed fences are discarded
+            message("assistant", "```python\nnever closed", vec![]),
+            with_output,
+        ]);
+
+        let blocks = extract_code_blocks(&session);
+        let summary: Vec<_> = blocks
+            .iter()
+            .map(|b| (b.language.as_deref(), b.content.as_str(), b.role.as_str(), b.message_index))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Some("python"), "print('hi')", "user", 0),
+                (Some("Rust"), "fn main() {}", "assistant", 1),
+                (None, "plain text", "assistant", 1),
+                (Some("markdown"), "```rust\nlet x = 1;\n```", "assistant", 2),
+                (Some("toml"), "[package]", "tool_result", 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_count_error_signals() {
+        let mut failing_build = message("user", "[ToolResult: ...]", vec![]);
+        failing_build.tool_results = vec![
+            "error: expected `;`\nerror: aborting".to_string(),
+            "test result: FAILED. 1 passed; 1 failed".to_string(),
+        ];
+
+        let session = session_with(vec![
+            // User text isn't scanned
+            message("user", "why does it panic?", vec![]),
+            failing_build,
+            message("assistant", "The build failed: a missing semicolon", vec![]),
+            message("assistant", "Fixed the Error: it builds now", vec![]),
+        ]);
+
+        let signals = count_error_signals(&session, DEFAULT_ERROR_PATTERNS);
+        assert_eq!(signals, vec!["error:", "failed", "FAILED", "failed", "Error:"]);
+
+        // Patterns are case-sensitive
+        assert!(count_error_signals(&session, &["PANIC", "semicolon!"]).is_empty());
+    }
+
+    fn summary(id: &str, project: Option<&str>, created: &str, updated: &str, messages: usize) -> SessionSummary {
+        SessionSummary {
+            id: id.to_string(),
+            project_path: project.map(str::to_string),
+            cwd: None,
+            created_at: Some(created.to_string()),
+            updated_at: Some(updated.to_string()),
+            message_count: messages,
+            user_messages: 0,
+            assistant_messages: 0,
+            preview: String::new(),
+            tags: None,
+            match_context: None,
+            score: None,
+            total_cost_usd: None,
+            total_duration_ms: None,
+            git_repo: None,
+            git_branch: None,
+        }
+    }
+
+    fn sorted_ids(field: SortField, order: SortOrder) -> Vec<String> {
+        let mut sessions = vec![
+            summary("a", Some("/beta"), "2024-01-03T00:00:00Z", "2024-01-04T00:00:00Z", 5),
+            summary("b", None, "2024-01-01T00:00:00Z", "2024-01-06T00:00:00Z", 12),
+            summary("c", Some("/alpha"), "2024-01-02T00:00:00Z", "2024-01-05T00:00:00Z", 5),
+            summary("d", None, "2024-01-04T00:00:00Z", "2024-01-02T00:00:00Z", 1),
+        ];
+        SortOptions { field, order }.sort(&mut sessions);
+        sessions.into_iter().map(|s| s.id).collect()
+    }
+
+    #[test]
+    fn test_sort_by_updated_at() {
+        assert_eq!(sorted_ids(SortField::UpdatedAt, SortOrder::Desc), ["b", "c", "a", "d"]);
+        assert_eq!(sorted_ids(SortField::UpdatedAt, SortOrder::Asc), ["d", "a", "c", "b"]);
+    }
+
+    #[test]
+    fn test_sort_by_created_at() {
+        assert_eq!(sorted_ids(SortField::CreatedAt, SortOrder::Desc), ["d", "a", "c", "b"]);
+        assert_eq!(sorted_ids(SortField::CreatedAt, SortOrder::Asc), ["b", "c", "a", "d"]);
+    }
+
+    #[test]
+    fn test_sort_by_message_count() {
+        // a and c tie on 5 messages and fall back to updated_at
+        assert_eq!(sorted_ids(SortField::MessageCount, SortOrder::Desc), ["b", "c", "a", "d"]);
+        assert_eq!(sorted_ids(SortField::MessageCount, SortOrder::Asc), ["d", "a", "c", "b"]);
+    }
+
+    #[test]
+    fn test_sort_by_project_path() {
+        // Sessions without a project path come last, by updated_at
+        assert_eq!(sorted_ids(SortField::ProjectPath, SortOrder::Desc), ["a", "c", "b", "d"]);
+        assert_eq!(sorted_ids(SortField::ProjectPath, SortOrder::Asc), ["c", "a", "d", "b"]);
+    }
+
+    #[test]
+    fn test_sort_options_parse() {
+        assert_eq!("message_count".parse::<SortField>().unwrap(), SortField::MessageCount);
+        assert_eq!("asc".parse::<SortOrder>().unwrap(), SortOrder::Asc);
+        assert!("size".parse::<SortField>().is_err());
+        assert!("ascending".parse::<SortOrder>().is_err());
+    }
+
+    #[test]
+    fn test_extract_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cmds.jsonl");
+        let lines = [
+            serde_json::json!({
+                "type": "user", "sessionId": "cmds", "timestamp": "2025-01-01T10:00:00Z",
+                "message": {"role": "user", "content": "build and test it"}
+            }),
+            serde_json::json!({
+                "type": "assistant", "sessionId": "cmds", "timestamp": "2025-01-01T10:00:05Z",
+                "message": {"role": "assistant", "content": [
+                    {"type": "text", "text": "Building first"},
+                    {"type": "tool_use", "name": "Bash", "input": {"command": "cargo build --release --workspace --all-features"}},
+                    {"type": "tool_use", "name": "Read", "input": {"file_path": "/src/main.rs"}}
+                ]}
+            }),
+            serde_json::json!({
+                "type": "assistant", "sessionId": "cmds", "timestamp": "2025-01-01T10:00:09Z",
+                "message": {"role": "assistant", "content": [
+                    {"type": "tool_use", "name": "Bash", "input": {"command": "cargo test"}}
+                ]}
+            }),
+        ];
+        let content: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+        std::fs::write(&path, content.join("\n")).unwrap();
+
+        let store = SessionStore::with_path(dir.path().to_path_buf());
+        let session = store.try_parse_jsonl_session(&path).unwrap().unwrap();
+        let commands = extract_commands(&session);
+
+        assert_eq!(commands.len(), 2);
+        // The full command is kept, not the truncated rendering in `content`
+        assert_eq!(commands[0].command, "cargo build --release --workspace --all-features");
+        assert_eq!(commands[0].message_index, 1);
+        assert_eq!(commands[1].command, "cargo test");
+        assert_eq!(commands[1].message_index, 2);
+        assert!(commands[1].timestamp.is_some());
+    }
+
+    #[test]
+    fn test_modified_since() {
+        let at = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+        let dir = tempfile::tempdir().unwrap();
+        let file = std::fs::File::create(dir.path().join("s.jsonl")).unwrap();
+        file.set_modified(at("2024-06-10T12:00:00Z").into()).unwrap();
+        let metadata = file.metadata().unwrap();
+        let since = |since: &str| modified_since(&metadata, Some(at(since)));
+
+        assert!(modified_since(&metadata, None));
+        assert!(since("2024-06-01T00:00:00Z"));
+        // Up to a day after the mtime still counts
+        assert!(since("2024-06-11T11:00:00Z"));
+        assert!(!since("2024-06-11T13:00:00Z"));
+        assert!(!since("2024-07-01T00:00:00Z"));
+    }
+
+    #[test]
+    fn test_command_frequencies() {
+        let run = |session_id: &str, command: &str| CommandHistoryEntry {
+            session_id: session_id.to_string(),
+            entry: CommandEntry {
+                command: command.to_string(),
+                timestamp: None,
+                message_index: 0,
+            },
+        };
+        // Most recent first
+        let history = vec![
+            run("s3", "cargo test"),
+            run("s3", "git status"),
+            run("s2", "cargo build"),
+            run("s1", "cargo test"),
+            run("s1", "cargo build"),
+            run("s1", "cargo test"),
+        ];
+        let item = |command: &str, execution_count, session: &str| CommandHistoryItem {
+            command: command.to_string(),
+            execution_count,
+            last_seen_in_session: session.to_string(),
+        };
+        assert_eq!(
+            command_frequencies(&history),
+            vec![item("cargo test", 3, "s3"), item("cargo build", 2, "s2"), item("git status", 1, "s3")]
+        );
+        assert!(command_frequencies(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_parse_message_parts_text() {
+        assert_eq!(
+            parse_message_parts(&serde_json::json!({"role": "user", "content": "fix the build"})),
+            [MessagePart::Text { text: "fix the build".to_string() }]
+        );
+        let message = serde_json::json!({"role": "assistant", "content": [
+            {"type": "text", "text": "Looking now."},
+            {"type": "thinking", "thinking": "left out"},
+            {"type": "text", "text": "Found it."}
+        ]});
+        assert_eq!(
+            parse_message_parts(&message),
+            [
+                MessagePart::Text { text: "Looking now.".to_string() },
+                MessagePart::Text { text: "Found it.".to_string() },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_message_parts_tool_call() {
+        let long_content = "x".repeat(500);
+        let message = serde_json::json!({"role": "assistant", "content": [
+            {"type": "tool_use", "id": "toolu_1", "name": "Bash", "input": {"command": "cargo test"}},
+            {"type": "tool_use", "id": "toolu_2", "name": "Write", "input": {"file_path": "/a.rs", "content": long_content}}
+        ]});
+        let parts = parse_message_parts(&message);
+        assert_eq!(
+            parts[0],
+            MessagePart::ToolCall {
+                name: "Bash".to_string(),
+                input_summary: r#"{"command":"cargo test"}"#.to_string(),
+                tool_use_id: Some("toolu_1".to_string()),
+            }
+        );
+        let MessagePart::ToolCall { input_summary, .. } = &parts[1] else {
+            panic!("expected a tool call, got {:?}", parts[1]);
+        };
+        assert_eq!(input_summary.chars().count(), 200);
+        assert!(input_summary.starts_with(r#"{"content":"xxx"#));
+    }
+
+    #[test]
+    fn test_parse_message_parts_tool_result() {
+        let message = serde_json::json!({"role": "user", "content": [
+            {"type": "tool_result", "tool_use_id": "toolu_1", "content": "test result: ok"},
+            {"type": "tool_result", "tool_use_id": "toolu_2", "is_error": true,
+             "content": [{"type": "text", "text": "error[E0308]: mismatched types"}]}
+        ]});
+        assert_eq!(
+            parse_message_parts(&message),
+            [
+                MessagePart::ToolResult {
+                    tool_use_id: Some("toolu_1".to_string()),
+                    output_preview: "test result: ok".to_string(),
+                    is_error: false,
+                },
+                MessagePart::ToolResult {
+                    tool_use_id: Some("toolu_2".to_string()),
+                    output_preview: "error[E0308]: mismatched types".to_string(),
+                    is_error: true,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_message_parts_image() {
+        let message = serde_json::json!({"role": "user", "content": [
+            {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "iVBORw0KGgo="}},
+            {"type": "image", "source": {"type": "url", "url": "https://example.com/shot.jpg"}},
+            {"type": "text", "text": "what's wrong here?"}
+        ]});
+        assert_eq!(
+            parse_message_parts(&message),
+            [
+                MessagePart::Image { description: "image/png image, 8 bytes".to_string() },
+                MessagePart::Image { description: "image at https://example.com/shot.jpg".to_string() },
+                MessagePart::Text { text: "what's wrong here?".to_string() },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extract_full_text_for_search() {
+        let write = ToolCall {
+            name: "Write".to_string(),
+            input: serde_json::json!({"file_path": "/srv/app/config/settings.toml"}),
+        };
+        let session = session_with(vec![
+            message("user", "move the settings", vec![]),
+            message("assistant", "[Tool: Write on settings.toml]", vec![write]),
+            Message {
+                tool_results: vec!["File written".to_string()],
+                ..message("user", "thanks", vec![])
+            },
+        ]);
+
+        let text = extract_full_text_for_search(&session, false, None);
+        assert_eq!(text, "move the settings [Tool: Write on settings.toml] thanks");
+
+        let text = extract_full_text_for_search(&session, true, None);
+        assert_eq!(
+            text,
+            r#"move the settings [Tool: Write on settings.toml] {"file_path":"/srv/app/config/settings.toml"} thanks"#
+        );
+
+        let text = extract_full_text_for_search(&session, false, Some("user"));
+        assert_eq!(text, "move the settings thanks");
+
+        let text = extract_full_text_for_search(&session, false, Some("assistant"));
+        assert_eq!(
+            text,
+            r#"[Tool: Write on settings.toml] {"file_path":"/srv/app/config/settings.toml"} File written"#
+        );
+
+        assert_eq!(extract_full_text_for_search(&session, false, Some("User")), "");
+    }
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+
+        let prose = "The parser now keeps track of where each line starts, so errors point at the right column. \
+                     I also renamed a few helpers, because their old names didn't say what they did.";
+        assert_eq!(estimate_tokens(prose), (prose.len() as f64 / 4.0).ceil() as usize);
+
+        let code = "fn parse(input: &str) -> Result<Vec<Token>, Error> {\n    \
+                    let mut tokens = vec![];\n    for (i, c) in input.char_indices() { tokens.push(lex(i, c)?); }\n    \
+                    Ok(tokens)\n}";
+        assert_eq!(estimate_tokens(code), (code.len() as f64 / 3.0).ceil() as usize);
+        // The same number of bytes of code costs more tokens than prose
+        assert!(estimate_tokens(code) * prose.len() > estimate_tokens(prose) * code.len());
+    }
+
+    #[test]
+    fn test_extract_file_operations() {
+        let call = |name: &str, input: serde_json::Value| ToolCall { name: name.to_string(), input };
+        let session = session_with(vec![
+            message("user", "update the config loader", vec![]),
+            message("assistant", "", vec![
+                call("Read", serde_json::json!({"file_path": "/app/src/config.rs"})),
+                call("Bash", serde_json::json!({"command": "cargo check"})),
+            ]),
+            message("assistant", "", vec![
+                call("Write", serde_json::json!({"file_path": "/app/src/config.rs", "content": "v1"})),
+                call("Write", serde_json::json!({"file_path": "/app/src/config.rs", "content": "v2"})),
+            ]),
+            message("assistant", "", vec![call("Edit", serde_json::json!({"file_path": "/app/src/main.rs"}))]),
+        ]);
+
+        let operations = extract_file_operations(&session);
+        let summary: Vec<(&str, &str, usize)> = operations
+            .iter()
+            .map(|op| (op.file_path.as_str(), op.operation.as_str(), op.message_index))
+            .collect();
+        assert_eq!(summary, vec![
+            ("/app/src/config.rs", "Read", 1),
+            ("/app/src/config.rs", "Write", 2),
+            ("/app/src/config.rs", "Write", 2),
+            ("/app/src/main.rs", "Edit", 3),
+        ]);
+    }
+
+    fn message(role: &str, content: &str, tool_calls: Vec<ToolCall>) -> Message {
+        Message {
+            role: role.to_string(),
+            content: content.to_string(),
+            timestamp: None,
+            tool_calls,
+            tool_results: Vec::new(),
+        }
+    }
+
+    fn session_with(messages: Vec<Message>) -> Session {
+        Session {
+            id: "test-session".to_string(),
+            project_path: None,
+            cwd: Some("/home/user/project".to_string()),
+            created_at: None,
+            updated_at: None,
+            messages,
+            file_path: PathBuf::from("test-session.jsonl"),
+            total_cost_usd: None,
+            total_duration_ms: None,
+            result_entries: Vec::new(),
+            git_repo: None,
+            git_branch: None,
+        }
+    }
+
+    #[test]
+    fn test_session_to_markdown() {
+        let write = ToolCall {
+            name: "Write".to_string(),
+            input: serde_json::json!({"file_path": "/src/lib.rs", "content": "```rust\nfn main() {}\n```"}),
+        };
+        let rendered = render_tool_use(&write.name, Some(&write.input));
+        let session = session_with(vec![
+            message("user", "Fix the 日本語 parser 🚀", vec![]),
+            message("assistant", &format!("Done\n{}", rendered), vec![write]),
+        ]);
+
+        let md = session_to_markdown(&session, false);
+        assert!(md.starts_with("---\nid: \"test-session\"\ncwd: \"/home/user/project\"\ncreated_at: null\n"));
+        assert!(md.contains("## User\n\nFix the 日本語 parser 🚀\n"));
+        assert!(md.contains("## Assistant\n\nDone\n"));
+        assert!(!md.contains("[Tool:"));
+        assert!(!md.contains("```Write"));
+
+        let md = session_to_markdown(&session, true);
+        // The tool input contains a triple-backtick run, so its fence must be longer
+        assert!(md.contains("````Write\n"));
+        assert!(md.trim_end().ends_with("````"));
+    }
+
+    #[test]
+    fn test_session_to_plain_text() {
+        let session = session_with(vec![
+            message("user", "Fix the parser", vec![]),
+            message("assistant", "Done.\nIt parses now.", vec![]),
+        ]);
+
+        assert_eq!(
+            session_to_plain_text(&session, "---"),
+            "User: Fix the parser\n---\nAssistant: Done.\nIt parses now.\n"
+        );
+        assert_eq!(
+            session_to_plain_text(&session, "====="),
+            "User: Fix the parser\n=====\nAssistant: Done.\nIt parses now.\n"
+        );
+        assert_eq!(session_to_plain_text(&session_with(vec![]), "---"), "");
+    }
+
+    #[test]
+    fn test_timeline_deltas() {
+        let ts = |s: &str| Some(s.parse::<DateTime<Utc>>().unwrap());
+        let deltas = timeline_deltas(&[
+            ts("2024-01-01T10:00:00Z"),
+            ts("2024-01-01T10:00:01.500Z"),
+            ts("2024-01-01T10:00:01.500Z"),
+            // Clock skew: earlier than the event before it
+            ts("2024-01-01T10:00:00.250Z"),
+            None,
+            ts("2024-01-01T10:00:05Z"),
+            ts("2024-01-01T10:00:06Z"),
+        ]);
+        assert_eq!(deltas, vec![None, Some(1500), Some(0), Some(-1250), None, None, Some(1000)]);
+        assert!(timeline_deltas(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_session_timeline() {
+        let ts = |s: &str| Some(s.parse::<DateTime<Utc>>().unwrap());
+        let read = ToolCall {
+            name: "Read".to_string(),
+            input: serde_json::json!({"file_path": "/src/main.rs"}),
+        };
+        let mut messages = vec![
+            message("user", "Why does main panic?", vec![]),
+            message("assistant", "Let me look.\n[Tool: Read on /src/main.rs]", vec![read]),
+            message("user", "[ToolResult: fn main() { ... }]", vec![]),
+            message("assistant", "It unwraps a None.", vec![]),
+        ];
+        messages[2].tool_results = vec!["fn main() {\n    None::<u8>.unwrap();\n}".to_string()];
+        for (msg, time) in messages.iter_mut().zip(["10:00:00", "10:00:02", "10:00:03", "10:00:10"]) {
+            msg.timestamp = ts(&format!("2024-01-01T{}Z", time));
+        }
+        let mut session = session_with(messages);
+        session.created_at = ts("2024-01-01T10:00:00Z");
+        session.updated_at = ts("2024-01-01T10:00:10Z");
+
+        let timeline = session_timeline(&session);
+        assert_eq!(timeline.session_id, "test-session");
+        assert_eq!(timeline.duration_seconds, Some(10.0));
+        let events: Vec<_> = timeline
+            .events
+            .iter()
+            .map(|e| (e.event_type, e.delta_ms, e.summary.as_str()))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (TimelineEventType::UserMessage, None, "Why does main panic?"),
+                (TimelineEventType::AssistantMessage, Some(2000), "Let me look."),
+                (TimelineEventType::ToolCall, Some(0), "[Tool: Read on /src/main.rs]"),
+                (TimelineEventType::ToolResult, Some(1000), "fn main() { None::<u8>.unwrap(); }"),
+                (TimelineEventType::AssistantMessage, Some(7000), "It unwraps a None."),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_session_timeline_without_timestamps() {
+        let session = session_with(vec![message("user", "hi", vec![]), message("assistant", "hello", vec![])]);
+
+        let timeline = session_timeline(&session);
+        assert_eq!(timeline.duration_seconds, None);
+        assert_eq!(timeline.events.len(), 2);
+        assert!(timeline.events.iter().all(|e| e.timestamp.is_none() && e.delta_ms.is_none()));
+    }
+
+    #[test]
+    fn test_read_raw_lines_truncates() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.jsonl");
+        let line = serde_json::json!({
+            "type": "user", "message": {"role": "user", "content": "é".repeat(20)}
+        })
+        .to_string();
+        std::fs::write(&path, format!("{}\n\n{}\n{}\n", line, line, line)).unwrap();
+
+        let raw = read_raw_lines(&path, 10_000).unwrap();
+        assert!(!raw.truncated);
+        let indexes: Vec<usize> = raw.raw_lines.iter().map(|l| l.index).collect();
+        assert_eq!(indexes, vec![1, 3, 4]);
+        assert!(raw.raw_lines.iter().all(|l| l.parsed));
+
+        // The limit falls inside the second line's first two-byte character
+        let accent = line.find('é').unwrap();
+        let raw = read_raw_lines(&path, line.len() + accent + 1).unwrap();
+        assert!(raw.truncated);
+        assert_eq!(raw.raw_lines.len(), 2);
+        assert_eq!(raw.raw_lines[0].content, line);
+        assert_eq!(raw.raw_lines[1].content, line[..accent]);
+    }
+
+    #[test]
+    fn test_format_session() {
+        let mut session = session_with(vec![
+            message("user", "Fix the parser", vec![]),
+            message("assistant", "Done.", vec![]),
+        ]);
+        session.project_path = Some("/home/user/project".to_string());
+
+        let json: serde_json::Value =
+            serde_json::from_str(&format_session(&session, SessionFormat::Json, "---")).unwrap();
+        assert_eq!(json["id"], "test-session");
+        assert_eq!(json["project_path"], "/home/user/project");
+        assert_eq!(json["messages"][1]["role"], "assistant");
+        assert_eq!(json["messages"][1]["content"], "Done.");
+
+        assert_eq!(
+            format_session(&session, SessionFormat::Markdown, "---"),
+            session_to_markdown(&session, false)
+        );
+        assert_eq!(
+            format_session(&session, SessionFormat::PlainText, "***"),
+            "User: Fix the parser\n***\nAssistant: Done.\n"
+        );
+    }
+
+    #[test]
+    fn test_session_to_markdown_closes_open_fence() {
+        let session = session_with(vec![
+            message("assistant", "Here is code:\n```python\nprint('unterminated')", vec![]),
+            message("user", "thanks", vec![]),
+        ]);
+
+        let md = session_to_markdown(&session, false);
+        let body = &md[md.find("\n## ").unwrap()..];
+        // The fence opened by the assistant is closed before the next section
+        assert_eq!(unclosed_fence(body), None);
+        let user_heading = body.find("## User").unwrap();
+        assert!(body[..user_heading].ends_with("print('unterminated')\n```\n\n"));
+    }
+
+    #[test]
+    fn test_aggregate_tool_usage() {
+        let call = |name: &str| ToolCall {
+            name: name.to_string(),
+            input: serde_json::json!({}),
+        };
+        let sessions = vec![
+            session_with(vec![
+                message("user", "go", vec![]),
+                message("assistant", "[Tool: Bash]", vec![call("Bash"), call("Write")]),
+                message("assistant", "[Tool: Bash]", vec![call("Bash")]),
+            ]),
+            session_with(vec![message("assistant", "[Tool: Bash]", vec![call("Bash"), call("Write")])]),
+        ];
+
+        let counts = aggregate_tool_usage(&sessions);
+        assert_eq!(counts.len(), 2);
+        assert_eq!(counts["Bash"], 3);
+        assert_eq!(counts["Write"], 2);
+    }
+
+    #[test]
+    fn test_compute_heatmap() {
+        let started = |created_at: Option<&str>| {
+            let mut session = session_with(vec![message("user", "hi", vec![])]);
+            session.created_at = created_at.map(|t| t.parse().unwrap());
+            session
+        };
+        let sessions = vec![
+            started(Some("2025-01-08T09:15:00Z")),
+            // A Monday
+            started(Some("2025-01-06T14:00:00Z")),
+            started(Some("2025-01-06T14:59:59Z")),
+            started(None),
+        ];
+
+        let heatmap = compute_heatmap(&sessions);
+        assert_eq!(heatmap.by_hour.len(), 24);
+        assert_eq!(heatmap.by_day_of_week.len(), 7);
+        assert_eq!(heatmap.by_hour[14], 2);
+        assert_eq!(heatmap.by_hour[9], 1);
+        assert_eq!(heatmap.by_hour.iter().sum::<usize>(), 3);
+        assert_eq!(heatmap.by_day_of_week, [2, 0, 1, 0, 0, 0, 0]);
+        assert_eq!(
+            heatmap.by_date,
+            vec![
+                DateCount { date: "2025-01-06".to_string(), count: 2 },
+                DateCount { date: "2025-01-08".to_string(), count: 1 },
+            ]
+        );
+        assert_eq!(heatmap.skipped_sessions, 1);
+    }
+
+    #[test]
+    fn test_bucket_sessions_by_date() {
+        let started = |created_at: &str, content: &str| {
+            let mut session = session_with(vec![message("user", content, vec![]), message("assistant", "ok", vec![])]);
+            session.created_at = Some(created_at.parse().unwrap());
+            session
+        };
+        // Out of order, across the end of January, a Wednesday
+        let sessions = vec![
+            started("2024-02-01T08:00:00Z", "edit src/lib.rs and src/new.rs"),
+            started("2024-01-31T23:59:59Z", "edit src/lib.rs"),
+            started("2024-01-15T10:00:00Z", "edit src/main.rs"),
+            Session {
+                created_at: None,
+                ..session_with(vec![message("user", "undated", vec![])])
+            },
+        ];
+        let summary = |buckets: Vec<TimelineBucket>| -> Vec<(String, usize, usize, Vec<String>)> {
+            buckets.into_iter().map(|b| (b.date, b.session_count, b.message_count, b.new_files)).collect()
+        };
+        let files = |names: &[&str]| names.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            summary(bucket_sessions_by_date(&sessions, BucketSize::Month)),
+            vec![
+                ("2024-01".to_string(), 2, 4, files(&["src/main.rs", "src/lib.rs"])),
+                ("2024-02".to_string(), 1, 2, files(&["src/new.rs"])),
+            ]
+        );
+        assert_eq!(
+            summary(bucket_sessions_by_date(&sessions, BucketSize::Week)),
+            vec![
+                ("2024-01-15".to_string(), 1, 2, files(&["src/main.rs"])),
+                ("2024-01-29".to_string(), 2, 4, files(&["src/lib.rs", "src/new.rs"])),
+            ]
+        );
+        let days = bucket_sessions_by_date(&sessions, BucketSize::Day);
+        let dates: Vec<&str> = days.iter().map(|b| b.date.as_str()).collect();
+        assert_eq!(dates, vec!["2024-01-15", "2024-01-31", "2024-02-01"]);
+        assert!(bucket_sessions_by_date(&[], BucketSize::Day).is_empty());
+    }
+
+    #[test]
+    fn test_aggregate_by_hour() {
+        let started = |created_at: Option<&str>, messages: usize| {
+            let mut session = session_with((0..messages).map(|_| message("user", "hi", vec![])).collect());
+            session.created_at = created_at.map(|t| t.parse().unwrap());
+            session
+        };
+        let sessions = vec![
+            started(Some("2025-01-06T15:30:00Z"), 4),
+            started(Some("2025-01-07T15:59:00Z"), 1),
+            // Wraps around to the next day's morning
+            started(Some("2025-01-07T23:10:00Z"), 3),
+            started(None, 10),
+        ];
+
+        let hours = aggregate_by_hour(&sessions, &chrono::FixedOffset::east_opt(2 * 3600).unwrap());
+        assert_eq!(hours[17], HourStats { hour: 17, session_count: 2, total_messages: 5, avg_messages: 2.5 });
+        assert_eq!(hours[1].session_count, 1);
+        assert_eq!(hours[15].session_count, 0);
+        assert_eq!(hours[15].avg_messages, 0.0);
+        assert_eq!(hours.iter().map(|h| h.session_count).sum::<usize>(), 3);
+        assert!(hours.iter().enumerate().all(|(i, h)| h.hour as usize == i));
+
+        let hours = aggregate_by_hour(&sessions, &chrono::FixedOffset::west_opt(8 * 3600).unwrap());
+        assert_eq!(hours[7].session_count, 2);
+        assert_eq!(hours[15].session_count, 1);
+
+        // Half an hour off UTC still lands each session in its own hour
+        let hours = aggregate_by_hour(&sessions, &chrono::FixedOffset::east_opt(5 * 3600 + 1800).unwrap());
+        assert_eq!(hours[21].session_count, 2);
+        assert_eq!(hours[4].session_count, 1);
+    }
+
+    #[test]
+    fn test_highlight_match_multibyte() {
+        // Every char before the match is multi-byte, so byte and char offsets diverge
+        let text = "héllo wörld ✓✓ needle ünïcode tail";
+        let start = text.find("needle").unwrap();
+        let end = start + "needle".len();
+
+        assert_eq!(
+            highlight_match(text, start, end, 50),
+            "héllo wörld ✓✓ **needle** ünïcode tail"
+        );
+        assert_eq!(highlight_match(text, start, end, 3), "...✓✓ **needle** ün...");
+    }
+
+    #[test]
+    fn test_fuzzy_match_span_multibyte() {
+        let matcher = SkimMatcherV2::default();
+        let text = "ünïcode ✓ needle";
+        let (start, end) = fuzzy_match_span(&matcher, text, "needle").unwrap();
+        assert_eq!(&text[start..end], "needle");
+        assert_eq!(highlight_match(text, start, end, 2), "...✓ **needle**");
+    }
+
+    #[test]
+    fn test_aggregate_statistics() {
+        let at = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+        let dated = |project: &str, start: &str, end: &str, messages: Vec<Message>| Session {
+            project_path: Some(project.to_string()),
+            created_at: Some(at(start)),
+            updated_at: Some(at(end)),
+            ..session_with(messages)
+        };
+        let sessions = vec![
+            dated(
+                "/a",
+                "2024-03-01T10:00:00Z",
+                "2024-03-02T09:00:00Z",
+                vec![message("user", "hi", vec![]), message("assistant", "hello", vec![])],
+            ),
+            dated(
+                "/b",
+                "2024-03-02T12:00:00Z",
+                "2024-03-02T13:00:00Z",
+                vec![
+                    message("user", "one", vec![]),
+                    message("assistant", "two", vec![]),
+                    message("user", "three", vec![]),
+                    message("system", "note", vec![]),
+                ],
+            ),
+            Session {
+                total_cost_usd: Some(1.5),
+                ..dated("/a", "2024-02-20T08:00:00Z", "2024-02-20T08:30:00Z", vec![])
+            },
+        ];
+
+        let aggregate = |sessions: &[Session]| {
+            let mut builder = StatisticsBuilder::default();
+            sessions.iter().for_each(|s| builder.add(s));
+            builder.finish()
+        };
+
+        let stats = aggregate(&sessions);
+        assert_eq!(stats.total_sessions, 3);
+        assert_eq!(stats.total_messages, 6);
+        assert_eq!(stats.avg_messages_per_session, 2.0);
+        assert_eq!(stats.total_user_messages, 3);
+        assert_eq!(stats.total_assistant_messages, 2);
+        assert_eq!(stats.busiest_day.as_deref(), Some("2024-03-02"));
+        assert_eq!(stats.most_active_project.as_deref(), Some("/a"));
+        assert_eq!(
+            stats.date_range,
+            Some((at("2024-02-20T08:00:00Z"), at("2024-03-02T13:00:00Z")))
+        );
+        assert_eq!(stats.total_cost_usd, 1.5);
+        // Sessions without a recorded cost don't pull the average down
+        assert_eq!(stats.avg_cost_usd, 1.5);
+
+        let empty = aggregate(&[]);
+        assert_eq!(empty.total_sessions, 0);
+        assert_eq!(empty.avg_messages_per_session, 0.0);
+        assert_eq!(empty.busiest_day, None);
+        assert_eq!(empty.date_range, None);
+    }
+
+    /// Write `lines` to a session file in a fresh temp dir and validate it
+    fn validate_lines(lines: &[&str]) -> ValidationReport {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("s1.jsonl");
+        std::fs::write(&path, lines.join("\n")).unwrap();
+        validate_jsonl(&path)
+    }
+
+    const GOOD_LINE: &str =
+        r#"{"type":"user","sessionId":"s1","timestamp":"2024-01-01T00:00:00.000Z","message":{"role":"user","content":"hi"}}"#;
+
+    #[test]
+    fn test_validate_jsonl_valid() {
+        let report = validate_lines(&[
+            GOOD_LINE,
+            r#"{"type":"assistant","sessionId":"s1","timestamp":"2024-01-01T00:00:01.000Z"}"#,
+            "",
+        ]);
+        assert!(report.valid, "{:?}", report.errors);
+        assert_eq!(report.line_count, 2);
+        assert_eq!(report.parseable_lines, 2);
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_validate_jsonl_invalid_json() {
+        let report = validate_lines(&[GOOD_LINE, "{not json}", r#"{"type":"user","sessionId":"s1""#]);
+        assert!(!report.valid);
+        assert_eq!(report.line_count, 3);
+        assert_eq!(report.parseable_lines, 1);
+        assert!(report.errors[0].starts_with("Line 2: invalid JSON"));
+        assert!(report.errors[1].starts_with("Line 3: truncated JSON"));
+    }
+
+    #[test]
+    fn test_validate_jsonl_invalid_utf8() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("s1.jsonl");
+        let mut bytes = GOOD_LINE.as_bytes().to_vec();
+        bytes.extend_from_slice(b"\n{\"type\":\"user\",\"x\":\"\xff\xfe\"}");
+        std::fs::write(&path, bytes).unwrap();
+
+        let report = validate_jsonl(&path);
+        assert!(!report.valid);
+        assert_eq!(report.errors, vec!["Line 2: invalid UTF-8"]);
+    }
+
+    #[test]
+    fn test_validate_jsonl_unknown_type() {
+        let report = validate_lines(&[GOOD_LINE, r#"{"type":"bogus","sessionId":"s1"}"#]);
+        assert!(!report.valid);
+        assert_eq!(report.errors, vec!["Line 2: unrecognized type \"bogus\""]);
+    }
+
+    #[test]
+    fn test_validate_jsonl_bad_timestamp() {
+        let report = validate_lines(&[GOOD_LINE, r#"{"type":"user","timestamp":"yesterday"}"#]);
+        assert!(!report.valid);
+        assert_eq!(report.errors, vec!["Line 2: timestamp \"yesterday\" is not ISO-8601"]);
+    }
+
+    #[test]
+    fn test_validate_jsonl_inconsistent_session_id() {
+        let report = validate_lines(&[GOOD_LINE, r#"{"type":"user","sessionId":"s2"}"#]);
+        assert!(!report.valid);
+        assert_eq!(report.errors, vec!["Line 2: sessionId \"s2\" differs from \"s1\" on line 1"]);
+    }
+
+    #[test]
+    fn test_validate_jsonl_duplicate_timestamps() {
+        let report = validate_lines(&[
+            GOOD_LINE,
+            r#"{"type":"assistant","timestamp":"2024-01-01T00:00:00.0004Z"}"#,
+        ]);
+        assert!(!report.valid);
+        assert_eq!(report.errors.len(), 1);
+        assert!(report.errors[0].starts_with("Lines 1 and 2: duplicate timestamp"));
+    }
+
+    #[test]
+    fn test_validate_jsonl_missing_agent_is_warning() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("s1.jsonl");
+        let lines = [
+            GOOD_LINE,
+            r#"{"type":"user","toolUseResult":{"agentId":"present"}}"#,
+            r#"{"type":"user","toolUseResult":{"agentId":"gone"}}"#,
+        ];
+        std::fs::write(&path, lines.join("\n")).unwrap();
+        std::fs::write(dir.path().join("agent-present.jsonl"), GOOD_LINE).unwrap();
+
+        let report = validate_jsonl(&path);
+        assert!(report.valid, "{:?}", report.errors);
+        assert_eq!(report.warnings.len(), 1);
+        assert!(report.warnings[0].contains("agent-gone.jsonl"));
+    }
+
+    fn bash(command: &str) -> ToolCall {
+        ToolCall {
+            name: "Bash".to_string(),
+            input: serde_json::json!({"command": command}),
+        }
+    }
+
+    /// Key terms with equal counts come back in no particular order
+    fn sorted(mut items: Vec<String>) -> Vec<String> {
+        items.sort();
+        items
+    }
+
+    fn context(initial_request: &str, files: usize, terms: usize) -> SessionContext {
+        SessionContext {
+            id: "ctx".to_string(),
+            created_at: "2024-05-01T09:30:00Z".parse().ok(),
+            cwd: Some("/home/user/app".to_string()),
+            git_repo: None,
+            git_branch: None,
+            initial_request: Some(initial_request.to_string()),
+            message_count: 12,
+            token_estimate: None,
+            token_estimate_by_role: None,
+            files_mentioned: (0..files).map(|i| format!("/home/user/app/src/module_{}.rs", i)).collect(),
+            key_terms: (0..terms).map(|i| format!("term{}", i)).collect(),
+            commands: Vec::new(),
+            most_recent_file: None,
+            most_recent_tool: None,
+            tool_summary: None,
+            aggregated: false,
+            missing_ids: Vec::new(),
+        }
+    }
+
+    /// A session of `len` messages, message `i` mentioning `src/step_{i}.rs`
+    fn numbered_session(len: usize) -> Session {
+        session_with(
+            (0..len)
+                .map(|i| message("user", &format!("look at src/step_{}.rs", i), Vec::new()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_unanswered_without_assistant_messages() {
+        let session = session_with(vec![message("user", "are you there?", Vec::new())]);
+        assert!(!has_assistant_response(&session));
+        assert!(is_unanswered(&session, false));
+        assert!(!is_unanswered(&session, true));
+
+        // A lone message is never a conversation, whoever sent it
+        let session = session_with(vec![message("assistant", "hello", Vec::new())]);
+        assert!(has_assistant_response(&session));
+        assert!(is_unanswered(&session, false));
+    }
+
+    #[test]
+    fn test_unanswered_user_last() {
+        let session = session_with(vec![
+            message("user", "fix the build", Vec::new()),
+            message("assistant", "fixed", Vec::new()),
+            message("user", "now the tests", Vec::new()),
+        ]);
+        assert!(has_assistant_response(&session));
+        assert!(is_unanswered(&session, false));
+        assert!(is_unanswered(&session, true));
+    }
+
+    #[test]
+    fn test_unanswered_assistant_last() {
+        let session = session_with(vec![
+            message("user", "fix the build", Vec::new()),
+            message("assistant", "fixed", Vec::new()),
+        ]);
+        assert!(!is_unanswered(&session, false));
+        assert!(!is_unanswered(&session, true));
+    }
+
+    #[test]
+    fn test_session_delta() {
+        let mut session = numbered_session(10);
+        session.result_entries = vec![
+            ResultEntry { message_count: 4, cost_usd: Some(0.25), duration_ms: Some(1000) },
+            ResultEntry { message_count: 7, cost_usd: Some(0.5), duration_ms: None },
+            ResultEntry { message_count: 10, cost_usd: Some(0.125), duration_ms: Some(300) },
+        ];
+
+        let delta = session_delta(&session, 5);
+        assert_eq!((delta.from_index, delta.to_index), (5, 10));
+        assert!(!delta.no_new_messages);
+        let contents: Vec<&str> = delta.new_messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents[0], "look at src/step_5.rs");
+        assert_eq!(contents.len(), 5);
+        assert_eq!(delta.new_cost_usd, Some(0.625));
+        assert_eq!(delta.new_duration_ms, Some(300));
+
+        // Polling again from to_index finds nothing new
+        let delta = session_delta(&session, 10);
+        assert!(delta.no_new_messages);
+        assert!(delta.new_messages.is_empty());
+        assert_eq!(delta.new_cost_usd, None);
+
+        let delta = session_delta(&session, 25);
+        assert_eq!((delta.from_index, delta.to_index), (10, 10));
+        assert!(delta.no_new_messages);
+    }
+
+    fn todo_texts(session: &Session, patterns: &[&str]) -> Vec<(String, String)> {
+        extract_todos_from_session(session, patterns)
+            .into_iter()
+            .map(|todo| (todo.pattern, todo.text))
+            .collect()
+    }
+
+    #[test]
+    fn test_extract_todos_in_code_blocks() {
+        let session = session_with(vec![
+            message("user", "fix the parser", Vec::new()),
+            message(
+                "assistant",
+                "Here's a start:\n```rust\nfn parse() {\n    // TODO: handle escapes\n    let x = 1; // FIXME overflow\n}\n/* HACK: skip BOM */\n```",
+                Vec::new(),
+            ),
+        ]);
+        let todos = extract_todos_from_session(&session, &DEFAULT_TODO_PATTERNS);
+        assert_eq!(
+            todo_texts(&session, &DEFAULT_TODO_PATTERNS),
+            [
+                ("TODO".to_string(), "TODO: handle escapes".to_string()),
+                ("FIXME".to_string(), "FIXME overflow".to_string()),
+                ("HACK".to_string(), "HACK: skip BOM".to_string()),
+            ]
+        );
+        assert!(todos.iter().all(|todo| todo.message_index == 1 && todo.role == "assistant"));
+    }
+
+    #[test]
+    fn test_extract_todos_in_prose() {
+        let session = session_with(vec![
+            message("user", "NOTE: the tests need a database.\nTODOS are tracked elsewhere", Vec::new()),
+            message("assistant", "Done. One thing left (XXX: the retry logic), and I noted the rest.", Vec::new()),
+        ]);
+        assert_eq!(
+            todo_texts(&session, &DEFAULT_TODO_PATTERNS),
+            [
+                ("NOTE".to_string(), "NOTE: the tests need a database.".to_string()),
+                ("XXX".to_string(), "XXX: the retry logic), and I noted the rest.".to_string()),
+            ]
+        );
+        // Only the patterns asked for
+        assert_eq!(
+            todo_texts(&session, &["XXX"]),
+            [("XXX".to_string(), "XXX: the retry logic), and I noted the rest.".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_extract_todos_multi_line() {
+        let session = session_with(vec![message(
+            "assistant",
+            "```python\n# TODO: cache the lookups\n#       once the schema settles\n# unrelated comment\n```\n\
+             TODO: write the migration\n  and back it up first\nThen deploy.\n\
+             /*\n * FIXME: this leaks\n *   the handle\n * TODO: close it\n */",
+            Vec::new(),
+        )]);
+        assert_eq!(
+            todo_texts(&session, &DEFAULT_TODO_PATTERNS),
+            [
+                ("TODO".to_string(), "TODO: cache the lookups once the schema settles".to_string()),
+                ("TODO".to_string(), "TODO: write the migration and back it up first".to_string()),
+                ("FIXME".to_string(), "FIXME: this leaks the handle".to_string()),
+                ("TODO".to_string(), "TODO: close it".to_string()),
+            ]
+        );
+    }
+
+    fn context_files(session: &Session, depth: ContextDepth) -> Vec<String> {
+        session_context(session, depth, false, false, ContextLimits::default()).files_mentioned
+    }
+
+    #[test]
+    fn test_session_context_limits() {
+        let session = session_with(
+            (0..25)
+                .map(|i| message("user", &format!("edit src/mod_{:02}.rs for feature{}x", i, i), Vec::new()))
+                .collect(),
+        );
+
+        let limits = ContextLimits { max_files: 5, max_terms: 3, ..ContextLimits::default() };
+        let context = session_context(&session, ContextDepth::All, false, false, limits);
+        assert_eq!(context.files_mentioned.len(), 5);
+        assert_eq!(context.key_terms.len(), 3);
+
+        let context = session_context(&session, ContextDepth::All, false, false, ContextLimits::default());
+        assert_eq!(context.files_mentioned.len(), 20);
+        assert_eq!(context.key_terms.len(), 15);
+
+        // 0 lifts the limit
+        let limits = ContextLimits { max_files: 0, max_terms: 0, ..ContextLimits::default() };
+        let context = session_context(&session, ContextDepth::All, false, false, limits);
+        assert_eq!(context.files_mentioned.len(), 25);
+        assert!(context.key_terms.len() > 15);
+    }
+
+    #[test]
+    fn test_session_context_last_n() {
+        let session = numbered_session(10);
+        assert_eq!(
+            context_files(&session, ContextDepth::LastN(3)),
+            vec!["src/step_7.rs", "src/step_8.rs", "src/step_9.rs"]
+        );
+        // The initial request and message count still cover the whole session
+        let context = session_context(&session, ContextDepth::LastN(3), false, false, ContextLimits::default());
+        assert_eq!(context.message_count, 10);
+        assert_eq!(context.initial_request.as_deref(), Some("look at src/step_0.rs"));
+
+        assert_eq!(context_files(&numbered_session(2), ContextDepth::LastN(3)).len(), 2);
+        assert!(context_files(&numbered_session(0), ContextDepth::LastN(3)).is_empty());
+    }
+
+    #[test]
+    fn test_session_context_first_n() {
+        let session = numbered_session(10);
+        assert_eq!(
+            context_files(&session, ContextDepth::FirstN(2)),
+            vec!["src/step_0.rs", "src/step_1.rs"]
+        );
+        assert_eq!(context_files(&numbered_session(2), ContextDepth::FirstN(3)).len(), 2);
+        assert!(context_files(&numbered_session(0), ContextDepth::FirstN(3)).is_empty());
+    }
+
+    #[test]
+    fn test_session_context_all() {
+        assert_eq!(context_files(&numbered_session(10), ContextDepth::All).len(), 10);
+        assert_eq!(context_files(&numbered_session(1), ContextDepth::All), vec!["src/step_0.rs"]);
+        assert!(context_files(&numbered_session(0), ContextDepth::All).is_empty());
+    }
+
+    #[test]
+    fn test_session_context_most_recent_file() {
+        let call = |name: &str, input: serde_json::Value| ToolCall { name: name.to_string(), input };
+        let session = session_with(vec![
+            message("user", "fix the build", vec![]),
+            message("assistant", "[Tool: Edit on lib.rs]", vec![call("Edit", serde_json::json!({"file_path": "src/lib.rs"}))]),
+            message(
+                "assistant",
+                "[Tool: Read on Cargo.toml] [Tool: Write on main.rs]",
+                vec![
+                    call("Read", serde_json::json!({"file_path": "Cargo.toml"})),
+                    call("Write", serde_json::json!({"file_path": "src/main.rs"})),
+                ],
+            ),
+            message("assistant", "Done, the build passes.", vec![]),
+        ]);
+        let context = session_context(&session, ContextDepth::All, false, false, ContextLimits::default());
+        assert_eq!(context.most_recent_file.as_deref(), Some("src/main.rs"));
+        assert_eq!(context.most_recent_tool.as_deref(), Some("Write"));
+
+        // A later call without a file_path is the most recent tool, not file
+        let mut session = session;
+        session.messages.push(message("assistant", "[Tool: Bash]", vec![call("Bash", serde_json::json!({"command": "cargo test"}))]));
+        let context = session_context(&session, ContextDepth::FirstN(1), false, false, ContextLimits::default());
+        assert_eq!(context.most_recent_file.as_deref(), Some("src/main.rs"));
+        assert_eq!(context.most_recent_tool.as_deref(), Some("Bash"));
+
+        let context = session_context(&numbered_session(3), ContextDepth::All, false, false, ContextLimits::default());
+        assert_eq!(context.most_recent_file, None);
+        assert_eq!(context.most_recent_tool, None);
+    }
+
+    fn tool_call(name: &str, input: serde_json::Value) -> ToolCall {
+        ToolCall { name: name.to_string(), input }
+    }
+
+    #[test]
+    fn test_summarize_tool_calls_no_tools() {
+        assert!(summarize_tool_calls(&numbered_session(3)).is_empty());
+        assert!(summarize_tool_calls(&session_with(vec![])).is_empty());
+    }
+
+    #[test]
+    fn test_summarize_tool_calls_one_tool() {
+        let read = |path: &str| tool_call("Read", serde_json::json!({"file_path": path}));
+        let session = session_with(vec![
+            message("assistant", "", vec![read("src/lib.rs"), read("src/main.rs")]),
+            message("assistant", "", vec![read("src/lib.rs")]),
+        ]);
+        assert_eq!(
+            summarize_tool_calls(&session),
+            vec![ToolCallSummary {
+                tool_name: "Read".to_string(),
+                call_count: 3,
+                files_touched: vec!["src/lib.rs".to_string(), "src/main.rs".to_string()],
+                commands_run: vec![],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_summarize_tool_calls_mixed() {
+        let long_command = format!("cargo test {}", "--features extra ".repeat(10));
+        let session = session_with(vec![
+            message("user", "", vec![tool_call("Write", serde_json::json!({"file_path": "ignored.rs"}))]),
+            message(
+                "assistant",
+                "",
+                vec![
+                    tool_call("Bash", serde_json::json!({"command": "cargo build"})),
+                    tool_call("Write", serde_json::json!({"file_path": "src/main.rs"})),
+                ],
+            ),
+            message(
+                "assistant",
+                "",
+                vec![
+                    tool_call("Bash", serde_json::json!({"command": long_command})),
+                    tool_call("Bash", serde_json::json!({"command": "cargo build"})),
+                    tool_call("Read", serde_json::json!({"file_path": "Cargo.toml"})),
+                ],
+            ),
+        ]);
+
+        let summary = summarize_tool_calls(&session);
+        let names: Vec<_> = summary.iter().map(|s| (s.tool_name.as_str(), s.call_count)).collect();
+        // User messages don't count; ties are broken by name
+        assert_eq!(names, vec![("Bash", 3), ("Read", 1), ("Write", 1)]);
+        assert_eq!(summary[0].commands_run.len(), 2);
+        assert_eq!(summary[0].commands_run[0], "cargo build");
+        assert_eq!(summary[0].commands_run[1].chars().count(), 83);
+        assert!(summary[0].commands_run[1].ends_with("..."));
+        assert!(summary[0].files_touched.is_empty());
+        assert_eq!(summary[2].files_touched, vec!["src/main.rs"]);
+
+        // Only asked for, the summary covers the context's depth
+        assert_eq!(session_context(&session, ContextDepth::All, false, false, ContextLimits::default()).tool_summary, None);
+        let last = session_context(&session, ContextDepth::LastN(1), true, false, ContextLimits::default()).tool_summary.unwrap();
+        assert_eq!(last.iter().map(|s| s.call_count).sum::<usize>(), 3);
+    }
+
+    #[test]
+    fn test_context_depth_from_args() {
+        assert_eq!(ContextDepth::from_args(None, None).unwrap(), ContextDepth::All);
+        assert_eq!(ContextDepth::from_args(Some(5), None).unwrap(), ContextDepth::LastN(5));
+        assert_eq!(ContextDepth::from_args(Some(5), Some("first_n")).unwrap(), ContextDepth::FirstN(5));
+        assert_eq!(ContextDepth::from_args(Some(5), Some("all")).unwrap(), ContextDepth::All);
+        assert!(ContextDepth::from_args(None, Some("last_n")).is_err());
+        assert!(ContextDepth::from_args(Some(5), Some("middle")).is_err());
+    }
+
+    #[test]
+    fn test_context_to_prompt() {
+        let prompt = context_to_prompt(&context("Fix the login bug", 2, 2), 1000);
+        assert_eq!(
+            prompt,
+            "In session ctx from 2024-05-01, while working in /home/user/app, the user asked: 'Fix the login bug'. \
+             The session involved 12 messages and touched files: /home/user/app/src/module_0.rs, \
+             /home/user/app/src/module_1.rs. Key technical terms: term0, term1."
+        );
+    }
+
+    #[test]
+    fn test_context_to_prompt_truncates() {
+        let prompt = context_to_prompt(&context("Fix the login bug", 100, 100), 1000);
+        assert_eq!(prompt.chars().count(), 1000);
+        assert!(prompt.ends_with("..."));
+        assert!(prompt.contains("the user asked: 'Fix the login bug'."));
+
+        // An initial request over the whole budget is cut first, leaving room for the rest
+        let long_request = "é".repeat(2000);
+        let prompt = context_to_prompt(&context(&long_request, 1, 1), 1000);
+        assert!(prompt.chars().count() <= 1000);
+        assert!(prompt.contains("...'. The session involved 12 messages"));
+        assert!(prompt.ends_with("Key technical terms: term0."));
+    }
+
+    #[test]
+    fn test_context_token_estimate() {
+        // About 200 tokens of prose and 100 of code, by a tokenizer's count
+        let prose = "The quick brown fox jumps over the lazy dog near the river bank. ".repeat(14);
+        let code = "fn main() { let x = vec![1, 2, 3]; println!(\"{:?}\", x); }\n".repeat(4);
+        let session = session_with(vec![message("user", &prose, vec![]), message("assistant", &code, vec![])]);
+
+        let context = session_context(&session, ContextDepth::All, false, true, ContextLimits::default());
+        let by_role = context.token_estimate_by_role.unwrap();
+        assert!((100..=300).contains(&by_role.user), "user: {}", by_role.user);
+        assert!((50..=150).contains(&by_role.assistant), "assistant: {}", by_role.assistant);
+        let total = context.token_estimate.unwrap();
+        assert!((150..=450).contains(&total), "total: {}", total);
+
+        let context = session_context(&session, ContextDepth::All, false, false, ContextLimits::default());
+        assert_eq!(context.token_estimate, None);
+        assert_eq!(context.token_estimate_by_role, None);
+    }
+
+    #[test]
+    fn test_generate_session_summary() {
+        let session = session_with(vec![
+            message("user", "Why does the parser panic on empty input in src/parser.rs?", vec![]),
+            message("assistant", "The parser indexes before checking length; fixing src/parser.rs", vec![]),
+        ]);
+        let summary = generate_session_summary(&session);
+        assert!(summary.starts_with("In session test-session, the user asked about 'Why does the parser panic"));
+        assert!(summary.contains("2 messages touching files src/parser.rs."));
+        let topics = summary.split("Key topics: ").nth(1).unwrap();
+        assert!(topics.split(", ").any(|term| term.trim_end_matches('.') == "parser"));
+
+        // Nothing to list: no files, and only stop words and short words
+        let summary = generate_session_summary(&session_with(vec![message("assistant", "it is so", vec![])]));
+        assert_eq!(summary, "In session test-session, the user made no request. The conversation involved 1 message.");
+    }
+
+    #[test]
+    fn test_diff_identical_sessions() {
+        let mut session = session_with(vec![
+            message("user", "Fix the parser in src/parser.rs", vec![]),
+            message("assistant", "Running tests", vec![bash("cargo test"), bash("cargo test")]),
+        ]);
+        session.created_at = Some("2025-01-01T10:00:00Z".parse().unwrap());
+        session.updated_at = Some("2025-01-01T10:05:00Z".parse().unwrap());
+
+        let diff = diff_sessions(&session, &session);
+        assert!(diff.only_in_a.files_mentioned.is_empty());
+        assert!(diff.only_in_a.key_terms.is_empty());
+        assert!(diff.only_in_a.commands.is_empty());
+        assert!(diff.only_in_b.files_mentioned.is_empty());
+        assert!(diff.only_in_b.key_terms.is_empty());
+        assert!(diff.only_in_b.commands.is_empty());
+        assert_eq!(diff.in_both.files_mentioned, extract_file_paths(&session, ContextLimits::DEFAULT_MAX_FILES));
+        assert_eq!(sorted(diff.in_both.key_terms), sorted(extract_key_terms(&session, ContextLimits::DEFAULT_MAX_TERMS)));
+        assert_eq!(diff.in_both.commands, vec!["cargo test"]);
+        assert_eq!(diff.duration_delta_seconds, Some(0));
+    }
+
+    #[test]
+    fn test_diff_disjoint_sessions() {
+        let mut a = session_with(vec![
+            message("user", "Update src/parser.rs tokenizer", vec![]),
+            message("assistant", "Compiled", vec![bash("cargo build")]),
+        ]);
+        a.created_at = Some("2025-01-01T10:00:00Z".parse().unwrap());
+        a.updated_at = Some("2025-01-01T10:01:00Z".parse().unwrap());
+        let mut b = session_with(vec![
+            message("user", "Style web/index.css buttons", vec![]),
+            message("assistant", "Linted", vec![bash("npm run lint")]),
+        ]);
+        b.id = "other".to_string();
+        b.created_at = Some("2025-01-02T10:00:00Z".parse().unwrap());
+        b.updated_at = Some("2025-01-02T10:11:00Z".parse().unwrap());
+
+        let diff = diff_sessions(&a, &b);
+        assert_eq!(diff.only_in_a.files_mentioned, extract_file_paths(&a, ContextLimits::DEFAULT_MAX_FILES));
+        assert_eq!(diff.only_in_b.files_mentioned, extract_file_paths(&b, ContextLimits::DEFAULT_MAX_FILES));
+        assert_eq!(sorted(diff.only_in_a.key_terms), sorted(extract_key_terms(&a, ContextLimits::DEFAULT_MAX_TERMS)));
+        assert_eq!(sorted(diff.only_in_b.key_terms), sorted(extract_key_terms(&b, ContextLimits::DEFAULT_MAX_TERMS)));
+        assert_eq!(diff.only_in_a.commands, vec!["cargo build"]);
+        assert_eq!(diff.only_in_b.commands, vec!["npm run lint"]);
+        assert!(diff.in_both.files_mentioned.is_empty());
+        assert!(diff.in_both.key_terms.is_empty());
+        assert!(diff.in_both.commands.is_empty());
+        // B ran ten minutes longer
+        assert_eq!(diff.duration_delta_seconds, Some(600));
+
+        b.updated_at = None;
+        assert_eq!(diff_sessions(&a, &b).duration_delta_seconds, None);
+    }
+
+    #[test]
+    fn test_detect_language_from_code_blocks() {
+        let session = session_with(vec![
+            message("user", "why doesn't this compile?\n```rust\nfn main() {\n    let mut x = 1;\n}\n```", vec![]),
+            message("assistant", "Borrow it instead:\n```rs\npub fn fix(x: &mut i32) {}\n```\n```json\n{}\n```", vec![]),
+        ]);
+
+        let scores = detect_language(&session);
+        assert_eq!(scores.len(), 1);
+        assert_eq!(scores["rust"], 2 * CODE_BLOCK_WEIGHT + 6);
+        assert_eq!(dominant_language(&scores), Some(("rust", 1.0)));
+    }
+
+    #[test]
+    fn test_detect_language_from_file_paths() {
+        let edit = ToolCall {
+            name: "Edit".to_string(),
+            input: serde_json::json!({"file_path": "/home/user/app/models.py"}),
+        };
+        let session = session_with(vec![
+            message("user", "Look at src/app/views.py and src/app/urls.py, and the README at docs/index.md", vec![]),
+            message("assistant", "Updating the model, then scripts/build.rs", vec![edit]),
+        ]);
+
+        let scores = detect_language(&session);
+        assert_eq!(scores["python"], 3 * FILE_EXTENSION_WEIGHT);
+        assert_eq!(scores["rust"], FILE_EXTENSION_WEIGHT);
+        let (language, confidence) = dominant_language(&scores).unwrap();
+        assert_eq!(language, "python");
+        assert!((confidence - 0.75).abs() < 1e-9);
+
+        assert!(dominant_language(&detect_language(&session_with(vec![
+            message("user", "hello there", vec![]),
+        ])))
+        .is_none());
+    }
+
+    #[test]
+    fn test_canonical_language() {
+        assert_eq!(canonical_language("Rust"), Some("rust"));
+        assert_eq!(canonical_language("py"), Some("python"));
+        assert_eq!(canonical_language("tsx"), Some("typescript"));
+        assert_eq!(canonical_language("golang"), Some("go"));
+        assert_eq!(canonical_language("bash"), Some("shell"));
+        assert_eq!(canonical_language("json"), None);
+    }
+
+    #[test]
+    fn test_merge_sessions_orders_by_timestamp() {
+        let at = |role: &str, content: &str, time: &str| Message {
+            timestamp: Some(time.parse().unwrap()),
+            ..message(role, content, vec![])
+        };
+        let a = session_with(vec![
+            at("user", "start the refactor", "2025-01-01T10:00:00Z"),
+            at("assistant", "moved the parser", "2025-01-01T10:05:00Z"),
+        ]);
+        let mut b = session_with(vec![
+            at("user", "keep going", "2025-01-01T10:02:00Z"),
+            at("assistant", "renamed the lexer", "2025-01-01T10:10:00Z"),
+        ]);
+        b.id = "second".to_string();
+
+        let merged = merge_sessions(&a, &b);
+        let contents: Vec<&str> = merged.messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, vec!["start the refactor", "keep going", "moved the parser", "renamed the lexer"]);
+        assert_ne!(merged.id, a.id);
+        assert_ne!(merged.id, b.id);
+        assert_eq!(merged.created_at, Some("2025-01-01T10:00:00Z".parse().unwrap()));
+        assert_eq!(merged.updated_at, Some("2025-01-01T10:10:00Z".parse().unwrap()));
+
+        // Without timestamps on every message, all of A comes before B
+        let untimed = session_with(vec![message("user", "no clock here", vec![])]);
+        let merged = merge_sessions(&b, &untimed);
+        let contents: Vec<&str> = merged.messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, vec!["keep going", "renamed the lexer", "no clock here"]);
+    }
+
+    #[test]
+    fn test_merge_sessions_removes_duplicates() {
+        let at = |role: &str, content: &str, time: &str| Message {
+            timestamp: Some(time.parse().unwrap()),
+            ..message(role, content, vec![])
+        };
+        let a = session_with(vec![
+            at("user", "fix the login bug", "2025-01-01T10:00:00Z"),
+            at("assistant", "found it in auth.rs", "2025-01-01T10:01:00Z"),
+        ]);
+        // A resumed session starting with its predecessor's history
+        let b = session_with(vec![
+            at("user", "fix the login bug", "2025-01-01T10:00:00Z"),
+            at("assistant", "found it in auth.rs", "2025-01-01T10:01:00Z"),
+            at("user", "yes", "2025-01-01T11:00:00Z"),
+            at("user", "yes", "2025-01-01T11:05:00Z"),
+        ]);
+
+        let merged = merge_sessions(&a, &b);
+        assert_eq!(merged.messages.len(), 4);
+        // The same words at another time are a different message
+        assert_eq!(merged.messages.iter().filter(|m| m.content == "yes").count(), 2);
+    }
+
+    #[test]
+    fn test_merge_sessions_with_empty_session() {
+        let a = session_with(vec![message("user", "only message", vec![])]);
+        let empty = session_with(vec![]);
+
+        assert_eq!(merge_sessions(&a, &empty).messages.len(), 1);
+        assert_eq!(merge_sessions(&empty, &a).messages.len(), 1);
+        let merged = merge_sessions(&empty, &empty);
+        assert!(merged.messages.is_empty());
+        assert_eq!(merged.cwd.as_deref(), Some("/home/user/project"));
+    }
+
+    #[test]
+    fn test_write_session_jsonl_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("merged.jsonl");
+        let session = merge_sessions(
+            &session_with(vec![message("user", "question", vec![])]),
+            &session_with(vec![message("assistant", "answer", vec![])]),
+        );
+
+        write_session_jsonl(&session, &path).unwrap();
+        assert!(write_session_jsonl(&session, &path).is_err());
+
+        let store = SessionStore::with_path(dir.path().to_path_buf());
+        let parsed = store.try_parse_jsonl_session(&path).unwrap().unwrap();
+        assert_eq!(parsed.id, session.id);
+        let contents: Vec<&str> = parsed.messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, vec!["question", "answer"]);
+    }
+
+    #[test]
+    fn test_jaccard_similarity() {
+        let set = |items: &[&str]| items.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(jaccard_similarity(&[], &[]), 0.0);
+        assert_eq!(jaccard_similarity(&set(&["a.rs"]), &[]), 0.0);
+        assert_eq!(jaccard_similarity(&set(&["a.rs", "b.rs"]), &set(&["b.rs", "a.rs"])), 1.0);
+        // {a, b, c} and {b, c, d} share 2 of 4 distinct items
+        assert_eq!(
+            jaccard_similarity(&set(&["a.rs", "b.rs", "c.rs"]), &set(&["b.rs", "c.rs", "d.rs"])),
+            0.5
+        );
+        assert_eq!(jaccard_similarity(&set(&["a.rs"]), &set(&["b.rs"])), 0.0);
+    }
+
+    #[test]
+    fn test_in_directory() {
+        let mut session = session_with(vec![]);
+        session.cwd = Some("/home/user/project/backend".to_string());
+        session.project_path = Some("/home/user/project".to_string());
+
+        assert!(in_directory(&session, Path::new("/home/user/project")));
+        assert!(in_directory(&session, Path::new("/home/user/project/")));
+        assert!(in_directory(&session, Path::new("/home/user/project/backend")));
+        assert!(in_directory(&session, Path::new("/home")));
+        // Shares a string prefix but isn't a parent directory
+        assert!(!in_directory(&session, Path::new("/home/user/proj")));
+        assert!(!in_directory(&session, Path::new("/home/user/project/back")));
+
+        // Only the project path matches
+        session.cwd = Some("/tmp".to_string());
+        assert!(in_directory(&session, Path::new("/home/user/project")));
+        assert!(!in_directory(&session, Path::new("/home/user/project/backend")));
+    }
+
+    #[test]
+    fn test_expand_home() {
+        let home = dirs::home_dir().unwrap();
+        assert_eq!(expand_home("~"), home);
+        assert_eq!(expand_home("~/projects/myapp"), home.join("projects/myapp"));
+        assert_eq!(expand_home("/abs/path"), PathBuf::from("/abs/path"));
+        assert_eq!(expand_home("~other/x"), PathBuf::from("~other/x"));
+    }
+
+    #[test]
+    fn test_extract_project_path() {
         let path = Path::new("/home/user/.claude/projects/-home-user-myproject/session.jsonl");
         let project = extract_project_path(path);
         assert_eq!(project, Some("/home/user/myproject".to_string()));
     }
+
+    #[test]
+    fn test_decode_project_hash() {
+        // Paths from the root
+        assert_eq!(decode_project_hash("-home-alice-project"), "/home/alice/project");
+        assert_eq!(decode_project_hash("-"), "/");
+        // A doubled dash is a dash within a component name
+        assert_eq!(decode_project_hash("-home-alice-my--project"), "/home/alice/my-project");
+        assert_eq!(decode_project_hash("-srv-a--b--c-d"), "/srv/a-b-c/d");
+        // Two literal dashes in a row
+        assert_eq!(decode_project_hash("-tmp-x----y"), "/tmp/x--y");
+        // Windows drive letters
+        assert_eq!(decode_project_hash("C--Users-alice-my--app"), "C:\\Users\\alice\\my-app");
+        assert_eq!(decode_project_hash("d--"), "d:\\");
+        // Anything else is left alone
+        assert_eq!(decode_project_hash("myproject"), "myproject");
+        assert_eq!(decode_project_hash("my-project"), "my-project");
+    }
 }
@@ -0,0 +1,113 @@
+//! Completions for search queries
+//!
+//! A search for a word the sessions never use finds nothing, however often
+//! it's tried. `TermIndex` holds every session's key terms with how often they
+//! occur, so a query prefix can be completed to terms that are actually there.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::sessions::{key_term_counts, Session};
+
+/// Suggestions returned unless asked for more or fewer
+pub const DEFAULT_SUGGESTIONS: usize = 5;
+
+/// Key terms of a set of sessions, lowercased, with their total occurrences
+#[derive(Debug, Default, Clone)]
+pub struct TermIndex {
+    terms: BTreeMap<String, usize>,
+}
+
+/// A term completing a prefix
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TermSuggestion {
+    pub term: String,
+    /// Occurrences across all sessions
+    pub count: usize,
+}
+
+impl TermIndex {
+    /// Index the key terms of `sessions`
+    pub fn build<'a>(sessions: impl IntoIterator<Item = &'a Session>) -> Self {
+        let mut terms = BTreeMap::new();
+        for session in sessions {
+            for (term, count) in key_term_counts(session) {
+                *terms.entry(term).or_insert(0) += count;
+            }
+        }
+        Self { terms }
+    }
+
+    /// Number of distinct terms
+    pub fn len(&self) -> usize {
+        self.terms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Up to `limit` terms starting with `prefix`, ignoring case, most
+    /// frequent first. An empty prefix gives the most frequent terms overall.
+    pub fn suggestions_for(&self, prefix: &str, limit: usize) -> Vec<TermSuggestion> {
+        let prefix = prefix.trim().to_lowercase();
+        let mut matches: Vec<(&String, &usize)> = self
+            .terms
+            .range(prefix.clone()..)
+            .take_while(|(term, _)| term.starts_with(&prefix))
+            .collect();
+        // Ties in alphabetical order, which the range already gives
+        matches.sort_by_key(|&(_, count)| std::cmp::Reverse(*count));
+        matches
+            .into_iter()
+            .take(limit)
+            .map(|(term, &count)| TermSuggestion {
+                term: term.clone(),
+                count,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(terms: &[(&str, usize)]) -> TermIndex {
+        TermIndex {
+            terms: terms.iter().map(|&(term, count)| (term.to_string(), count)).collect(),
+        }
+    }
+
+    fn terms(suggestions: Vec<TermSuggestion>) -> Vec<String> {
+        suggestions.into_iter().map(|s| s.term).collect()
+    }
+
+    #[test]
+    fn test_prefix_matching() {
+        let index = index(&[("runtime", 4), ("rust", 9), ("error", 20), ("rustc", 9), ("trust", 3)]);
+        assert_eq!(terms(index.suggestions_for("ru", 5)), vec!["rust", "rustc", "runtime"]);
+        assert_eq!(terms(index.suggestions_for("rust", 5)), vec!["rust", "rustc"]);
+        assert_eq!(terms(index.suggestions_for("ru", 1)), vec!["rust"]);
+        assert!(index.suggestions_for("zig", 5).is_empty());
+    }
+
+    #[test]
+    fn test_prefix_ignores_case() {
+        let index = index(&[("rust", 9), ("runtime", 4)]);
+        assert_eq!(index.suggestions_for("RU", 5), index.suggestions_for("ru", 5));
+        assert_eq!(terms(index.suggestions_for(" Rus ", 5)), vec!["rust"]);
+    }
+
+    #[test]
+    fn test_empty_prefix_gives_top_terms() {
+        let index = index(&[("runtime", 4), ("rust", 9), ("error", 20), ("async", 1)]);
+        assert_eq!(
+            index.suggestions_for("", 2),
+            vec![
+                TermSuggestion { term: "error".to_string(), count: 20 },
+                TermSuggestion { term: "rust".to_string(), count: 9 },
+            ]
+        );
+    }
+}
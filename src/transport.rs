@@ -0,0 +1,308 @@
+//! Transports carrying JSON-RPC messages between MCP clients and the server
+//!
+//! `stdio` reads one request per line on stdin and writes one response per line
+//! to stdout. `http` accepts requests as `POST /mcp` bodies and streams server
+//! notifications to `GET /mcp/events` as Server-Sent Events, with Prometheus
+//! metrics at `GET /metrics`.
+
+use anyhow::{Context, Result};
+use axum::extract::State;
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::Router;
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::convert::Infallible;
+use std::future::Future;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::StreamExt;
+
+use crate::config::warning;
+use crate::http::{ResponseBuilder, EVENT_STREAM_CONTENT_TYPE};
+use crate::protocol::{JsonRpcRequest, JsonRpcResponse};
+use crate::{handle_request, ServerState};
+
+/// How long requests still running at shutdown get to finish
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// How the server talks to its client
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    /// JSON-RPC over stdin/stdout, one message per line
+    Stdio,
+    /// JSON-RPC over HTTP POST, notifications over Server-Sent Events
+    Http,
+}
+
+/// Serve JSON-RPC over stdin/stdout until stdin closes and every request read
+/// has been answered, or until `shutdown` resolves and requests already read
+/// have had `SHUTDOWN_GRACE_PERIOD` to finish. Serialized server notifications
+/// received on `notifications` are written between responses.
+pub async fn run_stdio(
+    state: ServerState,
+    mut notifications: mpsc::Receiver<String>,
+    shutdown: impl Future<Output = ()>,
+) -> Result<()> {
+    let stdin = tokio::io::stdin();
+    let mut stdout = tokio::io::stdout();
+    let reader = BufReader::new(stdin);
+    let mut lines = reader.lines();
+    let (reply_tx, mut replies) = mpsc::channel::<Value>(64);
+    tokio::pin!(shutdown);
+
+    // Tool calls each run on their own task, so a slow one doesn't hold up
+    // later requests, such as one cancelling it, and their replies may come
+    // out of order. Everything else is handled here one message at a time,
+    // in the order it arrived. Unbounded, so queueing never waits on the
+    // replies only this loop writes out.
+    let (ordered_tx, mut ordered) = mpsc::unbounded_channel::<Value>();
+    tokio::spawn({
+        let state = state.clone();
+        let reply_tx = reply_tx.clone();
+        async move {
+            while let Some(message) = ordered.recv().await {
+                if let Some(reply) = handle_json(&state, message).await {
+                    let _ = reply_tx.send(reply).await;
+                }
+            }
+        }
+    });
+    let mut shutting_down = false;
+
+    // MCP servers communicate via JSON-RPC over stdio. Every message is
+    // written whole within one branch, so stopping between branches never
+    // leaves a partial line on stdout.
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => {
+                shutting_down = true;
+                break;
+            }
+            line = lines.next_line() => {
+                let Ok(Some(line)) = line else {
+                    break;
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let message = match serde_json::from_str::<Value>(&line) {
+                    Ok(message) => message,
+                    Err(e) => {
+                        let error = JsonRpcResponse::error(Value::Null, -32700, format!("Parse error: {}", e));
+                        write_line(&mut stdout, &serde_json::to_string(&error)?).await?;
+                        continue;
+                    }
+                };
+
+                let calls = tool_call_ids(&message);
+                if calls.is_empty() {
+                    let _ = ordered_tx.send(message);
+                    continue;
+                }
+                // Registered before the next line is read, so a cancellation
+                // right behind the call finds it
+                for id in calls {
+                    state.register_call(id);
+                }
+                let state = state.clone();
+                let reply_tx = reply_tx.clone();
+                tokio::spawn(async move {
+                    if let Some(reply) = handle_json(&state, message).await {
+                        let _ = reply_tx.send(reply).await;
+                    }
+                });
+            }
+            Some(reply) = replies.recv() => {
+                write_line(&mut stdout, &serde_json::to_string(&reply)?).await?;
+            }
+            Some(notification) = notifications.recv() => {
+                write_line(&mut stdout, &notification).await?;
+            }
+        }
+    }
+
+    // Answer requests still running when stdin closed or shutdown began
+    drop(ordered_tx);
+    drop(reply_tx);
+    let drain = async {
+        while let Some(reply) = replies.recv().await {
+            write_line(&mut stdout, &serde_json::to_string(&reply)?).await?;
+        }
+        Ok::<_, anyhow::Error>(())
+    };
+    if shutting_down {
+        if tokio::time::timeout(SHUTDOWN_GRACE_PERIOD, drain).await.is_err() {
+            warning!("shutting down with requests still running");
+        }
+    } else {
+        drain.await?;
+    }
+
+    stdout.shutdown().await?;
+    Ok(())
+}
+
+/// Write one message to stdout, terminated by a newline
+async fn write_line(stdout: &mut tokio::io::Stdout, message: &str) -> Result<()> {
+    stdout.write_all(message.as_bytes()).await?;
+    stdout.write_all(b"\n").await?;
+    stdout.flush().await?;
+    Ok(())
+}
+
+/// IDs of the `tools/call` requests in a stdio message, a single request or
+/// a batch of them
+fn tool_call_ids(message: &Value) -> Vec<&Value> {
+    let requests = match message {
+        Value::Array(batch) => batch.iter().collect(),
+        request => vec![request],
+    };
+    requests
+        .into_iter()
+        .filter(|request| request.get("method").and_then(Value::as_str) == Some("tools/call"))
+        .filter_map(|request| request.get("id"))
+        .collect()
+}
+
+/// Handle one stdio message holding a request or a batch (array) of requests.
+/// Returns the reply to write, or `None` when only notifications were sent.
+async fn handle_json(state: &ServerState, message: Value) -> Option<Value> {
+    let Value::Array(batch) = message else {
+        return handle_message(state, message).await.and_then(|r| serde_json::to_value(r).ok());
+    };
+
+    if batch.is_empty() {
+        let error = JsonRpcResponse::error(Value::Null, -32600, "Invalid Request: empty batch");
+        return serde_json::to_value(error).ok();
+    }
+
+    // Requests in a batch are independent, so handle them concurrently
+    let responses: Vec<JsonRpcResponse> = join_all(batch.into_iter().map(|m| handle_message(state, m)))
+        .await
+        .into_iter()
+        .flatten()
+        .collect();
+
+    // A batch of only notifications gets no reply at all
+    if responses.is_empty() {
+        return None;
+    }
+    serde_json::to_value(responses).ok()
+}
+
+/// Handle a single JSON-RPC message, returning `None` for notifications
+async fn handle_message(state: &ServerState, message: Value) -> Option<JsonRpcResponse> {
+    match serde_json::from_value::<JsonRpcRequest>(message) {
+        Ok(request) => {
+            // Check if this is a notification (no id means notification)
+            let is_notification = request.is_notification();
+
+            let response = handle_request(state, request).await;
+
+            // Don't send response for notifications
+            (!is_notification).then_some(response)
+        }
+        Err(e) => Some(JsonRpcResponse::error(
+            Value::Null,
+            -32600,
+            format!("Invalid Request: {}", e),
+        )),
+    }
+}
+
+/// Shared state for HTTP handlers
+#[derive(Clone)]
+struct HttpState {
+    server: ServerState,
+    /// Serialized JSON-RPC notifications fanned out to every SSE subscriber
+    notifications: broadcast::Sender<String>,
+}
+
+/// Serve JSON-RPC over HTTP on `host:port` until `shutdown` resolves. Serialized
+/// server notifications received on `notifications` go to every SSE subscriber.
+pub async fn run_http(
+    server: ServerState,
+    host: &str,
+    port: u16,
+    mut notifications: mpsc::Receiver<String>,
+    shutdown: impl Future<Output = ()>,
+) -> Result<()> {
+    let (broadcast_tx, _) = broadcast::channel(64);
+    let state = HttpState {
+        server,
+        notifications: broadcast_tx.clone(),
+    };
+
+    tokio::spawn(async move {
+        while let Some(notification) = notifications.recv().await {
+            // No subscribers is not an error; the notification is just dropped
+            let _ = broadcast_tx.send(notification);
+        }
+    });
+
+    let app = Router::new()
+        .route("/mcp", post(handle_post))
+        .route("/mcp/events", get(handle_events))
+        .route("/metrics", get(handle_metrics))
+        .with_state(state);
+
+    let listener = tokio::net::TcpListener::bind((host, port))
+        .await
+        .with_context(|| format!("Failed to bind {}:{}", host, port))?;
+    // Logged to stderr so callers binding port 0 can find the actual address
+    eprintln!("claude-code-mcp listening on http://{}", listener.local_addr()?);
+
+    // Stops at once, as SSE subscribers would hold up a graceful shutdown forever
+    tokio::select! {
+        result = axum::serve(listener, app) => result?,
+        _ = shutdown => {}
+    }
+    Ok(())
+}
+
+/// `POST /mcp`: one JSON-RPC request in the body, its response in the reply,
+/// in the media type the `Accept` header prefers (see `http`)
+async fn handle_post(State(state): State<HttpState>, headers: HeaderMap, body: String) -> Response {
+    let accept = headers.get(header::ACCEPT).and_then(|v| v.to_str().ok());
+    let reply = ResponseBuilder::new(accept);
+    match serde_json::from_str::<JsonRpcRequest>(&body) {
+        Ok(request) => {
+            let is_notification = request.is_notification();
+            let reply = reply.request_id(request.id.as_ref().unwrap_or(&Value::Null));
+            let response = handle_request(&state.server, request).await;
+
+            if is_notification {
+                return reply.status(StatusCode::ACCEPTED).empty();
+            }
+            reply.json(&response)
+        }
+        Err(e) => reply.json(&JsonRpcResponse::error(Value::Null, -32700, format!("Parse error: {}", e))),
+    }
+}
+
+/// `GET /mcp/events`: stream server notifications as Server-Sent Events
+async fn handle_events(State(state): State<HttpState>) -> Response {
+    let stream = BroadcastStream::new(state.notifications.subscribe())
+        .filter_map(|message| message.ok().map(|data| Ok::<_, Infallible>(Event::default().data(data))));
+    let mut response = Sse::new(stream).keep_alive(KeepAlive::default()).into_response();
+    response
+        .headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(EVENT_STREAM_CONTENT_TYPE));
+    response
+}
+
+/// `GET /metrics`: request and tool call metrics in the Prometheus text format
+async fn handle_metrics(State(state): State<HttpState>) -> Response {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.server.render_metrics(),
+    )
+        .into_response()
+}
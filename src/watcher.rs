@@ -0,0 +1,138 @@
+//! File-system watcher that tells clients when sessions appear or change
+//!
+//! Watches ~/.claude/projects/ for session file activity. A newly created file
+//! produces a `notifications/tools/list_changed` notification, and a change to
+//! a session whose resource a client subscribed to produces a
+//! `notifications/resources/updated` notification with the new contents. The
+//! transport writes them to stdout or fans them out to SSE subscribers. Any
+//! session file activity, or a new project directory, also marks the session
+//! cache stale.
+
+use anyhow::{Context, Result};
+use notify::event::CreateKind;
+use notify::{EventKind, RecursiveMode, Watcher};
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+
+use crate::protocol::{JsonRpcNotification, ResourceUpdateNotification};
+use crate::sessions::is_session_file;
+use crate::{read_session_resource, session_uri, ServerState};
+
+/// Quiet period after a new session file before notifying, so the burst of
+/// writes that follows a file's creation produces a single notification
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Session files touched by one file-system event
+struct SessionFileEvent {
+    created: bool,
+    paths: Vec<PathBuf>,
+}
+
+/// Set once the watcher is running; advertised as `tools.listChanged` and
+/// `resources.subscribe`
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Whether a watcher is running and clients will get list_changed notifications
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// Start watching `projects_dir` in a background task, checking changed
+/// sessions against the subscriptions in `state`. Serialized notifications are
+/// sent on `notifications` until the receiving side is dropped, and
+/// `invalidate` is signalled as soon as a session file changes.
+pub fn spawn(
+    projects_dir: &Path,
+    state: ServerState,
+    notifications: mpsc::Sender<String>,
+    invalidate: watch::Sender<()>,
+) -> Result<()> {
+    let (events_tx, mut events) = mpsc::unbounded_channel::<SessionFileEvent>();
+
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        let Ok(event) = res else {
+            return;
+        };
+        // A new project directory is what a project-filtered query needs to
+        // see, and the files written into it straight away may go unreported
+        if matches!(event.kind, EventKind::Create(CreateKind::Folder)) {
+            invalidate.send_replace(());
+        }
+        let paths: Vec<PathBuf> = event.paths.into_iter().filter(|p| is_session_file(p)).collect();
+        if !paths.is_empty() {
+            // Right away rather than after the debounce, so a client that
+            // acts on the notification already sees the change
+            invalidate.send_replace(());
+            let created = matches!(event.kind, EventKind::Create(_));
+            let _ = events_tx.send(SessionFileEvent { created, paths });
+        }
+    })
+    .context("Failed to create file watcher")?;
+
+    watcher
+        .watch(projects_dir, RecursiveMode::Recursive)
+        .with_context(|| format!("Failed to watch {}", projects_dir.display()))?;
+
+    ACTIVE.store(true, Ordering::Relaxed);
+
+    tokio::spawn(async move {
+        // Keep the watcher alive for as long as the task runs
+        let _watcher = watcher;
+
+        while let Some(event) = events.recv().await {
+            let mut created = event.created;
+            let mut changed: BTreeSet<PathBuf> = event.paths.into_iter().collect();
+
+            // Wait until session file activity settles
+            loop {
+                match tokio::time::timeout(DEBOUNCE, events.recv()).await {
+                    Ok(Some(event)) => {
+                        created |= event.created;
+                        changed.extend(event.paths);
+                    }
+                    Ok(None) => return,
+                    Err(_) => break,
+                }
+            }
+
+            let mut pending = Vec::new();
+            if created {
+                pending.push(JsonRpcNotification::new("notifications/tools/list_changed", json!({})));
+            }
+            for path in changed {
+                let Some(uri) = session_uri(&path).filter(|uri| state.is_subscribed(uri)) else {
+                    continue;
+                };
+                let read_uri = uri.clone();
+                // The file may be large, and may be gone again by now
+                let Ok(Ok(content)) =
+                    tokio::task::spawn_blocking(move || read_session_resource(&read_uri, &path)).await
+                else {
+                    continue;
+                };
+                let params = ResourceUpdateNotification {
+                    uri,
+                    contents: vec![content],
+                };
+                if let Ok(params) = serde_json::to_value(params) {
+                    pending.push(JsonRpcNotification::new("notifications/resources/updated", params));
+                }
+            }
+
+            for notification in pending {
+                let Ok(message) = serde_json::to_string(&notification) else {
+                    continue;
+                };
+                if notifications.send(message).await.is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
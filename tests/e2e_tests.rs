@@ -4,7 +4,7 @@
 
 use std::io::{BufRead, BufReader, Write};
 use std::process::{Command, Stdio};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// Helper to spawn the MCP server and communicate with it
 struct McpTestClient {
@@ -15,9 +15,18 @@ struct McpTestClient {
 
 impl McpTestClient {
     fn new() -> Self {
-        let binary = PathBuf::from(env!("CARGO_BIN_EXE_claude-code-mcp"));
+        Self::spawn(Command::new(env!("CARGO_BIN_EXE_claude-code-mcp")))
+    }
+
+    /// Spawn the server with `home` as its home directory
+    fn with_home(home: &Path) -> Self {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_claude-code-mcp"));
+        command.env("HOME", home).env_remove("CLAUDE_CODE_MCP_DIR");
+        Self::spawn(command)
+    }
 
-        let mut child = Command::new(&binary)
+    fn spawn(mut command: Command) -> Self {
+        let mut child = command
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
@@ -31,15 +40,38 @@ impl McpTestClient {
     }
 
     fn send_request(&mut self, request: &serde_json::Value) -> serde_json::Value {
-        let request_str = serde_json::to_string(request).unwrap();
-        writeln!(self.stdin, "{}", request_str).expect("Failed to write request");
+        self.send(request);
+        self.read_response()
+    }
+
+    /// Write a message without waiting for a response
+    fn send(&mut self, message: &serde_json::Value) {
+        let message_str = serde_json::to_string(message).unwrap();
+        writeln!(self.stdin, "{}", message_str).expect("Failed to write request");
         self.stdin.flush().expect("Failed to flush");
+    }
 
+    fn read_response(&mut self) -> serde_json::Value {
         let mut response_line = String::new();
         self.stdout.read_line(&mut response_line).expect("Failed to read response");
 
         serde_json::from_str(&response_line).expect("Failed to parse response")
     }
+
+    /// Call a tool and return the text content of its result
+    fn call_tool(&mut self, name: &str, arguments: serde_json::Value) -> String {
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "tools/call",
+            "params": {"name": name, "arguments": arguments}
+        });
+        let response = self.send_request(&request);
+        response["result"]["content"][0]["text"]
+            .as_str()
+            .expect("Tool result has no text content")
+            .to_string()
+    }
 }
 
 impl Drop for McpTestClient {
@@ -48,6 +80,89 @@ impl Drop for McpTestClient {
     }
 }
 
+/// A server whose stdout is read on a thread, for tests that wait on
+/// notifications, so one that never arrives fails instead of hanging
+struct StreamingTestClient {
+    child: std::process::Child,
+    stdin: std::process::ChildStdin,
+    messages: std::sync::mpsc::Receiver<serde_json::Value>,
+}
+
+impl StreamingTestClient {
+    /// Spawn the server with `home` as its home directory
+    fn with_home(home: &Path) -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_claude-code-mcp"))
+            .env("HOME", home)
+            .env_remove("CLAUDE_CODE_MCP_DIR")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Failed to spawn MCP server");
+        let stdin = child.stdin.take().expect("Failed to get stdin");
+        let stdout = BufReader::new(child.stdout.take().expect("Failed to get stdout"));
+
+        let (messages_tx, messages) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for line in stdout.lines().map_while(Result::ok) {
+                let message: serde_json::Value = serde_json::from_str(&line).unwrap();
+                if messages_tx.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self { child, stdin, messages }
+    }
+
+    fn send(&mut self, message: &serde_json::Value) {
+        writeln!(self.stdin, "{}", message).expect("Failed to write request");
+        self.stdin.flush().expect("Failed to flush");
+    }
+
+    /// The next message from the server, or `None` after `timeout`
+    fn recv(&self, timeout: std::time::Duration) -> Option<serde_json::Value> {
+        self.messages.recv_timeout(timeout).ok()
+    }
+}
+
+impl Drop for StreamingTestClient {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Write a synthetic session file under `<home>/.claude/projects/<project>/<id>.jsonl`.
+/// Each message is a `(role, content, timestamp)` triple.
+fn write_session(home: &Path, project: &str, id: &str, cwd: &str, messages: &[(&str, &str, &str)]) {
+    let lines: Vec<serde_json::Value> = messages
+        .iter()
+        .map(|(role, content, timestamp)| serde_json::json!({
+            "type": role,
+            "sessionId": id,
+            "cwd": cwd,
+            "timestamp": timestamp,
+            "message": {"role": role, "content": content}
+        }))
+        .collect();
+    write_session_lines(home, project, id, &lines);
+}
+
+/// Write raw JSONL entries as a session file
+fn write_session_lines(home: &Path, project: &str, id: &str, lines: &[serde_json::Value]) {
+    let dir = home.join(".claude").join("projects").join(project);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let lines: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+    std::fs::write(dir.join(format!("{}.jsonl", id)), lines.join("\n")).unwrap();
+}
+
+/// The sessions on a page of `search_sessions` results
+fn search_items(text: &str) -> Vec<serde_json::Value> {
+    let page: serde_json::Value = serde_json::from_str(text).unwrap();
+    page["items"].as_array().expect("search results have items").clone()
+}
 
 // ===== Protocol Tests =====
 
@@ -99,7 +214,54 @@ fn test_tools_list() {
     assert!(tool_names.contains(&"search_sessions"));
     assert!(tool_names.contains(&"get_session"));
     assert!(tool_names.contains(&"get_session_context"));
-    assert_eq!(tools.len(), 4);
+    assert!(tool_names.contains(&"list_projects"));
+    assert!(tool_names.contains(&"get_session_messages"));
+    assert!(tool_names.contains(&"get_session_diff"));
+    assert!(tool_names.contains(&"get_session_commands"));
+    assert!(tool_names.contains(&"find_sessions_touching_file"));
+    assert!(tool_names.contains(&"tag_session"));
+    assert!(tool_names.contains(&"untag_session"));
+    assert!(tool_names.contains(&"list_tags"));
+    assert!(tool_names.contains(&"find_sessions_by_tag"));
+    assert!(tool_names.contains(&"export_session_markdown"));
+    assert!(tool_names.contains(&"get_tool_usage_stats"));
+    assert!(tool_names.contains(&"get_sessions_statistics"));
+    assert!(tool_names.contains(&"validate_session"));
+    assert!(tool_names.contains(&"rebuild_index"));
+    assert!(tool_names.contains(&"get_related_sessions"));
+    assert!(tool_names.contains(&"find_sessions_by_cwd"));
+    assert!(tool_names.contains(&"extract_code_blocks"));
+    assert!(tool_names.contains(&"compare_sessions"));
+    assert!(tool_names.contains(&"list_agent_sessions"));
+    assert!(tool_names.contains(&"export_sessions_csv"));
+    assert!(tool_names.contains(&"find_sessions_with_errors"));
+    assert!(tool_names.contains(&"get_activity_heatmap"));
+    assert!(tool_names.contains(&"copy_session_to_clipboard"));
+    assert!(tool_names.contains(&"get_longest_sessions"));
+    assert!(tool_names.contains(&"get_shortest_sessions"));
+    assert!(tool_names.contains(&"get_session_timeline"));
+    assert!(tool_names.contains(&"get_most_recent_session"));
+    assert!(tool_names.contains(&"add_session_note"));
+    assert!(tool_names.contains(&"get_session_notes"));
+    assert!(tool_names.contains(&"delete_session_note"));
+    assert!(tool_names.contains(&"find_sessions_by_language"));
+    assert!(tool_names.contains(&"list_session_files"));
+    assert!(tool_names.contains(&"inspect_jsonl"));
+    assert!(tool_names.contains(&"get_session_token_estimate"));
+    assert!(tool_names.contains(&"merge_sessions"));
+    assert!(tool_names.contains(&"find_duplicate_sessions"));
+    assert!(tool_names.contains(&"show_config"));
+    assert!(tool_names.contains(&"get_session_cost"));
+    assert!(tool_names.contains(&"get_command_history"));
+    assert!(tool_names.contains(&"list_tools"));
+    assert!(tool_names.contains(&"find_sessions_without_assistant_response"));
+    assert!(tool_names.contains(&"extract_todos"));
+    assert!(tool_names.contains(&"find_todos_across_sessions"));
+    assert!(tool_names.contains(&"find_sessions_by_git_repo"));
+    assert!(tool_names.contains(&"suggestions_for"));
+    assert!(tool_names.contains(&"watch_session"));
+    assert!(tool_names.contains(&"get_project_timeline"));
+    assert_eq!(tools.len(), 52);
 }
 
 #[test]
@@ -175,6 +337,7 @@ fn test_parse_error() {
     assert!(response["error"]["message"].as_str().unwrap().contains("Parse error"));
 
     let _ = child.kill();
+    let _ = child.wait();
 }
 
 #[test]
@@ -237,6 +400,34 @@ fn test_search_sessions_tool() {
     assert!(response["result"]["content"].is_array());
 }
 
+#[test]
+fn test_suggestions_for() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "one", "/proj", &[
+        ("user", "Rust runtime panics in Rust tests", "2024-01-01T00:00:00Z"),
+    ]);
+    write_session(home.path(), "-proj", "two", "/proj", &[
+        ("user", "rust error handling with anyhow", "2024-01-02T00:00:00Z"),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+    let suggest = |client: &mut McpTestClient, arguments: serde_json::Value| -> Vec<(String, u64)> {
+        let result: serde_json::Value = serde_json::from_str(&client.call_tool("suggestions_for", arguments)).unwrap();
+        result["suggestions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| (s["term"].as_str().unwrap().to_string(), s["count"].as_u64().unwrap()))
+            .collect()
+    };
+
+    assert_eq!(
+        suggest(&mut client, serde_json::json!({"prefix": "RU"})),
+        vec![("rust".to_string(), 3), ("runtime".to_string(), 1)]
+    );
+    assert_eq!(suggest(&mut client, serde_json::json!({"prefix": "", "limit": 1})), vec![("rust".to_string(), 3)]);
+    assert!(suggest(&mut client, serde_json::json!({"prefix": "zig"})).is_empty());
+}
+
 #[test]
 fn test_search_sessions_missing_query() {
     let mut client = McpTestClient::new();
@@ -281,6 +472,259 @@ fn test_get_session_tool() {
         || content.as_str().unwrap().contains("Failed"));
 }
 
+/// A home with one two-message session, `fmt`
+fn format_test_home() -> tempfile::TempDir {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "fmt", "/proj", &[
+        ("user", "rename the config loader", "2024-04-01T10:00:00Z"),
+        ("assistant", "Renamed it to load_config", "2024-04-01T10:00:05Z"),
+    ]);
+    home
+}
+
+#[test]
+fn test_get_session_format_json() {
+    let home = format_test_home();
+    let mut client = McpTestClient::with_home(home.path());
+
+    // json is the default
+    let default = client.call_tool("get_session", serde_json::json!({"session_id": "fmt"}));
+    let json = client.call_tool("get_session", serde_json::json!({"session_id": "fmt", "format": "json"}));
+    assert_eq!(default, json);
+    let session: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(session["messages"][1]["content"], "Renamed it to load_config");
+}
+
+#[test]
+fn test_get_session_typed_content() {
+    let home = tempfile::tempdir().unwrap();
+    write_session_lines(home.path(), "-proj", "typed", &[
+        serde_json::json!({
+            "type": "assistant", "sessionId": "typed", "timestamp": "2024-04-01T10:00:00Z",
+            "message": {"role": "assistant", "content": [
+                {"type": "text", "text": "Running the tests."},
+                {"type": "tool_use", "id": "toolu_1", "name": "Bash", "input": {"command": "cargo test"}}
+            ]}
+        }),
+        serde_json::json!({
+            "type": "user", "sessionId": "typed", "timestamp": "2024-04-01T10:00:05Z",
+            "message": {"role": "user", "content": [
+                {"type": "tool_result", "tool_use_id": "toolu_1", "is_error": true, "content": "{\"failed\": 2}"}
+            ]}
+        }),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    let text = client.call_tool("get_session", serde_json::json!({"session_id": "typed", "content_format": "typed"}));
+    let session: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(
+        session["messages"],
+        serde_json::json!([
+            {"role": "assistant", "timestamp": "2024-04-01T10:00:00Z", "parts": [
+                {"type": "text", "text": "Running the tests."},
+                {"type": "tool_call", "name": "Bash", "input_summary": "{\"command\":\"cargo test\"}", "tool_use_id": "toolu_1"}
+            ]},
+            {"role": "user", "timestamp": "2024-04-01T10:00:05Z", "parts": [
+                {"type": "tool_result", "tool_use_id": "toolu_1", "output_preview": "{\"failed\": 2}", "is_error": true}
+            ]}
+        ])
+    );
+
+    let text = client.call_tool(
+        "get_session",
+        serde_json::json!({"session_id": "typed", "content_format": "typed", "max_messages": 1}),
+    );
+    let session: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(session["messages"].as_array().unwrap().len(), 1);
+    assert_eq!(session["messages"][0]["role"], "assistant");
+
+    let text = client.call_tool("get_session", serde_json::json!({"session_id": "typed"}));
+    let session: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert!(session["messages"][0]["content"].is_string());
+
+    let response = client.send_request(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "tools/call",
+        "params": {"name": "get_session", "arguments": {"session_id": "typed", "content_format": "typed", "format": "markdown"}}
+    }));
+    assert_eq!(response["result"]["isError"], true);
+}
+
+#[test]
+fn test_get_session_raw_lines() {
+    let home = tempfile::tempdir().unwrap();
+    let dir = home.path().join(".claude").join("projects").join("-proj");
+    std::fs::create_dir_all(&dir).unwrap();
+    let valid = serde_json::json!({
+        "type": "user", "sessionId": "raw", "timestamp": "2024-04-01T10:00:00Z",
+        "message": {"role": "user", "content": "why is the second line missing?"}
+    })
+    .to_string();
+    let invalid = r#"{"type": "assistant", "message": {"role": "assistant", "content": "cut off"#;
+    std::fs::write(dir.join("raw.jsonl"), format!("{}\n{}\n", valid, invalid)).unwrap();
+    let mut client = McpTestClient::with_home(home.path());
+
+    let text = client.call_tool("get_session", serde_json::json!({"session_id": "raw", "raw": true}));
+    let session: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(session["messages"].as_array().unwrap().len(), 1);
+    assert_eq!(session["truncated"], false);
+    assert_eq!(
+        session["raw_lines"],
+        serde_json::json!([
+            {"index": 1, "content": valid, "parsed": true},
+            {"index": 2, "content": invalid, "parsed": false}
+        ])
+    );
+
+    // Without raw the response is unchanged
+    let text = client.call_tool("get_session", serde_json::json!({"session_id": "raw"}));
+    let session: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert!(session.get("raw_lines").is_none());
+}
+
+#[test]
+fn test_get_session_format_markdown() {
+    let home = format_test_home();
+    let mut client = McpTestClient::with_home(home.path());
+
+    let markdown = client.call_tool("get_session", serde_json::json!({"session_id": "fmt", "format": "markdown"}));
+    assert!(markdown.contains("## User\n\nrename the config loader"));
+    assert!(markdown.contains("## Assistant\n\nRenamed it to load_config"));
+}
+
+#[test]
+fn test_get_session_format_plain_text() {
+    let home = format_test_home();
+    let mut client = McpTestClient::with_home(home.path());
+
+    let text = client.call_tool("get_session", serde_json::json!({"session_id": "fmt", "format": "plain_text"}));
+    assert_eq!(text, "User: rename the config loader\n---\nAssistant: Renamed it to load_config\n");
+    for json_syntax in ["{", "}", "\"role\"", "\"content\""] {
+        assert!(!text.contains(json_syntax), "plain text contains {}", json_syntax);
+    }
+
+    let text = client.call_tool(
+        "get_session",
+        serde_json::json!({"session_id": "fmt", "format": "plain_text", "separator": "***"}),
+    );
+    assert!(text.contains("loader\n***\nAssistant"));
+
+    let response = client.send_request(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "tools/call",
+        "params": {"name": "get_session", "arguments": {"session_id": "fmt", "format": "yaml"}}
+    }));
+    assert_eq!(response["result"]["isError"], true);
+}
+
+#[test]
+fn test_copy_session_to_clipboard() {
+    let home = format_test_home();
+    // Never touch the clipboard of the machine running the tests
+    let mut command = Command::new(env!("CARGO_BIN_EXE_claude-code-mcp"));
+    command
+        .env("HOME", home.path())
+        .env_remove("CLAUDE_CODE_MCP_DIR")
+        .env("CLAUDE_CODE_MCP_CLIPBOARD", "false");
+    let mut client = McpTestClient::spawn(command);
+
+    let response = client.send_request(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "tools/call",
+        "params": {"name": "copy_session_to_clipboard", "arguments": {"session_id": "fmt", "format": "plain_text"}}
+    }));
+    let result = &response["result"];
+    assert!(result.get("isError").is_none());
+
+    let status: serde_json::Value = serde_json::from_str(result["content"][0]["text"].as_str().unwrap()).unwrap();
+    assert_eq!(status["status"], "clipboard_unavailable");
+    assert!(status["reason"].as_str().unwrap().contains("clipboard setting"));
+    assert_eq!(
+        result["content"][1]["text"],
+        "User: rename the config loader\n---\nAssistant: Renamed it to load_config\n"
+    );
+
+    let response = client.send_request(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "tools/call",
+        "params": {"name": "copy_session_to_clipboard", "arguments": {"session_id": "missing"}}
+    }));
+    assert_eq!(response["result"]["isError"], true);
+}
+
+#[test]
+fn test_get_session_context_single() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "one", "/proj", &[
+        ("user", "fix the bug in src/auth.rs", "2024-01-01T00:00:00Z"),
+        ("assistant", "fixed", "2024-01-01T00:01:00Z"),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    let text = client.call_tool("get_session_context", serde_json::json!({"session_id": "one"}));
+    let context: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(context["id"], "one");
+    assert_eq!(context["message_count"], 2);
+    assert_eq!(context["files_mentioned"], serde_json::json!(["src/auth.rs"]));
+    assert!(context.get("aggregated").is_none());
+    assert!(context.get("missing_ids").is_none());
+}
+
+#[test]
+fn test_get_session_context_prompt_format() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "one", "/proj", &[
+        ("user", "fix the bug in src/auth.rs", "2024-01-01T00:00:00Z"),
+        ("assistant", "fixed", "2024-01-01T00:01:00Z"),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    let text = client.call_tool("get_session_context", serde_json::json!({"session_id": "one", "format": "prompt"}));
+    assert!(text.starts_with(
+        "In session one from 2024-01-01, while working in /proj, the user asked: 'fix the bug in src/auth.rs'. \
+         The session involved 2 messages and touched files: src/auth.rs."
+    ));
+    assert!(text.chars().count() <= 1000);
+}
+
+#[test]
+fn test_get_session_context_multiple() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "one", "/proj", &[
+        ("user", "fix the bug in src/auth.rs", "2024-01-01T00:00:00Z"),
+        ("assistant", "fixed", "2024-01-01T00:01:00Z"),
+    ]);
+    write_session(home.path(), "-proj", "two", "/proj", &[
+        ("user", "add tests for src/auth.rs and src/db.rs", "2024-01-02T00:00:00Z"),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    let text = client.call_tool(
+        "get_session_context",
+        serde_json::json!({"session_ids": ["one", "missing", "two"]}),
+    );
+    let context: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(context["aggregated"], true);
+    assert_eq!(context["message_count"], 3);
+    assert_eq!(context["cwd"], "/proj");
+    assert_eq!(context["files_mentioned"], serde_json::json!(["src/auth.rs", "src/db.rs"]));
+    assert_eq!(context["missing_ids"], serde_json::json!(["missing"]));
+    let request = context["initial_request"].as_str().unwrap();
+    assert!(request.contains("[one] fix the bug") && request.contains("[two] add tests"));
+
+    let response = client.send_request(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "tools/call",
+        "params": {"name": "get_session_context", "arguments": {"session_ids": ["missing"]}}
+    }));
+    assert_eq!(response["result"]["isError"], true);
+}
+
 #[test]
 fn test_get_session_missing_id() {
     let mut client = McpTestClient::new();
@@ -302,6 +746,246 @@ fn test_get_session_missing_id() {
     assert!(response["result"]["isError"] == true);
 }
 
+#[test]
+fn test_get_session_context_max_files() {
+    let home = tempfile::tempdir().unwrap();
+    let files: Vec<String> = (0..30).map(|i| format!("src/file_{}.rs", i)).collect();
+    let content = format!("touch {}", files.join(" "));
+    write_session(home.path(), "-home-user-project", "many", "/home/user/project", &[
+        ("user", &content, "2025-01-01T10:00:00Z"),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+    let files_mentioned = |client: &mut McpTestClient, arguments: serde_json::Value| -> usize {
+        let context: serde_json::Value = serde_json::from_str(&client.call_tool("get_session_context", arguments)).unwrap();
+        context["files_mentioned"].as_array().unwrap().len()
+    };
+
+    assert_eq!(files_mentioned(&mut client, serde_json::json!({"session_id": "many"})), 20);
+    assert_eq!(files_mentioned(&mut client, serde_json::json!({"session_id": "many", "max_files": 5})), 5);
+    assert_eq!(files_mentioned(&mut client, serde_json::json!({"session_id": "many", "max_files": 0})), 30);
+
+    let context: serde_json::Value =
+        serde_json::from_str(&client.call_tool("get_session_context", serde_json::json!({"session_id": "many"}))).unwrap();
+    assert!(context["token_estimate"].as_u64().unwrap() > 0);
+    assert_eq!(context["token_estimate_by_role"]["user"], context["token_estimate"]);
+    assert_eq!(context["token_estimate_by_role"]["assistant"], 0);
+    let context: serde_json::Value = serde_json::from_str(&client.call_tool(
+        "get_session_context",
+        serde_json::json!({"session_id": "many", "include_token_estimate": false}),
+    ))
+    .unwrap();
+    assert!(context.get("token_estimate").is_none());
+
+    let response = client.send_request(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "tools/call",
+        "params": {"name": "get_session_context", "arguments": {"session_id": "many", "max_files": 201}}
+    }));
+    assert_eq!(response["result"]["isError"], true);
+}
+
+#[test]
+fn test_get_project_timeline() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-home-user-app", "first", "/home/user/app", &[
+        ("user", "scaffold src/main.rs", "2024-03-28T09:00:00Z"),
+        ("assistant", "done", "2024-03-28T09:05:00Z"),
+    ]);
+    write_session(home.path(), "-home-user-app", "second", "/home/user/app", &[
+        ("user", "split src/main.rs into src/cli.rs", "2024-04-02T09:00:00Z"),
+    ]);
+    write_session(home.path(), "-home-user-other", "elsewhere", "/home/user/other", &[
+        ("user", "unrelated", "2024-03-28T10:00:00Z"),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    let timeline: serde_json::Value = serde_json::from_str(&client.call_tool(
+        "get_project_timeline",
+        serde_json::json!({"project_path": "/home/user/app", "bucket": "month"}),
+    ))
+    .unwrap();
+    assert_eq!(timeline["total_sessions"], 2);
+    assert_eq!(timeline["total_messages"], 3);
+    assert_eq!(timeline["buckets"][0]["date"], "2024-03");
+    assert_eq!(timeline["buckets"][0]["new_files"], serde_json::json!(["src/main.rs"]));
+    assert_eq!(timeline["buckets"][1]["date"], "2024-04");
+    assert_eq!(timeline["buckets"][1]["new_files"], serde_json::json!(["src/cli.rs"]));
+
+    let timeline: serde_json::Value =
+        serde_json::from_str(&client.call_tool("get_project_timeline", serde_json::json!({"project_path": "/home/user/app"})))
+            .unwrap();
+    assert_eq!(timeline["buckets"][1]["date"], "2024-04-02");
+
+    for arguments in [
+        serde_json::json!({"project_path": "/home/user/none"}),
+        serde_json::json!({"project_path": "/home/user/app", "bucket": "year"}),
+    ] {
+        let response = client.send_request(&serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "tools/call",
+            "params": {"name": "get_project_timeline", "arguments": arguments}
+        }));
+        assert_eq!(response["result"]["isError"], true);
+    }
+}
+
+#[test]
+fn test_watch_session_times_out() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "idle", "/proj", &[
+        ("user", "start", "2025-01-01T10:00:00Z"),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    let started = std::time::Instant::now();
+    let result: serde_json::Value = serde_json::from_str(&client.call_tool(
+        "watch_session",
+        serde_json::json!({"session_id": "idle", "poll_interval_ms": 10, "timeout_ms": 300}),
+    ))
+    .unwrap();
+    assert!(started.elapsed() >= std::time::Duration::from_millis(300));
+    assert_eq!(result["timed_out"], true);
+    assert_eq!(result["new_messages"], serde_json::json!([]));
+    assert_eq!(result["current_message_count"], 1);
+}
+
+#[test]
+fn test_watch_session_new_messages() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "busy", "/proj", &[
+        ("user", "start", "2025-01-01T10:00:00Z"),
+    ]);
+    let mut client = StreamingTestClient::with_home(home.path());
+
+    client.send(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "tools/call",
+        "params": {"name": "watch_session", "arguments": {"session_id": "busy", "poll_interval_ms": 100, "timeout_ms": 10000}}
+    }));
+    let path = home.path().join(".claude/projects/-proj/busy.jsonl");
+    let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+    let entry = serde_json::json!({
+        "type": "assistant",
+        "sessionId": "busy",
+        "timestamp": "2025-01-01T10:00:05Z",
+        "message": {"role": "assistant", "content": "still working"}
+    });
+    // Keep appending until the watch answers, since a message written before
+    // the server read the session counts as already there
+    let mut appended = 0;
+    let response = loop {
+        writeln!(file, "\n{}", entry).unwrap();
+        appended += 1;
+        if let Some(response) = client.recv(std::time::Duration::from_millis(200)) {
+            break response;
+        }
+        assert!(appended < 50, "watch_session never answered");
+    };
+    let result: serde_json::Value =
+        serde_json::from_str(response["result"]["content"][0]["text"].as_str().unwrap()).unwrap();
+    assert_eq!(result["timed_out"], false);
+    let new_messages = result["new_messages"].as_array().unwrap();
+    assert!(!new_messages.is_empty());
+    assert!(new_messages.iter().all(|m| m["content"] == "still working"));
+    let count = result["current_message_count"].as_u64().unwrap() as usize;
+    assert!(count > new_messages.len() && count <= 1 + appended, "{} messages", count);
+}
+
+#[test]
+fn test_watch_session_short_tool_timeout() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "busy", "/proj", &[("user", "start", "2025-01-01T10:00:00Z")]);
+    let mut command = Command::new(env!("CARGO_BIN_EXE_claude-code-mcp"));
+    command
+        .env("HOME", home.path())
+        .env_remove("CLAUDE_CODE_MCP_DIR")
+        .args(["--tool-timeout-secs", "1"]);
+    let mut client = McpTestClient::spawn(command);
+
+    // Still waits and polls, for half the second the call may take
+    let started = std::time::Instant::now();
+    let text = client.call_tool(
+        "watch_session",
+        serde_json::json!({"session_id": "busy", "poll_interval_ms": 100, "timeout_ms": 5000}),
+    );
+    let elapsed = started.elapsed();
+    let result: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(result["timed_out"], true);
+    assert!(elapsed >= std::time::Duration::from_millis(400), "{:?}", elapsed);
+}
+
+#[test]
+fn test_get_session_summary_only() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-home-user-project", "summarized", "/home/user/project", &[
+        ("user", "Make the lexer handle tabs in src/lexer.rs", "2025-01-01T10:00:00Z"),
+        ("assistant", "Updated src/lexer.rs to treat tabs as whitespace", "2025-01-01T10:01:00Z"),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    let summary = client.call_tool("get_session", serde_json::json!({"session_id": "summarized", "summary_only": true}));
+    assert!(summary.starts_with("In session summarized, the user asked about 'Make the lexer handle tabs"));
+    assert!(summary.contains("2 messages touching files src/lexer.rs."));
+    assert!(summary.contains("Key topics: "));
+
+    let response = client.send_request(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "tools/call",
+        "params": {"name": "get_session", "arguments": {"session_id": "summarized", "summary_only": true, "raw": true}}
+    }));
+    assert_eq!(response["result"]["isError"], true);
+}
+
+#[test]
+fn test_get_session_context_streaming() {
+    let home = tempfile::tempdir().unwrap();
+    let messages: Vec<(&str, String, &str)> = (0..210)
+        .map(|i| {
+            let role = if i % 2 == 0 { "user" } else { "assistant" };
+            (role, format!("Editing src/module_{}.rs for the parser", i % 5), "2025-01-01T10:00:00Z")
+        })
+        .collect();
+    let messages: Vec<(&str, &str, &str)> = messages.iter().map(|(r, c, t)| (*r, c.as_str(), *t)).collect();
+    write_session(home.path(), "-home-user-project", "long", "/home/user/project", &messages);
+    write_session(home.path(), "-home-user-project", "short", "/home/user/project", &messages[..10]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    client.send(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 42,
+        "method": "tools/call",
+        "params": {"name": "get_session_context", "arguments": {"session_id": "long", "streaming": true}}
+    }));
+    // The response and the notification may come in either order
+    let (first, second) = (client.read_response(), client.read_response());
+    let (notification, response) = if first.get("method").is_some() { (first, second) } else { (second, first) };
+
+    assert_eq!(response["id"], 42);
+    let context: serde_json::Value =
+        serde_json::from_str(response["result"]["content"][0]["text"].as_str().unwrap()).unwrap();
+    assert_eq!(context["message_count"], 210);
+    assert_eq!(context["pending"], serde_json::json!(["files_mentioned", "key_terms"]));
+    assert!(context.get("files_mentioned").is_none());
+
+    assert_eq!(notification["method"], "notifications/tools/progress");
+    assert_eq!(notification["params"]["requestId"], 42);
+    let files = notification["params"]["partial"]["files_mentioned"].as_array().unwrap();
+    assert!(files.iter().any(|f| f == "src/module_0.rs"));
+    assert!(notification["params"]["partial"]["key_terms"].is_array());
+
+    // A short session is answered whole
+    let context: serde_json::Value = serde_json::from_str(
+        &client.call_tool("get_session_context", serde_json::json!({"session_id": "short", "streaming": true})),
+    )
+    .unwrap();
+    assert!(context.get("pending").is_none());
+    assert!(!context["files_mentioned"].as_array().unwrap().is_empty());
+}
+
 #[test]
 fn test_get_session_context_tool() {
     let mut client = McpTestClient::new();
@@ -325,6 +1009,31 @@ fn test_get_session_context_tool() {
         || content.as_str().unwrap().contains("Failed"));
 }
 
+#[test]
+fn test_list_tools_tool() {
+    let home = tempfile::tempdir().unwrap();
+    let mut client = McpTestClient::with_home(home.path());
+
+    let response = client.send_request(&serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"}));
+    let expected: Vec<&str> = response["result"]["tools"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| t["name"].as_str().unwrap())
+        .collect();
+
+    // Works without a Claude directory to read sessions from
+    let tools: Vec<serde_json::Value> =
+        serde_json::from_str(&client.call_tool("list_tools", serde_json::json!({}))).unwrap();
+    let names: Vec<&str> = tools.iter().map(|t| t["name"].as_str().unwrap()).collect();
+    assert_eq!(names, expected);
+    assert!(names.contains(&"list_tools"));
+    for tool in &tools {
+        assert!(!tool["description"].as_str().unwrap().is_empty(), "{}", tool["name"]);
+        assert_eq!(tool["inputSchema"]["type"], "object", "{}", tool["name"]);
+    }
+}
+
 #[test]
 fn test_unknown_tool() {
     let mut client = McpTestClient::new();
@@ -430,7 +1139,10 @@ fn test_server_version() {
 
 #[test]
 fn test_capabilities() {
-    let mut client = McpTestClient::new();
+    // No ~/.claude/projects to watch and no tool manifest from an earlier
+    // run, so tool list changes are not advertised
+    let home = tempfile::tempdir().unwrap();
+    let mut client = McpTestClient::with_home(home.path());
 
     let request = serde_json::json!({
         "jsonrpc": "2.0",
@@ -444,6 +1156,83 @@ fn test_capabilities() {
     let capabilities = &response["result"]["capabilities"];
     assert!(capabilities["tools"].is_object());
     assert_eq!(capabilities["tools"]["listChanged"], false);
+    assert!(capabilities["resources"].is_object());
+    assert_eq!(capabilities["resources"]["listChanged"], false);
+    assert_eq!(capabilities["resources"]["subscribe"], false);
+    assert_eq!(capabilities["prompts"]["listChanged"], false);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_tool_list_changed_after_upgrade() {
+    let home = tempfile::tempdir().unwrap();
+    let manifest_path = home.path().join(".claude-code-mcp").join("last_tool_manifest.json");
+    std::fs::create_dir_all(manifest_path.parent().unwrap()).unwrap();
+    std::fs::write(&manifest_path, r#"{"version": "0.0.1", "tool_names": ["list_sessions", "retired_tool"]}"#).unwrap();
+
+    let initialize = serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}});
+    let initialized = serde_json::json!({"jsonrpc": "2.0", "method": "notifications/initialized"});
+
+    let mut client = McpTestClient::with_home(home.path());
+    let response = client.send_request(&initialize);
+    assert_eq!(response["result"]["capabilities"]["tools"]["listChanged"], true);
+    client.send(&initialized);
+    let notification = client.read_response();
+    assert_eq!(notification["method"], "notifications/tools/list_changed");
+
+    // A clean shutdown saves the current tools
+    let status = Command::new("kill")
+        .args(["-TERM", &client.child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(client.child.wait().unwrap().code(), Some(0));
+    let saved: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&manifest_path).unwrap()).unwrap();
+    assert_eq!(saved["version"], env!("CARGO_PKG_VERSION"));
+    let saved_tools = saved["tool_names"].as_array().unwrap();
+    assert!(saved_tools.contains(&serde_json::json!("list_sessions")));
+    assert!(!saved_tools.contains(&serde_json::json!("retired_tool")));
+
+    // The next run has the same tools, so there is nothing to refresh
+    let mut client = McpTestClient::with_home(home.path());
+    let response = client.send_request(&initialize);
+    assert_eq!(response["result"]["capabilities"]["tools"]["listChanged"], false);
+    client.send(&initialized);
+    let response = client.send_request(&serde_json::json!({"jsonrpc": "2.0", "id": 2, "method": "ping"}));
+    assert_eq!(response["id"], 2);
+}
+
+fn initialize_with_version(version: &str) -> serde_json::Value {
+    let mut client = McpTestClient::new();
+    client.send_request(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "initialize",
+        "params": {"protocolVersion": version, "capabilities": {}}
+    }))
+}
+
+#[test]
+fn test_protocol_version_supported() {
+    let response = initialize_with_version("2024-11-05");
+    assert_eq!(response["result"]["protocolVersion"], "2024-11-05");
+    assert!(response["result"].get("downgraded").is_none());
+}
+
+#[test]
+fn test_protocol_version_older_client() {
+    let response = initialize_with_version("2024-01-01");
+    assert_eq!(response["result"]["protocolVersion"], "2024-11-05");
+    assert_eq!(response["result"]["downgraded"], true);
+}
+
+#[test]
+fn test_protocol_version_unknown() {
+    let response = initialize_with_version("v42");
+    assert!(response["result"].is_null());
+    assert_eq!(response["error"]["code"], -32602);
+    let message = response["error"]["message"].as_str().unwrap();
+    assert!(message.contains("v42") && message.contains("2024-11-05"));
 }
 
 // ===== Edge Cases =====
@@ -505,3 +1294,2541 @@ fn test_string_id() {
     assert_eq!(response["id"], "string-id-123");
     assert!(response["result"]["tools"].is_array());
 }
+
+// ===== Synthetic Session Tests =====
+
+#[test]
+fn test_list_projects_tool() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-home-user-alpha", "alpha-1", "/home/user/alpha",
+        &[("user", "first alpha task", "2025-01-01T10:00:00Z")]);
+    write_session(home.path(), "-home-user-alpha", "alpha-2", "/home/user/alpha/sub",
+        &[("user", "second alpha task", "2025-01-03T10:00:00Z")]);
+    write_session(home.path(), "-home-user-beta", "beta-1", "/home/user/beta",
+        &[("user", "beta task", "2025-01-02T10:00:00Z")]);
+
+    // A corrupt file should be skipped rather than failing the call
+    let beta_dir = home.path().join(".claude/projects/-home-user-beta");
+    std::fs::write(beta_dir.join("corrupt.jsonl"), "{not json").unwrap();
+
+    let mut client = McpTestClient::with_home(home.path());
+    let text = client.call_tool("list_projects", serde_json::json!({"include_cwd": true}));
+    let projects: Vec<serde_json::Value> = serde_json::from_str(&text).unwrap();
+
+    assert_eq!(projects.len(), 2);
+    assert_eq!(projects[0]["project_path"], "/home/user/alpha");
+    assert_eq!(projects[0]["session_count"], 2);
+    assert_eq!(projects[0]["cwds"].as_array().unwrap().len(), 2);
+    assert_eq!(projects[1]["project_path"], "/home/user/beta");
+    assert_eq!(projects[1]["session_count"], 1);
+
+    let text = client.call_tool("list_projects", serde_json::json!({}));
+    let projects: Vec<serde_json::Value> = serde_json::from_str(&text).unwrap();
+    assert!(projects[0].get("cwds").is_none());
+}
+
+#[test]
+fn test_list_sessions_pagination() {
+    let home = tempfile::tempdir().unwrap();
+    for i in 0..7 {
+        let id = format!("session-{}", i);
+        let timestamp = format!("2025-01-0{}T10:00:00Z", i + 1);
+        write_session(home.path(), "-home-user-paged", &id, "/home/user/paged",
+            &[("user", "paged work", &timestamp)]);
+    }
+
+    let mut client = McpTestClient::with_home(home.path());
+    let mut seen = Vec::new();
+    let mut cursor: Option<String> = None;
+
+    for page_number in 0..3 {
+        let mut arguments = serde_json::json!({"limit": 3});
+        if let Some(c) = &cursor {
+            arguments["cursor"] = serde_json::json!(c);
+        }
+        let text = client.call_tool("list_sessions", arguments);
+        let page: serde_json::Value = serde_json::from_str(&text).unwrap();
+
+        let ids: Vec<String> = page["items"].as_array().unwrap().iter()
+            .map(|s| s["id"].as_str().unwrap().to_string())
+            .collect();
+        for id in &ids {
+            assert!(!seen.contains(id), "{} returned on more than one page", id);
+        }
+        seen.extend(ids);
+
+        cursor = page["next_cursor"].as_str().map(|s| s.to_string());
+        if page_number < 2 {
+            assert!(cursor.is_some());
+        }
+    }
+
+    assert!(cursor.is_none());
+    assert_eq!(seen.len(), 7);
+    // Most recent first
+    assert_eq!(seen[0], "session-6");
+    assert_eq!(seen[6], "session-0");
+}
+
+#[test]
+fn test_list_sessions_invalid_cursor() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-home-user-paged", "session-0", "/home/user/paged",
+        &[("user", "paged work", "2025-01-01T10:00:00Z")]);
+
+    let mut client = McpTestClient::with_home(home.path());
+    let text = client.call_tool("list_sessions", serde_json::json!({"cursor": "not a cursor!"}));
+    assert!(text.contains("Invalid cursor"));
+}
+
+#[test]
+fn test_get_session_messages_tool() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-home-user-msgs", "msgs-1", "/home/user/msgs", &[
+        ("user", "message 0", "2025-01-01T10:00:00Z"),
+        ("assistant", "message 1", "2025-01-01T10:00:01Z"),
+        ("user", "message 2", "2025-01-01T10:00:02Z"),
+        ("assistant", "message 3", "2025-01-01T10:00:03Z"),
+        ("user", "message 4", "2025-01-01T10:00:04Z"),
+    ]);
+
+    let mut client = McpTestClient::with_home(home.path());
+
+    let text = client.call_tool("get_session_messages",
+        serde_json::json!({"session_id": "msgs-1", "from_index": 1, "to_index": 4}));
+    let slice: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(slice["total_messages"], 5);
+    assert_eq!(slice["returned_messages"], 3);
+    assert_eq!(slice["messages"][0]["content"], "message 1");
+    assert_eq!(slice["messages"][2]["content"], "message 3");
+
+    let text = client.call_tool("get_session_messages",
+        serde_json::json!({"session_id": "msgs-1", "role": "user"}));
+    let slice: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(slice["returned_messages"], 3);
+
+    let text = client.call_tool("get_session_messages",
+        serde_json::json!({"session_id": "msgs-1", "role": "system"}));
+    let slice: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(slice["returned_messages"], 0);
+
+    let text = client.call_tool("get_session_messages",
+        serde_json::json!({"session_id": "msgs-1", "from_index": 3, "to_index": 1}));
+    assert!(text.contains("must not be greater"));
+}
+
+#[test]
+fn test_search_sessions_regex_mode() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-home-user-dates", "with-dates", "/home/user/dates", &[
+        ("user", "the release went out on 2024-03-15 and again on 2024-04-01", "2025-01-01T10:00:00Z"),
+    ]);
+    write_session(home.path(), "-home-user-dates", "one-date", "/home/user/dates", &[
+        ("user", "the deadline is 2025-06-30", "2025-01-02T10:00:00Z"),
+    ]);
+    write_session(home.path(), "-home-user-dates", "no-dates", "/home/user/dates", &[
+        ("user", "refactor the parser, version 2024 of the spec", "2025-01-03T10:00:00Z"),
+    ]);
+
+    let mut client = McpTestClient::with_home(home.path());
+    let text = client.call_tool("search_sessions",
+        serde_json::json!({"query": r"\d{4}-\d{2}-\d{2}", "search_mode": "regex"}));
+    let results = search_items(&text);
+
+    let ids: Vec<&str> = results.iter().map(|r| r["id"].as_str().unwrap()).collect();
+    // Sorted by match count: two dates before one
+    assert_eq!(ids, vec!["with-dates", "one-date"]);
+}
+
+#[test]
+fn test_search_sessions_invalid_regex() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-home-user-dates", "s1", "/home/user/dates",
+        &[("user", "hello", "2025-01-01T10:00:00Z")]);
+
+    let mut client = McpTestClient::with_home(home.path());
+    let request = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "tools/call",
+        "params": {
+            "name": "search_sessions",
+            "arguments": {"query": "(unclosed", "search_mode": "regex"}
+        }
+    });
+    let response = client.send_request(&request);
+
+    assert!(response["result"]["isError"] == true);
+    let text = response["result"]["content"][0]["text"].as_str().unwrap();
+    assert!(text.contains("Invalid regex"));
+}
+
+#[test]
+fn test_get_session_token_estimate() {
+    let home = tempfile::tempdir().unwrap();
+    let long_reply = "word ".repeat(100_000);
+    write_session(home.path(), "-proj", "chatty", "/proj", &[
+        ("user", "Explain the build", "2025-01-01T10:00:00Z"),
+        ("assistant", &long_reply, "2025-01-01T10:00:05Z"),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    let text = client.call_tool("get_session_token_estimate", serde_json::json!({"session_id": "chatty"}));
+    let estimate: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(estimate["estimated_tokens_user_only"], 5);
+    assert_eq!(estimate["estimated_tokens_assistant_only"], 125_000);
+    assert_eq!(estimate["estimated_tokens"], 125_005);
+    assert!(estimate["warning"].as_str().unwrap().contains("get_session_context"));
+
+    let text = client.call_tool("get_session_token_estimate",
+        serde_json::json!({"session_id": "chatty", "role_filter": "user"}));
+    let estimate: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(estimate["estimated_tokens"], 5);
+    assert!(estimate.get("warning").is_none());
+}
+
+#[test]
+fn test_get_session_cost() {
+    let home = tempfile::tempdir().unwrap();
+    let entry = |role: &str, content: &str| serde_json::json!({
+        "type": role, "sessionId": "paid", "timestamp": "2025-01-01T10:00:00Z",
+        "message": {"role": role, "content": content}
+    });
+    write_session_lines(home.path(), "-proj", "paid", &[
+        entry("user", "first question"),
+        entry("assistant", "first answer"),
+        serde_json::json!({"type": "result", "sessionId": "paid", "costUSD": 0.5, "durationMs": 1200}),
+        entry("user", "second question"),
+        entry("assistant", "second answer"),
+        serde_json::json!({"type": "result", "sessionId": "paid", "costUSD": 0.25, "durationMs": 800}),
+    ]);
+    write_session(home.path(), "-proj", "free", "/proj", &[
+        ("user", "no result lines here", "2025-01-02T10:00:00Z"),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    let text = client.call_tool("get_session_cost", serde_json::json!({"session_id": "paid"}));
+    let cost: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(cost["session_id"], "paid");
+    assert_eq!(cost["cost_usd"], 0.75);
+    assert_eq!(cost["duration_ms"], 2000);
+    assert_eq!(cost["cost_per_message"], 0.1875);
+
+    let text = client.call_tool("get_session_cost", serde_json::json!({"session_id": "free"}));
+    let cost: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert!(cost["cost_usd"].is_null());
+    assert!(cost["cost_per_message"].is_null());
+
+    let text = client.call_tool("get_sessions_statistics", serde_json::json!({}));
+    let stats: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(stats["total_cost_usd"], 0.75);
+    assert_eq!(stats["avg_cost_usd"], 0.75);
+}
+
+#[test]
+fn test_get_command_history() {
+    let home = tempfile::tempdir().unwrap();
+    let bash = |id: &str, timestamp: &str, commands: &[&str]| {
+        let calls: Vec<serde_json::Value> = commands
+            .iter()
+            .map(|c| serde_json::json!({"type": "tool_use", "name": "Bash", "input": {"command": c}}))
+            .collect();
+        serde_json::json!({
+            "type": "assistant", "sessionId": id, "timestamp": timestamp,
+            "message": {"role": "assistant", "content": calls}
+        })
+    };
+    write_session_lines(home.path(), "-home-user-api", "api", &[
+        bash("api", "2025-01-01T10:00:00Z", &["cargo build", "cargo test"]),
+        bash("api", "2025-01-01T11:00:00Z", &["cargo test"]),
+    ]);
+    write_session_lines(home.path(), "-home-user-web", "web", &[
+        bash("web", "2025-01-02T10:00:00Z", &["npm test", "cargo test"]),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+    let call = |client: &mut McpTestClient, arguments: serde_json::Value| -> Vec<serde_json::Value> {
+        serde_json::from_str(&client.call_tool("get_command_history", arguments)).unwrap()
+    };
+
+    let unique = call(&mut client, serde_json::json!({}));
+    assert_eq!(unique.len(), 3);
+    assert_eq!(unique[0]["command"], "cargo test");
+    assert_eq!(unique[0]["execution_count"], 3);
+    assert_eq!(unique[0]["last_seen_in_session"], "web");
+
+    let runs = call(&mut client, serde_json::json!({"unique": false, "limit": 2}));
+    assert_eq!(runs.len(), 2);
+    assert_eq!(runs[0]["session_id"], "web");
+    assert_eq!(runs[0]["timestamp"], "2025-01-02T10:00:00Z");
+    assert_eq!(runs[1]["session_id"], "web");
+
+    let api = call(&mut client, serde_json::json!({"project_filter": "/home/user/api"}));
+    let commands: Vec<&str> = api.iter().map(|c| c["command"].as_str().unwrap()).collect();
+    assert_eq!(commands, vec!["cargo test", "cargo build"]);
+    assert_eq!(api[0]["last_seen_in_session"], "api");
+}
+
+#[test]
+fn test_todo_tools() {
+    let home = tempfile::tempdir().unwrap();
+    let entry = |id: &str, role: &str, timestamp: &str, content: &str| {
+        serde_json::json!({
+            "type": role, "sessionId": id, "timestamp": timestamp,
+            "message": {"role": role, "content": content}
+        })
+    };
+    write_session_lines(home.path(), "-home-user-api", "api", &[
+        entry("api", "user", "2025-01-01T10:00:00Z", "add retries"),
+        entry("api", "assistant", "2025-01-01T10:00:05Z", "```rust\n// TODO: back off exponentially\n```\nFIXME: the timeout is hardcoded"),
+    ]);
+    write_session_lines(home.path(), "-home-user-web", "web", &[
+        entry("web", "assistant", "2025-01-02T10:00:00Z", "HACK: inline the styles for now"),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    let todos: Vec<serde_json::Value> =
+        serde_json::from_str(&client.call_tool("extract_todos", serde_json::json!({"session_id": "api"}))).unwrap();
+    assert_eq!(todos.len(), 2);
+    assert_eq!(todos[0]["text"], "TODO: back off exponentially");
+    assert_eq!(todos[0]["pattern"], "TODO");
+    assert_eq!(todos[0]["role"], "assistant");
+    assert_eq!(todos[0]["message_index"], 1);
+    assert_eq!(todos[0]["timestamp"], "2025-01-01T10:00:05Z");
+
+    let todos: Vec<serde_json::Value> = serde_json::from_str(&client.call_tool(
+        "extract_todos",
+        serde_json::json!({"session_id": "api", "patterns": ["FIXME"]}),
+    ))
+    .unwrap();
+    assert_eq!(todos.len(), 1);
+    assert_eq!(todos[0]["text"], "FIXME: the timeout is hardcoded");
+
+    let found: Vec<serde_json::Value> =
+        serde_json::from_str(&client.call_tool("find_todos_across_sessions", serde_json::json!({}))).unwrap();
+    let sessions: Vec<&str> = found.iter().map(|t| t["session_id"].as_str().unwrap()).collect();
+    assert_eq!(sessions, vec!["web", "api", "api"]);
+
+    let found: Vec<serde_json::Value> = serde_json::from_str(&client.call_tool(
+        "find_todos_across_sessions",
+        serde_json::json!({"project_filter": "/home/user/api", "limit": 1}),
+    ))
+    .unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0]["session_id"], "api");
+
+    let response = client.send_request(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "tools/call",
+        "params": {"name": "find_todos_across_sessions", "arguments": {"patterns": []}}
+    }));
+    assert_eq!(response["result"]["isError"], true);
+}
+
+#[test]
+fn test_list_session_files() {
+    let home = tempfile::tempdir().unwrap();
+    write_session_lines(home.path(), "-home-user-project", "files", &[
+        serde_json::json!({
+            "type": "user", "sessionId": "files", "timestamp": "2025-01-01T10:00:00Z",
+            "message": {"role": "user", "content": "rewrite the config loader"}
+        }),
+        serde_json::json!({
+            "type": "assistant", "sessionId": "files", "timestamp": "2025-01-01T10:00:01Z",
+            "message": {"role": "assistant", "content": [
+                {"type": "tool_use", "name": "Read", "input": {"file_path": "/app/config.rs"}},
+                {"type": "tool_use", "name": "Write", "input": {"file_path": "/app/config.rs", "content": "v1"}}
+            ]}
+        }),
+        serde_json::json!({
+            "type": "assistant", "sessionId": "files", "timestamp": "2025-01-01T10:00:05Z",
+            "message": {"role": "assistant", "content": [
+                {"type": "tool_use", "name": "Write", "input": {"file_path": "/app/config.rs", "content": "v2"}},
+                {"type": "tool_use", "name": "Edit", "input": {"file_path": "/app/main.rs"}}
+            ]}
+        }),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+    let files = |text: String| -> Vec<(String, String)> {
+        let result: serde_json::Value = serde_json::from_str(&text).unwrap();
+        result["files"].as_array().unwrap().iter()
+            .map(|f| (f["file_path"].as_str().unwrap().to_string(), f["operation"].as_str().unwrap().to_string()))
+            .collect()
+    };
+    let pair = |path: &str, op: &str| (path.to_string(), op.to_string());
+
+    let unique = files(client.call_tool("list_session_files", serde_json::json!({"session_id": "files"})));
+    assert_eq!(unique, vec![pair("/app/config.rs", "Read"), pair("/app/main.rs", "Edit")]);
+
+    let writes = files(client.call_tool("list_session_files",
+        serde_json::json!({"session_id": "files", "operations": ["write"], "unique": false})));
+    assert_eq!(writes, vec![pair("/app/config.rs", "Write"), pair("/app/config.rs", "Write")]);
+
+    let text = client.call_tool("list_session_files", serde_json::json!({"session_id": "missing"}));
+    assert!(text.contains("Session not found"));
+}
+
+#[test]
+fn test_find_sessions_touching_file() {
+    let home = tempfile::tempdir().unwrap();
+    write_session_lines(home.path(), "-home-user-project", "tool-read", &[
+        serde_json::json!({
+            "type": "user", "sessionId": "tool-read", "timestamp": "2025-01-01T10:00:00Z",
+            "message": {"role": "user", "content": "look at the entry point"}
+        }),
+        serde_json::json!({
+            "type": "assistant", "sessionId": "tool-read", "timestamp": "2025-01-01T10:00:01Z",
+            "message": {"role": "assistant", "content": [
+                {"type": "tool_use", "name": "Read", "input": {"file_path": "/home/user/project/src/main.rs"}}
+            ]}
+        }),
+    ]);
+    write_session(home.path(), "-home-user-project", "text-mention", "/home/user/project",
+        &[("user", "please fix src/main.rs today", "2025-01-02T10:00:00Z")]);
+    write_session(home.path(), "-home-user-project", "other-file", "/home/user/project",
+        &[("user", "please fix src/domain.rs today", "2025-01-03T10:00:00Z")]);
+
+    let mut client = McpTestClient::with_home(home.path());
+    let ids = |text: String| -> Vec<String> {
+        let results: Vec<serde_json::Value> = serde_json::from_str(&text).unwrap();
+        results.iter().map(|r| r["id"].as_str().unwrap().to_string()).collect()
+    };
+
+    // Suffix (default) matches whole trailing components only
+    let found = ids(client.call_tool("find_sessions_touching_file",
+        serde_json::json!({"file_path": "main.rs"})));
+    assert_eq!(found, vec!["text-mention", "tool-read"]);
+
+    let found = ids(client.call_tool("find_sessions_touching_file",
+        serde_json::json!({"file_path": "/home/user/project/src/main.rs", "match_mode": "exact"})));
+    assert_eq!(found, vec!["tool-read"]);
+
+    let found = ids(client.call_tool("find_sessions_touching_file",
+        serde_json::json!({"file_path": "ain.rs", "match_mode": "contains"})));
+    assert_eq!(found, vec!["other-file", "text-mention", "tool-read"]);
+}
+
+#[test]
+fn test_session_tags() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-home-user-tags", "auth-fix", "/home/user/tags",
+        &[("user", "fix the auth bug", "2025-01-01T10:00:00Z")]);
+    write_session(home.path(), "-home-user-tags", "docs", "/home/user/tags",
+        &[("user", "write the docs", "2025-01-02T10:00:00Z")]);
+
+    {
+        let mut client = McpTestClient::with_home(home.path());
+        client.call_tool("tag_session", serde_json::json!({"session_id": "auth-fix", "tag": "bugfix"}));
+        // A prefix finds the session, and the tag is kept under its full ID
+        let text = client.call_tool("tag_session", serde_json::json!({"session_id": "auth-f", "tag": "auth"}));
+        let tagged: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(tagged["session_id"], "auth-fix");
+        client.call_tool("tag_session", serde_json::json!({"session_id": "docs", "tag": "docs"}));
+
+        let text = client.call_tool("tag_session",
+            serde_json::json!({"session_id": "missing", "tag": "x"}));
+        assert!(text.contains("Session not found"));
+    }
+
+    // Tags survive a server restart
+    let mut client = McpTestClient::with_home(home.path());
+    let tags: Vec<String> = serde_json::from_str(&client.call_tool("list_tags", serde_json::json!({}))).unwrap();
+    assert_eq!(tags, vec!["auth", "bugfix", "docs"]);
+
+    let text = client.call_tool("find_sessions_by_tag", serde_json::json!({"tag": "bugfix"}));
+    let sessions: Vec<serde_json::Value> = serde_json::from_str(&text).unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0]["id"], "auth-fix");
+    assert_eq!(sessions[0]["tags"], serde_json::json!(["auth", "bugfix"]));
+
+    client.call_tool("untag_session", serde_json::json!({"session_id": "auth-fix", "tag": "bugfix"}));
+    let text = client.call_tool("list_sessions", serde_json::json!({}));
+    let page: serde_json::Value = serde_json::from_str(&text).unwrap();
+    let auth = page["items"].as_array().unwrap().iter().find(|s| s["id"] == "auth-fix").unwrap();
+    assert_eq!(auth["tags"], serde_json::json!(["auth"]));
+}
+
+#[test]
+fn test_session_notes() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-home-user-notes", "auth-fix", "/home/user/notes",
+        &[("user", "fix the auth bug", "2025-01-01T10:00:00Z")]);
+
+    let note_id = {
+        let mut client = McpTestClient::with_home(home.path());
+        let text = client.call_tool("add_session_note",
+            serde_json::json!({"session_id": "auth-fix", "note": "This is where the auth bug got fixed"}));
+        let note: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(note["text"], "This is where the auth bug got fixed");
+
+        // Kept under the full ID of the session a prefix finds
+        let text = client.call_tool("add_session_note",
+            serde_json::json!({"session_id": "auth-f", "note": "Found by prefix"}));
+        let by_prefix: serde_json::Value = serde_json::from_str(&text).unwrap();
+        let text = client.call_tool("delete_session_note",
+            serde_json::json!({"session_id": "auth-fix", "note_id": by_prefix["id"]}));
+        assert!(!text.contains("not found"), "{}", text);
+
+        let text = client.call_tool("add_session_note",
+            serde_json::json!({"session_id": "auth-fix", "note": "x".repeat(2001)}));
+        assert!(text.contains("longer than 2000 characters"));
+        let text = client.call_tool("add_session_note",
+            serde_json::json!({"session_id": "missing", "note": "x"}));
+        assert!(text.contains("Session not found"));
+
+        note["id"].as_str().unwrap().to_string()
+    };
+
+    // Notes survive a server restart
+    let mut client = McpTestClient::with_home(home.path());
+    let text = client.call_tool("get_session_notes", serde_json::json!({"session_id": "auth-fix"}));
+    let notes: Vec<serde_json::Value> = serde_json::from_str(&text).unwrap();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes[0]["id"], note_id);
+
+    client.call_tool("delete_session_note", serde_json::json!({"session_id": "auth-fix", "note_id": note_id}));
+    let text = client.call_tool("get_session_notes", serde_json::json!({"session_id": "auth-fix"}));
+    assert_eq!(serde_json::from_str::<Vec<serde_json::Value>>(&text).unwrap().len(), 0);
+
+    let text = client.call_tool("delete_session_note", serde_json::json!({"session_id": "auth-fix", "note_id": note_id}));
+    assert!(text.contains("not found"));
+    let text = client.call_tool("get_session_notes", serde_json::json!({"session_id": "missing"}));
+    assert!(text.contains("Session not found"), "{}", text);
+}
+
+#[test]
+fn test_session_notes_by_prefix() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-home-user-notes", "auth-fix", "/home/user/notes",
+        &[("user", "fix the auth bug", "2025-01-01T10:00:00Z")]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    let text = client.call_tool("add_session_note", serde_json::json!({"session_id": "auth", "note": "Found by prefix"}));
+    let note: serde_json::Value = serde_json::from_str(&text).unwrap();
+
+    let text = client.call_tool("get_session_notes", serde_json::json!({"session_id": "auth"}));
+    let notes: Vec<serde_json::Value> = serde_json::from_str(&text).unwrap();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes[0]["id"], note["id"]);
+
+    let text = client.call_tool("delete_session_note", serde_json::json!({"session_id": "auth", "note_id": note["id"]}));
+    let deleted: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(deleted["session_id"], "auth-fix");
+    let text = client.call_tool("get_session_notes", serde_json::json!({"session_id": "auth-fix"}));
+    assert!(serde_json::from_str::<Vec<serde_json::Value>>(&text).unwrap().is_empty());
+}
+
+#[test]
+fn test_resources_list_and_read() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-home-user-res", "res-1", "/home/user/res",
+        &[("user", "resource one", "2025-01-01T10:00:00Z")]);
+    write_session(home.path(), "-home-user-res", "res-2", "/home/user/res",
+        &[("user", "resource two", "2025-01-02T10:00:00Z")]);
+
+    let mut client = McpTestClient::with_home(home.path());
+    let response = client.send_request(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "resources/list"
+    }));
+
+    let resources = response["result"]["resources"].as_array().unwrap();
+    assert_eq!(resources.len(), 2);
+    assert_eq!(resources[0]["uri"], "claude-session://res-2");
+    assert_eq!(resources[0]["mimeType"], "application/jsonl");
+    assert_eq!(resources[0]["description"], "resource two");
+    assert!(response["result"]["nextCursor"].is_null());
+
+    let response = client.send_request(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "resources/read",
+        "params": {"uri": "claude-session://res-1"}
+    }));
+    let contents = response["result"]["contents"].as_array().unwrap();
+    assert_eq!(contents[0]["uri"], "claude-session://res-1");
+    let text = contents[0]["text"].as_str().unwrap();
+    assert!(text.contains("resource one"));
+    assert!(text.lines().all(|l| serde_json::from_str::<serde_json::Value>(l).is_ok()));
+
+    let response = client.send_request(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 3,
+        "method": "resources/read",
+        "params": {"uri": "claude-session://missing"}
+    }));
+    assert_eq!(response["error"]["code"], -32002);
+}
+
+#[test]
+fn test_find_sessions_by_git_repo() {
+    let home = tempfile::tempdir().unwrap();
+    let checkout = tempfile::tempdir().unwrap();
+    let entry = |id: &str, cwd: &str, extra: serde_json::Value| {
+        let mut entry = serde_json::json!({
+            "type": "user",
+            "sessionId": id,
+            "cwd": cwd,
+            "timestamp": "2025-01-01T10:00:00Z",
+            "message": {"role": "user", "content": format!("Work in {}", id)}
+        });
+        entry.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        entry
+    };
+    write_session_lines(home.path(), "-home-user-widgets", "on-main", &[entry(
+        "on-main",
+        "/home/user/widgets",
+        serde_json::json!({"gitRepo": "https://github.com/Acme/Widgets.git", "gitBranch": "main"}),
+    )]);
+    // Older entries may lack the fields, and some writers use snake_case
+    write_session_lines(home.path(), "-home-user-widgets", "on-feature", &[
+        entry("on-feature", "/home/user/widgets", serde_json::json!({})),
+        entry("on-feature", "/home/user/widgets", serde_json::json!({"git_repo": "acme/widgets", "git_branch": "feature"})),
+    ]);
+    let checkout_dir = checkout.path().to_str().unwrap();
+    write_session_lines(home.path(), "-tmp-checkout", "unrecorded", &[entry("unrecorded", checkout_dir, serde_json::json!({}))]);
+    let mut client = McpTestClient::with_home(home.path());
+    let find = |client: &mut McpTestClient, arguments: serde_json::Value| -> Vec<String> {
+        let sessions: Vec<serde_json::Value> =
+            serde_json::from_str(&client.call_tool("find_sessions_by_git_repo", arguments)).unwrap();
+        let mut ids: Vec<String> = sessions.iter().map(|s| s["id"].as_str().unwrap().to_string()).collect();
+        ids.sort();
+        ids
+    };
+
+    assert_eq!(find(&mut client, serde_json::json!({"repo": "WIDGETS"})), vec!["on-feature", "on-main"]);
+    assert_eq!(find(&mut client, serde_json::json!({"repo": "github.com/acme"})), vec!["on-main"]);
+    assert_eq!(find(&mut client, serde_json::json!({"repo": "widgets", "branch": "feature"})), vec!["on-feature"]);
+    assert!(find(&mut client, serde_json::json!({"repo": "gadgets"})).is_empty());
+
+    let sessions: Vec<serde_json::Value> =
+        serde_json::from_str(&client.call_tool("find_sessions_by_git_repo", serde_json::json!({"repo": "acme", "branch": "main"})))
+            .unwrap();
+    assert_eq!(sessions[0]["git_repo"], "https://github.com/Acme/Widgets.git");
+    assert_eq!(sessions[0]["git_branch"], "main");
+    let context: serde_json::Value =
+        serde_json::from_str(&client.call_tool("get_session_context", serde_json::json!({"session_id": "on-feature"}))).unwrap();
+    assert_eq!(context["git_repo"], "acme/widgets");
+    assert_eq!(context["git_branch"], "feature");
+
+    // A session without the fields only matches through its cwd's checkout
+    let name = checkout.path().file_name().unwrap().to_str().unwrap().to_string();
+    assert!(find(&mut client, serde_json::json!({"repo": name})).is_empty());
+    let status = std::process::Command::new("git")
+        .args(["init", "-q", "-b", "trunk"])
+        .current_dir(checkout.path())
+        .status()
+        .expect("This test needs git on the PATH");
+    assert!(status.success(), "git init failed");
+    assert_eq!(find(&mut client, serde_json::json!({"repo": name, "resolve_git": true})), vec!["unrecorded"]);
+    assert_eq!(find(&mut client, serde_json::json!({"repo": name, "branch": "trunk", "resolve_git": true})), vec!["unrecorded"]);
+}
+
+#[test]
+fn test_find_sessions_without_assistant_response() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "answered", "/proj", &[
+        ("user", "fix it", "2025-01-01T10:00:00Z"),
+        ("assistant", "fixed", "2025-01-01T10:00:05Z"),
+    ]);
+    write_session(home.path(), "-proj", "unanswered", "/proj", &[("user", "hello?", "2025-01-02T10:00:00Z")]);
+    write_session(home.path(), "-proj", "dropped", "/proj", &[
+        ("user", "fix it", "2025-01-03T10:00:00Z"),
+        ("assistant", "on it", "2025-01-03T10:00:05Z"),
+        ("user", "and the tests", "2025-01-03T10:01:00Z"),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+    let mut find = |arguments: serde_json::Value| -> Vec<serde_json::Value> {
+        serde_json::from_str(&client.call_tool("find_sessions_without_assistant_response", arguments)).unwrap()
+    };
+
+    let found = find(serde_json::json!({}));
+    let ids: Vec<&str> = found.iter().map(|s| s["id"].as_str().unwrap()).collect();
+    assert_eq!(ids, vec!["dropped", "unanswered"]);
+    assert!(found.iter().all(|s| s["last_message_role"] == "user"));
+
+    let found = find(serde_json::json!({"abandoned_only": true}));
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0]["id"], "dropped");
+
+    assert_eq!(find(serde_json::json!({"limit": 1})).len(), 1);
+}
+
+#[test]
+fn test_session_resource_views() {
+    let home = tempfile::tempdir().unwrap();
+    write_session_lines(home.path(), "-home-user-res", "views", &[
+        serde_json::json!({
+            "type": "user", "sessionId": "views", "timestamp": "2025-01-01T10:00:00Z",
+            "message": {"role": "user", "content": "run the tests in src/lib.rs"}
+        }),
+        serde_json::json!({
+            "type": "assistant", "sessionId": "views", "timestamp": "2025-01-01T10:00:05Z",
+            "message": {"role": "assistant", "content": [
+                {"type": "tool_use", "name": "Bash", "input": {"command": "cargo test"}}
+            ]}
+        }),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+    let mut read = |uri: &str| {
+        client.send_request(&serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "resources/read",
+            "params": {"uri": uri}
+        }))
+    };
+
+    let response = read("claude-session://views/context");
+    let content = &response["result"]["contents"][0];
+    assert_eq!(content["uri"], "claude-session://views/context");
+    assert_eq!(content["mimeType"], "application/json");
+    let context: serde_json::Value = serde_json::from_str(content["text"].as_str().unwrap()).unwrap();
+    assert_eq!(context["initial_request"], "run the tests in src/lib.rs");
+    assert_eq!(context["commands"], serde_json::json!(["cargo test"]));
+
+    let response = read("claude-session://views/commands");
+    let commands: serde_json::Value =
+        serde_json::from_str(response["result"]["contents"][0]["text"].as_str().unwrap()).unwrap();
+    assert_eq!(commands["session_id"], "views");
+    assert_eq!(commands["commands"][0]["command"], "cargo test");
+
+    assert_eq!(read("claude-session://missing/context")["error"]["code"], -32002);
+    assert_eq!(read("claude-session://views/files")["error"]["code"], -32602);
+
+    // Only session files send updates
+    let response = client.send_request(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "resources/subscribe",
+        "params": {"uri": "claude-session://views/context"}
+    }));
+    assert_eq!(response["error"]["code"], -32602);
+
+    let response = client.send_request(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 3,
+        "method": "tools/call",
+        "params": {"name": "get_session", "arguments": {"session_id": "views", "embed_resource": true}}
+    }));
+    let content = response["result"]["content"].as_array().unwrap();
+    assert_eq!(content.len(), 2);
+    assert_eq!(content[0]["type"], "text");
+    assert_eq!(content[1]["type"], "resource");
+    assert_eq!(content[1]["resource"]["uri"], "claude-session://views");
+    assert_eq!(content[1]["resource"]["mimeType"], "application/jsonl");
+    assert_eq!(content[1]["resource"]["text"].as_str().unwrap().lines().count(), 2);
+}
+
+#[test]
+fn test_new_session_sends_list_changed() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "existing", "/proj", &[("user", "hello", "2024-01-01T00:00:00Z")]);
+    let mut client = StreamingTestClient::with_home(home.path());
+
+    client.send(&serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}));
+    let timeout = std::time::Duration::from_secs(10);
+    let response = client.recv(timeout).expect("No initialize response");
+    assert_eq!(response["result"]["capabilities"]["tools"]["listChanged"], true);
+    assert_eq!(response["result"]["capabilities"]["resources"]["subscribe"], true);
+
+    write_session(home.path(), "-proj", "fresh", "/proj", &[("user", "new", "2024-01-02T00:00:00Z")]);
+
+    let notification = client.recv(timeout).expect("No list_changed notification");
+    assert_eq!(notification["method"], "notifications/tools/list_changed");
+    assert!(notification.get("id").is_none());
+}
+
+#[test]
+fn test_list_sessions_sees_new_session() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "existing", "/proj", &[("user", "hello", "2024-01-01T00:00:00Z")]);
+    let mut client = StreamingTestClient::with_home(home.path());
+
+    let timeout = std::time::Duration::from_secs(10);
+    let list_sessions = |client: &mut StreamingTestClient, id: u64| {
+        client.send(&serde_json::json!({
+            "jsonrpc": "2.0", "id": id, "method": "tools/call",
+            "params": {"name": "list_sessions", "arguments": {}}
+        }));
+        let response = client.recv(timeout).expect("No list_sessions response");
+        let text = response["result"]["content"][0]["text"].as_str().unwrap();
+        let page = serde_json::from_str::<serde_json::Value>(text).unwrap();
+        let ids: Vec<String> = page["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| s["id"].as_str().unwrap().to_string())
+            .collect();
+        (ids, page["_meta"]["cache_age_seconds"].as_f64().unwrap())
+    };
+
+    let (ids, first_age) = list_sessions(&mut client, 1);
+    assert_eq!(ids, vec!["existing"]);
+
+    // Served from the same cached load until the watcher reports a change,
+    // so the cache only gets older
+    let (ids, second_age) = list_sessions(&mut client, 2);
+    assert_eq!(ids, vec!["existing"]);
+    assert!(second_age >= first_age, "{} < {}", second_age, first_age);
+
+    write_session(home.path(), "-proj", "fresh", "/proj", &[("user", "new", "2024-01-02T00:00:00Z")]);
+    let notification = client.recv(timeout).expect("No list_changed notification");
+    assert_eq!(notification["method"], "notifications/tools/list_changed");
+
+    let (ids, _) = list_sessions(&mut client, 3);
+    assert_eq!(ids, vec!["fresh", "existing"]);
+}
+
+#[test]
+fn test_project_filter_sees_new_project() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "existing", "/proj", &[("user", "hello", "2024-01-01T00:00:00Z")]);
+    let mut client = StreamingTestClient::with_home(home.path());
+
+    let timeout = std::time::Duration::from_secs(10);
+    let sessions_in_srv = |client: &mut StreamingTestClient, id: u64| {
+        client.send(&serde_json::json!({
+            "jsonrpc": "2.0", "id": id, "method": "tools/call",
+            "params": {"name": "get_sessions_by_hour", "arguments": {"project_filter": "/srv"}}
+        }));
+        // Past any notifications about the new session
+        let response = std::iter::from_fn(|| client.recv(timeout))
+            .find(|message| message["id"] == id)
+            .expect("No get_sessions_by_hour response");
+        let text = response["result"]["content"][0]["text"].as_str().unwrap();
+        let usage: serde_json::Value = serde_json::from_str(text).unwrap();
+        usage["hours"].as_array().unwrap().iter().map(|h| h["session_count"].as_u64().unwrap()).sum::<u64>()
+    };
+
+    // Builds the project index, which must not miss projects created later
+    assert_eq!(sessions_in_srv(&mut client, 1), 0);
+    write_session(home.path(), "-srv", "served", "/srv", &[("user", "deploy", "2024-01-02T00:00:00Z")]);
+    // Seen once the watcher reports the new project; ask until it has
+    let found = (2..50).any(|id| {
+        let count = sessions_in_srv(&mut client, id);
+        count == 1 || {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            false
+        }
+    });
+    assert!(found, "The new project never showed up");
+}
+
+#[test]
+fn test_batch_requests() {
+    let mut client = McpTestClient::new();
+
+    let batch = serde_json::json!([
+        {"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}},
+        {"jsonrpc": "2.0", "method": "notifications/initialized"},
+        {"jsonrpc": "2.0", "id": "two", "method": "tools/list"},
+        {"jsonrpc": "2.0", "id": 3, "method": "tools/call", "params": {"name": "list_sessions", "arguments": {"limit": 1}}}
+    ]);
+    let response = client.send_request(&batch);
+
+    // The notification gets no entry in the reply
+    let responses = response.as_array().expect("Batch reply should be an array");
+    assert_eq!(responses.len(), 3);
+    let ids: Vec<_> = responses.iter().map(|r| r["id"].clone()).collect();
+    assert_eq!(ids, vec![serde_json::json!(1), serde_json::json!("two"), serde_json::json!(3)]);
+    assert_eq!(responses[0]["result"]["serverInfo"]["name"], "claude-code-mcp");
+    assert!(responses[1]["result"]["tools"].is_array());
+    assert!(responses[2]["result"]["content"].is_array());
+}
+
+#[test]
+fn test_empty_batch() {
+    let mut client = McpTestClient::new();
+
+    let response = client.send_request(&serde_json::json!([]));
+
+    assert!(response.is_object());
+    assert_eq!(response["error"]["code"], -32600);
+    assert!(response["id"].is_null());
+}
+
+#[test]
+fn test_prompts_list_and_get() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "auth-work", "/proj", &[
+        ("user", "Fix the login bug in src/auth.rs", "2024-01-01T00:00:00Z"),
+        ("assistant", "Patched the token check", "2024-01-01T00:01:00Z"),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    let response = client.send_request(&serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": "prompts/list"}));
+    let names: Vec<&str> = response["result"]["prompts"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| p["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, vec!["recent-work-summary", "find-related-sessions", "code-archaeology"]);
+
+    let response = client.send_request(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "prompts/get",
+        "params": {"name": "code-archaeology", "arguments": {"file_path": "auth.rs"}}
+    }));
+    let message = &response["result"]["messages"][0];
+    assert_eq!(message["role"], "user");
+    assert_eq!(message["content"]["type"], "text");
+    let text = message["content"]["text"].as_str().unwrap();
+    assert!(text.contains("auth-work"));
+    assert!(text.contains("Fix the login bug"));
+
+    let response = client.send_request(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 3,
+        "method": "prompts/get",
+        "params": {"name": "recent-work-summary", "arguments": {"count": "1"}}
+    }));
+    let text = response["result"]["messages"][0]["content"]["text"].as_str().unwrap();
+    assert!(text.contains("auth-work"));
+}
+
+#[test]
+fn test_prompts_get_errors() {
+    let home = tempfile::tempdir().unwrap();
+    let mut client = McpTestClient::with_home(home.path());
+
+    let response = client.send_request(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "prompts/get",
+        "params": {"name": "no-such-prompt"}
+    }));
+    assert_eq!(response["error"]["code"], -32602);
+
+    let response = client.send_request(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "prompts/get",
+        "params": {"name": "find-related-sessions", "arguments": {}}
+    }));
+    assert_eq!(response["error"]["code"], -32602);
+    assert!(response["error"]["message"].as_str().unwrap().contains("topic"));
+}
+
+#[test]
+fn test_get_sessions_statistics() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "s1", "/proj", &[
+        ("user", "hi", "2024-05-01T10:00:00Z"),
+        ("assistant", "hello", "2024-05-01T10:01:00Z"),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    let text = client.call_tool("get_sessions_statistics", serde_json::json!({}));
+    let stats: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(stats["total_sessions"], 1);
+    assert_eq!(stats["total_messages"], 2);
+    assert_eq!(stats["busiest_day"], "2024-05-01");
+    assert_eq!(stats["most_active_project"], "/proj");
+}
+
+#[test]
+fn test_validate_session() {
+    let home = tempfile::tempdir().unwrap();
+    write_session_lines(home.path(), "-proj", "broken", &[
+        serde_json::json!({"type": "user", "sessionId": "broken", "timestamp": "2024-01-01T00:00:00Z",
+            "message": {"role": "user", "content": "hi"}}),
+        serde_json::json!({"type": "user", "sessionId": "other", "timestamp": "2024-01-01T00:00:01Z"}),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    let text = client.call_tool("validate_session", serde_json::json!({"session_id": "broken"}));
+    let report: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(report["valid"], false);
+    assert_eq!(report["line_count"], 2);
+    assert_eq!(report["parseable_lines"], 2);
+    assert!(report["errors"][0].as_str().unwrap().contains("sessionId"));
+}
+
+#[test]
+fn test_search_with_index() {
+    let home = tempfile::tempdir().unwrap();
+    let index_dir = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "ws", "/proj", &[
+        ("user", "refactor the websocket reconnect logic", "2024-01-01T00:00:00Z"),
+    ]);
+    write_session(home.path(), "-proj", "ui", "/proj", &[
+        ("user", "add dark mode to settings", "2024-01-02T00:00:00Z"),
+    ]);
+
+    let mut command = Command::new(env!("CARGO_BIN_EXE_claude-code-mcp"));
+    command.env("HOME", home.path()).arg("--index-path").arg(index_dir.path());
+    let mut client = McpTestClient::spawn(command);
+
+    let text = client.call_tool("search_sessions", serde_json::json!({"query": "websocket", "highlight": true}));
+    let results = search_items(&text);
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0]["id"], "ws");
+    assert!(results[0]["match_context"].as_str().unwrap().contains("**websocket**"));
+
+    let text = client.call_tool("rebuild_index", serde_json::json!({}));
+    let rebuilt: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(rebuilt["indexed_sessions"], 2);
+    assert_eq!(rebuilt["sessions_found"], 2);
+}
+
+#[test]
+fn test_request_log_file() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "logged", "/proj", &[
+        ("user", "a private question", "2024-01-01T00:00:00Z"),
+    ]);
+    let log_file = home.path().join("audit.jsonl");
+    let mut command = Command::new(env!("CARGO_BIN_EXE_claude-code-mcp"));
+    command.env("HOME", home.path()).arg("--log-file").arg(&log_file);
+    let mut client = McpTestClient::spawn(command);
+
+    client.call_tool("list_sessions", serde_json::json!({}));
+    client.call_tool("get_session", serde_json::json!({"session_id": "logged"}));
+    client.call_tool("get_session", serde_json::json!({"session_id": "missing"}));
+
+    // Records are written in the background
+    let mut records: Vec<serde_json::Value> = Vec::new();
+    for _ in 0..50 {
+        let contents = std::fs::read_to_string(&log_file).unwrap_or_default();
+        records = contents.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        if records.len() >= 3 {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    assert_eq!(records.len(), 3);
+    assert!(records.iter().all(|r| r["method"] == "tools/call" && r["request_id"] == 1));
+    records.sort_by_key(|r| r["status"].as_str().unwrap().to_string());
+    assert_eq!(records[0]["status"], "error");
+    assert_eq!(records[0]["tool"], "get_session");
+    assert_eq!(records[1]["status"], "success");
+    assert!(records[0]["duration_ms"].is_u64());
+    // Neither arguments nor results are logged
+    let contents = std::fs::read_to_string(&log_file).unwrap();
+    assert!(!contents.contains("private") && !contents.contains("missing"));
+}
+
+#[test]
+fn test_rebuild_index_without_index() {
+    // Without --index-path only the session cache is rebuilt
+    let home = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(home.path().join(".claude")).unwrap();
+    let mut client = McpTestClient::with_home(home.path());
+
+    let text = client.call_tool("rebuild_index", serde_json::json!({}));
+    let rebuilt: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(rebuilt["sessions_found"], 0);
+    assert!(rebuilt.get("indexed_sessions").is_none());
+}
+
+#[test]
+fn test_get_related_sessions() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "target", "/proj", &[
+        ("user", "update src/auth.rs and src/db.rs", "2024-01-01T00:00:00Z"),
+    ]);
+    write_session(home.path(), "-proj", "close", "/proj", &[
+        ("user", "refactor src/auth.rs and src/db.rs", "2024-01-02T00:00:00Z"),
+    ]);
+    write_session(home.path(), "-proj", "partial", "/proj", &[
+        ("user", "tweak src/auth.rs and src/ui.rs", "2024-01-03T00:00:00Z"),
+    ]);
+    write_session(home.path(), "-proj", "unrelated", "/proj", &[
+        ("user", "write docs/intro.md", "2024-01-04T00:00:00Z"),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    let text = client.call_tool("get_related_sessions", serde_json::json!({"session_id": "target"}));
+    let related: Vec<serde_json::Value> = serde_json::from_str(&text).unwrap();
+    let ids: Vec<&str> = related.iter().map(|r| r["id"].as_str().unwrap()).collect();
+    assert_eq!(ids, vec!["close", "partial"]);
+    assert_eq!(related[0]["similarity_score"], 1.0);
+    // {auth, db} vs {auth, ui}: 1 shared of 3
+    assert!((related[1]["similarity_score"].as_f64().unwrap() - 1.0 / 3.0).abs() < 1e-9);
+
+    let text = client.call_tool("get_related_sessions", serde_json::json!({"session_id": "missing"}));
+    assert!(text.contains("Session not found"));
+}
+
+#[test]
+fn test_find_sessions_by_cwd() {
+    let home = tempfile::tempdir().unwrap();
+    let myapp = home.path().join("projects/myapp");
+    let myapp = myapp.to_str().unwrap();
+    write_session(home.path(), "-myapp", "app-root", myapp, &[("user", "a", "2024-01-01T00:00:00Z")]);
+    write_session(home.path(), "-myapp", "app-sub", &format!("{}/server", myapp),
+        &[("user", "b", "2024-01-02T00:00:00Z")]);
+    write_session(home.path(), "-myapp2", "other-app", &format!("{}2", myapp),
+        &[("user", "c", "2024-01-03T00:00:00Z")]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    let text = client.call_tool("find_sessions_by_cwd", serde_json::json!({"cwd_prefix": "~/projects/myapp"}));
+    let sessions: Vec<serde_json::Value> = serde_json::from_str(&text).unwrap();
+    let ids: Vec<&str> = sessions.iter().map(|s| s["id"].as_str().unwrap()).collect();
+    assert_eq!(ids, vec!["app-sub", "app-root"]);
+}
+
+#[test]
+fn test_extract_code_blocks() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "code", "/proj", &[
+        ("user", "```python\nprint(1)\n```", "2024-01-01T00:00:00Z"),
+        ("assistant", "```rust\nfn main() {}\n```\n```RUST\nstruct A;\n```", "2024-01-01T00:00:01Z"),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    let text = client.call_tool("extract_code_blocks", serde_json::json!({"session_id": "code"}));
+    let blocks: Vec<serde_json::Value> = serde_json::from_str(&text).unwrap();
+    assert_eq!(blocks.len(), 3);
+
+    let text = client.call_tool("extract_code_blocks", serde_json::json!({"session_id": "code", "language": "Rust"}));
+    let blocks: Vec<serde_json::Value> = serde_json::from_str(&text).unwrap();
+    let contents: Vec<&str> = blocks.iter().map(|b| b["content"].as_str().unwrap()).collect();
+    assert_eq!(contents, vec!["fn main() {}", "struct A;"]);
+    assert_eq!(blocks[0]["role"], "assistant");
+    assert_eq!(blocks[0]["message_index"], 1);
+}
+
+#[test]
+fn test_compare_sessions() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "a", "/proj", &[
+        ("user", "update src/auth.rs and src/db.rs", "2024-01-01T00:00:00Z"),
+        ("assistant", "done", "2024-01-01T00:01:00Z"),
+    ]);
+    write_session(home.path(), "-proj", "b", "/proj", &[
+        ("user", "refactor src/db.rs and src/api.rs", "2024-01-02T00:00:00Z"),
+        ("assistant", "done", "2024-01-02T00:05:00Z"),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    let text = client.call_tool("compare_sessions", serde_json::json!({"session_id_a": "a", "session_id_b": "b"}));
+    let diff: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(diff["only_in_a"]["files_mentioned"], serde_json::json!(["src/auth.rs"]));
+    assert_eq!(diff["only_in_b"]["files_mentioned"], serde_json::json!(["src/api.rs"]));
+    assert_eq!(diff["in_both"]["files_mentioned"], serde_json::json!(["src/db.rs"]));
+    assert_eq!(diff["duration_delta_seconds"], 240);
+
+    let response = client.send_request(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "tools/call",
+        "params": {"name": "compare_sessions", "arguments": {"session_id_a": "a", "session_id_b": "missing"}}
+    }));
+    assert_eq!(response["result"]["isError"], true);
+    let message = response["result"]["content"][0]["text"].as_str().unwrap();
+    assert!(message.contains("missing") && message.contains("session_id_b"));
+}
+
+#[test]
+fn test_claude_dir_override() {
+    let home = tempfile::tempdir().unwrap();
+    let flag_dir = tempfile::tempdir().unwrap();
+    let env_dir = tempfile::tempdir().unwrap();
+    // write_session puts files under <dir>/.claude, so point the overrides there
+    write_session(flag_dir.path(), "-proj", "from-flag", "/proj", &[("user", "hi", "2024-01-01T00:00:00Z")]);
+    write_session(env_dir.path(), "-proj", "from-env", "/proj", &[("user", "hi", "2024-01-01T00:00:00Z")]);
+    let list = |client: &mut McpTestClient| -> Vec<String> {
+        let text = client.call_tool("list_sessions", serde_json::json!({}));
+        let page: serde_json::Value = serde_json::from_str(&text).unwrap();
+        page["items"].as_array().unwrap().iter().map(|s| s["id"].as_str().unwrap().to_string()).collect()
+    };
+
+    let mut command = Command::new(env!("CARGO_BIN_EXE_claude-code-mcp"));
+    command.env("HOME", home.path()).env("CLAUDE_CODE_MCP_DIR", env_dir.path().join(".claude"));
+    assert_eq!(list(&mut McpTestClient::spawn(command)), vec!["from-env"]);
+
+    // The flag wins over the environment variable
+    let mut command = Command::new(env!("CARGO_BIN_EXE_claude-code-mcp"));
+    command
+        .env("HOME", home.path())
+        .env("CLAUDE_CODE_MCP_DIR", env_dir.path().join(".claude"))
+        .arg("--claude-dir")
+        .arg(flag_dir.path().join(".claude"));
+    assert_eq!(list(&mut McpTestClient::spawn(command)), vec!["from-flag"]);
+}
+
+#[test]
+fn test_config_file() {
+    let home = tempfile::tempdir().unwrap();
+    let data = tempfile::tempdir().unwrap();
+    write_session(data.path(), "-proj", "from-config", "/proj", &[("user", "hi", "2024-01-01T00:00:00Z")]);
+    let config_dir = home.path().join(".claude-code-mcp");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(
+        config_dir.join("config.toml"),
+        format!(
+            "claude_dir = {:?}\ntool_timeout_secs = 90\nrate_limit_per_sec = 5\nlog_level = \"info\"\n",
+            data.path().join(".claude")
+        ),
+    )
+    .unwrap();
+
+    // The environment overrides the file, and flags override both
+    let mut command = Command::new(env!("CARGO_BIN_EXE_claude-code-mcp"));
+    command
+        .env("HOME", home.path())
+        .env_remove("CLAUDE_CODE_MCP_DIR")
+        .env("CLAUDE_CODE_MCP_TOOL_TIMEOUT_SECS", "45")
+        .env("CLAUDE_CODE_MCP_RATE_LIMIT_PER_SEC", "20")
+        .args(["--rate-limit", "0"]);
+    let mut client = McpTestClient::spawn(command);
+
+    let text = client.call_tool("list_sessions", serde_json::json!({}));
+    assert!(text.contains("from-config"));
+
+    let text = client.call_tool("show_config", serde_json::json!({}));
+    let shown: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(shown["config_file_found"], true);
+    assert_eq!(shown["config"]["claude_dir"], data.path().join(".claude").to_str().unwrap());
+    assert_eq!(shown["config"]["tool_timeout_secs"], 45);
+    assert_eq!(shown["config"]["rate_limit_per_sec"], 0);
+    assert_eq!(shown["config"]["log_level"], "info");
+    assert_eq!(shown["config"]["transport"], "stdio");
+    assert_eq!(shown["config"]["max_sessions_in_cache"], 1000);
+}
+
+#[test]
+fn test_disabled_tools() {
+    let home = tempfile::tempdir().unwrap();
+    let config_dir = home.path().join(".claude-code-mcp");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(
+        config_dir.join("config.toml"),
+        "disabled_tools = [\"get_session_context\", \"get_sesion\"]\n",
+    )
+    .unwrap();
+
+    // Names that match no tool are warned about at startup
+    let output = Command::new(env!("CARGO_BIN_EXE_claude-code-mcp"))
+        .env("HOME", home.path())
+        .env_remove("CLAUDE_CODE_MCP_DIR")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("no tool named get_sesion"), "{}", stderr);
+    assert!(!stderr.contains("get_session_context"), "{}", stderr);
+
+    let mut client = McpTestClient::with_home(home.path());
+    let response = client.send_request(&serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"}));
+    let names: Vec<&str> = response["result"]["tools"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| t["name"].as_str().unwrap())
+        .collect();
+    assert!(!names.contains(&"get_session_context"));
+    assert!(names.contains(&"get_session"));
+
+    // Refused exactly like a tool that doesn't exist, even with bad arguments
+    let call = |client: &mut McpTestClient, name: &str| {
+        client.send_request(&serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "tools/call",
+            "params": {"name": name, "arguments": {"depth": 42}}
+        }))
+    };
+    let disabled = call(&mut client, "get_session_context");
+    assert_eq!(disabled["result"]["content"][0]["text"], "Unknown tool: get_session_context");
+    let unknown = call(&mut client, "no_such_tool");
+    assert_eq!(
+        disabled.to_string().replace("get_session_context", "no_such_tool"),
+        unknown.to_string()
+    );
+}
+
+#[test]
+fn test_show_tools_false() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "hidden", "/proj", &[("user", "hi", "2025-01-01T00:00:00Z")]);
+    let mut command = Command::new(env!("CARGO_BIN_EXE_claude-code-mcp"));
+    command.env("HOME", home.path()).env_remove("CLAUDE_CODE_MCP_DIR").args(["--show-tools", "false"]);
+    let mut client = McpTestClient::spawn(command);
+
+    let response = client.send_request(&serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"}));
+    assert_eq!(response["result"]["tools"], serde_json::json!([]));
+
+    // Tools can still be called by name
+    let text = client.call_tool("list_sessions", serde_json::json!({}));
+    let page: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(page["items"][0]["id"], "hidden");
+}
+
+#[test]
+fn test_invalid_config_file() {
+    let home = tempfile::tempdir().unwrap();
+    let config_dir = home.path().join(".claude-code-mcp");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(config_dir.join("config.toml"), "tool_timeout = 90\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_claude-code-mcp"))
+        .env("HOME", home.path())
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("config.toml"), "{}", stderr);
+    assert!(stderr.contains("tool_timeout"), "{}", stderr);
+}
+
+#[test]
+fn test_list_agent_sessions() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "parent", "/proj", &[
+        ("user", "refactor the parser", "2024-01-01T00:00:00Z"),
+        ("assistant", "delegating", "2024-01-01T00:10:00Z"),
+    ]);
+    // Agent transcripts are logged under the parent's sessionId
+    write_session_lines(home.path(), "-proj", "agent-explore", &[serde_json::json!({
+        "type": "user",
+        "sessionId": "parent",
+        "timestamp": "2024-01-01T00:05:00Z",
+        "message": {"role": "user", "content": "find the parser entry points"}
+    })]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    let text = client.call_tool("list_agent_sessions", serde_json::json!({"parent_session_id": "parent"}));
+    let agents: Vec<serde_json::Value> = serde_json::from_str(&text).unwrap();
+    assert_eq!(agents.len(), 1);
+    assert_eq!(agents[0]["id"], "agent-explore");
+    assert_eq!(agents[0]["parent_session_id"], "parent");
+
+    let text = client.call_tool("list_sessions", serde_json::json!({}));
+    let page: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(page["items"].as_array().unwrap().len(), 1);
+
+    let text = client.call_tool("list_sessions", serde_json::json!({"include_agents": true}));
+    let page: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(page["items"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn test_structured_tool_errors() {
+    let home = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(home.path().join(".claude")).unwrap();
+    let mut client = McpTestClient::with_home(home.path());
+
+    let error_data = |client: &mut McpTestClient, name: &str, arguments: serde_json::Value| {
+        let response = client.send_request(&serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "tools/call",
+            "params": {"name": name, "arguments": arguments}
+        }));
+        assert_eq!(response["result"]["isError"], true);
+        // The readable message comes first, the structured error after it
+        let message = response["result"]["content"][0]["text"].as_str().unwrap().to_string();
+        let data: serde_json::Value =
+            serde_json::from_str(response["result"]["content"][1]["text"].as_str().unwrap()).unwrap();
+        assert_eq!(data["detail"], message.as_str());
+        data
+    };
+
+    let data = error_data(&mut client, "get_session", serde_json::json!({"session_id": "missing"}));
+    assert_eq!(data["error_kind"], "not_found");
+    assert!(data["path"].is_null());
+
+    let data = error_data(&mut client, "get_session", serde_json::json!({}));
+    assert_eq!(data["error_kind"], "invalid_argument");
+
+    // Malformed session IDs are refused before any file is looked up
+    for (name, arguments) in [
+        ("get_session", serde_json::json!({"session_id": "../../etc/passwd"})),
+        ("get_session_context", serde_json::json!({"session_id": "abc.jsonl"})),
+        ("get_session_context", serde_json::json!({"session_ids": ["abc", "a/b"]})),
+        ("get_session_diff", serde_json::json!({"session_id": "abc\\def", "since_message_index": 0})),
+    ] {
+        let data = error_data(&mut client, name, arguments);
+        assert_eq!(data["error_kind"], "invalid_argument");
+        assert_eq!(data["detail"], "Invalid session_id format: must be alphanumeric with hyphens and underscores only");
+    }
+
+    let data = error_data(&mut client, "search_sessions", serde_json::json!({"query": "(", "search_mode": "regex"}));
+    assert_eq!(data["error_kind"], "invalid_argument");
+
+    // JSON-RPC level errors carry the same structure in `data`
+    let response = client.send_request(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "resources/read",
+        "params": {"uri": "claude-session://missing"}
+    }));
+    assert_eq!(response["error"]["data"]["error_kind"], "not_found");
+    assert_eq!(response["error"]["data"]["detail"], response["error"]["message"]);
+}
+
+#[test]
+fn test_search_sessions_tool_inputs() {
+    let home = tempfile::tempdir().unwrap();
+    write_session_lines(home.path(), "-proj", "deploy", &[
+        serde_json::json!({
+            "type": "user", "sessionId": "deploy", "timestamp": "2025-01-01T10:00:00Z",
+            "message": {"role": "user", "content": "ship it"}
+        }),
+        serde_json::json!({
+            "type": "assistant", "sessionId": "deploy", "timestamp": "2025-01-01T10:00:01Z",
+            "message": {"role": "assistant", "content": [
+                {"type": "tool_use", "name": "Grep", "input": {"pattern": "TODO", "path": "/srv/www/releases/v42"}}
+            ]}
+        }),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    for mode in ["fuzzy", "regex"] {
+        let query = "/srv/www/releases";
+        let without = search_items(&client.call_tool(
+            "search_sessions",
+            serde_json::json!({"query": query, "search_mode": mode}),
+        ));
+        assert!(without.is_empty(), "{} matched without tool inputs", mode);
+
+        let with = search_items(&client.call_tool(
+            "search_sessions",
+            serde_json::json!({"query": query, "search_mode": mode, "search_tool_inputs": true, "highlight": true}),
+        ));
+        assert_eq!(with.len(), 1, "{} missed the tool input", mode);
+        assert_eq!(with[0]["id"], "deploy");
+        assert!(with[0]["match_context"].as_str().unwrap().contains("releases"));
+    }
+}
+
+#[test]
+fn test_search_sessions_role_filter() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "roles", "/proj", &[
+        ("user", "does anything use needle1?", "2025-01-01T10:00:00Z"),
+        ("assistant", "yes, the parser depends on needle2", "2025-01-01T10:00:01Z"),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+    let mut search = |query: &str, role: Option<&str>| {
+        let mut arguments = serde_json::json!({"query": query, "search_mode": "regex"});
+        if let Some(role) = role {
+            arguments["role_filter"] = role.into();
+        }
+        search_items(&client.call_tool("search_sessions", arguments)).len()
+    };
+
+    assert_eq!(search("needle1", None), 1);
+    assert_eq!(search("needle2", None), 1);
+    assert_eq!(search("needle1", Some("user")), 1);
+    assert_eq!(search("needle2", Some("user")), 0);
+    assert_eq!(search("needle1", Some("assistant")), 0);
+    assert_eq!(search("needle2", Some("assistant")), 1);
+    // Roles are matched case-sensitively
+    assert_eq!(search("needle2", Some("Assistant")), 0);
+}
+
+#[test]
+fn test_search_sessions_date_range() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "january", "/proj", &[
+        ("user", "tune the database pool", "2025-01-10T10:00:00Z"),
+    ]);
+    write_session(home.path(), "-proj", "march", "/proj", &[
+        ("user", "database migration", "2025-03-10T10:00:00Z"),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+    let mut ids = |arguments: serde_json::Value| -> Vec<String> {
+        let mut ids: Vec<String> = search_items(&client.call_tool("search_sessions", arguments))
+            .iter()
+            .map(|s| s["id"].as_str().unwrap().to_string())
+            .collect();
+        ids.sort();
+        ids
+    };
+
+    assert_eq!(ids(serde_json::json!({"query": "database"})), vec!["january", "march"]);
+    assert_eq!(ids(serde_json::json!({"query": "database", "since": "2025-02-01"})), vec!["march"]);
+    assert_eq!(ids(serde_json::json!({"query": "database", "until": "2025-01-10"})), vec!["january"]);
+    assert!(ids(serde_json::json!({"query": "database", "since": "2025-04-01"})).is_empty());
+
+    let text = client.call_tool("search_sessions", serde_json::json!({"query": "database", "since": "last week"}));
+    assert!(text.contains("Invalid since"), "{}", text);
+}
+
+/// Serve a stand-in embedding model on a local port, returning its URL. A
+/// text's embedding counts the words "rust", "python" and "garden" in it.
+fn spawn_embedding_server() -> String {
+    use std::io::Read;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/embed", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else {
+                continue;
+            };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut content_length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            let request: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            let text = request["input"].as_str().unwrap().to_lowercase();
+            let embedding: Vec<f32> = ["rust", "python", "garden"].iter().map(|w| text.matches(w).count() as f32).collect();
+            let body = serde_json::json!({"embedding": embedding}).to_string();
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+        }
+    });
+    url
+}
+
+/// Accept connections on a local port without ever answering, returning its URL
+fn spawn_unresponsive_server() -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/embed", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        // Held open so clients wait rather than see the connection close
+        let connections: Vec<_> = listener.incoming().map_while(Result::ok).collect();
+        drop(connections);
+    });
+    url
+}
+
+#[test]
+fn test_search_sessions_semantic() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "rusty", "/proj", &[
+        ("user", "port the parser to rust", "2025-03-01T10:00:00Z"),
+        ("assistant", "the rust borrow checker complains", "2025-03-01T10:00:05Z"),
+    ]);
+    write_session(home.path(), "-proj", "snake", "/proj", &[
+        ("user", "why is my python script slow", "2025-03-02T10:00:00Z"),
+        ("assistant", "profile the python code first, and a little rust could help", "2025-03-02T10:00:05Z"),
+    ]);
+    write_session(home.path(), "-proj", "outdoors", "/proj", &[
+        ("user", "plan the garden beds", "2025-03-03T10:00:00Z"),
+    ]);
+    let endpoint = spawn_embedding_server();
+    let with_endpoint = |home: &Path| {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_claude-code-mcp"));
+        command.env("HOME", home).env_remove("CLAUDE_CODE_MCP_DIR").args(["--embedding-endpoint", &endpoint]);
+        command
+    };
+
+    // No endpoint configured
+    let mut client = McpTestClient::with_home(home.path());
+    let text = client.call_tool("search_sessions", serde_json::json!({"query": "python", "search_mode": "semantic"}));
+    assert!(text.contains("--embedding-endpoint"), "{}", text);
+
+    // No cache built yet
+    let mut client = McpTestClient::spawn(with_endpoint(home.path()));
+    let text = client.call_tool("search_sessions", serde_json::json!({"query": "python", "search_mode": "semantic"}));
+    assert!(text.contains("build-embeddings"), "{}", text);
+
+    let output = with_endpoint(home.path()).arg("build-embeddings").output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Saved embeddings of 3 sessions"));
+    assert!(home.path().join(".claude-code-mcp").join("embeddings.cache").exists());
+
+    let text = client.call_tool("search_sessions", serde_json::json!({"query": "Python", "search_mode": "semantic", "limit": 2}));
+    let page: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(page["search_mode"], "semantic");
+    assert_eq!(page["total_matched"], 3);
+    let items = page["items"].as_array().unwrap();
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0]["id"], "snake");
+    assert_eq!(items[0]["score"], 894);
+    assert_eq!(items[1]["score"], 0);
+    assert!(page["next_cursor"].is_string());
+
+    let text = client.call_tool(
+        "search_sessions",
+        serde_json::json!({"query": "rust", "search_mode": "semantic", "min_score": 100}),
+    );
+    let ids: Vec<String> = search_items(&text).iter().map(|s| s["id"].as_str().unwrap().to_string()).collect();
+    assert_eq!(ids, vec!["rusty", "snake"]);
+
+    for (name, value) in [
+        ("highlight", serde_json::json!(true)),
+        ("search_tool_inputs", serde_json::json!(true)),
+        ("role_filter", serde_json::json!("user")),
+    ] {
+        let mut arguments = serde_json::json!({"query": "rust", "search_mode": "semantic"});
+        arguments[name] = value;
+        let response = client.send_request(&serde_json::json!({
+            "jsonrpc": "2.0", "id": 2, "method": "tools/call",
+            "params": {"name": "search_sessions", "arguments": arguments}
+        }));
+        assert_eq!(response["result"]["isError"], true);
+        let text = response["result"]["content"][0]["text"].as_str().unwrap();
+        assert!(text.contains(&format!("{} is not supported", name)), "{}", text);
+    }
+}
+
+#[test]
+fn test_search_sessions_min_score() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "exact", "/proj", &[
+        ("user", "restart the server", "2024-01-01T00:00:00Z"),
+    ]);
+    write_session(home.path(), "-proj", "scattered", "/proj", &[
+        ("user", "some extra variety of random text here", "2024-01-02T00:00:00Z"),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    let all = search_items(&client.call_tool("search_sessions", serde_json::json!({"query": "server"})));
+    assert_eq!(all.len(), 2);
+    let scores: Vec<i64> = all.iter().map(|s| s["score"].as_i64().expect("results carry a score")).collect();
+    assert!(scores[0] > scores[1]);
+
+    let none = search_items(&client.call_tool(
+        "search_sessions",
+        serde_json::json!({"query": "server", "min_score": 999999}),
+    ));
+    assert!(none.is_empty());
+
+    let best = search_items(&client.call_tool(
+        "search_sessions",
+        serde_json::json!({"query": "server", "min_score": scores[0]}),
+    ));
+    assert_eq!(best.len(), 1);
+    assert_eq!(best[0]["id"], "exact");
+
+    let zero = search_items(&client.call_tool(
+        "search_sessions",
+        serde_json::json!({"query": "server", "min_score": 0}),
+    ));
+    assert!(zero.len() > best.len());
+    assert_eq!(zero.len(), all.len());
+}
+
+#[test]
+fn test_search_sessions_boolean() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "rust-async", "/proj", &[
+        ("user", "Make the Rust server async with smol", "2024-01-01T00:00:00Z"),
+    ]);
+    write_session(home.path(), "-proj", "rust-tokio", "/proj", &[
+        ("user", "Make the Rust server async with tokio", "2024-01-02T00:00:00Z"),
+    ]);
+    write_session(home.path(), "-proj", "rust-sync", "/proj", &[
+        ("user", "Rust CLI argument parsing", "2024-01-03T00:00:00Z"),
+    ]);
+    write_session(home.path(), "-proj", "python-async", "/proj", &[
+        ("user", "python async crawler", "2024-01-04T00:00:00Z"),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    let text = client.call_tool(
+        "search_sessions",
+        serde_json::json!({"query": "rust AND async NOT tokio", "search_mode": "boolean", "highlight": true}),
+    );
+    let results = search_items(&text);
+    let ids: Vec<&str> = results.iter().map(|s| s["id"].as_str().unwrap()).collect();
+    assert_eq!(ids, vec!["rust-async"]);
+    assert!(results[0]["match_context"].as_str().unwrap().contains("**Rust**"));
+
+    let text = client.call_tool(
+        "search_sessions",
+        serde_json::json!({"query": "(python OR cli) AND NOT tokio", "query_mode": "boolean"}),
+    );
+    let results = search_items(&text);
+    let mut ids: Vec<&str> = results.iter().map(|s| s["id"].as_str().unwrap()).collect();
+    ids.sort();
+    assert_eq!(ids, vec!["python-async", "rust-sync"]);
+
+    let response = client.send_request(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "tools/call",
+        "params": {"name": "search_sessions", "arguments": {"query": "NOT tokio", "search_mode": "boolean"}}
+    }));
+    assert_eq!(response["result"]["isError"], true);
+    let data: serde_json::Value =
+        serde_json::from_str(response["result"]["content"][1]["text"].as_str().unwrap()).unwrap();
+    assert_eq!(data["error_kind"], "invalid_argument");
+}
+
+#[test]
+fn test_resource_subscription_updates() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "watched", "/proj", &[("user", "hello", "2024-01-01T00:00:00Z")]);
+    write_session(home.path(), "-proj", "ignored", "/proj", &[("user", "hello", "2024-01-01T00:00:00Z")]);
+    let mut client = StreamingTestClient::with_home(home.path());
+    let timeout = std::time::Duration::from_secs(10);
+
+    client.send(&serde_json::json!({
+        "jsonrpc": "2.0", "id": 1, "method": "resources/subscribe",
+        "params": {"uri": "claude-session://watched"}
+    }));
+    let response = client.recv(timeout).expect("No subscribe response");
+    assert_eq!(response["id"], 1);
+    assert!(response["error"].is_null());
+
+    write_session(home.path(), "-proj", "ignored", "/proj", &[("user", "changed", "2024-01-02T00:00:00Z")]);
+    write_session(home.path(), "-proj", "watched", "/proj", &[
+        ("user", "hello", "2024-01-01T00:00:00Z"),
+        ("assistant", "an update", "2024-01-01T00:00:05Z"),
+    ]);
+
+    let notification = client.recv(timeout).expect("No resources/updated notification");
+    assert_eq!(notification["method"], "notifications/resources/updated");
+    assert_eq!(notification["params"]["uri"], "claude-session://watched");
+    let text = notification["params"]["contents"][0]["text"].as_str().unwrap();
+    assert!(text.contains("an update"));
+    // Only the subscribed session is reported
+    assert!(client.recv(std::time::Duration::from_secs(2)).is_none());
+
+    client.send(&serde_json::json!({
+        "jsonrpc": "2.0", "id": 2, "method": "resources/unsubscribe",
+        "params": {"uri": "claude-session://watched"}
+    }));
+    assert_eq!(client.recv(timeout).expect("No unsubscribe response")["id"], 2);
+
+    write_session(home.path(), "-proj", "watched", "/proj", &[("user", "again", "2024-01-03T00:00:00Z")]);
+    assert!(client.recv(std::time::Duration::from_secs(2)).is_none());
+}
+
+#[test]
+fn test_export_sessions_csv() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "january", "/proj", &[
+        ("user", "plan the release, then tag it", "2024-01-15T10:00:00Z"),
+        ("assistant", "done", "2024-01-15T10:05:00Z"),
+    ]);
+    write_session(home.path(), "-proj", "february", "/proj", &[
+        ("user", "fix the build", "2024-02-15T10:00:00Z"),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    let csv = client.call_tool("export_sessions_csv", serde_json::json!({}));
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(
+        lines[0],
+        "id,project_path,cwd,created_at,updated_at,message_count,user_messages,assistant_messages,preview"
+    );
+    assert_eq!(lines.len(), 3);
+    assert!(lines[1].starts_with("february,"));
+    assert!(lines[2].starts_with("january,"));
+    assert!(lines[2].ends_with(",2,1,1,\"plan the release, then tag it\""));
+
+    let csv = client.call_tool("export_sessions_csv", serde_json::json!({"since": "2024-01-01", "until": "2024-01-31"}));
+    let ids: Vec<&str> = csv.lines().skip(1).map(|l| l.split(',').next().unwrap()).collect();
+    assert_eq!(ids, vec!["january"]);
+
+    let response = client.send_request(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "tools/call",
+        "params": {"name": "export_sessions_csv", "arguments": {"since": "last tuesday"}}
+    }));
+    assert_eq!(response["result"]["isError"], true);
+}
+
+#[test]
+fn test_rate_limit_throttles_tool_calls() {
+    let home = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(home.path().join(".claude/projects")).unwrap();
+    let mut client = McpTestClient::with_home(home.path());
+
+    let responses: Vec<serde_json::Value> = (0..20)
+        .map(|i| {
+            client.send_request(&serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": i,
+                "method": "tools/call",
+                "params": {"name": "list_sessions", "arguments": {}}
+            }))
+        })
+        .collect();
+
+    let throttled: Vec<&serde_json::Value> = responses.iter().filter(|r| r.get("error").is_some()).collect();
+    assert!(!throttled.is_empty(), "expected some of 20 rapid calls to be throttled");
+    assert!(throttled.len() < 20, "the first calls should get through");
+    for response in throttled {
+        assert_eq!(response["error"]["code"], -32000);
+        assert!(response["error"]["data"]["retry_after_ms"].as_u64().unwrap() > 0);
+    }
+
+    // Other requests aren't limited
+    let response = client.send_request(&serde_json::json!({"jsonrpc": "2.0", "id": 99, "method": "tools/list"}));
+    assert!(response["result"]["tools"].is_array());
+}
+
+#[test]
+fn test_find_sessions_with_errors() {
+    let home = tempfile::tempdir().unwrap();
+    write_session_lines(home.path(), "-proj", "broken-build", &[
+        serde_json::json!({
+            "type": "user", "sessionId": "broken-build", "timestamp": "2024-03-01T10:00:00Z",
+            "message": {"role": "user", "content": "build it"}
+        }),
+        serde_json::json!({
+            "type": "user", "sessionId": "broken-build", "timestamp": "2024-03-01T10:00:05Z",
+            "message": {"role": "user", "content": [
+                {"type": "tool_result", "tool_use_id": "t1", "content": "error: could not compile `app`"}
+            ]}
+        }),
+        serde_json::json!({
+            "type": "assistant", "sessionId": "broken-build", "timestamp": "2024-03-01T10:00:09Z",
+            "message": {"role": "assistant", "content": "The build failed on a type error."}
+        }),
+    ]);
+    write_session(home.path(), "-proj", "smooth", "/proj", &[
+        ("user", "why would this panic?", "2024-03-02T10:00:00Z"),
+        ("assistant", "It won't; all good.", "2024-03-02T10:00:05Z"),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    let result = client.call_tool("find_sessions_with_errors", serde_json::json!({}));
+    let sessions: Vec<serde_json::Value> = serde_json::from_str(&result).unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0]["id"], "broken-build");
+    assert_eq!(sessions[0]["matched_patterns"], serde_json::json!(["error:", "failed"]));
+    assert_eq!(sessions[0]["error_count"], 2);
+
+    // Custom patterns replace the defaults and are case-sensitive
+    let result = client.call_tool("find_sessions_with_errors", serde_json::json!({"error_patterns": ["all good"]}));
+    let sessions: Vec<serde_json::Value> = serde_json::from_str(&result).unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0]["id"], "smooth");
+    let result = client.call_tool("find_sessions_with_errors", serde_json::json!({"error_patterns": ["COULD NOT COMPILE"]}));
+    assert_eq!(result.trim(), "[]");
+}
+
+#[test]
+fn test_tool_call_timeout() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "slow", "/proj", &[("user", "rust", "2024-01-01T00:00:00Z")]);
+    let output = Command::new(env!("CARGO_BIN_EXE_claude-code-mcp"))
+        .env("HOME", home.path())
+        .env_remove("CLAUDE_CODE_MCP_DIR")
+        .args(["--embedding-endpoint", &spawn_embedding_server(), "build-embeddings"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // An endpoint that never answers, like a hung file system, keeps the call running
+    let mut command = Command::new(env!("CARGO_BIN_EXE_claude-code-mcp"));
+    command
+        .env("HOME", home.path())
+        .env_remove("CLAUDE_CODE_MCP_DIR")
+        .args(["--embedding-endpoint", &spawn_unresponsive_server()])
+        .arg("--tool-timeout-secs")
+        .arg("1");
+    let mut client = McpTestClient::spawn(command);
+
+    let started = std::time::Instant::now();
+    let response = client.send_request(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "tools/call",
+        "params": {"name": "search_sessions", "arguments": {"query": "rust", "search_mode": "semantic"}}
+    }));
+    assert!(started.elapsed() < std::time::Duration::from_secs(4));
+
+    assert_eq!(response["result"]["isError"], true);
+    assert_eq!(response["result"]["content"][0]["text"], "Tool call timed out after 1 seconds");
+    let data: serde_json::Value =
+        serde_json::from_str(response["result"]["content"][1]["text"].as_str().unwrap()).unwrap();
+    assert_eq!(data["error_kind"], "timeout");
+
+    // The server keeps answering other requests
+    let response = client.send_request(&serde_json::json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list"}));
+    assert!(response["result"]["tools"].is_array());
+}
+
+#[test]
+fn test_cancel_tool_call() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "slow", "/proj", &[("user", "hi", "2024-01-01T00:00:00Z")]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    // Waits for messages that never come until it is cancelled
+    let started = std::time::Instant::now();
+    client.send(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 42,
+        "method": "tools/call",
+        "params": {"name": "watch_session", "arguments": {"session_id": "slow", "poll_interval_ms": 100, "timeout_ms": 10000}}
+    }));
+    client.send(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/cancelled",
+        "params": {"requestId": 42, "reason": "user pressed stop"}
+    }));
+
+    let response = client.read_response();
+    assert!(started.elapsed() < std::time::Duration::from_secs(4));
+    assert_eq!(response["id"], 42);
+    assert_eq!(response["result"]["isError"], true);
+    assert_eq!(response["result"]["content"][0]["text"], "Request cancelled by client");
+    let data: serde_json::Value =
+        serde_json::from_str(response["result"]["content"][1]["text"].as_str().unwrap()).unwrap();
+    assert_eq!(data["error_kind"], "cancelled");
+
+    // Cancelling a finished or unknown request is ignored
+    client.send(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/cancelled",
+        "params": {"requestId": 42}
+    }));
+    let response = client.send_request(&serde_json::json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list"}));
+    assert_eq!(response["id"], 2);
+}
+
+#[test]
+fn test_cancel_right_after_call() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "slow", "/proj", &[("user", "hi", "2024-01-01T00:00:00Z")]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    // Call and cancellation arrive in one write, so the server reads the
+    // cancellation before the call's task can have started
+    let started = std::time::Instant::now();
+    for id in 0..10 {
+        let call = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "tools/call",
+            "params": {"name": "watch_session", "arguments": {"session_id": "slow", "poll_interval_ms": 100, "timeout_ms": 10000}}
+        });
+        let cancel = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/cancelled",
+            "params": {"requestId": id}
+        });
+        write!(client.stdin, "{}\n{}\n", call, cancel).unwrap();
+        client.stdin.flush().unwrap();
+    }
+
+    let mut ids: Vec<i64> = (0..10)
+        .map(|_| {
+            let response = client.read_response();
+            assert_eq!(response["result"]["content"][0]["text"], "Request cancelled by client");
+            response["id"].as_i64().unwrap()
+        })
+        .collect();
+    ids.sort();
+    assert_eq!(ids, (0..10).collect::<Vec<_>>());
+    assert!(started.elapsed() < std::time::Duration::from_secs(4));
+
+    // Other requests are answered in the order they were sent
+    let methods = ["ping", "tools/list", "prompts/list", "resources/list"];
+    let batch: String = (100..120)
+        .map(|id| format!("{}\n", serde_json::json!({"jsonrpc": "2.0", "id": id, "method": methods[id % 4]})))
+        .collect();
+    client.stdin.write_all(batch.as_bytes()).unwrap();
+    client.stdin.flush().unwrap();
+    let ids: Vec<u64> = (100..120).map(|_| client.read_response()["id"].as_u64().unwrap()).collect();
+    assert_eq!(ids, (100..120).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_ping() {
+    let home = tempfile::tempdir().unwrap();
+    let mut command = Command::new(env!("CARGO_BIN_EXE_claude-code-mcp"));
+    command
+        .args(["--rate-limit", "1"])
+        .env("HOME", home.path())
+        .env_remove("CLAUDE_CODE_MCP_DIR");
+    let mut client = McpTestClient::spawn(command);
+
+    // Far more than the rate limit allows for tool calls
+    for id in 0..100 {
+        let response = client.send_request(&serde_json::json!({"jsonrpc": "2.0", "id": id, "method": "ping"}));
+        assert_eq!(response["id"], id);
+        assert_eq!(response["result"], serde_json::json!({}));
+        assert!(response.get("error").is_none());
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_sigterm_finishes_running_requests() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "slow", "/proj", &[("user", "hi", "2024-01-01T00:00:00Z")]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    let response = client.send_request(&serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"}));
+    assert_eq!(response["id"], 1);
+
+    // Runs for a second before it answers that nothing was written
+    client.send(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "tools/call",
+        "params": {"name": "watch_session", "arguments": {"session_id": "slow", "poll_interval_ms": 100, "timeout_ms": 1000}}
+    }));
+    // Let the server read the request before it is told to stop
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    let status = Command::new("kill")
+        .args(["-TERM", &client.child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    // The call still running is answered, then stdout is closed
+    let response = client.read_response();
+    assert_eq!(response["id"], 2);
+    let result: serde_json::Value =
+        serde_json::from_str(response["result"]["content"][0]["text"].as_str().unwrap()).unwrap();
+    assert_eq!(result["timed_out"], true);
+    let mut rest = String::new();
+    assert_eq!(client.stdout.read_line(&mut rest).unwrap(), 0);
+
+    let exit = client.child.wait().unwrap();
+    assert_eq!(exit.code(), Some(0));
+}
+
+#[test]
+fn test_get_activity_heatmap() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-home-user-app", "monday", "/home/user/app", &[
+        ("user", "start the week", "2025-01-06T14:00:00Z"),
+    ]);
+    write_session(home.path(), "-home-user-app", "wednesday", "/home/user/app", &[
+        ("user", "midweek fix", "2025-01-08T09:30:00Z"),
+    ]);
+    write_session(home.path(), "-home-user-other", "other", "/home/user/other", &[
+        ("user", "side project", "2025-01-06T22:00:00Z"),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    let text = client.call_tool("get_activity_heatmap", serde_json::json!({}));
+    let heatmap: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(heatmap["by_hour"].as_array().unwrap().len(), 24);
+    assert_eq!(heatmap["by_hour"][14], 1);
+    assert_eq!(heatmap["by_hour"][22], 1);
+    assert_eq!(heatmap["by_day_of_week"], serde_json::json!([2, 0, 1, 0, 0, 0, 0]));
+    assert_eq!(
+        heatmap["by_date"],
+        serde_json::json!([{"date": "2025-01-06", "count": 2}, {"date": "2025-01-08", "count": 1}])
+    );
+    assert_eq!(heatmap["skipped_sessions"], 0);
+
+    let text = client.call_tool(
+        "get_activity_heatmap",
+        serde_json::json!({"project_filter": "/home/user/app", "since": "2025-01-07"}),
+    );
+    let heatmap: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(heatmap["by_date"], serde_json::json!([{"date": "2025-01-08", "count": 1}]));
+}
+
+#[test]
+fn test_rebuild_index() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "before", "/proj", &[("user", "first", "2025-02-01T10:00:00Z")]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    let text = client.call_tool("list_sessions", serde_json::json!({}));
+    assert!(!text.contains("after"));
+
+    write_session(home.path(), "-proj", "after", "/proj", &[("user", "second", "2025-02-02T10:00:00Z")]);
+    // Not valid UTF-8, so it can't be read line by line
+    let broken = home.path().join(".claude/projects/-proj/broken.jsonl");
+    std::fs::write(&broken, b"{\"type\": \"user\", \"message\": \"\xff\xfe\"}\n").unwrap();
+
+    let text = client.call_tool("rebuild_index", serde_json::json!({}));
+    let result: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(result["sessions_found"], 2);
+    assert_eq!(result["sessions_failed"], 1);
+    assert!(result["duration_ms"].is_u64());
+    let errors = result["errors"].as_array().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].as_str().unwrap().starts_with(&format!("{}: ", broken.display())), "{}", errors[0]);
+
+    let text = client.call_tool("list_sessions", serde_json::json!({}));
+    let page: serde_json::Value = serde_json::from_str(&text).unwrap();
+    let ids: Vec<&str> = page["items"].as_array().unwrap().iter().map(|s| s["id"].as_str().unwrap()).collect();
+    assert_eq!(ids, vec!["after", "before"]);
+}
+
+#[test]
+fn test_get_sessions_by_hour() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-home-user-app", "afternoon", "/home/user/app", &[
+        ("user", "review the pull request", "2025-01-06T15:30:00Z"),
+        ("assistant", "looks good", "2025-01-06T15:31:00Z"),
+    ]);
+    write_session(home.path(), "-home-user-app", "late", "/home/user/app", &[
+        ("user", "one more thing", "2025-01-07T23:45:00Z"),
+    ]);
+    write_session(home.path(), "-home-user-blog", "blog", "/home/user/blog", &[
+        ("user", "draft a post", "2025-01-08T15:05:00Z"),
+        ("assistant", "here is a draft", "2025-01-08T15:06:00Z"),
+        ("user", "shorter", "2025-01-08T15:07:00Z"),
+        ("assistant", "done", "2025-01-08T15:08:00Z"),
+    ]);
+    let mut command = Command::new(env!("CARGO_BIN_EXE_claude-code-mcp"));
+    // Two hours ahead of UTC, as a POSIX TZ string
+    command.env("HOME", home.path()).env_remove("CLAUDE_CODE_MCP_DIR").env("TZ", "XYZ-2");
+    let mut client = McpTestClient::spawn(command);
+
+    let text = client.call_tool("get_sessions_by_hour", serde_json::json!({}));
+    let usage: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(usage["timezone"], "XYZ-2");
+    assert_eq!(usage["unknown_time"], 0);
+    assert!(usage.get("note").is_none());
+    let hours = usage["hours"].as_array().unwrap();
+    assert_eq!(hours.len(), 24);
+    assert_eq!(
+        hours[17],
+        serde_json::json!({"hour": 17, "session_count": 2, "total_messages": 6, "avg_messages": 3.0})
+    );
+    assert_eq!(hours[1]["session_count"], 1);
+    assert_eq!(hours[15]["session_count"], 0);
+
+    let text = client.call_tool(
+        "get_sessions_by_hour",
+        serde_json::json!({"project_filter": "/home/user/app", "until": "2025-01-06"}),
+    );
+    let usage: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(usage["hours"][17]["session_count"], 1);
+    assert_eq!(usage["hours"][1]["session_count"], 0);
+}
+
+#[test]
+fn test_get_sessions_by_hour_follows_dst() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "winter", "/proj", &[("user", "hello", "2025-01-15T15:30:00Z")]);
+    write_session(home.path(), "-proj", "summer", "/proj", &[("user", "hello", "2025-07-15T15:30:00Z")]);
+    let mut command = Command::new(env!("CARGO_BIN_EXE_claude-code-mcp"));
+    // Central European Time, an hour further ahead in summer
+    command
+        .env("HOME", home.path())
+        .env_remove("CLAUDE_CODE_MCP_DIR")
+        .env("TZ", "CET-1CEST,M3.5.0,M10.5.0/3");
+    let mut client = McpTestClient::spawn(command);
+
+    let text = client.call_tool("get_sessions_by_hour", serde_json::json!({}));
+    let usage: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(usage["hours"][16]["session_count"], 1);
+    assert_eq!(usage["hours"][17]["session_count"], 1);
+}
+
+#[test]
+fn test_list_sessions_grouped_by_project() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-home-user-api", "api-old", "/home/user/api", &[
+        ("user", "add the login route", "2025-03-01T10:00:00Z"),
+    ]);
+    write_session(home.path(), "-home-user-api", "api-new", "/home/user/api", &[
+        ("user", "add rate limiting", "2025-03-05T10:00:00Z"),
+    ]);
+    write_session(home.path(), "-home-user-api", "api-mid", "/home/user/api", &[
+        ("user", "fix the session cookie", "2025-03-03T10:00:00Z"),
+    ]);
+    write_session(home.path(), "-home-user-web", "web-latest", "/home/user/web", &[
+        ("user", "restyle the navbar", "2025-03-07T10:00:00Z"),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    let text = client.call_tool("list_sessions", serde_json::json!({"group_by_project": true, "limit": 2}));
+    let result: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(result["total_groups"], 2);
+    let groups = result["groups"].as_array().unwrap();
+    assert_eq!(groups.len(), 2);
+
+    // The project with the most recent session comes first
+    assert_eq!(groups[0]["project_path"], "/home/user/web");
+    assert_eq!(groups[0]["session_count"], 1);
+    assert_eq!(groups[0]["sessions"][0]["id"], "web-latest");
+
+    // limit applies per group, and session_count still counts every session
+    assert_eq!(groups[1]["project_path"], "/home/user/api");
+    assert_eq!(groups[1]["session_count"], 3);
+    let ids: Vec<&str> = groups[1]["sessions"].as_array().unwrap().iter().map(|s| s["id"].as_str().unwrap()).collect();
+    assert_eq!(ids, vec!["api-new", "api-mid"]);
+
+    let text = client.call_tool("list_sessions", serde_json::json!({"group_by_project": true, "project_limit": 1}));
+    let result: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(result["total_groups"], 2);
+    assert_eq!(result["groups"].as_array().unwrap().len(), 1);
+    assert_eq!(result["groups"][0]["project_path"], "/home/user/web");
+
+    // The flat listing is unchanged
+    let text = client.call_tool("list_sessions", serde_json::json!({}));
+    let page: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(page["items"].as_array().unwrap().len(), 4);
+}
+
+#[test]
+fn test_list_sessions_full_content() {
+    let home = tempfile::tempdir().unwrap();
+    for day in 1..=12 {
+        write_session(home.path(), "-proj", &format!("day-{:02}", day), "/proj", &[
+            ("user", &format!("question on day {}", day), &format!("2025-03-{:02}T10:00:00Z", day)),
+            ("assistant", &format!("answer on day {}", day), &format!("2025-03-{:02}T10:00:05Z", day)),
+        ]);
+    }
+    let mut client = McpTestClient::with_home(home.path());
+
+    // Summaries by default
+    let text = client.call_tool("list_sessions", serde_json::json!({"limit": 2}));
+    let page: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(page["items"][0]["preview"], "question on day 12");
+    assert!(page["items"][0].get("messages").is_none());
+    assert!(page.get("total_bytes").is_none());
+
+    // Whole sessions, capped at 10 per page
+    let text = client.call_tool("list_sessions", serde_json::json!({"full_content": true, "limit": 50}));
+    let page: serde_json::Value = serde_json::from_str(&text).unwrap();
+    let items = page["items"].as_array().unwrap();
+    assert_eq!(items.len(), 10);
+    assert_eq!(items[0]["id"], "day-12");
+    assert_eq!(items[0]["messages"][1]["content"], "answer on day 12");
+    assert_eq!(page["total_bytes"].as_u64().unwrap() as usize, serde_json::to_vec(items).unwrap().len());
+
+    let cursor = page["next_cursor"].as_str().unwrap();
+    let text = client.call_tool("list_sessions", serde_json::json!({"full_content": true, "cursor": cursor}));
+    let page: serde_json::Value = serde_json::from_str(&text).unwrap();
+    let ids: Vec<&str> = page["items"].as_array().unwrap().iter().map(|s| s["id"].as_str().unwrap()).collect();
+    assert_eq!(ids, vec!["day-02", "day-01"]);
+    assert!(page["next_cursor"].is_null());
+
+    let text = client.call_tool("list_sessions", serde_json::json!({"full_content": true, "group_by_project": true}));
+    assert!(text.contains("full_content can't be combined"), "{}", text);
+}
+
+#[test]
+fn test_list_sessions_full_content_too_large() {
+    let home = tempfile::tempdir().unwrap();
+    let long = "x".repeat(300_000);
+    for id in ["a", "b", "c", "d"] {
+        write_session(home.path(), "-proj", id, "/proj", &[("user", &long, "2025-03-01T10:00:00Z")]);
+    }
+    let mut client = McpTestClient::with_home(home.path());
+
+    let text = client.call_tool("list_sessions", serde_json::json!({"full_content": true}));
+    assert!(text.contains("more than the 1 MB"), "{}", text);
+
+    let text = client.call_tool("list_sessions", serde_json::json!({"full_content": true, "limit": 3}));
+    let page: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(page["items"].as_array().unwrap().len(), 3);
+    assert!(page["total_bytes"].as_u64().unwrap() > 900_000);
+}
+
+#[test]
+fn test_list_sessions_fields() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-home-user-api", "older", "/home/user/api", &[
+        ("user", "add the login route", "2025-03-01T10:00:00Z"),
+    ]);
+    write_session(home.path(), "-home-user-api", "newer", "/home/user/api", &[
+        ("user", "add rate limiting", "2025-03-05T10:00:00Z"),
+        ("assistant", "done", "2025-03-05T10:01:00Z"),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+    let keys = |summary: &serde_json::Value| {
+        let mut keys: Vec<String> = summary.as_object().unwrap().keys().cloned().collect();
+        keys.sort();
+        keys
+    };
+
+    let text = client.call_tool("list_sessions", serde_json::json!({"fields": ["id", "updated_at"], "limit": 1}));
+    let page: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(page["items"].as_array().unwrap().len(), 1);
+    assert_eq!(keys(&page["items"][0]), vec!["id", "updated_at"]);
+    assert_eq!(page["items"][0]["id"], "newer");
+    // Paging and _meta are untouched
+    assert!(page["next_cursor"].is_string());
+    assert!(page["_meta"]["cache_age_seconds"].is_number());
+
+    // Sorting by a field that isn't returned still works
+    let text = client.call_tool(
+        "list_sessions",
+        serde_json::json!({"fields": ["preview"], "sort_by": "message_count", "sort_order": "asc"}),
+    );
+    let page: serde_json::Value = serde_json::from_str(&text).unwrap();
+    let previews: Vec<&str> = page["items"].as_array().unwrap().iter().map(|s| s["preview"].as_str().unwrap()).collect();
+    assert_eq!(previews, vec!["add the login route", "add rate limiting"]);
+
+    let text = client.call_tool("list_sessions", serde_json::json!({"fields": ["preview"], "preview_length": 7}));
+    let page: serde_json::Value = serde_json::from_str(&text).unwrap();
+    let previews: Vec<&str> = page["items"].as_array().unwrap().iter().map(|s| s["preview"].as_str().unwrap()).collect();
+    assert_eq!(previews, vec!["add rat...", "add the..."]);
+    let text = client.call_tool("list_sessions", serde_json::json!({"preview_length": 0}));
+    let page: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert!(page["items"].as_array().unwrap().iter().all(|s| s["preview"] == ""));
+
+    let text = client.call_tool("list_sessions", serde_json::json!({"fields": ["message_count"], "group_by_project": true}));
+    let grouped: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(grouped["groups"][0]["project_path"], "/home/user/api");
+    for session in grouped["groups"][0]["sessions"].as_array().unwrap() {
+        assert_eq!(keys(session), vec!["message_count"]);
+    }
+
+    let text = client.call_tool("list_sessions", serde_json::json!({"fields": ["id", "secret"]}));
+    assert!(text.contains("Invalid arguments for list_sessions"), "{}", text);
+}
+
+#[test]
+fn test_merge_sessions() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-home-user-app", "part-one", "/home/user/app", &[
+        ("user", "port the parser to nom", "2025-01-01T10:00:00Z"),
+        ("assistant", "started with the lexer", "2025-01-01T10:05:00Z"),
+    ]);
+    // Resumed: repeats the last message of part one
+    write_session(home.path(), "-home-user-app", "part-two", "/home/user/app", &[
+        ("assistant", "started with the lexer", "2025-01-01T10:05:00Z"),
+        ("user", "now the expressions", "2025-01-01T11:00:00Z"),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+
+    let output = home.path().join(".claude").join("projects").join("-home-user-app").join("merged.jsonl");
+    let text = client.call_tool("merge_sessions", serde_json::json!({
+        "session_id_a": "part-one", "session_id_b": "part-two", "output_path": output
+    }));
+    let result: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(result["total_messages"], 3);
+    assert_eq!(result["duplicates_removed"], 1);
+
+    // Every line of the merged file carries the new session ID
+    let session_id = result["session_id"].as_str().unwrap();
+    assert!(!["part-one", "part-two"].contains(&session_id));
+    let written = std::fs::read_to_string(&output).unwrap();
+    for line in written.lines() {
+        let entry: serde_json::Value = serde_json::from_str(line).unwrap();
+        assert_eq!(entry["sessionId"], session_id);
+    }
+
+    let text = client.call_tool("inspect_jsonl", serde_json::json!({"file_path": output}));
+    let session: serde_json::Value = serde_json::from_str(&text).unwrap();
+    let contents: Vec<&str> = session["messages"].as_array().unwrap().iter()
+        .map(|m| m["content"].as_str().unwrap())
+        .collect();
+    assert_eq!(contents, vec!["port the parser to nom", "started with the lexer", "now the expressions"]);
+
+    // Existing files and paths outside the home directory are refused
+    let text = client.call_tool("merge_sessions", serde_json::json!({
+        "session_id_a": "part-one", "session_id_b": "part-two", "output_path": output
+    }));
+    assert!(text.contains("Failed to write merged session"));
+    let text = client.call_tool("merge_sessions", serde_json::json!({
+        "session_id_a": "part-one", "session_id_b": "part-two", "output_path": "/tmp/merged.jsonl"
+    }));
+    assert!(text.contains("must be under"));
+}
+
+#[test]
+fn test_inspect_jsonl() {
+    let home = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(home.path().join(".claude").join("projects")).unwrap();
+    let backups = home.path().join("backups");
+    std::fs::create_dir_all(&backups).unwrap();
+    let line = serde_json::json!({
+        "type": "user", "sessionId": "archived", "timestamp": "2025-01-01T10:00:00Z",
+        "message": {"role": "user", "content": "an old session kept elsewhere"}
+    });
+    std::fs::write(backups.join("archived.jsonl"), format!("{}\n", line)).unwrap();
+    std::fs::write(backups.join("notes.txt"), "not a session").unwrap();
+    let mut client = McpTestClient::with_home(home.path());
+
+    let path = backups.join("archived.jsonl");
+    let text = client.call_tool("inspect_jsonl", serde_json::json!({"file_path": path}));
+    let session: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(session["id"], "archived");
+    assert_eq!(session["messages"][0]["content"], "an old session kept elsewhere");
+
+    let rejected = [
+        ("backups/archived.jsonl".to_string(), "must be absolute"),
+        (backups.join("notes.txt").display().to_string(), "must end in .jsonl"),
+        (backups.join("..").join("backups").join("archived.jsonl").display().to_string(), "must be under"),
+        ("/etc/archived.jsonl".to_string(), "must be under"),
+    ];
+    for (file_path, reason) in rejected {
+        let text = client.call_tool("inspect_jsonl", serde_json::json!({"file_path": file_path}));
+        assert!(text.contains(reason), "{}: {}", file_path, text);
+    }
+}
+
+#[test]
+fn test_get_most_recent_session() {
+    let home = tempfile::tempdir().unwrap();
+    let projects = home.path().join(".claude").join("projects");
+    let mut client = McpTestClient::with_home(home.path());
+
+    let response = client.send_request(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "tools/call",
+        "params": {"name": "get_most_recent_session", "arguments": {}}
+    }));
+    assert_eq!(response["result"]["isError"], true);
+
+    write_session(home.path(), "-home-user-app", "earlier", "/home/user/app", &[
+        ("user", "set up the project", "2025-04-01T09:00:00Z"),
+    ]);
+    write_session(home.path(), "-home-user-lib", "later", "/home/user/lib", &[
+        ("user", "publish the crate", "2025-04-02T09:00:00Z"),
+        ("assistant", "published 0.2.0", "2025-04-02T09:05:00Z"),
+    ]);
+    // Files are ranked by modification time, so make the order unambiguous
+    let age = |path: PathBuf, seconds: u64| {
+        let file = std::fs::File::options().append(true).open(path).unwrap();
+        file.set_modified(std::time::SystemTime::now() - std::time::Duration::from_secs(seconds)).unwrap();
+    };
+    age(projects.join("-home-user-app").join("earlier.jsonl"), 3600);
+    age(projects.join("-home-user-lib").join("later.jsonl"), 60);
+
+    let text = client.call_tool("get_most_recent_session", serde_json::json!({}));
+    let session: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(session["id"], "later");
+    assert_eq!(session["messages"][1]["content"], "published 0.2.0");
+
+    let text = client.call_tool("get_most_recent_session", serde_json::json!({"project_filter": "/home/user/app"}));
+    let session: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(session["id"], "earlier");
+}
+
+#[test]
+fn test_get_longest_and_shortest_sessions() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "-proj", "quick", "/proj", &[
+        ("user", "what time is it in UTC?", "2025-02-01T10:00:00Z"),
+    ]);
+    write_session(home.path(), "-proj", "short", "/proj", &[
+        ("user", "rename foo to bar", "2025-02-02T10:00:00Z"),
+        ("assistant", "renamed", "2025-02-02T10:00:05Z"),
+    ]);
+    write_session(home.path(), "-proj", "deep", "/proj", &[
+        ("user", "track down the deadlock", "2025-02-03T10:00:00Z"),
+        ("assistant", "looking at the lock order", "2025-02-03T10:01:00Z"),
+        ("user", "any luck?", "2025-02-03T11:00:00Z"),
+        ("assistant", "found it in the pool", "2025-02-03T11:30:00Z"),
+    ]);
+    let mut client = McpTestClient::with_home(home.path());
+    let ids = |text: String| -> Vec<String> {
+        let sessions: Vec<serde_json::Value> = serde_json::from_str(&text).unwrap();
+        sessions.iter().map(|s| s["id"].as_str().unwrap().to_string()).collect()
+    };
+
+    let longest = client.call_tool("get_longest_sessions", serde_json::json!({}));
+    assert_eq!(ids(longest), vec!["deep", "short"]);
+
+    let shortest = client.call_tool("get_shortest_sessions", serde_json::json!({"limit": 1}));
+    assert_eq!(ids(shortest), vec!["short"]);
+
+    let shortest = client.call_tool("get_shortest_sessions", serde_json::json!({"limit": 1, "include_trivial": true}));
+    assert_eq!(ids(shortest), vec!["quick"]);
+}
+
+#[test]
+fn test_search_sessions_pagination() {
+    let home = tempfile::tempdir().unwrap();
+    // Seven sessions mention "deploy", one to seven times, plus one that doesn't
+    for count in 1..=7 {
+        let content = vec!["deploy the service"; count].join(". ");
+        let timestamp = format!("2024-02-0{}T10:00:00Z", count);
+        write_session(home.path(), "-proj", &format!("deploy-{}", count), "/proj", &[("user", &content, &timestamp)]);
+    }
+    write_session(home.path(), "-proj", "unrelated", "/proj", &[("user", "write the docs", "2024-02-09T10:00:00Z")]);
+    // Paging through three modes takes more calls than the default rate limit allows
+    let mut command = Command::new(env!("CARGO_BIN_EXE_claude-code-mcp"));
+    command.env("HOME", home.path()).env_remove("CLAUDE_CODE_MCP_DIR").args(["--rate-limit", "0"]);
+    let mut client = McpTestClient::spawn(command);
+
+    for (mode, query) in [("regex", "deploy"), ("fuzzy", "deploy"), ("boolean", "deploy")] {
+        let search = |client: &mut McpTestClient, limit: usize, cursor: Option<&str>| -> serde_json::Value {
+            let mut arguments = serde_json::json!({"query": query, "search_mode": mode, "limit": limit});
+            if let Some(cursor) = cursor {
+                arguments["cursor"] = cursor.into();
+            }
+            serde_json::from_str(&client.call_tool("search_sessions", arguments)).unwrap()
+        };
+
+        let full = search(&mut client, 50, None);
+        assert_eq!(full["query"], query);
+        assert_eq!(full["search_mode"], mode);
+        assert_eq!(full["total_matched"], 7, "{} search", mode);
+        assert!(full["next_cursor"].is_null());
+        let full_ids: Vec<String> = full["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| s["id"].as_str().unwrap().to_string())
+            .collect();
+
+        let mut paged_ids = Vec::new();
+        let mut cursor: Option<String> = None;
+        let mut pages = 0;
+        loop {
+            let page = search(&mut client, 3, cursor.as_deref());
+            assert_eq!(page["total_matched"], 7);
+            paged_ids.extend(page["items"].as_array().unwrap().iter().map(|s| s["id"].as_str().unwrap().to_string()));
+            pages += 1;
+            match page["next_cursor"].as_str() {
+                Some(next) => cursor = Some(next.to_string()),
+                None => break,
+            }
+        }
+
+        assert_eq!(pages, 3, "{} search", mode);
+        // Same results in the same order, none repeated or skipped
+        assert_eq!(paged_ids, full_ids, "{} search", mode);
+    }
+
+    // Regex results are ranked by match count
+    let page: serde_json::Value = serde_json::from_str(&client.call_tool(
+        "search_sessions",
+        serde_json::json!({"query": "deploy", "search_mode": "regex", "limit": 2}),
+    ))
+    .unwrap();
+    assert_eq!(page["items"][0]["id"], "deploy-7");
+    assert_eq!(page["items"][1]["id"], "deploy-6");
+}
+
+#[test]
+fn test_tool_arguments_validated_against_schema() {
+    let home = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(home.path().join(".claude/projects")).unwrap();
+    let mut client = McpTestClient::with_home(home.path());
+
+    let mut call = |name: &str, arguments: serde_json::Value| -> (bool, String) {
+        let response = client.send_request(&serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "tools/call",
+            "params": {"name": name, "arguments": arguments}
+        }));
+        let text = response["result"]["content"][0]["text"].as_str().unwrap().to_string();
+        (response["result"]["isError"] == true, text)
+    };
+
+    // Out of range
+    let (is_error, text) = call("list_sessions", serde_json::json!({"limit": 0}));
+    assert!(is_error);
+    assert!(text.starts_with("Invalid arguments for list_sessions:"), "{}", text);
+    assert!(text.contains("- /limit: 0 is less than the minimum of 1"), "{}", text);
+    assert!(call("list_sessions", serde_json::json!({"limit": 101})).0);
+
+    // Wrong type, with every error listed
+    let (is_error, text) = call("list_sessions", serde_json::json!({"limit": "20", "include_agents": "yes"}));
+    assert!(is_error);
+    assert!(text.contains("- /limit: "), "{}", text);
+    assert!(text.contains("- /include_agents: "), "{}", text);
+
+    // Missing required argument
+    let (is_error, text) = call("search_sessions", serde_json::json!({"limit": 5}));
+    assert!(is_error);
+    assert!(text.contains("\"query\" is a required property"), "{}", text);
+
+    // Valid arguments go through
+    assert!(!call("list_sessions", serde_json::json!({"limit": 1})).0);
+}
@@ -0,0 +1,358 @@
+//! Integration tests for the HTTP/SSE transport
+//!
+//! Spawns the server with `--transport http --port 0` and talks to it over HTTP
+
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Helper to spawn the MCP server in HTTP mode on a random port
+struct HttpTestServer {
+    child: std::process::Child,
+    base_url: String,
+    client: reqwest::blocking::Client,
+}
+
+impl HttpTestServer {
+    fn new() -> Self {
+        Self::spawn(Command::new(env!("CARGO_BIN_EXE_claude-code-mcp")))
+    }
+
+    /// A server reading sessions from `home`, started with extra `args`
+    fn with_home(home: &Path, args: &[&str], envs: &[(&str, &str)]) -> Self {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_claude-code-mcp"));
+        command.env("HOME", home).args(args).envs(envs.iter().copied());
+        Self::spawn(command)
+    }
+
+    fn spawn(mut command: Command) -> Self {
+        let mut child = command
+            .args(["--transport", "http", "--port", "0"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("Failed to spawn MCP server");
+
+        // The server logs its bound address to stderr once it is listening
+        let mut stderr = BufReader::new(child.stderr.take().expect("Failed to get stderr"));
+        let mut line = String::new();
+        let base_url = loop {
+            line.clear();
+            if stderr.read_line(&mut line).expect("Failed to read stderr") == 0 {
+                panic!("Server exited before listening");
+            }
+            if let Some(addr) = line.trim().strip_prefix("claude-code-mcp listening on ") {
+                break addr.to_string();
+            }
+        };
+
+        Self {
+            child,
+            base_url,
+            client: reqwest::blocking::Client::new(),
+        }
+    }
+
+    fn post(&self, body: &serde_json::Value) -> reqwest::blocking::Response {
+        self.client
+            .post(format!("{}/mcp", self.base_url))
+            .json(body)
+            .send()
+            .expect("Failed to send request")
+    }
+}
+
+impl Drop for HttpTestServer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[test]
+fn test_http_initialize() {
+    let server = HttpTestServer::new();
+
+    let response = server.post(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "initialize",
+        "params": {}
+    }));
+
+    assert_eq!(response.status(), 200);
+    let content_type = response.headers()["content-type"].to_str().unwrap().to_string();
+    assert!(content_type.starts_with("application/json"));
+
+    let body: serde_json::Value = response.json().unwrap();
+    assert_eq!(body["id"], 1);
+    assert_eq!(body["result"]["serverInfo"]["name"], "claude-code-mcp");
+}
+
+#[test]
+fn test_http_tools_list_and_call() {
+    let server = HttpTestServer::new();
+
+    let body: serde_json::Value = server
+        .post(&serde_json::json!({"jsonrpc": "2.0", "id": "a", "method": "tools/list"}))
+        .json()
+        .unwrap();
+    assert_eq!(body["id"], "a");
+    assert!(body["result"]["tools"].is_array());
+
+    let body: serde_json::Value = server
+        .post(&serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "tools/call",
+            "params": {"name": "nonexistent_tool", "arguments": {}}
+        }))
+        .json()
+        .unwrap();
+    assert!(body["result"]["isError"] == true);
+}
+
+#[test]
+fn test_http_notification_accepted() {
+    let server = HttpTestServer::new();
+
+    let response = server.post(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/initialized"
+    }));
+
+    assert_eq!(response.status(), 202);
+}
+
+#[test]
+fn test_http_parse_error() {
+    let server = HttpTestServer::new();
+
+    let response = server
+        .client
+        .post(format!("{}/mcp", server.base_url))
+        .body("{invalid json")
+        .send()
+        .unwrap();
+
+    let body: serde_json::Value = response.json().unwrap();
+    assert_eq!(body["error"]["code"], -32700);
+}
+
+#[test]
+fn test_http_sse_endpoint() {
+    let server = HttpTestServer::new();
+
+    let response = server
+        .client
+        .get(format!("{}/mcp/events", server.base_url))
+        .send()
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "text/event-stream; charset=utf-8");
+}
+
+#[test]
+fn test_http_content_negotiation() {
+    let server = HttpTestServer::new();
+    let ping = serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": "ping"});
+    let content_type = |accept: Option<&str>| {
+        let mut request = server.client.post(format!("{}/mcp", server.base_url)).json(&ping);
+        if let Some(accept) = accept {
+            request = request.header("Accept", accept);
+        }
+        let response = request.send().unwrap();
+        assert_eq!(response.status(), 200);
+        response.headers()["content-type"].to_str().unwrap().to_string()
+    };
+
+    assert_eq!(content_type(None), "application/json");
+    assert_eq!(content_type(Some("*/*")), "application/json");
+    assert_eq!(content_type(Some("application/json-rpc")), "application/json-rpc");
+    assert_eq!(content_type(Some("application/jsonrpc+json")), "application/jsonrpc+json");
+    assert_eq!(
+        content_type(Some("application/json;q=0.4, application/json-rpc;q=0.8")),
+        "application/json-rpc"
+    );
+    assert_eq!(content_type(Some("text/html")), "application/json");
+}
+
+#[test]
+fn test_http_response_headers() {
+    let server = HttpTestServer::new();
+
+    let response = server.post(&serde_json::json!({"jsonrpc": "2.0", "id": 42, "method": "ping"}));
+    assert_eq!(response.headers()["x-request-id"], "42");
+    let length: usize = response.headers()["content-length"].to_str().unwrap().parse().unwrap();
+    assert_eq!(response.bytes().unwrap().len(), length);
+
+    let response = server.post(&serde_json::json!({"jsonrpc": "2.0", "id": "req-7", "method": "tools/list"}));
+    assert_eq!(response.headers()["x-request-id"], "req-7");
+    assert!(response.headers().contains_key("content-length"));
+
+    // Nothing to echo for notifications and unparseable requests
+    let response = server.post(&serde_json::json!({"jsonrpc": "2.0", "method": "notifications/initialized"}));
+    assert_eq!(response.status(), 202);
+    assert!(response.headers().get("x-request-id").is_none());
+    let response = server.client.post(format!("{}/mcp", server.base_url)).body("{invalid").send().unwrap();
+    assert!(response.headers().get("x-request-id").is_none());
+    assert_eq!(response.headers()["content-type"], "application/json");
+}
+
+#[test]
+fn test_http_metrics_endpoint() {
+    let server = HttpTestServer::new();
+
+    for (id, name) in [(1, "list_projects"), (2, "list_projects"), (3, "nonexistent_tool")] {
+        server.post(&serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "tools/call",
+            "params": {"name": name, "arguments": {}}
+        }));
+    }
+
+    let response = server
+        .client
+        .get(format!("{}/metrics", server.base_url))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let content_type = response.headers()["content-type"].to_str().unwrap();
+    assert!(content_type.starts_with("text/plain"));
+
+    let body = response.text().unwrap();
+    assert!(body.contains("# TYPE mcp_tool_calls_total counter"));
+    assert!(body.contains("mcp_tool_calls_total{tool=\"unknown\",status=\"error\"} 1\n"));
+
+    let list_projects_calls: u64 = body
+        .lines()
+        .filter(|line| line.starts_with("mcp_tool_calls_total{tool=\"list_projects\","))
+        .map(|line| line.rsplit(' ').next().unwrap().parse::<u64>().unwrap())
+        .sum();
+    assert_eq!(list_projects_calls, 2);
+    assert!(body.contains("mcp_tool_duration_seconds_count{tool=\"list_projects\"} 2\n"));
+    assert!(body.contains("mcp_requests_total{method=\"tools/call\",status=\"ok\"} 3\n"));
+    assert!(body.contains("mcp_sessions_cached 0\n"));
+}
+
+/// Write a one-message session file under `home`
+fn write_session(home: &Path, id: &str) {
+    let dir = home.join(".claude").join("projects").join("-proj");
+    std::fs::create_dir_all(&dir).unwrap();
+    let line = serde_json::json!({
+        "type": "user", "sessionId": id, "cwd": "/proj", "timestamp": "2025-01-01T10:00:00Z",
+        "message": {"role": "user", "content": "hello"}
+    });
+    std::fs::write(dir.join(format!("{}.jsonl", id)), line.to_string()).unwrap();
+}
+
+#[test]
+fn test_http_context_not_streamed() {
+    let home = tempfile::tempdir().unwrap();
+    let dir = home.path().join(".claude").join("projects").join("-proj");
+    std::fs::create_dir_all(&dir).unwrap();
+    let lines: Vec<String> = (0..210)
+        .map(|i| {
+            let role = if i % 2 == 0 { "user" } else { "assistant" };
+            serde_json::json!({
+                "type": role, "sessionId": "long", "cwd": "/proj", "timestamp": "2025-01-01T10:00:00Z",
+                "message": {"role": role, "content": format!("Editing src/module_{}.rs", i % 5)}
+            })
+            .to_string()
+        })
+        .collect();
+    std::fs::write(dir.join("long.jsonl"), lines.join("\n")).unwrap();
+    let server = HttpTestServer::with_home(home.path(), &[], &[]);
+
+    // Progress would go to every SSE subscriber, so the context comes whole
+    let body: serde_json::Value = server
+        .post(&serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "tools/call",
+            "params": {"name": "get_session_context", "arguments": {"session_id": "long", "streaming": true}}
+        }))
+        .json()
+        .unwrap();
+    let context: serde_json::Value =
+        serde_json::from_str(body["result"]["content"][0]["text"].as_str().unwrap()).unwrap();
+    assert_eq!(context["message_count"], 210);
+    assert!(context.get("pending").is_none());
+    assert!(!context["files_mentioned"].as_array().unwrap().is_empty());
+}
+
+/// Call `list_sessions` from `clients` clients at once, each with its own
+/// connection, returning each client's response
+fn concurrent_tool_calls(server: &HttpTestServer, clients: u64, name: &str, arguments: serde_json::Value) -> Vec<serde_json::Value> {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let tasks: Vec<_> = (0..clients)
+            .map(|id| {
+                let url = format!("{}/mcp", server.base_url);
+                let request = serde_json::json!({
+                    "jsonrpc": "2.0", "id": id, "method": "tools/call",
+                    "params": {"name": name, "arguments": arguments}
+                });
+                tokio::task::spawn(async move {
+                    let response = reqwest::Client::new().post(url).json(&request).send().await.unwrap();
+                    let body: serde_json::Value = response.json().await.unwrap();
+                    assert_eq!(body["id"], id);
+                    body
+                })
+            })
+            .collect();
+
+        let mut responses = Vec::new();
+        for task in tasks {
+            responses.push(task.await.unwrap());
+        }
+        responses
+    })
+}
+
+#[test]
+fn test_http_concurrent_clients() {
+    let home = tempfile::tempdir().unwrap();
+    for id in ["one", "two", "three"] {
+        write_session(home.path(), id);
+    }
+    let server = HttpTestServer::with_home(home.path(), &["--rate-limit", "0"], &[]);
+
+    for body in concurrent_tool_calls(&server, 50, "list_sessions", serde_json::json!({})) {
+        assert!(body.get("error").is_none(), "{}", body);
+        let text = body["result"]["content"][0]["text"].as_str().unwrap();
+        let page: serde_json::Value = serde_json::from_str(text).unwrap();
+        assert_eq!(page["items"].as_array().unwrap().len(), 3);
+    }
+}
+
+#[test]
+fn test_http_overloaded() {
+    let home = tempfile::tempdir().unwrap();
+    write_session(home.path(), "one");
+    let server = HttpTestServer::with_home(home.path(), &["--rate-limit", "0", "--max-concurrent-requests", "2"], &[]);
+
+    // Slow calls, so the first two are still running when the rest arrive
+    let arguments = serde_json::json!({"session_id": "one", "poll_interval_ms": 100, "timeout_ms": 500});
+    let responses = concurrent_tool_calls(&server, 50, "watch_session", arguments);
+    let overloaded = responses.iter().filter(|body| body["error"]["code"] == -32000).count();
+    let succeeded = responses.iter().filter(|body| body["result"]["content"].is_array()).count();
+    assert_eq!(overloaded + succeeded, 50);
+    assert!(succeeded >= 2, "{} calls succeeded", succeeded);
+    assert!(overloaded > 0);
+    let rejected = responses.iter().find(|body| body.get("error").is_some()).unwrap();
+    assert_eq!(rejected["error"]["message"], "Server overloaded, retry later");
+
+    // Permits come back once the calls finish
+    let body: serde_json::Value = server
+        .post(&serde_json::json!({
+            "jsonrpc": "2.0", "id": "after", "method": "tools/call",
+            "params": {"name": "list_sessions", "arguments": {}}
+        }))
+        .json()
+        .unwrap();
+    assert!(body["result"]["content"].is_array(), "{}", body);
+}